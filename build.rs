@@ -4,6 +4,7 @@ fn main() -> Result<()> {
     const PROTOS: &[&str] = &[
         "service-apis/proto/sited_io/websites/v1/website.proto",
         "service-apis/proto/sited_io/websites/v1/static_page.proto",
+        "service-apis/proto/sited_io/websites/v1/event.proto",
     ];
     const INCLUDES: &[&str] = &["service-apis/proto"];
 
@@ -0,0 +1,4 @@
+ALTER TABLE
+  domains
+ADD
+  COLUMN domain_unicode VARCHAR;
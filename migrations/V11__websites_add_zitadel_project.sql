@@ -0,0 +1,15 @@
+-- null for websites created before, their app stays in the shared project
+-- until their branding is synced the first time
+ALTER TABLE
+  websites
+ADD
+  COLUMN zitadel_org_id VARCHAR,
+ADD
+  COLUMN zitadel_project_id VARCHAR;
+
+ALTER TABLE
+  customizations
+ADD
+  COLUMN branding_sync_at TIMESTAMP WITH TIME ZONE,
+ADD
+  COLUMN branding_sync_attempts INT8 NOT NULL DEFAULT 0;
@@ -0,0 +1,18 @@
+CREATE TABLE outbox (
+  outbox_id SERIAL PRIMARY KEY,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  website_id VARCHAR NOT NULL,
+  subject VARCHAR NOT NULL,
+  message_id VARCHAR NOT NULL,
+  payload BYTEA NOT NULL,
+  status VARCHAR NOT NULL DEFAULT 'PENDING',
+  attempts INT8 NOT NULL DEFAULT 0,
+  next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  locked_until TIMESTAMP WITH TIME ZONE,
+  last_error VARCHAR,
+  delivered_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX idx_outbox_status_outbox_id ON outbox (status, outbox_id);
+-- finds the held back messages of a website, see OutboxMessage::list_pending
+CREATE INDEX idx_outbox_website_id_outbox_id ON outbox (website_id, outbox_id);
@@ -0,0 +1,43 @@
+-- The foreign keys of V2, V3, V4 and V6 were declared inline without a name.
+-- Those migrations need CockroachDB 21.2 or later (ON UPDATE NOW() in V2),
+-- which names them <table>_<column>_fkey like Postgres.
+ALTER TABLE
+  domains DROP CONSTRAINT domains_website_id_fkey;
+ALTER TABLE
+  domains
+ADD
+  CONSTRAINT fk_domains_website_id FOREIGN KEY (website_id) REFERENCES websites(website_id) ON DELETE CASCADE;
+
+ALTER TABLE
+  customizations DROP CONSTRAINT customizations_website_id_fkey;
+ALTER TABLE
+  customizations
+ADD
+  CONSTRAINT fk_customizations_website_id FOREIGN KEY (website_id) REFERENCES websites(website_id) ON DELETE CASCADE;
+
+ALTER TABLE
+  pages DROP CONSTRAINT pages_website_id_fkey;
+ALTER TABLE
+  pages
+ADD
+  CONSTRAINT fk_pages_website_id FOREIGN KEY (website_id) REFERENCES websites(website_id) ON DELETE CASCADE;
+
+ALTER TABLE
+  static_pages DROP CONSTRAINT static_pages_website_id_fkey;
+ALTER TABLE
+  static_pages
+ADD
+  CONSTRAINT fk_static_pages_website_id FOREIGN KEY (website_id) REFERENCES websites(website_id) ON DELETE CASCADE;
+
+ALTER TABLE
+  static_pages DROP CONSTRAINT static_pages_page_id_fkey;
+ALTER TABLE
+  static_pages
+ADD
+  CONSTRAINT fk_static_pages_page_id FOREIGN KEY (page_id) REFERENCES pages(page_id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS idx_domains_user_id ON domains (user_id);
+CREATE INDEX IF NOT EXISTS idx_customizations_user_id ON customizations (user_id);
+CREATE INDEX IF NOT EXISTS idx_pages_user_id ON pages (user_id);
+CREATE INDEX IF NOT EXISTS idx_static_pages_website_id ON static_pages (website_id);
+CREATE INDEX IF NOT EXISTS idx_static_pages_user_id ON static_pages (user_id);
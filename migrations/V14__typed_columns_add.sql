@@ -0,0 +1,27 @@
+-- CockroachDB can not change the type of a column inside of a transaction,
+-- so the typed columns are added next to the untyped ones, backfilled and
+-- swapped in this and the following migrations.
+CREATE TYPE domain_status AS ENUM (
+  'DOMAIN_STATUS_INTERNAL',
+  'DOMAIN_STATUS_PENDING',
+  'DOMAIN_STATUS_ACTIVE',
+  'DOMAIN_STATUS_DEGRADED',
+  'DOMAIN_STATUS_REDIRECT'
+);
+
+CREATE TYPE page_type AS ENUM ('PAGE_TYPE_STATIC', 'PAGE_TYPE_SHOP');
+
+ALTER TABLE
+  domains
+ADD
+  COLUMN status_typed domain_status;
+
+ALTER TABLE
+  pages
+ADD
+  COLUMN page_type_typed page_type;
+
+ALTER TABLE
+  static_pages
+ADD
+  COLUMN components_typed JSONB;
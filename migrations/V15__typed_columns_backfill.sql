@@ -0,0 +1,14 @@
+UPDATE
+  domains
+SET
+  status_typed = status::domain_status;
+
+UPDATE
+  pages
+SET
+  page_type_typed = page_type::page_type;
+
+UPDATE
+  static_pages
+SET
+  components_typed = components::JSONB;
@@ -0,0 +1,10 @@
+DROP INDEX IF EXISTS domains_domain_status_idx;
+
+ALTER TABLE
+  domains DROP COLUMN status;
+
+ALTER TABLE
+  pages DROP COLUMN page_type;
+
+ALTER TABLE
+  static_pages DROP COLUMN components;
@@ -0,0 +1,23 @@
+ALTER TABLE
+  domains RENAME COLUMN status_typed TO status;
+ALTER TABLE
+  domains
+ALTER COLUMN
+  status
+SET
+  NOT NULL;
+
+ALTER TABLE
+  pages RENAME COLUMN page_type_typed TO page_type;
+ALTER TABLE
+  pages
+ALTER COLUMN
+  page_type
+SET
+  NOT NULL;
+
+ALTER TABLE
+  static_pages RENAME COLUMN components_typed TO components;
+
+CREATE INDEX IF NOT EXISTS idx_domains_domain_status ON domains (domain, status);
+CREATE INDEX IF NOT EXISTS idx_static_pages_components ON static_pages USING GIN (components);
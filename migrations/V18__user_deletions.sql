@@ -0,0 +1,9 @@
+CREATE TABLE user_deletions (
+  user_id VARCHAR PRIMARY KEY,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  attempts INT8 NOT NULL DEFAULT 0,
+  next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  last_error VARCHAR
+);
+
+CREATE INDEX idx_user_deletions_next_attempt_at ON user_deletions (next_attempt_at);
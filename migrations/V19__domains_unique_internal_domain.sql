@@ -0,0 +1,5 @@
+-- generated and claimed subdomains belong to one website, custom domains may
+-- be pending for several websites until one of them is verified
+CREATE UNIQUE INDEX uq_domains_internal_domain ON domains (domain)
+WHERE
+  status IN ('DOMAIN_STATUS_INTERNAL', 'DOMAIN_STATUS_REDIRECT');
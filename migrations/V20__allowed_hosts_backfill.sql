@@ -0,0 +1,15 @@
+-- domains served before allowed_hosts existed, see OnDemandTlsProvider.
+-- Degraded domains keep their custom hostname until they become pending.
+INSERT INTO
+  allowed_hosts (hostname)
+SELECT
+  domain
+FROM
+  domains
+WHERE
+  status IN (
+    'DOMAIN_STATUS_INTERNAL',
+    'DOMAIN_STATUS_ACTIVE',
+    'DOMAIN_STATUS_DEGRADED',
+    'DOMAIN_STATUS_REDIRECT'
+  ) ON CONFLICT (hostname) DO NOTHING;
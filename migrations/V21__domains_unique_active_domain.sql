@@ -0,0 +1,5 @@
+-- a custom domain is served for one website, pending copies of it on other
+-- websites can not be activated while it is active or degraded
+CREATE UNIQUE INDEX uq_domains_active_domain ON domains (domain)
+WHERE
+  status IN ('DOMAIN_STATUS_ACTIVE', 'DOMAIN_STATUS_DEGRADED');
@@ -0,0 +1,8 @@
+ALTER TABLE
+  domains
+ADD
+  COLUMN last_checked_at TIMESTAMP WITH TIME ZONE,
+ADD
+  COLUMN last_check_succeeded BOOLEAN,
+ADD
+  COLUMN last_check_message VARCHAR;
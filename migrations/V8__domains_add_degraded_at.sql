@@ -0,0 +1,4 @@
+ALTER TABLE
+  domains
+ADD
+  COLUMN degraded_at TIMESTAMP WITH TIME ZONE;
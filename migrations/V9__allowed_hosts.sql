@@ -0,0 +1,4 @@
+CREATE TABLE allowed_hosts (
+  hostname VARCHAR NOT NULL PRIMARY KEY,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
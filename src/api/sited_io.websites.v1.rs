@@ -360,6 +360,38 @@ pub struct DomainResponse {
     pub domain: ::prost::alloc::string::String,
     #[prost(enumeration = "DomainStatus", tag = "3")]
     pub status: i32,
+    #[prost(uint64, tag = "4")]
+    pub created_at: u64,
+    #[prost(uint64, tag = "5")]
+    pub updated_at: u64,
+    #[prost(message, optional, tag = "6")]
+    pub verification: ::core::option::Option<DomainVerification>,
+    #[prost(message, optional, tag = "7")]
+    pub last_check: ::core::option::Option<DomainCheckResult>,
+    #[prost(string, tag = "8")]
+    pub domain_unicode: ::prost::alloc::string::String,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DomainVerification {
+    #[prost(string, tag = "1")]
+    pub record_type: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub record_name: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub record_value: ::prost::alloc::string::String,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DomainCheckResult {
+    #[prost(uint64, tag = "1")]
+    pub checked_at: u64,
+    #[prost(bool, tag = "2")]
+    pub succeeded: bool,
+    #[prost(string, tag = "3")]
+    pub message: ::prost::alloc::string::String,
 }
 #[derive(serde::Deserialize, serde::Serialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -380,6 +412,40 @@ pub struct CreateDomainResponse {
 #[derive(serde::Deserialize, serde::Serialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetDomainRequest {
+    #[prost(int64, tag = "1")]
+    pub domain_id: i64,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetDomainResponse {
+    #[prost(message, optional, tag = "1")]
+    pub domain: ::core::option::Option<DomainResponse>,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListDomainsRequest {
+    #[prost(string, tag = "1")]
+    pub website_id: ::prost::alloc::string::String,
+    #[prost(enumeration = "DomainStatus", optional, tag = "2")]
+    pub status: ::core::option::Option<i32>,
+    #[prost(message, optional, tag = "3")]
+    pub pagination: ::core::option::Option<super::super::types::v1::PaginationRequest>,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListDomainsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub domains: ::prost::alloc::vec::Vec<DomainResponse>,
+    #[prost(message, optional, tag = "2")]
+    pub pagination: ::core::option::Option<super::super::types::v1::PaginationResponse>,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CheckDomainStatusRequest {
     #[prost(int64, tag = "1")]
     pub domain_id: i64,
@@ -403,6 +469,17 @@ pub struct DeleteDomainRequest {
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DeleteDomainResponse {}
 #[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DomainStatusEvent {
+    #[prost(string, tag = "1")]
+    pub website_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub user_id: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub domain: ::core::option::Option<DomainResponse>,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum DomainStatus {
@@ -410,6 +487,8 @@ pub enum DomainStatus {
     Internal = 1,
     Pending = 2,
     Active = 3,
+    Degraded = 4,
+    Redirect = 5,
 }
 impl DomainStatus {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -422,6 +501,8 @@ impl DomainStatus {
             DomainStatus::Internal => "DOMAIN_STATUS_INTERNAL",
             DomainStatus::Pending => "DOMAIN_STATUS_PENDING",
             DomainStatus::Active => "DOMAIN_STATUS_ACTIVE",
+            DomainStatus::Degraded => "DOMAIN_STATUS_DEGRADED",
+            DomainStatus::Redirect => "DOMAIN_STATUS_REDIRECT",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -431,6 +512,8 @@ impl DomainStatus {
             "DOMAIN_STATUS_INTERNAL" => Some(Self::Internal),
             "DOMAIN_STATUS_PENDING" => Some(Self::Pending),
             "DOMAIN_STATUS_ACTIVE" => Some(Self::Active),
+            "DOMAIN_STATUS_DEGRADED" => Some(Self::Degraded),
+            "DOMAIN_STATUS_REDIRECT" => Some(Self::Redirect),
             _ => None,
         }
     }
@@ -449,6 +532,20 @@ pub mod domain_service_server {
             tonic::Response<super::CreateDomainResponse>,
             tonic::Status,
         >;
+        async fn get_domain(
+            &self,
+            request: tonic::Request<super::GetDomainRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetDomainResponse>,
+            tonic::Status,
+        >;
+        async fn list_domains(
+            &self,
+            request: tonic::Request<super::ListDomainsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ListDomainsResponse>,
+            tonic::Status,
+        >;
         async fn check_domain_status(
             &self,
             request: tonic::Request<super::CheckDomainStatusRequest>,
@@ -589,6 +686,98 @@ pub mod domain_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/sited_io.websites.v1.DomainService/GetDomain" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetDomainSvc<T: DomainService>(pub Arc<T>);
+                    impl<
+                        T: DomainService,
+                    > tonic::server::UnaryService<super::GetDomainRequest>
+                    for GetDomainSvc<T> {
+                        type Response = super::GetDomainResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetDomainRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as DomainService>::get_domain(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetDomainSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/sited_io.websites.v1.DomainService/ListDomains" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListDomainsSvc<T: DomainService>(pub Arc<T>);
+                    impl<
+                        T: DomainService,
+                    > tonic::server::UnaryService<super::ListDomainsRequest>
+                    for ListDomainsSvc<T> {
+                        type Response = super::ListDomainsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListDomainsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as DomainService>::list_domains(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ListDomainsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/sited_io.websites.v1.DomainService/CheckDomainStatus" => {
                     #[allow(non_camel_case_types)]
                     struct CheckDomainStatusSvc<T: DomainService>(pub Arc<T>);
@@ -1309,6 +1498,9 @@ pub struct GetWebsiteRequest {
 pub struct GetWebsiteResponse {
     #[prost(message, optional, tag = "1")]
     pub website: ::core::option::Option<WebsiteResponse>,
+    /// Set if the requested domain only redirects to the main domain of the website.
+    #[prost(string, optional, tag = "2")]
+    pub redirect_to: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(serde::Deserialize, serde::Serialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -1347,6 +1539,52 @@ pub struct UpdateWebsiteResponse {
 #[derive(serde::Deserialize, serde::Serialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SetSubdomainRequest {
+    #[prost(string, tag = "1")]
+    pub website_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub subdomain: ::prost::alloc::string::String,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SetSubdomainResponse {
+    #[prost(message, optional, tag = "1")]
+    pub website: ::core::option::Option<WebsiteResponse>,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckSubdomainAvailabilityRequest {
+    #[prost(string, tag = "1")]
+    pub subdomain: ::prost::alloc::string::String,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckSubdomainAvailabilityResponse {
+    #[prost(bool, tag = "1")]
+    pub available: bool,
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ResyncWebsiteRequest {
+    #[prost(string, tag = "1")]
+    pub website_id: ::prost::alloc::string::String,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ResyncWebsiteResponse {
+    #[prost(bool, tag = "1")]
+    pub changed: bool,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DeleteWebsiteRequest {
     #[prost(string, tag = "1")]
     pub website_id: ::prost::alloc::string::String,
@@ -1397,6 +1635,27 @@ pub mod website_service_server {
             tonic::Response<super::DeleteWebsiteResponse>,
             tonic::Status,
         >;
+        async fn set_subdomain(
+            &self,
+            request: tonic::Request<super::SetSubdomainRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SetSubdomainResponse>,
+            tonic::Status,
+        >;
+        async fn check_subdomain_availability(
+            &self,
+            request: tonic::Request<super::CheckSubdomainAvailabilityRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CheckSubdomainAvailabilityResponse>,
+            tonic::Status,
+        >;
+        async fn resync_website(
+            &self,
+            request: tonic::Request<super::ResyncWebsiteRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ResyncWebsiteResponse>,
+            tonic::Status,
+        >;
     }
     #[derive(Debug)]
     pub struct WebsiteServiceServer<T: WebsiteService> {
@@ -1707,6 +1966,151 @@ pub mod website_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/sited_io.websites.v1.WebsiteService/SetSubdomain" => {
+                    #[allow(non_camel_case_types)]
+                    struct SetSubdomainSvc<T: WebsiteService>(pub Arc<T>);
+                    impl<
+                        T: WebsiteService,
+                    > tonic::server::UnaryService<super::SetSubdomainRequest>
+                    for SetSubdomainSvc<T> {
+                        type Response = super::SetSubdomainResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SetSubdomainRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as WebsiteService>::set_subdomain(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SetSubdomainSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/sited_io.websites.v1.WebsiteService/CheckSubdomainAvailability" => {
+                    #[allow(non_camel_case_types)]
+                    struct CheckSubdomainAvailabilitySvc<T: WebsiteService>(pub Arc<T>);
+                    impl<
+                        T: WebsiteService,
+                    > tonic::server::UnaryService<
+                        super::CheckSubdomainAvailabilityRequest,
+                    > for CheckSubdomainAvailabilitySvc<T> {
+                        type Response = super::CheckSubdomainAvailabilityResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                super::CheckSubdomainAvailabilityRequest,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as WebsiteService>::check_subdomain_availability(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = CheckSubdomainAvailabilitySvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/sited_io.websites.v1.WebsiteService/ResyncWebsite" => {
+                    #[allow(non_camel_case_types)]
+                    struct ResyncWebsiteSvc<T: WebsiteService>(pub Arc<T>);
+                    impl<
+                        T: WebsiteService,
+                    > tonic::server::UnaryService<super::ResyncWebsiteRequest>
+                    for ResyncWebsiteSvc<T> {
+                        type Response = super::ResyncWebsiteResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ResyncWebsiteRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as WebsiteService>::resync_website(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ResyncWebsiteSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
@@ -2110,3 +2514,78 @@ pub mod static_page_service_server {
         const NAME: &'static str = "sited_io.websites.v1.StaticPageService";
     }
 }
+/// Published on every change of a website or one of its entities, on the
+/// subject "websites.v1.<event_type>":
+///
+///    website.created        website.updated        website.deleted
+///    page.created           page.updated           page.deleted
+///    static_page.created    static_page.updated    static_page.deleted
+///    domain.created         domain.updated         domain.deleted
+///    customization.updated
+///
+/// Deleted events carry the entity as it was before the deletion. Consumers
+/// should skip events with a version they do not know.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EventEnvelope {
+    /// Version of the envelope and its payloads, currently 1.
+    #[prost(uint32, tag = "1")]
+    pub version: u32,
+    /// Unique id of the event, to recognize redeliveries.
+    #[prost(string, tag = "2")]
+    pub event_id: ::prost::alloc::string::String,
+    /// "<entity>.<action>", e.g. "page.updated".
+    #[prost(string, tag = "3")]
+    pub event_type: ::prost::alloc::string::String,
+    /// Unix timestamp in seconds of the change.
+    #[prost(uint64, tag = "4")]
+    pub timestamp: u64,
+    /// User id of who made the change. Empty for changes made by the service
+    /// itself, e.g. by the domain check job.
+    #[prost(string, tag = "5")]
+    pub actor: ::prost::alloc::string::String,
+    #[prost(string, tag = "6")]
+    pub website_id: ::prost::alloc::string::String,
+    #[prost(oneof = "event_envelope::Payload", tags = "10, 11, 12, 13, 14")]
+    pub payload: ::core::option::Option<event_envelope::Payload>,
+}
+/// Nested message and enum types in `EventEnvelope`.
+pub mod event_envelope {
+    #[derive(serde::Deserialize, serde::Serialize)]
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Payload {
+        #[prost(message, tag = "10")]
+        Website(super::WebsiteResponse),
+        #[prost(message, tag = "11")]
+        Page(super::PageResponse),
+        #[prost(message, tag = "12")]
+        StaticPage(super::StaticPageResponse),
+        #[prost(message, tag = "13")]
+        Domain(super::DomainResponse),
+        #[prost(message, tag = "14")]
+        Customization(super::CustomizationResponse),
+    }
+}
+/// Consumed by this service when a user account was deleted, on the subject
+/// "users.user.deleted". All websites of the user are deleted.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UserDeletedEvent {
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+}
+/// Consumed by this service when a shop was deleted, on the subject
+/// "commerce.shop.deleted". Shop pages showing the shop are deleted, a home
+/// page showing it becomes an empty static page.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ShopDeletedEvent {
+    #[prost(string, tag = "1")]
+    pub shop_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub user_id: ::prost::alloc::string::String,
+}
@@ -35,20 +35,20 @@ pub fn init_jwks_verifier(
     ))
 }
 
-fn get_token(metadata: &MetadataMap) -> Result<String, Status> {
+fn get_token(metadata: &MetadataMap) -> Option<String> {
     metadata
         .get(AUTHORIZATION.as_str())
         .and_then(|v| v.to_str().ok())
         .and_then(|header_value| header_value.split_once(' '))
         .map(|(_, token)| token.to_string())
-        .ok_or_else(|| Status::unauthenticated(""))
 }
 
 pub async fn get_user_id(
     metadata: &MetadataMap,
     verifier: &RemoteJwksVerifier,
 ) -> Result<String, Status> {
-    let token = get_token(metadata)?;
+    let token =
+        get_token(metadata).ok_or_else(|| Status::unauthenticated(""))?;
 
     verifier
         .verify::<()>(&token)
@@ -0,0 +1,597 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::Notify;
+use tonic::{Code, Status};
+
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
+use crate::api::sited_io::websites::v1::DomainStatus;
+use crate::identity::{AppProject, Branding, DynIdentityProvider};
+use crate::images::ImageService;
+use crate::model::{Customization, Website};
+use crate::publisher::{EventAction, Publisher, SYSTEM_ACTOR};
+use crate::repository::DynStore;
+use crate::website_cache::WebsiteCache;
+use crate::WebsiteService;
+
+/// Pushes the colours and logo of websites to the label policy of their
+/// ZITADEL project, see `AppProject`.
+///
+/// Changing a customization marks its branding as due in the database and
+/// wakes the job up, so `UpdateCustomization` never waits for the identity
+/// provider. Failed syncs are retried with exponential backoff. Each sync is
+/// claimed for `LEASE` first, so replicas do not push the same branding twice
+/// and a sync interrupted by a restart is picked up again.
+///
+/// Websites created before each website got a project of its own have their
+/// app in the shared project, which can not be branded. Their first sync moves
+/// the app into a project of its own. The app gets a new client id, so their
+/// users have to sign in again once.
+pub struct BrandingSyncJob {
+    store: DynStore,
+    identity_provider: DynIdentityProvider,
+    image_service: ImageService,
+    publisher: Publisher,
+    website_cache: WebsiteCache,
+    interval: Duration,
+    notify: Arc<Notify>,
+}
+
+impl BrandingSyncJob {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+    const LEASE: Duration = Duration::from_secs(5 * 60);
+    const BASE_DELAY: Duration = Duration::from_secs(30);
+    const MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+    pub fn new(
+        store: DynStore,
+        identity_provider: DynIdentityProvider,
+        image_service: ImageService,
+        publisher: Publisher,
+        website_cache: WebsiteCache,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            store,
+            identity_provider,
+            image_service,
+            publisher,
+            website_cache,
+            interval,
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Handle to wake the job up after a customization changed.
+    pub fn notifier(&self) -> Arc<Notify> {
+        self.notify.clone()
+    }
+
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = self.notify.notified() => {}
+                }
+                self.run_once(Utc::now()).await;
+            }
+        })
+    }
+
+    /// Syncs the brandings due at `now`.
+    pub async fn run_once(&self, now: DateTime<Utc>) {
+        let customizations =
+            match self.store.customizations().list_branding_due(now).await {
+                Ok(customizations) => customizations,
+                Err(err) => {
+                    tracing::log::error!("[BrandingSyncJob.run_once]: {}", err);
+                    return;
+                }
+            };
+
+        for customization in customizations {
+            let website_id = customization.website_id.clone();
+            if let Err(err) = self.sync(customization, now).await {
+                tracing::log::error!(
+                    "[BrandingSyncJob.run_once] website_id {}: {}",
+                    website_id,
+                    err
+                );
+            }
+        }
+    }
+
+    fn retry_delay(attempts: i64) -> Duration {
+        let exponent = u32::try_from(attempts.saturating_sub(1))
+            .unwrap_or(u32::MAX)
+            .min(16);
+        Self::BASE_DELAY
+            .saturating_mul(2u32.pow(exponent))
+            .min(Self::MAX_DELAY)
+    }
+
+    async fn set_branding(
+        &self,
+        customization: &Customization,
+    ) -> Result<(), Status> {
+        let Some(website) =
+            self.store.websites().get(&customization.website_id).await?
+        else {
+            return Ok(());
+        };
+
+        let project = match website.zitadel_project() {
+            Some(project) => project,
+            None => self.move_to_own_project(&website).await?,
+        };
+
+        let branding = Branding {
+            primary_color: customization.primary_color.clone(),
+            secondary_color: customization.secondary_color.clone(),
+            logo_url: self
+                .image_service
+                .get_opt_image_url(customization.logo_image_url.clone()),
+        };
+
+        self.identity_provider
+            .set_branding(&project, &branding)
+            .await
+    }
+
+    /// Moves the app of a website from the shared project into a project of
+    /// its own. Left-overs of a failed move are removed by `Reconciler`.
+    async fn move_to_own_project(
+        &self,
+        website: &Website,
+    ) -> Result<AppProject, Status> {
+        let domain = website
+            .domains
+            .iter()
+            .find(|d| d.status == DomainStatus::Internal)
+            .map(|d| &d.domain)
+            .ok_or_else(|| {
+                Status::failed_precondition(format!(
+                    "website '{}' has no internal domain",
+                    website.website_id
+                ))
+            })?;
+
+        let project = self.identity_provider.add_project(domain).await?;
+        let (redirect_uris, post_logout_redirect_uris) =
+            WebsiteService::build_redirect_uris(&[domain]);
+        let app = self
+            .identity_provider
+            .add_app(
+                Some(&project),
+                domain,
+                redirect_uris,
+                post_logout_redirect_uris,
+            )
+            .await?;
+
+        let transaction = self.store.begin().await?;
+
+        let updated_website = transaction
+            .websites()
+            .update_zitadel_app(
+                &website.website_id,
+                &app.client_id,
+                &app.app_id,
+                Some(&project),
+            )
+            .await?;
+
+        let website_response =
+            WebsiteService::to_response(&self.image_service, updated_website);
+
+        self.publisher
+            .publish_website(transaction.outbox(), &website_response, false)
+            .await?;
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Updated,
+                SYSTEM_ACTOR,
+                &website.website_id,
+                Payload::Website(website_response),
+            )
+            .await?;
+
+        transaction.commit().await?;
+        self.website_cache.invalidate(&website.website_id);
+
+        WebsiteService::sync_redirect_uris(
+            self.store.as_ref(),
+            self.identity_provider.as_ref(),
+            &website.website_id,
+        )
+        .await?;
+
+        self.identity_provider
+            .remove_app(None, &website.zitadel_app_id)
+            .await?;
+
+        Ok(project)
+    }
+
+    async fn sync(
+        &self,
+        customization: Customization,
+        now: DateTime<Utc>,
+    ) -> Result<(), Status> {
+        let Some(sync_at) = customization.branding_sync_at else {
+            return Ok(());
+        };
+
+        let lease_until = now + Self::LEASE;
+
+        let Some(customization) = self
+            .store
+            .customizations()
+            .claim_branding_sync(
+                &customization.website_id,
+                sync_at,
+                lease_until,
+            )
+            .await?
+        else {
+            return Ok(());
+        };
+        // read back, the database may store less precision than chrono
+        let lease_until = customization.branding_sync_at.unwrap_or(lease_until);
+
+        match self.set_branding(&customization).await {
+            Ok(()) => {
+                self.store
+                    .customizations()
+                    .finish_branding_sync(
+                        &customization.website_id,
+                        lease_until,
+                    )
+                    .await?;
+                Ok(())
+            }
+            // retrying does not help
+            Err(err) if err.code() == Code::FailedPrecondition => {
+                self.store
+                    .customizations()
+                    .finish_branding_sync(
+                        &customization.website_id,
+                        lease_until,
+                    )
+                    .await?;
+                Err(err)
+            }
+            Err(err) => {
+                let retry_at = now
+                    + Self::retry_delay(customization.branding_sync_attempts);
+                self.store
+                    .customizations()
+                    .retry_branding_sync(
+                        &customization.website_id,
+                        lease_until,
+                        retry_at,
+                    )
+                    .await?;
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+
+    use crate::api::sited_io::websites::v1::DomainStatus;
+    use crate::identity::{
+        AppProject, Branding, IdentityProvider, InMemoryIdentityProvider,
+    };
+    use crate::images::ImageService;
+    use crate::model::Customization;
+    use crate::publisher::Publisher;
+    use crate::repository::{InMemoryStore, Repositories};
+    use crate::website_cache::WebsiteCache;
+
+    use super::BrandingSyncJob;
+
+    const WEBSITE_ID: &str = "website";
+    const USER_ID: &str = "user";
+    const COLOR: &str = "#ff0000";
+
+    struct Harness {
+        store: InMemoryStore,
+        identity_provider: InMemoryIdentityProvider,
+        project: AppProject,
+        job: BrandingSyncJob,
+    }
+
+    impl Harness {
+        /// A website whose changed colour is due to be synced.
+        async fn new() -> Self {
+            let store = InMemoryStore::new();
+            let identity_provider = InMemoryIdentityProvider::new();
+            let project =
+                identity_provider.add_project(WEBSITE_ID).await.unwrap();
+
+            let website_id = WEBSITE_ID.to_string();
+            let user_id = USER_ID.to_string();
+            store
+                .websites()
+                .create(
+                    &website_id,
+                    &user_id,
+                    &website_id,
+                    &website_id,
+                    &website_id,
+                    &project,
+                )
+                .await
+                .unwrap();
+            store
+                .customizations()
+                .create(&website_id, &user_id)
+                .await
+                .unwrap();
+            store
+                .customizations()
+                .update(&website_id, &user_id, Some(COLOR.to_string()), None)
+                .await
+                .unwrap();
+
+            let image_service = ImageService::new(
+                "bucket".to_string(),
+                "http://localhost".to_string(),
+                "access-key-id".to_string(),
+                "secret-access-key".to_string(),
+                "https://images.sited.io".to_string(),
+                1024 * 1024,
+            )
+            .await;
+
+            let job = BrandingSyncJob::new(
+                Arc::new(store.clone()),
+                Arc::new(identity_provider.clone()),
+                image_service,
+                Publisher::new(),
+                WebsiteCache::disabled(),
+                BrandingSyncJob::DEFAULT_INTERVAL,
+            );
+
+            Self {
+                store,
+                identity_provider,
+                project,
+                job,
+            }
+        }
+
+        async fn customization(&self) -> Customization {
+            self.store
+                .customizations()
+                .get(&WEBSITE_ID.to_string())
+                .await
+                .unwrap()
+                .unwrap()
+        }
+
+        fn is_synced(&self) -> bool {
+            self.is_synced_to(&self.project)
+        }
+
+        fn is_synced_to(&self, project: &AppProject) -> bool {
+            self.identity_provider.get_branding(project)
+                == Some(Branding {
+                    primary_color: Some(COLOR.to_string()),
+                    ..Default::default()
+                })
+        }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn retry_delay() {
+        let cases = [
+            (1, secs(30)),
+            (2, secs(60)),
+            (3, secs(120)),
+            (7, secs(1920)),
+            (8, secs(60 * 60)),
+            (i64::MAX, secs(60 * 60)),
+        ];
+
+        for (attempts, expected) in cases {
+            assert_eq!(
+                BrandingSyncJob::retry_delay(attempts),
+                expected,
+                "attempts {attempts}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn syncs_due_branding() {
+        let harness = Harness::new().await;
+
+        harness.job.run_once(Utc::now()).await;
+
+        assert!(harness.is_synced());
+        let customization = harness.customization().await;
+        assert_eq!(customization.branding_sync_at, None);
+        assert_eq!(customization.branding_sync_attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_sync_with_backoff() {
+        let harness = Harness::new().await;
+        let now = Utc::now();
+
+        harness.identity_provider.set_unavailable(true);
+        harness.job.run_once(now).await;
+
+        let customization = harness.customization().await;
+        assert_eq!(customization.branding_sync_at, Some(now + secs(30)));
+        assert_eq!(customization.branding_sync_attempts, 1);
+
+        harness.job.run_once(now + secs(30)).await;
+
+        let customization = harness.customization().await;
+        assert_eq!(customization.branding_sync_at, Some(now + secs(90)));
+        assert_eq!(customization.branding_sync_attempts, 2);
+
+        // not retried before the backoff passed
+        harness.identity_provider.set_unavailable(false);
+        harness.job.run_once(now + secs(89)).await;
+        assert!(!harness.is_synced());
+
+        harness.job.run_once(now + secs(90)).await;
+
+        assert!(harness.is_synced());
+        let customization = harness.customization().await;
+        assert_eq!(customization.branding_sync_at, None);
+        assert_eq!(customization.branding_sync_attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn moves_website_out_of_shared_project() {
+        let harness = Harness::new().await;
+        let website_id = WEBSITE_ID.to_string();
+        let domain = "website.sited.io".to_string();
+
+        // created before each website got a project of its own
+        let shared_app = harness
+            .identity_provider
+            .add_app(None, &domain, vec![], vec![])
+            .await
+            .unwrap();
+        harness
+            .store
+            .websites()
+            .update_zitadel_app(
+                &website_id,
+                &shared_app.client_id,
+                &shared_app.app_id,
+                None,
+            )
+            .await
+            .unwrap();
+        harness
+            .store
+            .domains()
+            .create(
+                &website_id,
+                &USER_ID.to_string(),
+                &domain,
+                &domain,
+                DomainStatus::Internal,
+            )
+            .await
+            .unwrap();
+
+        harness.job.run_once(Utc::now()).await;
+
+        let website = harness
+            .store
+            .websites()
+            .get(&website_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let project = website.zitadel_project().unwrap();
+        assert_ne!(project, harness.project);
+        assert_ne!(website.client_id, shared_app.client_id);
+        assert!(harness.is_synced_to(&project));
+        assert_eq!(harness.customization().await.branding_sync_at, None);
+
+        let app = harness
+            .identity_provider
+            .get_app(Some(&project), &website.zitadel_app_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            app.redirect_uris,
+            vec![format!("https://{domain}/user/sign-in-callback")]
+        );
+        assert!(harness
+            .identity_provider
+            .get_app(None, &shared_app.app_id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn leaves_claimed_sync_until_lease_expires() {
+        let harness = Harness::new().await;
+        let now = Utc::now();
+        let website_id = WEBSITE_ID.to_string();
+
+        // claimed by a replica that stopped while syncing
+        let sync_at = harness.customization().await.branding_sync_at.unwrap();
+        harness
+            .store
+            .customizations()
+            .claim_branding_sync(
+                &website_id,
+                sync_at,
+                now + BrandingSyncJob::LEASE,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        harness.job.run_once(now).await;
+        assert!(!harness.is_synced());
+
+        harness.job.run_once(now + BrandingSyncJob::LEASE).await;
+
+        assert!(harness.is_synced());
+        assert_eq!(harness.customization().await.branding_sync_at, None);
+    }
+
+    #[tokio::test]
+    async fn keeps_sync_due_if_customization_changed_while_syncing() {
+        let harness = Harness::new().await;
+        let now = Utc::now();
+        let website_id = WEBSITE_ID.to_string();
+        let lease_until: DateTime<Utc> = now + BrandingSyncJob::LEASE;
+
+        let sync_at = harness.customization().await.branding_sync_at.unwrap();
+        let customizations = harness.store.customizations();
+        customizations
+            .claim_branding_sync(&website_id, sync_at, lease_until)
+            .await
+            .unwrap()
+            .unwrap();
+        customizations
+            .update(&website_id, &USER_ID.to_string(), None, None)
+            .await
+            .unwrap();
+        customizations
+            .finish_branding_sync(&website_id, lease_until)
+            .await
+            .unwrap();
+
+        let customization = harness.customization().await;
+        assert!(customization
+            .branding_sync_at
+            .is_some_and(|at| at < lease_until));
+
+        // the previous change can not be claimed anymore
+        assert!(customizations
+            .claim_branding_sync(&website_id, sync_at, lease_until)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::{Arc, RwLock};
+
+use async_nats::header::NATS_MESSAGE_ID;
+use async_nats::jetstream;
+use async_nats::HeaderMap;
+use prost::Message;
+use tonic::{async_trait, Status};
+
+use crate::api::sited_io::websites::v1::EventEnvelope;
+
+/// A message relayed from the outbox.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerMessage {
+    pub subject: String,
+    /// Unique id of the message, to recognize redeliveries.
+    pub message_id: String,
+    pub payload: Vec<u8>,
+}
+
+/// Delivers messages to subscribers of other services.
+#[async_trait]
+pub trait MessageBroker: Send + Sync {
+    /// Returns once the broker accepted the message.
+    async fn publish(&self, message: &BrokerMessage) -> Result<(), Status>;
+}
+
+pub type DynMessageBroker = Arc<dyn MessageBroker>;
+
+/// Publishes to NATS, optionally through JetStream.
+///
+/// With JetStream a message only counts as published once the stream acked
+/// it. Every message carries its `message_id` as `Nats-Msg-Id`, so a retry
+/// after a lost ack is dropped as duplicate by the stream.
+#[derive(Debug, Clone)]
+pub struct NatsBroker {
+    client: async_nats::Client,
+    jetstream: Option<jetstream::Context>,
+}
+
+impl NatsBroker {
+    pub fn new(
+        client: async_nats::Client,
+        jetstream: Option<jetstream::Context>,
+    ) -> Self {
+        Self { client, jetstream }
+    }
+}
+
+#[async_trait]
+impl MessageBroker for NatsBroker {
+    async fn publish(&self, message: &BrokerMessage) -> Result<(), Status> {
+        let mut headers = HeaderMap::new();
+        headers.insert(NATS_MESSAGE_ID, message.message_id.as_str());
+        let subject = message.subject.clone();
+        let payload = message.payload.clone().into();
+
+        match &self.jetstream {
+            Some(jetstream) => {
+                jetstream
+                    .publish_with_headers(subject, headers, payload)
+                    .await
+                    .map_err(|err| Status::unavailable(err.to_string()))?
+                    .await
+                    .map_err(|err| Status::unavailable(err.to_string()))?;
+            }
+            None => {
+                self.client
+                    .publish_with_headers(subject, headers, payload)
+                    .await
+                    .map_err(|err| Status::unavailable(err.to_string()))?;
+                self.client
+                    .flush()
+                    .await
+                    .map_err(|err| Status::unavailable(err.to_string()))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Broker recording all messages in memory instead of delivering them. Used
+/// in tests to check which messages were published.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryBroker {
+    messages: Arc<RwLock<Vec<BrokerMessage>>>,
+}
+
+impl InMemoryBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn messages(&self) -> Vec<BrokerMessage> {
+        self.messages.read().unwrap().clone()
+    }
+
+    /// The events published on `websites.v1.>`, see `EventEnvelope`.
+    pub fn events(&self) -> Vec<EventEnvelope> {
+        self.messages
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|m| m.subject.starts_with("websites.v1."))
+            .filter_map(|m| EventEnvelope::decode(m.payload.as_slice()).ok())
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.messages.write().unwrap().clear();
+    }
+}
+
+#[async_trait]
+impl MessageBroker for InMemoryBroker {
+    async fn publish(&self, message: &BrokerMessage) -> Result<(), Status> {
+        self.messages.write().unwrap().push(message.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use crate::api::sited_io::websites::v1::event_envelope::Payload;
+    use crate::api::sited_io::websites::v1::PageResponse;
+    use crate::publisher::{EventAction, Publisher};
+
+    use super::{BrokerMessage, InMemoryBroker, MessageBroker};
+
+    #[tokio::test]
+    async fn records_messages_and_decodes_events() {
+        let broker = InMemoryBroker::new();
+        let event = Publisher::build_event(
+            EventAction::Created,
+            "user-1",
+            "website-1",
+            Payload::Page(PageResponse::default()),
+        );
+
+        broker
+            .publish(&BrokerMessage {
+                subject: "websites.website.upsert".to_string(),
+                message_id: "1".to_string(),
+                payload: vec![],
+            })
+            .await
+            .unwrap();
+        broker
+            .publish(&BrokerMessage {
+                subject: "websites.v1.page.created".to_string(),
+                message_id: event.event_id.clone(),
+                payload: event.encode_to_vec(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(broker.messages().len(), 2);
+        assert_eq!(broker.events(), vec![event]);
+
+        broker.clear();
+        assert!(broker.messages().is_empty());
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tonic::Status;
+
+use crate::api::sited_io::websites::v1::DomainStatus;
+use crate::edge::DynEdgeProvider;
+use crate::repository::DynStore;
+
+/// Domains a website is served on, and cached for.
+const SERVED_DOMAIN_STATUSES: [DomainStatus; 4] = [
+    DomainStatus::Internal,
+    DomainStatus::Redirect,
+    DomainStatus::Active,
+    DomainStatus::Degraded,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PurgeTarget {
+    /// Everything cached for the hostnames of the website.
+    Website,
+    /// Only the given pages, on all hostnames of the website.
+    Pages(HashSet<i64>),
+}
+
+#[derive(Debug)]
+struct PurgeRequest {
+    website_id: String,
+    page_id: Option<i64>,
+}
+
+#[derive(Debug)]
+struct PendingPurge {
+    target: PurgeTarget,
+    due: Instant,
+    deadline: Instant,
+}
+
+/// Purges waiting for their website to stay unchanged for `debounce`, but
+/// for no longer than `max_delay` after the first change.
+#[derive(Debug)]
+struct PendingPurges {
+    debounce: Duration,
+    max_delay: Duration,
+    purges: HashMap<String, PendingPurge>,
+}
+
+impl PendingPurges {
+    fn new(debounce: Duration, max_delay: Duration) -> Self {
+        Self {
+            debounce,
+            max_delay,
+            purges: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, request: PurgeRequest, now: Instant) {
+        let pending =
+            self.purges.entry(request.website_id).or_insert_with(|| {
+                PendingPurge {
+                    target: PurgeTarget::Pages(HashSet::new()),
+                    due: now,
+                    deadline: now + self.max_delay,
+                }
+            });
+
+        pending.due = (now + self.debounce).min(pending.deadline);
+
+        match (&mut pending.target, request.page_id) {
+            (PurgeTarget::Pages(page_ids), Some(page_id)) => {
+                page_ids.insert(page_id);
+            }
+            (target, None) => *target = PurgeTarget::Website,
+            (PurgeTarget::Website, Some(_)) => {}
+        }
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.purges.values().map(|p| p.due).min()
+    }
+
+    fn take_due(&mut self, now: Instant) -> Vec<(String, PurgeTarget)> {
+        let due: Vec<String> = self
+            .purges
+            .iter()
+            .filter(|(_, p)| p.due <= now)
+            .map(|(website_id, _)| website_id.clone())
+            .collect();
+
+        due.into_iter()
+            .filter_map(|website_id| {
+                self.purges
+                    .remove(&website_id)
+                    .map(|p| (website_id, p.target))
+            })
+            .collect()
+    }
+}
+
+/// Handle to request cache purges from the services. Requests return
+/// immediately, the purge runs in the background.
+#[derive(Debug, Clone)]
+pub struct CachePurger {
+    sender: Option<mpsc::UnboundedSender<PurgeRequest>>,
+}
+
+impl CachePurger {
+    /// A purger dropping all requests.
+    pub fn disabled() -> Self {
+        Self { sender: None }
+    }
+
+    fn send(&self, website_id: &str, page_id: Option<i64>) {
+        if let Some(sender) = &self.sender {
+            // only fails if the job stopped
+            let _ = sender.send(PurgeRequest {
+                website_id: website_id.to_string(),
+                page_id,
+            });
+        }
+    }
+
+    /// Purges all pages and assets of the website, e.g. after its
+    /// customization or navigation changed.
+    pub fn purge_website(&self, website_id: &str) {
+        self.send(website_id, None);
+    }
+
+    /// Purges a single page of the website, e.g. after its content changed.
+    pub fn purge_page(&self, website_id: &str, page_id: i64) {
+        self.send(website_id, Some(page_id));
+    }
+}
+
+/// Purges the CDN cache of websites after their content changed.
+///
+/// Requests are debounced per website, so a burst of saves in the editor
+/// purges once, `debounce` after the last save. A website that keeps changing
+/// is purged at least every `max_delay`. Requests for single pages are
+/// purged by URL on every hostname of the website, a request for the whole
+/// website purges its hostnames.
+pub struct CachePurgeJob {
+    store: DynStore,
+    edge_provider: DynEdgeProvider,
+    sender: mpsc::UnboundedSender<PurgeRequest>,
+    receiver: mpsc::UnboundedReceiver<PurgeRequest>,
+    pending: PendingPurges,
+}
+
+impl CachePurgeJob {
+    pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(5);
+    const MAX_DELAY_FACTOR: u32 = 12;
+
+    pub fn new(
+        store: DynStore,
+        edge_provider: DynEdgeProvider,
+        debounce: Duration,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            store,
+            edge_provider,
+            sender,
+            receiver,
+            pending: PendingPurges::new(
+                debounce,
+                debounce.saturating_mul(Self::MAX_DELAY_FACTOR),
+            ),
+        }
+    }
+
+    pub fn purger(&self) -> CachePurger {
+        CachePurger {
+            sender: Some(self.sender.clone()),
+        }
+    }
+
+    pub fn spawn(mut self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let next_due = self.pending.next_due();
+
+                tokio::select! {
+                    request = self.receiver.recv() => match request {
+                        Some(request) => {
+                            self.pending.add(request, Instant::now());
+                        }
+                        None => break,
+                    },
+                    _ = tokio::time::sleep_until(
+                        next_due.unwrap_or_else(Instant::now)
+                    ), if next_due.is_some() => {
+                        for (website_id, target) in
+                            self.pending.take_due(Instant::now())
+                        {
+                            if let Err(err) =
+                                self.purge(&website_id, target).await
+                            {
+                                tracing::log::error!(
+                                    "[CachePurgeJob.purge] website_id {}: {}",
+                                    website_id,
+                                    err
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    async fn purge(
+        &self,
+        website_id: &String,
+        target: PurgeTarget,
+    ) -> Result<(), Status> {
+        let Some(website) = self.store.websites().get(website_id).await? else {
+            return Ok(());
+        };
+
+        let hostnames: Vec<String> = website
+            .domains
+            .into_iter()
+            .filter(|d| SERVED_DOMAIN_STATUSES.contains(&d.status))
+            .map(|d| d.domain)
+            .collect();
+
+        match target {
+            PurgeTarget::Website => {
+                self.edge_provider.purge_hostnames(&hostnames).await
+            }
+            PurgeTarget::Pages(page_ids) => {
+                let mut paths = Vec::new();
+                for page_id in page_ids {
+                    // a deleted page changes the navigation of all pages
+                    match self.store.pages().get(page_id).await? {
+                        Some(page) => paths.push(page.path),
+                        None => {
+                            return self
+                                .edge_provider
+                                .purge_hostnames(&hostnames)
+                                .await
+                        }
+                    }
+                }
+
+                let urls: Vec<String> = hostnames
+                    .iter()
+                    .flat_map(|hostname| {
+                        paths.iter().map(move |path| {
+                            format!("https://{hostname}{path}")
+                        })
+                    })
+                    .collect();
+
+                self.edge_provider.purge_urls(&urls).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{PendingPurges, PurgeRequest, PurgeTarget};
+
+    fn request(website_id: &str, page_id: Option<i64>) -> PurgeRequest {
+        PurgeRequest {
+            website_id: website_id.to_string(),
+            page_id,
+        }
+    }
+
+    #[test]
+    fn burst_of_changes_is_purged_once_after_the_last() {
+        let mut pending =
+            PendingPurges::new(Duration::from_secs(5), Duration::from_secs(60));
+        let start = Instant::now();
+
+        pending.add(request("a", Some(1)), start);
+        pending.add(request("a", Some(2)), start + Duration::from_secs(3));
+        pending.add(request("a", Some(1)), start + Duration::from_secs(6));
+
+        assert!(pending.take_due(start + Duration::from_secs(10)).is_empty());
+        assert_eq!(
+            pending.take_due(start + Duration::from_secs(11)),
+            vec![("a".to_string(), PurgeTarget::Pages(HashSet::from([1, 2])))]
+        );
+        assert!(pending.next_due().is_none());
+    }
+
+    #[test]
+    fn website_purge_includes_pages() {
+        let mut pending =
+            PendingPurges::new(Duration::from_secs(5), Duration::from_secs(60));
+        let start = Instant::now();
+
+        pending.add(request("a", Some(1)), start);
+        pending.add(request("a", None), start);
+        pending.add(request("a", Some(2)), start);
+        pending.add(request("b", Some(3)), start);
+
+        let mut due = pending.take_due(start + Duration::from_secs(5));
+        due.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            due,
+            vec![
+                ("a".to_string(), PurgeTarget::Website),
+                ("b".to_string(), PurgeTarget::Pages(HashSet::from([3]))),
+            ]
+        );
+    }
+
+    #[test]
+    fn continuous_changes_are_purged_after_max_delay() {
+        let mut pending =
+            PendingPurges::new(Duration::from_secs(5), Duration::from_secs(12));
+        let start = Instant::now();
+
+        for second in 0..12 {
+            pending
+                .add(request("a", None), start + Duration::from_secs(second));
+        }
+
+        assert_eq!(pending.next_due(), Some(start + Duration::from_secs(12)));
+    }
+}
@@ -1,10 +1,15 @@
-use std::collections::HashMap;
+use std::time::Duration;
 
-use http::header::AUTHORIZATION;
-use http::{HeaderMap, HeaderValue};
-use reqwest::Client;
+use chrono::{DateTime, Utc};
+use http::header::{AUTHORIZATION, RETRY_AFTER};
+use http::{HeaderMap, HeaderValue, StatusCode};
+use rand::Rng;
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tonic::Status;
+use tonic::{async_trait, Status};
+
+use crate::edge::{EdgeHostname, EdgeProvider};
 
 #[derive(Debug, Serialize)]
 struct CreateDnsRecordRequest {
@@ -19,6 +24,7 @@ struct CreateDnsRecordRequest {
 #[derive(Debug, Serialize)]
 struct CreateCustomHostnameRequest {
     hostname: String,
+    custom_origin_server: String,
     ssl: CreateCustomHostnameSslRequest,
 }
 
@@ -30,70 +36,214 @@ struct CreateCustomHostnameSslRequest {
     wildcard: bool,
 }
 
+/// Either `hosts` or `files`, Cloudflare rejects requests with both.
+#[derive(Debug, Default, Serialize)]
+struct PurgeCacheRequest {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hosts: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CloudflareApiError {
+    pub code: i64,
+    pub message: String,
+}
+
 #[derive(Debug, Deserialize)]
-pub struct CloudflareResponses<B> {
-    pub result: Vec<B>,
-    pub errors: Vec<HashMap<String, String>>,
+struct CloudflareResponse<B> {
+    success: bool,
+    #[serde(default)]
+    errors: Vec<CloudflareApiError>,
+    result: Option<B>,
+    result_info: Option<CloudflareResultInfo>,
 }
 
-#[allow(unused)]
 #[derive(Debug, Deserialize)]
-pub struct CloudflareResponse<B> {
-    pub result: B,
-    pub errors: Vec<HashMap<String, String>>,
+struct CloudflareResultInfo {
+    page: u32,
+    total_pages: Option<u32>,
 }
 
 #[allow(unused)]
 #[derive(Debug, Deserialize)]
 pub struct DnsRecordResponse {
     pub id: String,
-    content: String,
-    name: String,
+    pub content: String,
+    pub name: String,
     proxied: bool,
     #[serde(rename = "type")]
-    _type: Option<String>,
+    pub _type: Option<String>,
     comment: Option<String>,
+    pub created_on: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CustomHostnameResponse {
     pub id: String,
     pub hostname: String,
+    pub custom_origin_server: Option<String>,
+    pub created_at: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct DnsLookupResponse {
-    #[serde(rename = "Status")]
-    pub status: usize,
-    #[serde(rename = "Answer")]
-    pub answer: Option<Vec<DnsLookupResponseAnswer>>,
-    #[serde(rename = "Authority")]
-    pub authority: Option<Vec<DnsLookupResponseAnswer>>,
-    #[serde(rename = "Additional")]
-    pub additional: Option<Vec<DnsLookupResponseAnswer>>,
+/// Parses the RFC 3339 timestamps of Cloudflare.
+fn parse_datetime(datetime: Option<String>) -> Option<DateTime<Utc>> {
+    datetime
+        .as_deref()
+        .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+        .map(|d| d.with_timezone(&Utc))
 }
 
 #[derive(Debug, Deserialize)]
-pub struct DnsLookupResponseAnswer {
-    pub name: String,
-    #[serde(rename = "type")]
-    pub _type: usize,
-    #[serde(rename = "TTL")]
-    pub ttl: usize,
-    pub data: String,
+struct DeletedResponse {}
+
+#[derive(Debug, Deserialize)]
+struct PurgeCacheResponse {}
+
+#[derive(Debug)]
+pub enum CloudflareError {
+    /// Request could not be sent or response body could not be read.
+    Request(reqwest::Error),
+    /// Cloudflare answered with an error status or `success: false`.
+    Api {
+        status: StatusCode,
+        errors: Vec<CloudflareApiError>,
+    },
+    /// Response body was not a Cloudflare API response.
+    Decode {
+        status: StatusCode,
+        err: serde_json::Error,
+    },
 }
 
-const CLOUDFLARE_DNS_URL: &str = "https://cloudflare-dns.com/dns-query";
+impl CloudflareError {
+    pub fn codes(&self) -> Vec<i64> {
+        match self {
+            Self::Api { errors, .. } => errors.iter().map(|e| e.code).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Requests that are not idempotent, e.g. creates, are only retried if
+    /// they were not processed: the connection failed or they were rate
+    /// limited. After a timeout or server error they may have been, so
+    /// retrying them could e.g. create a DNS record twice.
+    fn is_retryable(&self, idempotent: bool) -> bool {
+        match self {
+            Self::Request(err) => {
+                err.is_connect() || (idempotent && err.is_timeout())
+            }
+            Self::Api { status, .. } | Self::Decode { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS
+                    || (idempotent && status.is_server_error())
+            }
+        }
+    }
+}
+
+impl From<reqwest::Error> for CloudflareError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Request(err)
+    }
+}
+
+impl From<CloudflareError> for Status {
+    fn from(err: CloudflareError) -> Self {
+        tracing::log::error!("[CloudflareService]: {}", err);
+        match err {
+            CloudflareError::Api { status, .. }
+                if status == StatusCode::TOO_MANY_REQUESTS =>
+            {
+                Status::unavailable("")
+            }
+            _ => Status::internal(""),
+        }
+    }
+}
+
+impl std::fmt::Display for CloudflareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(err) => write!(f, "request failed: {}", err),
+            Self::Api { status, errors } => {
+                write!(f, "api error {}:", status)?;
+                for error in errors {
+                    write!(f, " [{}] {}", error.code, error.message)?;
+                }
+                Ok(())
+            }
+            Self::Decode { status, err } => {
+                write!(f, "could not decode response {}: {}", status, err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CloudflareError {}
+
+/// Retries of requests answered with 429 or 5xx, see
+/// `CloudflareError::is_retryable`. Delay doubles with every attempt, a
+/// random jitter of up to the same amount is added.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let jitter_ms = rand::thread_rng()
+            .gen_range(0..=u64::try_from(delay.as_millis()).unwrap_or(0));
+
+        (delay + Duration::from_millis(jitter_ms)).min(self.max_delay)
+    }
+}
 
 #[derive(Clone)]
 pub struct CloudflareService {
     api_url: String,
     zone_id: String,
+    /// Origin of the custom hostnames created. Equal to the fallback origin
+    /// of the zone, but set explicitly so the custom hostnames of this
+    /// service can be told apart from others in the zone, see `Reconciler`.
+    fallback_domain: String,
     client: Client,
+    retry_policy: RetryPolicy,
 }
 
 impl CloudflareService {
-    pub fn init(api_url: String, zone_id: String, token: String) -> Self {
+    /// Page sizes of the list endpoints, custom hostnames allow at most 50.
+    const DNS_RECORDS_PER_PAGE: u32 = 100;
+    const CUSTOM_HOSTNAMES_PER_PAGE: u32 = 50;
+    /// Maximum number of hosts or files per purge request.
+    const PURGE_BATCH_SIZE: usize = 30;
+
+    pub fn init(
+        api_url: String,
+        zone_id: String,
+        token: String,
+        fallback_domain: String,
+    ) -> Self {
         let mut default_headers = HeaderMap::with_capacity(1);
         default_headers.insert(
             AUTHORIZATION,
@@ -106,7 +256,132 @@ impl CloudflareService {
         Self {
             api_url,
             zone_id,
+            fallback_domain,
             client,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    fn zone_url(&self, path: &str) -> String {
+        format!("{}/zones/{}/{}", self.api_url, self.zone_id, path)
+    }
+
+    async fn send_once<B: DeserializeOwned>(
+        request: RequestBuilder,
+    ) -> Result<CloudflareResponse<B>, (CloudflareError, Option<Duration>)>
+    {
+        let response =
+            request.send().await.map_err(|err| (err.into(), None))?;
+
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs);
+
+        let body = response.bytes().await.map_err(|err| (err.into(), None))?;
+
+        let parsed: CloudflareResponse<B> = serde_json::from_slice(&body)
+            .map_err(|err| (CloudflareError::Decode { status, err }, None))?;
+
+        if !status.is_success() || !parsed.success {
+            return Err((
+                CloudflareError::Api {
+                    status,
+                    errors: parsed.errors,
+                },
+                retry_after,
+            ));
+        }
+
+        Ok(parsed)
+    }
+
+    /// Sends the request, retrying it per `RetryPolicy`. `idempotent` tells
+    /// whether sending it twice has the same effect as sending it once.
+    async fn send<B: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        idempotent: bool,
+    ) -> Result<CloudflareResponse<B>, CloudflareError> {
+        let mut attempt = 0;
+
+        loop {
+            // requests without streaming body can always be cloned
+            let current = request.try_clone().unwrap();
+
+            match Self::send_once(current).await {
+                Ok(response) => return Ok(response),
+                Err((err, retry_after))
+                    if err.is_retryable(idempotent)
+                        && attempt < self.retry_policy.max_retries =>
+                {
+                    let delay = self.retry_policy.delay(attempt, retry_after);
+                    tracing::log::warn!(
+                        "[CloudflareService.send]: retrying in {:?}: {}",
+                        delay,
+                        err
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err((err, _)) => return Err(err),
+            }
+        }
+    }
+
+    async fn send_result<B: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        idempotent: bool,
+    ) -> Result<B, CloudflareError> {
+        let response = self.send::<B>(request, idempotent).await?;
+        response.result.ok_or_else(|| CloudflareError::Api {
+            status: StatusCode::OK,
+            errors: response.errors,
+        })
+    }
+
+    /// Follows `result_info` until all pages are fetched.
+    async fn send_paginated<B: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        per_page: u32,
+    ) -> Result<Vec<B>, CloudflareError> {
+        let mut results = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let response = self
+                .send::<Vec<B>>(
+                    request
+                        .try_clone()
+                        .unwrap()
+                        .query(&[("page", page), ("per_page", per_page)]),
+                    true,
+                )
+                .await?;
+
+            let page_results = response.result.unwrap_or_default();
+            let is_empty = page_results.is_empty();
+            results.extend(page_results);
+
+            match response.result_info {
+                Some(CloudflareResultInfo {
+                    page: current,
+                    total_pages: Some(total_pages),
+                }) if current < total_pages && !is_empty => {
+                    page = current + 1;
+                }
+                _ => return Ok(results),
+            }
         }
     }
 
@@ -114,7 +389,7 @@ impl CloudflareService {
         &self,
         name: String,
         content: String,
-    ) -> Result<CloudflareResponse<DnsRecordResponse>, Status> {
+    ) -> Result<DnsRecordResponse, CloudflareError> {
         let body = CreateDnsRecordRequest {
             name,
             content,
@@ -123,83 +398,36 @@ impl CloudflareService {
             ttl: 1,
         };
 
-        self.client
-            .post(format!(
-                "{}/zones/{}/dns_records",
-                self.api_url, self.zone_id
-            ))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.create_dns_record]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })?
-            .json()
-            .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.create_dns_record]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })
+        self.send_result(
+            self.client.post(self.zone_url("dns_records")).json(&body),
+            false,
+        )
+        .await
     }
 
     pub async fn list_dns_records(
         &self,
         name: Option<String>,
-    ) -> Result<CloudflareResponses<DnsRecordResponse>, Status> {
-        let mut req = self.client.get(format!(
-            "{}/zones/{}/dns_records",
-            self.api_url, self.zone_id
-        ));
+    ) -> Result<Vec<DnsRecordResponse>, CloudflareError> {
+        let mut req = self.client.get(self.zone_url("dns_records"));
 
         if let Some(name) = name {
             req = req.query(&[("name", name)]);
         }
 
-        req.send()
-            .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.list_dns_records]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })?
-            .json()
-            .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.list_dns_records]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })
+        self.send_paginated(req, Self::DNS_RECORDS_PER_PAGE).await
     }
 
     pub async fn delete_dns_record(
         &self,
         record_id: String,
-    ) -> Result<(), Status> {
-        self.client
-            .delete(format!(
-                "{}/zones/{}/dns_records/{}",
-                self.api_url, self.zone_id, record_id
-            ))
-            .send()
-            .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.delete_dns_record]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })?;
+    ) -> Result<(), CloudflareError> {
+        self.send::<DeletedResponse>(
+            self.client
+                .delete(self.zone_url(&format!("dns_records/{}", record_id))),
+            true,
+        )
+        .await?;
 
         Ok(())
     }
@@ -207,9 +435,10 @@ impl CloudflareService {
     pub async fn create_custom_hostname(
         &self,
         hostname: String,
-    ) -> Result<CloudflareResponse<CustomHostnameResponse>, Status> {
+    ) -> Result<CustomHostnameResponse, CloudflareError> {
         let body = CreateCustomHostnameRequest {
             hostname,
+            custom_origin_server: self.fallback_domain.clone(),
             ssl: CreateCustomHostnameSslRequest {
                 method: "http",
                 _type: "dv",
@@ -217,114 +446,634 @@ impl CloudflareService {
             },
         };
 
-        self.client
-            .post(format!(
-                "{}/zones/{}/custom_hostnames",
-                self.api_url, self.zone_id
-            ))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.create_custom_hostname]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })?
-            .json()
-            .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.create_custom_hostname]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })
+        self.send_result(
+            self.client
+                .post(self.zone_url("custom_hostnames"))
+                .json(&body),
+            false,
+        )
+        .await
     }
 
     pub async fn list_custom_hostnames(
         &self,
-        hostname: &String,
-    ) -> Result<CloudflareResponses<CustomHostnameResponse>, Status> {
-        self.client
-            .get(format!(
-                "{}/zones/{}/custom_hostnames",
-                self.api_url, self.zone_id
-            ))
-            .query(&[("hostname", hostname)])
-            .send()
-            .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.list_custom_hostnames]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })?
-            .json()
+        hostname: Option<&String>,
+    ) -> Result<Vec<CustomHostnameResponse>, CloudflareError> {
+        let mut req = self.client.get(self.zone_url("custom_hostnames"));
+
+        if let Some(hostname) = hostname {
+            req = req.query(&[("hostname", hostname)]);
+        }
+
+        self.send_paginated(req, Self::CUSTOM_HOSTNAMES_PER_PAGE)
             .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.list_custom_hostnames]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })
     }
 
     pub async fn delete_custom_hostname(
         &self,
         custom_hostname_id: String,
+    ) -> Result<(), CloudflareError> {
+        self.send::<DeletedResponse>(
+            self.client.delete(
+                self.zone_url(&format!(
+                    "custom_hostnames/{}",
+                    custom_hostname_id
+                )),
+            ),
+            true,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Purging twice drops the same cache, so purges are retried like reads.
+    async fn purge_cache(
+        &self,
+        body: PurgeCacheRequest,
+    ) -> Result<(), CloudflareError> {
+        self.send::<PurgeCacheResponse>(
+            self.client.post(self.zone_url("purge_cache")).json(&body),
+            true,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Drops everything cached for the hostnames.
+    pub async fn purge_cache_by_hostnames(
+        &self,
+        hostnames: &[String],
+    ) -> Result<(), CloudflareError> {
+        for hosts in hostnames.chunks(Self::PURGE_BATCH_SIZE) {
+            self.purge_cache(PurgeCacheRequest {
+                hosts: hosts.to_vec(),
+                ..Default::default()
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops the cached responses of single URLs, e.g. of a page.
+    pub async fn purge_cache_by_urls(
+        &self,
+        urls: &[String],
+    ) -> Result<(), CloudflareError> {
+        for files in urls.chunks(Self::PURGE_BATCH_SIZE) {
+            self.purge_cache(PurgeCacheRequest {
+                files: files.to_vec(),
+                ..Default::default()
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EdgeProvider for CloudflareService {
+    async fn create_dns_record(
+        &self,
+        name: &str,
+        target: &str,
     ) -> Result<(), Status> {
-        let url = format!(
-            "{}/zones/{}/custom_hostnames/{}",
-            self.api_url, self.zone_id, custom_hostname_id
-        );
+        CloudflareService::create_dns_record(
+            self,
+            name.to_string(),
+            target.to_string(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_dns_records(&self, name: &str) -> Result<(), Status> {
+        for record in self.list_dns_records(Some(name.to_string())).await? {
+            self.delete_dns_record(record.id).await?;
+        }
+        Ok(())
+    }
+
+    async fn add_custom_hostname(&self, hostname: &str) -> Result<(), Status> {
+        self.create_custom_hostname(hostname.to_string()).await?;
+        Ok(())
+    }
+
+    async fn remove_custom_hostname(
+        &self,
+        hostname: &str,
+    ) -> Result<(), Status> {
+        for custom_hostname in self
+            .list_custom_hostnames(Some(&hostname.to_string()))
+            .await?
+        {
+            self.delete_custom_hostname(custom_hostname.id).await?;
+        }
+        Ok(())
+    }
+
+    async fn purge_hostnames(
+        &self,
+        hostnames: &[String],
+    ) -> Result<(), Status> {
+        self.purge_cache_by_hostnames(hostnames).await?;
+        Ok(())
+    }
 
-        self.client
-            .delete(url)
-            .send()
+    async fn purge_urls(&self, urls: &[String]) -> Result<(), Status> {
+        self.purge_cache_by_urls(urls).await?;
+        Ok(())
+    }
+
+    async fn list_dns_records(
+        &self,
+        target: &str,
+    ) -> Result<Option<Vec<EdgeHostname>>, Status> {
+        let records = CloudflareService::list_dns_records(self, None).await?;
+
+        Ok(Some(
+            records
+                .into_iter()
+                .filter(|r| {
+                    r._type.as_deref() == Some("CNAME") && r.content == target
+                })
+                .map(|r| EdgeHostname {
+                    name: r.name,
+                    created_at: parse_datetime(r.created_on),
+                })
+                .collect(),
+        ))
+    }
+
+    /// Custom hostnames with another origin were not added by this service.
+    async fn list_custom_hostnames(
+        &self,
+    ) -> Result<Option<Vec<EdgeHostname>>, Status> {
+        let custom_hostnames =
+            CloudflareService::list_custom_hostnames(self, None).await?;
+
+        Ok(Some(
+            custom_hostnames
+                .into_iter()
+                .filter(|h| {
+                    h.custom_origin_server.as_ref()
+                        == Some(&self.fallback_domain)
+                })
+                .map(|h| EdgeHostname {
+                    name: h.hostname,
+                    created_at: parse_datetime(h.created_at),
+                })
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+    use wiremock::matchers::{body_partial_json, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::edge::{EdgeHostname, EdgeProvider};
+
+    use super::{CloudflareError, CloudflareService, RetryPolicy};
+
+    const ZONE_ID: &str = "zone";
+    const FALLBACK_DOMAIN: &str = "fallback.sited.io";
+
+    async fn service(server: &MockServer) -> CloudflareService {
+        CloudflareService::init(
+            server.uri(),
+            ZONE_ID.to_string(),
+            "Bearer token".to_string(),
+            FALLBACK_DOMAIN.to_string(),
+        )
+        .with_retry_policy(RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        })
+    }
+
+    fn zone_path(path: &str) -> String {
+        format!("/zones/{}/{}", ZONE_ID, path)
+    }
+
+    fn hostname(id: &str) -> serde_json::Value {
+        json!({ "id": id, "hostname": format!("{id}.example.com") })
+    }
+
+    fn record(
+        id: &str,
+        record_type: &str,
+        name: &str,
+        content: &str,
+    ) -> serde_json::Value {
+        json!({
+            "id": id,
+            "type": record_type,
+            "name": name,
+            "content": content,
+            "proxied": true,
+        })
+    }
+
+    fn page(
+        result: Vec<serde_json::Value>,
+        page: u32,
+        total_pages: u32,
+    ) -> serde_json::Value {
+        json!({
+            "success": true,
+            "errors": [],
+            "result": result,
+            "result_info": { "page": page, "total_pages": total_pages },
+        })
+    }
+
+    #[tokio::test]
+    async fn create_custom_hostname_returns_result() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(zone_path("custom_hostnames")))
+            .and(body_partial_json(json!({
+                "hostname": "a.example.com",
+                "custom_origin_server": FALLBACK_DOMAIN,
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "errors": [],
+                "result": hostname("a"),
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = service(&server)
             .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.delete_custom_hostname]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })?
-            .text()
+            .create_custom_hostname("a.example.com".to_string())
             .await
             .unwrap();
 
-        Ok(())
+        assert_eq!(result.id, "a");
+        assert_eq!(result.hostname, "a.example.com");
     }
 
-    pub async fn dns_lookup(
-        &self,
-        domain: &String,
-    ) -> Result<DnsLookupResponse, Status> {
-        self.client
-            .get(CLOUDFLARE_DNS_URL)
-            .query(&[("name", domain)])
-            .header("accept", "application/dns-json")
-            .send()
+    #[tokio::test]
+    async fn api_errors_are_returned_with_codes() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(zone_path("custom_hostnames")))
+            .respond_with(ResponseTemplate::new(409).set_body_json(json!({
+                "success": false,
+                "errors": [{ "code": 1406, "message": "Duplicate hostname" }],
+                "result": null,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let err = service(&server)
             .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.dns_lookup]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })?
-            .json()
+            .create_custom_hostname("a.example.com".to_string())
             .await
-            .map_err(|err| {
-                tracing::log::error!(
-                    "[CloudflareService.dns_lookup]: {:?}",
-                    err
-                );
-                Status::internal("")
-            })
+            .unwrap_err();
+
+        assert!(matches!(err, CloudflareError::Api { .. }));
+        assert_eq!(err.codes(), vec![1406]);
+    }
+
+    #[tokio::test]
+    async fn unsuccessful_response_with_ok_status_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(zone_path("dns_records")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": false,
+                "errors": [{ "code": 81057, "message": "Record exists" }],
+                "result": null,
+            })))
+            .mount(&server)
+            .await;
+
+        let err = service(&server)
+            .await
+            .create_dns_record("a".to_string(), "fallback".to_string())
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.codes(), vec![81057]);
+    }
+
+    #[tokio::test]
+    async fn delete_fails_on_not_found() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path(zone_path("dns_records/missing")))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "success": false,
+                "errors": [{ "code": 81044, "message": "Record not found" }],
+                "result": null,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let err = service(&server)
+            .await
+            .delete_dns_record("missing".to_string())
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.codes(), vec![81044]);
+    }
+
+    #[tokio::test]
+    async fn delete_with_invalid_body_does_not_panic() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path(zone_path("custom_hostnames/a")))
+            .respond_with(ResponseTemplate::new(502).set_body_string("<html>"))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let err = service(&server)
+            .await
+            .delete_custom_hostname("a".to_string())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, CloudflareError::Decode { .. }));
+    }
+
+    #[tokio::test]
+    async fn list_follows_pagination() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(zone_path("custom_hostnames")))
+            .and(query_param("page", "1"))
+            .and(query_param("per_page", "50"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(
+                vec![hostname("a"), hostname("b")],
+                1,
+                2,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(zone_path("custom_hostnames")))
+            .and(query_param("page", "2"))
+            .and(query_param("per_page", "50"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(
+                vec![hostname("c")],
+                2,
+                2,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = service(&server)
+            .await
+            .list_custom_hostnames(None)
+            .await
+            .unwrap();
+
+        let ids: Vec<_> = result.into_iter().map(|h| h.id).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn edge_lists_only_managed_names() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(zone_path("dns_records")))
+            .and(query_param("per_page", "100"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(
+                vec![
+                    record("r1", "CNAME", "w1.sited.io", FALLBACK_DOMAIN),
+                    record("r2", "CNAME", "w2.sited.io", "other.example.com"),
+                    record("r3", "A", "w3.sited.io", FALLBACK_DOMAIN),
+                ],
+                1,
+                1,
+            )))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(zone_path("custom_hostnames")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(
+                vec![
+                    json!({
+                        "id": "h1",
+                        "hostname": "shop.example.com",
+                        "custom_origin_server": FALLBACK_DOMAIN,
+                        "created_at": "2024-06-01T12:00:00Z",
+                    }),
+                    json!({
+                        "id": "h2",
+                        "hostname": "foreign.example.com",
+                        "custom_origin_server": "origin.example.com",
+                    }),
+                    hostname("unmarked"),
+                ],
+                1,
+                1,
+            )))
+            .mount(&server)
+            .await;
+        let edge: &dyn EdgeProvider = &service(&server).await;
+
+        let records = edge.list_dns_records(FALLBACK_DOMAIN).await.unwrap();
+        let hostnames = edge.list_custom_hostnames().await.unwrap();
+
+        let names = |hostnames: Option<Vec<EdgeHostname>>| {
+            hostnames
+                .unwrap()
+                .into_iter()
+                .map(|h| h.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(records), vec!["w1.sited.io"]);
+        assert_eq!(names(hostnames.clone()), vec!["shop.example.com"]);
+        assert!(hostnames.unwrap()[0].created_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn rate_limited_request_is_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(zone_path("custom_hostnames")))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header("retry-after", "0")
+                    .set_body_json(json!({
+                        "success": false,
+                        "errors": [{ "code": 971, "message": "Throttled" }],
+                        "result": null,
+                    })),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(zone_path("custom_hostnames")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(
+                vec![hostname("a")],
+                1,
+                1,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = service(&server)
+            .await
+            .list_custom_hostnames(None)
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_until_exhausted() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(zone_path("dns_records")))
+            .respond_with(ResponseTemplate::new(503).set_body_json(json!({
+                "success": false,
+                "errors": [{ "code": 10000, "message": "Unavailable" }],
+                "result": null,
+            })))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let err = service(&server)
+            .await
+            .list_dns_records(None)
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.codes(), vec![10000]);
+    }
+
+    #[tokio::test]
+    async fn server_errors_of_creates_are_not_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(zone_path("dns_records")))
+            .respond_with(ResponseTemplate::new(502).set_body_json(json!({
+                "success": false,
+                "errors": [{ "code": 10000, "message": "Bad gateway" }],
+                "result": null,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let err = service(&server)
+            .await
+            .create_dns_record(
+                "a.sited.io".to_string(),
+                FALLBACK_DOMAIN.to_string(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.codes(), vec![10000]);
+    }
+
+    #[tokio::test]
+    async fn server_errors_of_purges_are_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(zone_path("purge_cache")))
+            .respond_with(ResponseTemplate::new(503).set_body_json(json!({
+                "success": false,
+                "errors": [{ "code": 10000, "message": "Unavailable" }],
+                "result": null,
+            })))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(zone_path("purge_cache")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "errors": [],
+                "result": { "id": "purge" },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        service(&server)
+            .await
+            .purge_cache_by_hostnames(&["a.example.com".to_string()])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(zone_path("dns_records")))
+            .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+                "success": false,
+                "errors": [{ "code": 10000, "message": "Authentication error" }],
+                "result": null,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert!(service(&server).await.list_dns_records(None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn purge_is_split_into_batches() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(zone_path("purge_cache")))
+            .and(body_partial_json(json!({ "hosts": ["a30.example.com"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "errors": [],
+                "result": { "id": "purge" },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(zone_path("purge_cache")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "errors": [],
+                "result": { "id": "purge" },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let hostnames: Vec<_> =
+            (0..31).map(|i| format!("a{i}.example.com")).collect();
+
+        service(&server)
+            .await
+            .purge_cache_by_hostnames(&hostnames)
+            .await
+            .unwrap();
     }
 }
@@ -1,7 +1,6 @@
 use std::ops::DerefMut;
 
 use deadpool_postgres::tokio_postgres::error::SqlState;
-use deadpool_postgres::tokio_postgres::types::{FromSql, Type, WrongType};
 use deadpool_postgres::tokio_postgres::Row;
 use deadpool_postgres::{
     tokio_postgres::NoTls, Config, CreatePoolError, Pool, PoolError, Runtime,
@@ -10,7 +9,7 @@ use deadpool_postgres::{
 use openssl::ssl::{SslConnector, SslMethod};
 use postgres_openssl::MakeTlsConnector;
 use refinery::Target;
-use sea_query::{Expr, Iden, PgFunc, SimpleExpr};
+use sea_query::{Expr, Func, Iden, PgFunc, SimpleExpr};
 use tonic::Status;
 
 mod embedded {
@@ -25,9 +24,26 @@ pub enum DbError {
     CreatePool(CreatePoolError),
     SeaQuery(sea_query::error::Error),
     Argument(&'static str),
+    /// Raised by stores without Postgres, like `UNIQUE_VIOLATION`.
+    UniqueViolation(&'static str),
+    /// Raised by stores without Postgres, like `FOREIGN_KEY_VIOLATION`.
+    ForeignKeyViolation(&'static str),
+    /// Raised by stores without Postgres if a row to update or return was
+    /// not found, like `query_one`.
+    RowCount,
 }
 
 impl DbError {
+    pub fn is_unique_violation(&self) -> bool {
+        match self {
+            Self::TokioPostgres(err) => {
+                err.code() == Some(&SqlState::UNIQUE_VIOLATION)
+            }
+            Self::UniqueViolation(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn ignore_to_ts_query<T>(self, default: T) -> Result<T, Self> {
         if let Self::TokioPostgres(err) = &self {
             if let Some(err) = err.as_db_error() {
@@ -107,6 +123,24 @@ impl From<DbError> for Status {
                 Status::internal("")
             }
             DbError::Argument(field) => Status::invalid_argument(field),
+            DbError::UniqueViolation(constraint) => {
+                Status::already_exists(format!(
+                    "duplicate key value violates unique constraint \"{}\"",
+                    constraint
+                ))
+            }
+            DbError::ForeignKeyViolation(constraint) => {
+                Status::failed_precondition(format!(
+                    "violates foreign key constraint \"{}\"",
+                    constraint
+                ))
+            }
+            DbError::RowCount => {
+                tracing::log::error!(
+                    "query returned an unexpected number of rows"
+                );
+                Status::internal("")
+            }
         }
     }
 }
@@ -119,6 +153,17 @@ impl std::fmt::Display for DbError {
 
 impl std::error::Error for DbError {}
 
+/// Pools of the primary and of the optional read replica. Public reads that
+/// may lag behind the primary are served from the replica, see
+/// `Store::replica`.
+#[derive(Clone)]
+pub struct DbPools {
+    pub primary: Pool,
+    pub replica: Option<Pool>,
+}
+
+/// The replica is connected to with the same port, credentials and root
+/// certificate as the primary.
 pub fn init_db_pool(
     host: String,
     port: u16,
@@ -126,7 +171,8 @@ pub fn init_db_pool(
     password: String,
     dbname: String,
     root_cert: Option<String>,
-) -> Result<Pool, CreatePoolError> {
+    replica_host: Option<String>,
+) -> Result<DbPools, CreatePoolError> {
     let mut config = Config::new();
     config.host = Some(host);
     config.port = Some(port);
@@ -134,6 +180,25 @@ pub fn init_db_pool(
     config.password = Some(password);
     config.dbname = Some(dbname);
 
+    let replica = match replica_host {
+        Some(replica_host) => {
+            let mut replica_config = config.clone();
+            replica_config.host = Some(replica_host);
+            Some(create_pool(replica_config, &root_cert)?)
+        }
+        None => None,
+    };
+
+    Ok(DbPools {
+        primary: create_pool(config, &root_cert)?,
+        replica,
+    })
+}
+
+fn create_pool(
+    mut config: Config,
+    root_cert: &Option<String>,
+) -> Result<Pool, CreatePoolError> {
     if let Some(root_cert) = root_cert {
         println!("Using root cert {}", root_cert);
         config.ssl_mode = Some(SslMode::Require);
@@ -167,31 +232,41 @@ pub fn build_simple_plain_ts_query(query: &String) -> Expr {
     )
 }
 
-pub struct ArrayAgg;
+pub struct JsonbAgg;
 
-impl Iden for ArrayAgg {
+impl Iden for JsonbAgg {
     fn unquoted(&self, s: &mut dyn std::fmt::Write) {
-        write!(s, "ARRAY_AGG").unwrap()
+        write!(s, "JSONB_AGG").unwrap()
     }
 }
 
-pub fn get_type_from_oid<'a, T>(
-    oid: i32,
-) -> Result<Type, Box<dyn std::error::Error + Sync + Send>>
+pub struct JsonbBuildObject;
+
+impl Iden for JsonbBuildObject {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(s, "JSONB_BUILD_OBJECT").unwrap()
+    }
+}
+
+/// Builds a JSON object of the given columns keyed by their names, so it can
+/// be deserialized into a struct with fields of the same names.
+pub fn json_object<T, C>(
+    table: T,
+    columns: impl IntoIterator<Item = C>,
+) -> SimpleExpr
 where
-    T: FromSql<'a>,
+    T: Iden + Copy + 'static,
+    C: Iden + 'static,
 {
-    match Type::from_oid(oid as u32) {
-        None => Err(format!(
-            "cannot decode OID {} inside of anonymous record",
-            oid,
-        )
-        .into()),
-        Some(ty) if !T::accepts(&ty) => {
-            Err(Box::new(WrongType::new::<T>(ty.clone())))
-        }
-        Some(ty) => Ok(ty),
-    }
+    Func::cust(JsonbBuildObject)
+        .args(columns.into_iter().flat_map(|column| {
+            [
+                // inlined, since the type of a bound key can not be inferred
+                SimpleExpr::Constant(column.to_string().into()),
+                Expr::col((table, column)).into(),
+            ]
+        }))
+        .into()
 }
 
 pub fn get_count_from_rows(rows: &[Row]) -> i64 {
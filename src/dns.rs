@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use hickory_resolver::config::{
+    NameServerConfigGroup, ResolverConfig, ResolverOpts,
+};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::proto::rr::RecordType;
+use hickory_resolver::TokioAsyncResolver;
+use reqwest::Client;
+use serde::Deserialize;
+use tonic::{async_trait, Status};
+
+pub const RECORD_TYPE_A: u16 = 1;
+pub const RECORD_TYPE_CNAME: u16 = 5;
+pub const RECORD_TYPE_AAAA: u16 = 28;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsAnswer {
+    pub name: String,
+    pub record_type: u16,
+    pub data: String,
+}
+
+impl DnsAnswer {
+    pub fn new(name: &str, record_type: u16, data: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            record_type,
+            data: data.to_string(),
+        }
+    }
+}
+
+/// Resolves the A records of a name, including the CNAME chain that led to
+/// them. Names and CNAME targets may be returned with a trailing dot.
+#[async_trait]
+pub trait DnsResolver: Send + Sync {
+    async fn lookup(&self, name: &str) -> Result<Vec<DnsAnswer>, Status>;
+}
+
+pub type DynDnsResolver = Arc<dyn DnsResolver>;
+
+/// Builds resolver from `DNS_RESOLVER` ("doh" or "native") and its options.
+pub fn init_dns_resolver(
+    kind: Option<String>,
+    doh_url: Option<String>,
+    nameserver: Option<String>,
+) -> Result<DynDnsResolver, Box<dyn std::error::Error>> {
+    match kind.as_deref() {
+        None | Some("doh") => Ok(Arc::new(DohResolver::new(
+            doh_url.unwrap_or_else(|| DohResolver::DEFAULT_URL.to_string()),
+        ))),
+        Some("native") => {
+            let nameserver = nameserver.map(|n| n.parse()).transpose()?;
+            Ok(Arc::new(NativeResolver::new(nameserver)?))
+        }
+        Some(other) => Err(format!("Unknown DNS_RESOLVER '{other}'").into()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Answer")]
+    answer: Option<Vec<DohResponseAnswer>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DohResponseAnswer {
+    name: String,
+    #[serde(rename = "type")]
+    _type: u16,
+    data: String,
+}
+
+/// DNS over HTTPS using the JSON API, e.g. of Cloudflare or Google.
+#[derive(Debug, Clone)]
+pub struct DohResolver {
+    url: String,
+    client: Client,
+}
+
+impl DohResolver {
+    pub const DEFAULT_URL: &'static str =
+        "https://cloudflare-dns.com/dns-query";
+
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl DnsResolver for DohResolver {
+    async fn lookup(&self, name: &str) -> Result<Vec<DnsAnswer>, Status> {
+        let response: DohResponse = self
+            .client
+            .get(&self.url)
+            .query(&[("name", name)])
+            .header("accept", "application/dns-json")
+            .send()
+            .await
+            .map_err(|err| {
+                tracing::log::error!("[DohResolver.lookup]: {:?}", err);
+                Status::internal("")
+            })?
+            .json()
+            .await
+            .map_err(|err| {
+                tracing::log::error!("[DohResolver.lookup]: {:?}", err);
+                Status::internal("")
+            })?;
+
+        Ok(response
+            .answer
+            .unwrap_or_default()
+            .into_iter()
+            .map(|a| DnsAnswer {
+                name: a.name,
+                record_type: a._type,
+                data: a.data,
+            })
+            .collect())
+    }
+}
+
+/// Plain DNS over UDP with TCP fallback. Uses the system configuration if no
+/// nameserver is given.
+#[derive(Clone)]
+pub struct NativeResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl NativeResolver {
+    pub fn new(
+        nameserver: Option<SocketAddr>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let resolver = match nameserver {
+            Some(addr) => TokioAsyncResolver::tokio(
+                ResolverConfig::from_parts(
+                    None,
+                    vec![],
+                    NameServerConfigGroup::from_ips_clear(
+                        &[addr.ip()],
+                        addr.port(),
+                        true,
+                    ),
+                ),
+                ResolverOpts::default(),
+            ),
+            None => TokioAsyncResolver::tokio_from_system_conf()?,
+        };
+
+        Ok(Self { resolver })
+    }
+}
+
+#[async_trait]
+impl DnsResolver for NativeResolver {
+    async fn lookup(&self, name: &str) -> Result<Vec<DnsAnswer>, Status> {
+        let lookup = match self
+            .resolver
+            .lookup(format!("{name}."), RecordType::A)
+            .await
+        {
+            Ok(lookup) => lookup,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ResolveErrorKind::NoRecordsFound { .. }
+                ) =>
+            {
+                return Ok(Vec::new());
+            }
+            Err(err) => {
+                tracing::log::error!("[NativeResolver.lookup]: {:?}", err);
+                return Err(Status::internal(""));
+            }
+        };
+
+        Ok(lookup
+            .records()
+            .iter()
+            .filter_map(|record| {
+                record.data().map(|data| DnsAnswer {
+                    name: record.name().to_utf8(),
+                    record_type: record.record_type().into(),
+                    data: data.to_string(),
+                })
+            })
+            .collect())
+    }
+}
+
+/// Resolver answering from a fixed table. Used for tests and offline
+/// development, unknown names resolve to no answers.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryResolver {
+    answers: Arc<RwLock<HashMap<String, Vec<DnsAnswer>>>>,
+}
+
+impl InMemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, name: &str, answers: Vec<DnsAnswer>) {
+        self.answers
+            .write()
+            .unwrap()
+            .insert(name.to_string(), answers);
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.answers.write().unwrap().remove(name);
+    }
+}
+
+#[async_trait]
+impl DnsResolver for InMemoryResolver {
+    async fn lookup(&self, name: &str) -> Result<Vec<DnsAnswer>, Status> {
+        Ok(self
+            .answers
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tonic::Status;
+
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
+use crate::api::sited_io::websites::v1::{DomainStatus, DomainStatusEvent};
+use crate::dns::DynDnsResolver;
+use crate::edge::DynEdgeProvider;
+use crate::identity::DynIdentityProvider;
+use crate::model::Domain;
+use crate::publisher::{EventAction, Publisher, SYSTEM_ACTOR};
+use crate::repository::{DynStore, Store};
+use crate::website_cache::WebsiteCache;
+use crate::{DomainService, WebsiteService};
+
+/// Periodically re-runs the DNS checks of `CheckDomainStatus` on active
+/// domains.
+///
+/// Domains that do not point to the fallback domain anymore are moved to
+/// `DOMAIN_STATUS_DEGRADED`. If they are still broken after the grace period,
+/// their custom hostname is removed from the edge and they go back to
+/// `DOMAIN_STATUS_PENDING`, so the owner can verify them again.
+pub struct DomainCheckJob {
+    store: DynStore,
+    identity_provider: DynIdentityProvider,
+    edge_provider: DynEdgeProvider,
+    dns_resolver: DynDnsResolver,
+    publisher: Publisher,
+    website_cache: WebsiteCache,
+    fallback_domain: String,
+    interval: Duration,
+    grace_period: Duration,
+}
+
+impl DomainCheckJob {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+    pub const DEFAULT_GRACE_PERIOD: Duration =
+        Duration::from_secs(72 * 60 * 60);
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        store: DynStore,
+        identity_provider: DynIdentityProvider,
+        edge_provider: DynEdgeProvider,
+        dns_resolver: DynDnsResolver,
+        publisher: Publisher,
+        website_cache: WebsiteCache,
+        fallback_domain: String,
+        interval: Duration,
+        grace_period: Duration,
+    ) -> Self {
+        Self {
+            store,
+            identity_provider,
+            edge_provider,
+            dns_resolver,
+            publisher,
+            website_cache,
+            fallback_domain,
+            interval,
+            grace_period,
+        }
+    }
+
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            loop {
+                interval.tick().await;
+                self.run_once().await;
+            }
+        })
+    }
+
+    pub async fn run_once(&self) {
+        for status in [DomainStatus::Active, DomainStatus::Degraded] {
+            let domains =
+                match self.store.domains().list_by_status(status).await {
+                    Ok(domains) => domains,
+                    Err(err) => {
+                        tracing::log::error!(
+                            "[DomainCheckJob.run_once]: {}",
+                            err
+                        );
+                        continue;
+                    }
+                };
+
+            for domain in domains {
+                let domain_id = domain.domain_id;
+                if let Err(err) = self.check_domain(domain).await {
+                    tracing::log::error!(
+                        "[DomainCheckJob.run_once] domain_id {}: {}",
+                        domain_id,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    async fn check_domain(&self, domain: Domain) -> Result<(), Status> {
+        let points_to_fallback = DomainService::points_to_fallback(
+            self.dns_resolver.as_ref(),
+            &self.fallback_domain,
+            &domain.domain,
+        )
+        .await?;
+
+        self.store
+            .domains()
+            .update_last_check(
+                domain.domain_id,
+                points_to_fallback,
+                &DomainService::build_check_message(
+                    &domain.domain,
+                    &self.fallback_domain,
+                    points_to_fallback,
+                ),
+            )
+            .await?;
+
+        let is_degraded = domain.status == DomainStatus::Degraded;
+
+        let (from_status, to_status, degraded_at) =
+            match (is_degraded, points_to_fallback) {
+                (false, false) => (
+                    DomainStatus::Active,
+                    DomainStatus::Degraded,
+                    Some(Utc::now()),
+                ),
+                (true, true) => {
+                    (DomainStatus::Degraded, DomainStatus::Active, None)
+                }
+                (true, false) if self.is_grace_period_over(&domain) => {
+                    self.edge_provider
+                        .remove_custom_hostname(&domain.domain)
+                        .await?;
+                    (DomainStatus::Degraded, DomainStatus::Pending, None)
+                }
+                _ => return Ok(()),
+            };
+
+        if Self::update_status(
+            self.store.as_ref(),
+            &self.publisher,
+            &self.website_cache,
+            &domain,
+            from_status,
+            to_status,
+            degraded_at,
+            SYSTEM_ACTOR,
+        )
+        .await?
+        .is_none()
+        {
+            return Ok(());
+        }
+
+        if to_status == DomainStatus::Pending {
+            WebsiteService::sync_redirect_uris(
+                self.store.as_ref(),
+                self.identity_provider.as_ref(),
+                &domain.website_id,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the domain from `from_status` to `to_status` and publishes the
+    /// change. Returns `None` if its status changed since it was read.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn update_status(
+        store: &dyn Store,
+        publisher: &Publisher,
+        website_cache: &WebsiteCache,
+        domain: &Domain,
+        from_status: DomainStatus,
+        to_status: DomainStatus,
+        degraded_at: Option<DateTime<Utc>>,
+        actor: &str,
+    ) -> Result<Option<Domain>, Status> {
+        let transaction = store.begin().await?;
+
+        let Some(updated_domain) = transaction
+            .domains()
+            .update_check_status(
+                domain.domain_id,
+                from_status,
+                to_status,
+                degraded_at,
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let domain_response =
+            DomainService::to_response(updated_domain.clone());
+
+        publisher
+            .publish_domain_status(
+                transaction.outbox(),
+                &DomainStatusEvent {
+                    website_id: domain.website_id.clone(),
+                    user_id: domain.user_id.clone(),
+                    domain: Some(domain_response.clone()),
+                },
+            )
+            .await?;
+        publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Updated,
+                actor,
+                &domain.website_id,
+                Payload::Domain(domain_response),
+            )
+            .await?;
+
+        transaction.commit().await?;
+        website_cache.invalidate(&domain.website_id);
+
+        Ok(Some(updated_domain))
+    }
+
+    fn is_grace_period_over(&self, domain: &Domain) -> bool {
+        domain.degraded_at.is_some_and(|degraded_at| {
+            Utc::now()
+                .signed_duration_since(degraded_at)
+                .to_std()
+                .is_ok_and(|elapsed| elapsed >= self.grace_period)
+        })
+    }
+}
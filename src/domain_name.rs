@@ -0,0 +1,218 @@
+use std::sync::OnceLock;
+
+use publicsuffix::{List, Psl};
+use tonic::Status;
+
+/// Snapshot of https://publicsuffix.org/list/public_suffix_list.dat
+static PUBLIC_SUFFIX_LIST: &str =
+    include_str!("../data/public_suffix_list.dat");
+
+fn public_suffix_list() -> &'static List {
+    static LIST: OnceLock<List> = OnceLock::new();
+    LIST.get_or_init(|| PUBLIC_SUFFIX_LIST.parse().unwrap())
+}
+
+/// Why a domain name was rejected, see `DomainName::parse`. Converts to
+/// `Status::invalid_argument` with the message for users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainNameError {
+    Invalid,
+    TooLong,
+    MissingDot,
+    LabelLength,
+    IpAddress,
+    UnknownSuffix,
+    PublicSuffix,
+    Reserved,
+}
+
+impl std::fmt::Display for DomainNameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid => write!(f, "Domain is not valid"),
+            Self::TooLong => write!(
+                f,
+                "Domain must not be longer than {} characters",
+                DomainName::MAX_LENGTH
+            ),
+            Self::MissingDot => write!(f, "Domain must contain a dot ('.')"),
+            Self::LabelLength => write!(
+                f,
+                "Every part of the domain must have between 1 and {} \
+                 characters",
+                DomainName::MAX_LABEL_LENGTH
+            ),
+            Self::IpAddress => write!(f, "Domain must not be an IP address"),
+            Self::UnknownSuffix => {
+                write!(f, "Domain does not end on a known top-level domain")
+            }
+            Self::PublicSuffix => {
+                write!(f, "Domain must not be a public suffix")
+            }
+            Self::Reserved => write!(f, "Domain is reserved"),
+        }
+    }
+}
+
+impl std::error::Error for DomainNameError {}
+
+impl From<DomainNameError> for Status {
+    fn from(err: DomainNameError) -> Self {
+        Status::invalid_argument(err.to_string())
+    }
+}
+
+/// A validated domain name in its ASCII (punycode) form, which is used for
+/// DNS, TLS and lookups, and its Unicode form, which is shown to users.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainName {
+    pub ascii: String,
+    pub unicode: String,
+}
+
+impl DomainName {
+    const MAX_LENGTH: usize = 253;
+    const MAX_LABEL_LENGTH: usize = 63;
+
+    /// Normalizes `input` to lowercase ASCII and checks it is a registrable
+    /// domain or a subdomain of one. Domains equal to or below one of
+    /// `reserved_domains` are rejected.
+    pub fn parse(
+        input: &str,
+        reserved_domains: &[&str],
+    ) -> Result<Self, DomainNameError> {
+        let input = input.trim();
+        let input = input.strip_suffix('.').unwrap_or(input);
+
+        let ascii = idna::domain_to_ascii_strict(input)
+            .map_err(|_| DomainNameError::Invalid)?;
+
+        Self::validate_labels(&ascii)?;
+        Self::validate_suffix(&ascii)?;
+
+        if reserved_domains.iter().any(|reserved| {
+            ascii == *reserved || ascii.ends_with(&format!(".{}", reserved))
+        }) {
+            return Err(DomainNameError::Reserved);
+        }
+
+        let (unicode, _) = idna::domain_to_unicode(&ascii);
+
+        Ok(Self { ascii, unicode })
+    }
+
+    fn validate_labels(ascii: &str) -> Result<(), DomainNameError> {
+        if ascii.len() > Self::MAX_LENGTH {
+            return Err(DomainNameError::TooLong);
+        }
+
+        let labels: Vec<&str> = ascii.split('.').collect();
+
+        if labels.len() < 2 {
+            return Err(DomainNameError::MissingDot);
+        }
+
+        for label in &labels {
+            if label.is_empty() || label.len() > Self::MAX_LABEL_LENGTH {
+                return Err(DomainNameError::LabelLength);
+            }
+
+            if label.starts_with('-')
+                || label.ends_with('-')
+                || !label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            {
+                return Err(DomainNameError::Invalid);
+            }
+        }
+
+        if labels
+            .last()
+            .is_some_and(|tld| tld.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return Err(DomainNameError::IpAddress);
+        }
+
+        Ok(())
+    }
+
+    fn validate_suffix(ascii: &str) -> Result<(), DomainNameError> {
+        let list = public_suffix_list();
+
+        if !list
+            .suffix(ascii.as_bytes())
+            .is_some_and(|suffix| suffix.is_known())
+        {
+            return Err(DomainNameError::UnknownSuffix);
+        }
+
+        if list.domain(ascii.as_bytes()).is_none() {
+            return Err(DomainNameError::PublicSuffix);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DomainName, DomainNameError};
+
+    const RESERVED: &[&str] = &["sited.io", "fallback.sited.cloud"];
+
+    fn parse(input: &str) -> Result<DomainName, DomainNameError> {
+        DomainName::parse(input, RESERVED)
+    }
+
+    #[test]
+    fn normalizes_to_lowercase() {
+        let domain = parse("Shop.Example.COM.").unwrap();
+        assert_eq!(domain.ascii, "shop.example.com");
+        assert_eq!(domain.unicode, "shop.example.com");
+    }
+
+    #[test]
+    fn converts_unicode_to_punycode() {
+        let domain = parse("Bäckerei.de").unwrap();
+        assert_eq!(domain.ascii, "xn--bckerei-5wa.de");
+        assert_eq!(domain.unicode, "bäckerei.de");
+
+        let domain = parse("xn--bckerei-5wa.de").unwrap();
+        assert_eq!(domain.unicode, "bäckerei.de");
+    }
+
+    #[test]
+    fn rejects_invalid_labels() {
+        assert!(parse("example").is_err());
+        assert!(parse("exa mple.com").is_err());
+        assert!(parse("-example.com").is_err());
+        assert!(parse("example..com").is_err());
+        assert!(parse(&format!("{}.com", "a".repeat(64))).is_err());
+        assert!(parse("127.0.0.1").is_err());
+        assert!(parse("https://example.com").is_err());
+    }
+
+    #[test]
+    fn rejects_public_suffixes() {
+        assert_eq!(parse("co.uk"), Err(DomainNameError::PublicSuffix));
+        assert!(parse("com").is_err());
+        assert_eq!(parse("github.io"), Err(DomainNameError::PublicSuffix));
+        assert!(parse("example.co.uk").is_ok());
+        assert_eq!(
+            parse("example.notatld"),
+            Err(DomainNameError::UnknownSuffix)
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_domains() {
+        assert_eq!(parse("sited.io"), Err(DomainNameError::Reserved));
+        assert_eq!(parse("shop.sited.io"), Err(DomainNameError::Reserved));
+        assert_eq!(
+            parse("Fallback.Sited.Cloud"),
+            Err(DomainNameError::Reserved)
+        );
+        assert!(parse("notsited.io").is_ok());
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use http::{Request, Response, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Server};
+use tonic::{async_trait, Status};
+
+use crate::repository::DynStore;
+
+/// The edge in front of the websites, terminating TLS and routing hostnames
+/// to the fallback domain.
+///
+/// Internal DNS records are the `<website_id>.<MAIN_DOMAIN>` hostnames, custom
+/// hostnames are the verified domains of customers.
+#[async_trait]
+pub trait EdgeProvider: Send + Sync {
+    async fn create_dns_record(
+        &self,
+        name: &str,
+        target: &str,
+    ) -> Result<(), Status>;
+
+    async fn delete_dns_records(&self, name: &str) -> Result<(), Status>;
+
+    async fn add_custom_hostname(&self, hostname: &str) -> Result<(), Status>;
+
+    async fn remove_custom_hostname(
+        &self,
+        hostname: &str,
+    ) -> Result<(), Status>;
+
+    /// Drops all cached responses of the hostnames.
+    async fn purge_hostnames(&self, hostnames: &[String])
+        -> Result<(), Status>;
+
+    /// Drops the cached responses of the URLs.
+    async fn purge_urls(&self, urls: &[String]) -> Result<(), Status>;
+
+    /// Lists the DNS records pointing to `target`, or `None` if the DNS
+    /// records are not managed by this provider.
+    async fn list_dns_records(
+        &self,
+        target: &str,
+    ) -> Result<Option<Vec<EdgeHostname>>, Status>;
+
+    /// Lists the custom hostnames added by `add_custom_hostname`, or `None`
+    /// if they cannot be told apart from the DNS records.
+    async fn list_custom_hostnames(
+        &self,
+    ) -> Result<Option<Vec<EdgeHostname>>, Status>;
+}
+
+pub type DynEdgeProvider = Arc<dyn EdgeProvider>;
+
+/// A DNS record or custom hostname on the edge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdgeHostname {
+    pub name: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// DNS records by name, with their target and creation time.
+type DnsRecords = HashMap<String, (String, DateTime<Utc>)>;
+
+/// Edge provider recording DNS records and custom hostnames in memory. Used
+/// for tests, nothing is served.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryEdgeProvider {
+    dns_records: Arc<RwLock<DnsRecords>>,
+    custom_hostnames: Arc<RwLock<HashMap<String, DateTime<Utc>>>>,
+    purged: Arc<RwLock<Vec<String>>>,
+    unavailable: Arc<RwLock<HashSet<String>>>,
+}
+
+impl InMemoryEdgeProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the target of the DNS record.
+    pub fn get_dns_record(&self, name: &str) -> Option<String> {
+        self.dns_records
+            .read()
+            .unwrap()
+            .get(name)
+            .map(|(target, _)| target.clone())
+    }
+
+    pub fn has_custom_hostname(&self, hostname: &str) -> bool {
+        self.custom_hostnames.read().unwrap().contains_key(hostname)
+    }
+
+    /// Returns the purged hostnames and URLs, oldest first.
+    pub fn get_purged(&self) -> Vec<String> {
+        self.purged.read().unwrap().clone()
+    }
+
+    /// Makes changes of the DNS records and custom hostname of the name fail,
+    /// like an outage of the provider.
+    pub fn set_unavailable(&self, name: &str, unavailable: bool) {
+        let mut names = self.unavailable.write().unwrap();
+        if unavailable {
+            names.insert(name.to_string());
+        } else {
+            names.remove(name);
+        }
+    }
+
+    fn check_available(&self, name: &str) -> Result<(), Unavailable> {
+        if self.unavailable.read().unwrap().contains(name) {
+            Err(Unavailable(name.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Name made unavailable with `InMemoryEdgeProvider::set_unavailable`.
+struct Unavailable(String);
+
+impl From<Unavailable> for Status {
+    fn from(Unavailable(name): Unavailable) -> Self {
+        Status::unavailable(format!("'{}' is unavailable", name))
+    }
+}
+
+#[async_trait]
+impl EdgeProvider for InMemoryEdgeProvider {
+    async fn create_dns_record(
+        &self,
+        name: &str,
+        target: &str,
+    ) -> Result<(), Status> {
+        self.check_available(name)?;
+        self.dns_records
+            .write()
+            .unwrap()
+            .insert(name.to_string(), (target.to_string(), Utc::now()));
+        Ok(())
+    }
+
+    async fn delete_dns_records(&self, name: &str) -> Result<(), Status> {
+        self.check_available(name)?;
+        self.dns_records.write().unwrap().remove(name);
+        Ok(())
+    }
+
+    async fn add_custom_hostname(&self, hostname: &str) -> Result<(), Status> {
+        self.check_available(hostname)?;
+        self.custom_hostnames
+            .write()
+            .unwrap()
+            .entry(hostname.to_string())
+            .or_insert_with(Utc::now);
+        Ok(())
+    }
+
+    async fn remove_custom_hostname(
+        &self,
+        hostname: &str,
+    ) -> Result<(), Status> {
+        self.check_available(hostname)?;
+        self.custom_hostnames.write().unwrap().remove(hostname);
+        Ok(())
+    }
+
+    async fn purge_hostnames(
+        &self,
+        hostnames: &[String],
+    ) -> Result<(), Status> {
+        self.purged.write().unwrap().extend_from_slice(hostnames);
+        Ok(())
+    }
+
+    async fn purge_urls(&self, urls: &[String]) -> Result<(), Status> {
+        self.purged.write().unwrap().extend_from_slice(urls);
+        Ok(())
+    }
+
+    async fn list_dns_records(
+        &self,
+        target: &str,
+    ) -> Result<Option<Vec<EdgeHostname>>, Status> {
+        Ok(Some(
+            self.dns_records
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(_, (record_target, _))| record_target == target)
+                .map(|(name, (_, created_at))| EdgeHostname {
+                    name: name.clone(),
+                    created_at: Some(*created_at),
+                })
+                .collect(),
+        ))
+    }
+
+    async fn list_custom_hostnames(
+        &self,
+    ) -> Result<Option<Vec<EdgeHostname>>, Status> {
+        Ok(Some(
+            self.custom_hostnames
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(name, created_at)| EdgeHostname {
+                    name: name.clone(),
+                    created_at: Some(*created_at),
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Edge provider for self-hosted setups running Caddy with on-demand TLS.
+///
+/// Every hostname is written to the `allowed_hosts` table, which Caddy checks
+/// through the "ask" endpoint served by [`serve_on_demand_tls_ask`] before it
+/// requests a certificate. DNS records are expected to be managed outside of
+/// this service, e.g. by a wildcard record for the main domain.
+#[derive(Clone)]
+pub struct OnDemandTlsProvider {
+    store: DynStore,
+}
+
+impl OnDemandTlsProvider {
+    pub fn new(store: DynStore) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl EdgeProvider for OnDemandTlsProvider {
+    async fn create_dns_record(
+        &self,
+        name: &str,
+        _target: &str,
+    ) -> Result<(), Status> {
+        self.store.allowed_hosts().create(name).await?;
+        Ok(())
+    }
+
+    async fn delete_dns_records(&self, name: &str) -> Result<(), Status> {
+        self.store.allowed_hosts().delete(name).await?;
+        Ok(())
+    }
+
+    async fn add_custom_hostname(&self, hostname: &str) -> Result<(), Status> {
+        self.store.allowed_hosts().create(hostname).await?;
+        Ok(())
+    }
+
+    async fn remove_custom_hostname(
+        &self,
+        hostname: &str,
+    ) -> Result<(), Status> {
+        self.store.allowed_hosts().delete(hostname).await?;
+        Ok(())
+    }
+
+    // Caddy does not cache responses
+    async fn purge_hostnames(
+        &self,
+        _hostnames: &[String],
+    ) -> Result<(), Status> {
+        Ok(())
+    }
+
+    async fn purge_urls(&self, _urls: &[String]) -> Result<(), Status> {
+        Ok(())
+    }
+
+    // internal names and custom hostnames share the allowed hosts
+    async fn list_dns_records(
+        &self,
+        _target: &str,
+    ) -> Result<Option<Vec<EdgeHostname>>, Status> {
+        Ok(None)
+    }
+
+    async fn list_custom_hostnames(
+        &self,
+    ) -> Result<Option<Vec<EdgeHostname>>, Status> {
+        Ok(None)
+    }
+}
+
+/// Returns the URL-decoded `domain` query parameter in its ASCII form, like
+/// hostnames are stored.
+fn ask_domain(query: Option<&str>) -> Option<String> {
+    form_urlencoded::parse(query?.as_bytes())
+        .find(|(key, _)| key == "domain")
+        .and_then(|(_, value)| idna::domain_to_ascii(&value).ok())
+}
+
+async fn handle_ask(
+    store: DynStore,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let status = match ask_domain(request.uri().query()) {
+        Some(domain) => match store.allowed_hosts().exists(&domain).await {
+            Ok(true) => StatusCode::OK,
+            Ok(false) => StatusCode::NOT_FOUND,
+            Err(err) => {
+                tracing::log::error!("[handle_ask]: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        },
+        None => StatusCode::BAD_REQUEST,
+    };
+
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    Ok(response)
+}
+
+/// Serves the endpoint configured as `on_demand_tls { ask ... }` in Caddy.
+/// Responds with 200 for `GET /?domain=<hostname>` if hostname is allowed.
+/// Fails if the address cannot be bound, errors while serving are logged.
+pub fn serve_on_demand_tls_ask(
+    store: DynStore,
+    addr: SocketAddr,
+) -> Result<tokio::task::JoinHandle<()>, hyper::Error> {
+    let make_service = make_service_fn(move |_| {
+        let store = store.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_ask(store.clone(), request)
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_service);
+
+    tracing::log::info!("on-demand TLS ask endpoint listening on {}", addr);
+
+    Ok(tokio::spawn(async move {
+        if let Err(err) = server.await {
+            tracing::log::error!("[serve_on_demand_tls_ask]: {}", err);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ask_domain;
+
+    #[test]
+    fn decodes_ask_domain() {
+        assert_eq!(
+            ask_domain(Some("domain=B%C3%BCcher.Example.com&x=1")).as_deref(),
+            Some("xn--bcher-kva.example.com")
+        );
+        assert_eq!(ask_domain(Some("x=1")), None);
+        assert_eq!(ask_domain(None), None);
+    }
+}
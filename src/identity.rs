@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use tonic::{async_trait, Status};
+
+/// OIDC client of a website, used by its visitors to sign in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcApp {
+    pub app_id: String,
+    pub client_id: String,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub post_logout_redirect_uris: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Project holding the OIDC app of a website, in an organization of its own.
+/// The project enforces the label policy of that organization, so the login
+/// screen of the app shows the branding of the website. Signing in is not
+/// restricted to the users of the organization.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AppProject {
+    pub org_id: String,
+    pub project_id: String,
+}
+
+/// Organization made by `IdentityProvider::add_project`, with the project
+/// and apps in it. `project_id` is `None` if the project was never created in
+/// the organization, e.g. after a restart in between.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectOrg {
+    pub org_id: String,
+    pub project_id: Option<String>,
+    pub name: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub apps: Vec<OidcApp>,
+}
+
+/// Colours and logo of a website shown on its login screen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Branding {
+    pub primary_color: Option<String>,
+    /// Background colour of the login screen.
+    pub secondary_color: Option<String>,
+    pub logo_url: Option<String>,
+}
+
+/// Provisions the OIDC clients of websites and the branding of their login
+/// screens.
+///
+/// Apps are created in the project of their website. Websites created before
+/// they got a project have their app in a shared project, passed as `None`.
+/// Those apps can not be branded.
+#[async_trait]
+pub trait IdentityProvider: Send + Sync {
+    /// Creates the project for the website called `name`.
+    async fn add_project(&self, name: &str) -> Result<AppProject, Status>;
+
+    /// Removes the project with its apps and branding. Removing a missing
+    /// project is not an error.
+    async fn remove_project(&self, project: &AppProject) -> Result<(), Status>;
+
+    /// Removes an organization made by `add_project`, with the project in it.
+    /// Removing a missing organization is not an error.
+    async fn remove_org(&self, org_id: &str) -> Result<(), Status>;
+
+    async fn add_app(
+        &self,
+        project: Option<&AppProject>,
+        name: &str,
+        redirect_uris: Vec<String>,
+        post_logout_redirect_uris: Vec<String>,
+    ) -> Result<OidcApp, Status>;
+
+    async fn get_app(
+        &self,
+        project: Option<&AppProject>,
+        app_id: &str,
+    ) -> Result<Option<OidcApp>, Status>;
+
+    async fn update_app(
+        &self,
+        project: Option<&AppProject>,
+        app_id: &str,
+        redirect_uris: Vec<String>,
+        post_logout_redirect_uris: Vec<String>,
+    ) -> Result<(), Status>;
+
+    async fn remove_app(
+        &self,
+        project: Option<&AppProject>,
+        app_id: &str,
+    ) -> Result<(), Status>;
+
+    /// Lists the apps of the shared project.
+    async fn list_apps(&self) -> Result<Vec<OidcApp>, Status>;
+
+    /// Lists the organizations made by `add_project`.
+    async fn list_projects(&self) -> Result<Vec<ProjectOrg>, Status>;
+
+    /// Applies `branding` to the login screen of the apps in `project`.
+    /// Does nothing if it is applied already.
+    async fn set_branding(
+        &self,
+        project: &AppProject,
+        branding: &Branding,
+    ) -> Result<(), Status>;
+}
+
+pub type DynIdentityProvider = Arc<dyn IdentityProvider>;
+
+/// Apps by id, with the project they are in.
+type Apps = HashMap<String, (Option<AppProject>, OidcApp)>;
+
+/// Projects with their name, creation time and branding.
+type Projects = HashMap<AppProject, (String, DateTime<Utc>, Branding)>;
+
+/// Identity provider keeping apps in memory. Used for local development and
+/// tests, apps are lost on restart.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryIdentityProvider {
+    apps: Arc<RwLock<Apps>>,
+    projects: Arc<RwLock<Projects>>,
+    unavailable: Arc<AtomicBool>,
+}
+
+impl InMemoryIdentityProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_branding(&self, project: &AppProject) -> Option<Branding> {
+        self.projects
+            .read()
+            .unwrap()
+            .get(project)
+            .map(|(_, _, branding)| branding.clone())
+    }
+
+    pub fn list_apps_of(&self, project: &AppProject) -> Vec<OidcApp> {
+        self.apps
+            .read()
+            .unwrap()
+            .values()
+            .filter(|(p, _)| p.as_ref() == Some(project))
+            .map(|(_, app)| app.clone())
+            .collect()
+    }
+
+    /// Makes every call fail, like an outage of the provider.
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::Relaxed);
+    }
+
+    fn check_available(&self) -> Result<(), Unavailable> {
+        if self.unavailable.load(Ordering::Relaxed) {
+            Err(Unavailable)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Outage set with `InMemoryIdentityProvider::set_unavailable`.
+struct Unavailable;
+
+impl From<Unavailable> for Status {
+    fn from(_: Unavailable) -> Self {
+        Status::unavailable("Identity provider is unavailable")
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for InMemoryIdentityProvider {
+    async fn add_project(&self, name: &str) -> Result<AppProject, Status> {
+        self.check_available()?;
+
+        let project = AppProject {
+            org_id: uuid::Uuid::new_v4().to_string(),
+            project_id: uuid::Uuid::new_v4().to_string(),
+        };
+
+        self.projects.write().unwrap().insert(
+            project.clone(),
+            (name.to_string(), Utc::now(), Branding::default()),
+        );
+
+        Ok(project)
+    }
+
+    async fn remove_project(&self, project: &AppProject) -> Result<(), Status> {
+        self.check_available()?;
+
+        self.projects.write().unwrap().remove(project);
+        self.apps
+            .write()
+            .unwrap()
+            .retain(|_, (p, _)| p.as_ref() != Some(project));
+
+        Ok(())
+    }
+
+    async fn remove_org(&self, org_id: &str) -> Result<(), Status> {
+        self.check_available()?;
+
+        self.projects
+            .write()
+            .unwrap()
+            .retain(|p, _| p.org_id != org_id);
+        self.apps
+            .write()
+            .unwrap()
+            .retain(|_, (p, _)| p.as_ref().is_none_or(|p| p.org_id != org_id));
+
+        Ok(())
+    }
+
+    async fn add_app(
+        &self,
+        project: Option<&AppProject>,
+        name: &str,
+        redirect_uris: Vec<String>,
+        post_logout_redirect_uris: Vec<String>,
+    ) -> Result<OidcApp, Status> {
+        self.check_available()?;
+
+        if let Some(project) = project {
+            if !self.projects.read().unwrap().contains_key(project) {
+                return Err(Status::not_found(format!(
+                    "Could not find project '{}'",
+                    project.project_id
+                )));
+            }
+        }
+
+        let app = OidcApp {
+            app_id: uuid::Uuid::new_v4().to_string(),
+            client_id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            redirect_uris,
+            post_logout_redirect_uris,
+            created_at: Some(Utc::now()),
+        };
+
+        self.apps
+            .write()
+            .unwrap()
+            .insert(app.app_id.clone(), (project.cloned(), app.clone()));
+
+        Ok(app)
+    }
+
+    async fn get_app(
+        &self,
+        project: Option<&AppProject>,
+        app_id: &str,
+    ) -> Result<Option<OidcApp>, Status> {
+        self.check_available()?;
+
+        Ok(self
+            .apps
+            .read()
+            .unwrap()
+            .get(app_id)
+            .filter(|(p, _)| p.as_ref() == project)
+            .map(|(_, app)| app.clone()))
+    }
+
+    async fn update_app(
+        &self,
+        project: Option<&AppProject>,
+        app_id: &str,
+        redirect_uris: Vec<String>,
+        post_logout_redirect_uris: Vec<String>,
+    ) -> Result<(), Status> {
+        self.check_available()?;
+
+        let mut apps = self.apps.write().unwrap();
+        let (_, app) = apps
+            .get_mut(app_id)
+            .filter(|(p, _)| p.as_ref() == project)
+            .ok_or_else(|| {
+                Status::not_found(format!("Could not find app '{}'", app_id))
+            })?;
+
+        app.redirect_uris = redirect_uris;
+        app.post_logout_redirect_uris = post_logout_redirect_uris;
+
+        Ok(())
+    }
+
+    async fn remove_app(
+        &self,
+        project: Option<&AppProject>,
+        app_id: &str,
+    ) -> Result<(), Status> {
+        self.check_available()?;
+
+        let mut apps = self.apps.write().unwrap();
+        if apps.get(app_id).is_some_and(|(p, _)| p.as_ref() == project) {
+            apps.remove(app_id);
+        }
+
+        Ok(())
+    }
+
+    async fn list_apps(&self) -> Result<Vec<OidcApp>, Status> {
+        self.check_available()?;
+
+        Ok(self
+            .apps
+            .read()
+            .unwrap()
+            .values()
+            .filter(|(p, _)| p.is_none())
+            .map(|(_, app)| app.clone())
+            .collect())
+    }
+
+    async fn list_projects(&self) -> Result<Vec<ProjectOrg>, Status> {
+        self.check_available()?;
+
+        Ok(self
+            .projects
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(project, (name, created_at, _))| ProjectOrg {
+                org_id: project.org_id.clone(),
+                project_id: Some(project.project_id.clone()),
+                name: name.clone(),
+                created_at: Some(*created_at),
+                apps: self.list_apps_of(project),
+            })
+            .collect())
+    }
+
+    async fn set_branding(
+        &self,
+        project: &AppProject,
+        branding: &Branding,
+    ) -> Result<(), Status> {
+        self.check_available()?;
+
+        let mut projects = self.projects.write().unwrap();
+        let (_, _, found) = projects.get_mut(project).ok_or_else(|| {
+            Status::not_found(format!(
+                "Could not find project '{}'",
+                project.project_id
+            ))
+        })?;
+
+        *found = branding.clone();
+
+        Ok(())
+    }
+}
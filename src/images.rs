@@ -4,6 +4,26 @@ use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_s3::Client;
 use tonic::Status;
 
+/// Why an image was rejected, see `ImageService::validate_image`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidImage {
+    TooLarge { max_size: usize },
+    UnsupportedType,
+}
+
+impl From<InvalidImage> for Status {
+    fn from(err: InvalidImage) -> Self {
+        match err {
+            InvalidImage::TooLarge { max_size } => Status::resource_exhausted(
+                format!("image.size: max_size={}", max_size),
+            ),
+            InvalidImage::UnsupportedType => Status::invalid_argument(
+                "image.type: allowed_types=jpg,png,webp",
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ImageService {
     client: Client,
@@ -52,12 +72,14 @@ impl ImageService {
         image_path.map(|p| self.get_image_url(&p))
     }
 
-    pub fn validate_image(&self, image_data: &[u8]) -> Result<(), Status> {
+    pub fn validate_image(
+        &self,
+        image_data: &[u8],
+    ) -> Result<(), InvalidImage> {
         if image_data.len() > self.max_size {
-            return Err(Status::resource_exhausted(format!(
-                "image.size: max_size={}",
-                self.max_size
-            )));
+            return Err(InvalidImage::TooLarge {
+                max_size: self.max_size,
+            });
         }
 
         if !(infer::image::is_jpeg(image_data)
@@ -65,9 +87,7 @@ impl ImageService {
             || infer::image::is_png(image_data)
             || infer::image::is_webp(image_data))
         {
-            return Err(Status::invalid_argument(
-                "image.type: allowed_types=jpg,png,webp",
-            ));
+            return Err(InvalidImage::UnsupportedType);
         }
 
         Ok(())
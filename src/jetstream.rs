@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use async_nats::jetstream::context::CreateStreamError;
+use async_nats::jetstream::{self, stream};
+
+/// Settings of the JetStream stream that stores the messages published by
+/// `Publisher`, see `SUBJECTS`.
+///
+/// The stream keeps messages by limits only, not by interest, so a new
+/// consumer can replay it from the start (`DeliverPolicy::All`) to rebuild
+/// its view of all websites. That needs the messages of websites that did
+/// not change for long, so `max_age` is zero by default, which keeps
+/// messages forever. Messages are published with a `Nats-Msg-Id` header, duplicates
+/// within `duplicate_window` are dropped.
+#[derive(Debug, Clone)]
+pub struct StreamSettings {
+    pub name: String,
+    pub max_age: Duration,
+    pub max_bytes: Option<i64>,
+    pub duplicate_window: Duration,
+    pub num_replicas: usize,
+}
+
+impl StreamSettings {
+    pub const DEFAULT_NAME: &'static str = "WEBSITES";
+    /// Only the subjects of `Publisher`. Request-reply subjects must not be
+    /// captured, the stream would store every request and ack it to the
+    /// reply inbox of the requester.
+    pub const SUBJECTS: [&'static str; 3] =
+        ["websites.v1.>", "websites.website.*", "websites.domain.*"];
+    pub const DEFAULT_MAX_AGE: Duration = Duration::ZERO;
+    /// Covers the retries of the outbox relay.
+    pub const DEFAULT_DUPLICATE_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+    pub fn config(&self) -> stream::Config {
+        let max_age = self.max_age;
+        // JetStream rejects a duplicate window longer than the maximum age
+        let duplicate_window = if max_age.is_zero() {
+            self.duplicate_window
+        } else {
+            self.duplicate_window.min(max_age)
+        };
+
+        stream::Config {
+            name: self.name.clone(),
+            description: Some("Events of the websites service".to_string()),
+            subjects: Self::SUBJECTS.iter().map(|s| s.to_string()).collect(),
+            retention: stream::RetentionPolicy::Limits,
+            storage: stream::StorageType::File,
+            discard: stream::DiscardPolicy::Old,
+            max_age,
+            max_bytes: self.max_bytes.unwrap_or(-1),
+            max_messages: -1,
+            max_messages_per_subject: -1,
+            max_consumers: -1,
+            duplicate_window,
+            num_replicas: self.num_replicas,
+            allow_direct: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        Self {
+            name: Self::DEFAULT_NAME.to_string(),
+            max_age: Self::DEFAULT_MAX_AGE,
+            max_bytes: None,
+            duplicate_window: Self::DEFAULT_DUPLICATE_WINDOW,
+            num_replicas: 1,
+        }
+    }
+}
+
+/// Creates the stream, or updates an existing one to the settings.
+pub async fn ensure_stream(
+    context: &jetstream::Context,
+    settings: &StreamSettings,
+) -> Result<stream::Info, CreateStreamError> {
+    let config = settings.config();
+
+    context.get_or_create_stream(config.clone()).await?;
+
+    // no-op if the stream was just created or is unchanged
+    context.update_stream(config).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::lookup::LookupResponder;
+
+    use super::StreamSettings;
+
+    /// Whether the NATS subject matches the pattern with `*` and `>`
+    /// wildcards.
+    fn matches(pattern: &str, subject: &str) -> bool {
+        let mut subject = subject.split('.');
+        for token in pattern.split('.') {
+            match (token, subject.next()) {
+                (">", Some(_)) => return true,
+                ("*", Some(_)) => {}
+                (token, Some(s)) if token == s => {}
+                _ => return false,
+            }
+        }
+        subject.next().is_none()
+    }
+
+    #[test]
+    fn defaults() {
+        let config = StreamSettings::default().config();
+
+        assert_eq!(
+            config.subjects,
+            vec!["websites.v1.>", "websites.website.*", "websites.domain.*"]
+        );
+        assert!(config.max_age.is_zero());
+        assert_eq!(config.max_bytes, -1);
+        assert_eq!(
+            config.duplicate_window,
+            StreamSettings::DEFAULT_DUPLICATE_WINDOW
+        );
+    }
+
+    #[test]
+    fn keeps_duplicate_window_without_max_age() {
+        let config = StreamSettings {
+            max_age: Duration::ZERO,
+            ..Default::default()
+        }
+        .config();
+
+        assert!(config.max_age.is_zero());
+        assert_eq!(
+            config.duplicate_window,
+            StreamSettings::DEFAULT_DUPLICATE_WINDOW
+        );
+    }
+
+    #[test]
+    fn limits_duplicate_window_to_max_age() {
+        let config = StreamSettings {
+            max_age: Duration::from_secs(60),
+            ..Default::default()
+        }
+        .config();
+
+        assert_eq!(config.duplicate_window, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn captures_events_but_not_lookups() {
+        let captured = |subject: &str| {
+            StreamSettings::SUBJECTS
+                .iter()
+                .any(|pattern| matches(pattern, subject))
+        };
+
+        assert!(captured("websites.v1.page.created"));
+        assert!(captured("websites.website.upsert"));
+        assert!(captured("websites.domain.degraded"));
+        assert!(!captured(LookupResponder::BY_DOMAIN_SUBJECT));
+        assert!(!captured(LookupResponder::PAGE_SUBJECT));
+    }
+}
@@ -1,15 +1,31 @@
+use std::time::Duration;
+
 use chrono::{DateTime, Utc};
-use tonic::Status;
 
 pub mod api;
 mod auth;
+pub mod branding;
+pub mod broker;
+pub mod cache_purge;
 pub mod cloudflare;
 pub mod db;
+pub mod dns;
+pub mod domain_check;
+pub mod domain_name;
+pub mod edge;
+pub mod identity;
 pub mod images;
+pub mod jetstream;
+pub mod lifecycle;
 pub mod logging;
+pub mod lookup;
 mod model;
+pub mod outbox;
 pub mod publisher;
+pub mod reconcile;
+pub mod repository;
 mod services;
+pub mod website_cache;
 pub mod zitadel;
 
 pub use auth::init_jwks_verifier;
@@ -21,13 +37,37 @@ pub fn get_env_var(var: &str) -> String {
     })
 }
 
+/// Parses the optional environment variable, panicking with its name if it is
+/// set but invalid.
+pub fn parse_env_var<T>(var: &str) -> Option<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    std::env::var(var).ok().map(|s| {
+        s.parse().unwrap_or_else(|err| {
+            panic!("ERROR: Invalid environment variable '{var}': {err}")
+        })
+    })
+}
+
+/// Parses the optional environment variable as a number of seconds.
+pub fn parse_env_var_secs(var: &str) -> Option<Duration> {
+    std::env::var(var).ok().map(|s| {
+        Duration::from_secs(s.parse().unwrap_or_else(|_| {
+            panic!(
+                "ERROR: Environment variable '{var}' must be an integer \
+                 number of seconds"
+            )
+        }))
+    })
+}
+
 pub fn datetime_to_timestamp(datetime: DateTime<Utc>) -> u64 {
     u64::try_from(datetime.timestamp()).unwrap()
 }
 
-pub fn i64_to_u32(n: i64) -> Result<u32, Status> {
-    n.try_into().map_err(|err| {
-        tracing::log::error!("{:?}", err);
-        Status::internal("")
-    })
+/// Caps counts beyond `u32::MAX`, counts are never negative.
+pub fn i64_to_u32(n: i64) -> u32 {
+    n.clamp(0, u32::MAX.into()).try_into().unwrap()
 }
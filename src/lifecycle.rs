@@ -0,0 +1,452 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_nats::jetstream::consumer::{pull, AckPolicy};
+use async_nats::jetstream::{self, stream, AckKind};
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use prost::Message;
+use tonic::{Code, Status};
+
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
+use crate::api::sited_io::websites::v1::{
+    PageType, ShopDeletedEvent, UserDeletedEvent,
+};
+use crate::cache_purge::CachePurger;
+use crate::publisher::{EventAction, Publisher, SYSTEM_ACTOR};
+use crate::repository::DynStore;
+use crate::website_cache::WebsiteCache;
+use crate::{PageService, WebsiteService};
+
+/// Cleans up after users and shops deleted in other services.
+///
+/// - `UserDeletedEvent`: every website of the user is deleted like by
+///   `DeleteWebsite`, including its ZITADEL app and DNS records. The user is
+///   recorded in `user_deletions` first. A website that fails to be deleted
+///   does not stop the others, the user is retried with exponential backoff
+///   until all its websites are gone, also after a restart.
+/// - `ShopDeletedEvent`: shop pages showing the shop are deleted. A home
+///   page showing it becomes an empty static page, as a website always has
+///   a home page.
+///
+/// Both only act on what is still there, so an event can be processed any
+/// number of times. Replicas share the events, see `spawn`.
+pub struct LifecycleConsumer {
+    store: DynStore,
+    website_service: Arc<WebsiteService>,
+    publisher: Publisher,
+    cache_purger: CachePurger,
+    website_cache: WebsiteCache,
+    user_deleted_subject: String,
+    shop_deleted_subject: String,
+}
+
+impl LifecycleConsumer {
+    pub const DEFAULT_USER_DELETED_SUBJECT: &'static str = "users.user.deleted";
+    pub const DEFAULT_SHOP_DELETED_SUBJECT: &'static str =
+        "commerce.shop.deleted";
+    const QUEUE_GROUP: &'static str = "websites";
+    /// Stream keeping the events until they are processed, it must be the
+    /// only stream capturing their subjects.
+    pub const STREAM_NAME: &'static str = "WEBSITES_LIFECYCLE";
+    const CONSUMER_NAME: &'static str = "websites-lifecycle";
+    const BATCH_SIZE: u64 = 100;
+    const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+    const LEASE: Duration = Duration::from_secs(10 * 60);
+    const BASE_DELAY: Duration = Duration::from_secs(60);
+    const MAX_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+
+    pub fn new(
+        store: DynStore,
+        website_service: Arc<WebsiteService>,
+        publisher: Publisher,
+        cache_purger: CachePurger,
+        website_cache: WebsiteCache,
+        user_deleted_subject: String,
+        shop_deleted_subject: String,
+    ) -> Self {
+        Self {
+            store,
+            website_service,
+            publisher,
+            cache_purger,
+            website_cache,
+            user_deleted_subject,
+            shop_deleted_subject,
+        }
+    }
+
+    /// Receives the events and retries failed user deletions.
+    ///
+    /// With JetStream the events are kept in the `STREAM_NAME` stream until
+    /// the durable consumer shared by the replicas acked them, so events
+    /// published while every replica is down are processed once one is
+    /// back. Failed events are redelivered after `RETRY_INTERVAL`, malformed
+    /// ones are dropped. Without JetStream the replicas share the events in
+    /// one queue group and events published while none is subscribed are
+    /// lost: the websites and pages of such users and shops are left behind
+    /// until they are deleted by hand.
+    pub async fn spawn(
+        self,
+        nats_client: async_nats::Client,
+        jetstream: Option<jetstream::Context>,
+    ) -> Result<tokio::task::JoinHandle<()>, Box<dyn std::error::Error>> {
+        let mut events = match jetstream {
+            Some(jetstream) => self.consume(jetstream).await?,
+            None => self.subscribe(nats_client).await?,
+        };
+
+        Ok(tokio::spawn(async move {
+            let mut retry = tokio::time::interval(Self::RETRY_INTERVAL);
+            loop {
+                let result = tokio::select! {
+                    Some(event) = events.next() => self.on_event(event).await,
+                    _ = retry.tick() => {
+                        self.retry_user_deletions(Utc::now()).await
+                    }
+                };
+
+                if let Err(err) = result {
+                    tracing::log::error!("[LifecycleConsumer]: {}", err);
+                }
+            }
+        }))
+    }
+
+    async fn subscribe(
+        &self,
+        nats_client: async_nats::Client,
+    ) -> Result<BoxStream<'static, Event>, async_nats::SubscribeError> {
+        let user_deleted = nats_client
+            .queue_subscribe(
+                self.user_deleted_subject.clone(),
+                Self::QUEUE_GROUP.into(),
+            )
+            .await?;
+        let shop_deleted = nats_client
+            .queue_subscribe(
+                self.shop_deleted_subject.clone(),
+                Self::QUEUE_GROUP.into(),
+            )
+            .await?;
+
+        Ok(futures::stream::select(user_deleted, shop_deleted)
+            .map(Event::Core)
+            .boxed())
+    }
+
+    /// Creates the stream and the durable consumer, if missing.
+    async fn consume(
+        &self,
+        jetstream: jetstream::Context,
+    ) -> Result<BoxStream<'static, Event>, Box<dyn std::error::Error>> {
+        let stream = jetstream
+            .get_or_create_stream(stream::Config {
+                name: Self::STREAM_NAME.to_string(),
+                description: Some(
+                    "Deletions processed by the websites service".to_string(),
+                ),
+                subjects: vec![
+                    self.user_deleted_subject.clone(),
+                    self.shop_deleted_subject.clone(),
+                ],
+                retention: stream::RetentionPolicy::WorkQueue,
+                storage: stream::StorageType::File,
+                ..Default::default()
+            })
+            .await?;
+
+        let consumer = stream
+            .get_or_create_consumer(
+                Self::CONSUMER_NAME,
+                pull::Config {
+                    durable_name: Some(Self::CONSUMER_NAME.to_string()),
+                    ack_policy: AckPolicy::Explicit,
+                    ack_wait: Self::LEASE,
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        Ok(consumer
+            .messages()
+            .await?
+            .filter_map(|message| async move {
+                match message {
+                    Ok(message) => Some(Event::JetStream(message)),
+                    Err(err) => {
+                        tracing::log::error!(
+                            "[LifecycleConsumer.consume]: {}",
+                            err
+                        );
+                        None
+                    }
+                }
+            })
+            .boxed())
+    }
+
+    async fn on_event(&self, event: Event) -> Result<(), Status> {
+        let message = match &event {
+            Event::Core(message) => message,
+            Event::JetStream(message) => &message.message,
+        };
+
+        let result = if message.subject.as_str() == self.user_deleted_subject {
+            self.on_user_deleted(&message.payload).await
+        } else {
+            self.on_shop_deleted(&message.payload).await
+        };
+
+        if let Event::JetStream(message) = &event {
+            if let Err(err) = message.ack_with(Self::ack_kind(&result)).await {
+                tracing::log::error!("[LifecycleConsumer.on_event]: {}", err);
+            }
+        }
+
+        result
+    }
+
+    /// Malformed events would fail again, so only other failures are
+    /// redelivered.
+    fn ack_kind(result: &Result<(), Status>) -> AckKind {
+        match result {
+            Err(err) if err.code() != Code::InvalidArgument => {
+                AckKind::Nak(Some(Self::RETRY_INTERVAL))
+            }
+            _ => AckKind::Ack,
+        }
+    }
+
+    pub async fn on_user_deleted(&self, payload: &[u8]) -> Result<(), Status> {
+        let UserDeletedEvent { user_id } = UserDeletedEvent::decode(payload)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        if user_id.is_empty() {
+            return Err(Status::invalid_argument("user_id"));
+        }
+
+        // not retried by other replicas while the first attempt runs
+        self.store
+            .user_deletions()
+            .create(&user_id, Utc::now() + Self::LEASE)
+            .await?;
+
+        self.delete_user(&user_id, 0).await
+    }
+
+    /// Retries the deleted users due at `now`.
+    pub async fn retry_user_deletions(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<(), Status> {
+        let user_deletions = self
+            .store
+            .user_deletions()
+            .list_due(now, Self::BATCH_SIZE)
+            .await?;
+
+        for user_deletion in user_deletions {
+            if !self
+                .store
+                .user_deletions()
+                .claim(
+                    &user_deletion.user_id,
+                    user_deletion.next_attempt_at,
+                    now + Self::LEASE,
+                )
+                .await?
+            {
+                continue;
+            }
+
+            if let Err(err) = self
+                .delete_user(&user_deletion.user_id, user_deletion.attempts)
+                .await
+            {
+                tracing::log::error!(
+                    "[LifecycleConsumer.retry_user_deletions] user_id {}: {}",
+                    user_deletion.user_id,
+                    err
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn retry_delay(attempts: i64) -> Duration {
+        let exponent = u32::try_from(attempts.saturating_sub(1))
+            .unwrap_or(u32::MAX)
+            .min(16);
+        Self::BASE_DELAY
+            .saturating_mul(2u32.pow(exponent))
+            .min(Self::MAX_DELAY)
+    }
+
+    /// Deletes the websites of the user, then the record of the user. After
+    /// a failure, the record is kept for a retry.
+    async fn delete_user(
+        &self,
+        user_id: &str,
+        attempts: i64,
+    ) -> Result<(), Status> {
+        match self.delete_websites(user_id).await {
+            Ok(()) => {
+                self.store.user_deletions().delete(user_id).await?;
+                Ok(())
+            }
+            Err(err) => {
+                let retry_at = Utc::now() + Self::retry_delay(attempts + 1);
+                self.store
+                    .user_deletions()
+                    .mark_failed(user_id, err.message(), retry_at)
+                    .await?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Tries to delete every website of the user, returns the last error.
+    async fn delete_websites(&self, user_id: &str) -> Result<(), Status> {
+        let user_id = Some(user_id.to_string());
+        let mut failed = HashSet::new();
+        let mut last_error = None;
+
+        // deleted websites drop out of the list, failed ones are skipped
+        loop {
+            let limit = Self::BATCH_SIZE + failed.len() as u64;
+            let (websites, _) =
+                self.store.websites().list(&user_id, limit, 0).await?;
+
+            let websites: Vec<_> = websites
+                .into_iter()
+                .filter(|w| !failed.contains(&w.website_id))
+                .collect();
+
+            if websites.is_empty() {
+                break;
+            }
+
+            for website in websites {
+                let website_id = website.website_id.clone();
+                tracing::log::info!(
+                    "[LifecycleConsumer] deleting website {} of deleted user",
+                    website_id
+                );
+                if let Err(err) = self
+                    .website_service
+                    .remove_website(website, SYSTEM_ACTOR)
+                    .await
+                {
+                    tracing::log::error!(
+                        "[LifecycleConsumer] website_id {}: {}",
+                        website_id,
+                        err
+                    );
+                    failed.insert(website_id);
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        match last_error {
+            Some(err) => Err(Status::new(
+                err.code(),
+                format!(
+                    "Could not delete {} websites: {}",
+                    failed.len(),
+                    err.message()
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn on_shop_deleted(&self, payload: &[u8]) -> Result<(), Status> {
+        let ShopDeletedEvent { shop_id, .. } =
+            ShopDeletedEvent::decode(payload)
+                .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        if shop_id.is_empty() {
+            return Err(Status::invalid_argument("shop_id"));
+        }
+
+        let pages = self
+            .store
+            .pages()
+            .list_by_content(PageType::Shop, &shop_id)
+            .await?;
+
+        for page in pages {
+            let transaction = self.store.begin().await?;
+
+            let website_id = page.website_id.clone();
+
+            if page.is_home_page {
+                let updated_page = transaction
+                    .pages()
+                    .update(
+                        page.page_id,
+                        &page.user_id,
+                        Some(PageType::Static),
+                        Some(String::new()),
+                        None,
+                        None,
+                        None,
+                    )
+                    .await?;
+
+                self.publisher
+                    .publish_event(
+                        transaction.outbox(),
+                        EventAction::Updated,
+                        SYSTEM_ACTOR,
+                        &website_id,
+                        Payload::Page(PageService::to_response(updated_page)),
+                    )
+                    .await?;
+
+                PageService::ensure_static_page(
+                    &self.publisher,
+                    &*transaction,
+                    page.page_id,
+                    &website_id,
+                    &page.user_id,
+                )
+                .await?;
+            } else {
+                transaction
+                    .pages()
+                    .delete_with_static_page(page.page_id, &page.user_id)
+                    .await?;
+
+                self.publisher
+                    .publish_event(
+                        transaction.outbox(),
+                        EventAction::Deleted,
+                        SYSTEM_ACTOR,
+                        &website_id,
+                        Payload::Page(PageService::to_response(page)),
+                    )
+                    .await?;
+            }
+
+            transaction.commit().await?;
+            self.website_cache.invalidate(&website_id);
+
+            self.cache_purger.purge_website(&website_id);
+        }
+
+        Ok(())
+    }
+}
+
+/// An event received from core NATS or from JetStream, which has to be
+/// acked.
+enum Event {
+    Core(async_nats::Message),
+    JetStream(jetstream::Message),
+}
@@ -0,0 +1,171 @@
+use futures::StreamExt;
+use prost::Message;
+use tonic::Status;
+
+use crate::api::sited_io::websites::v1::{GetPageRequest, GetWebsiteRequest};
+use crate::images::ImageService;
+use crate::repository::DynStore;
+use crate::website_cache::WebsiteCache;
+use crate::{PageService, WebsiteService};
+
+/// Answers lookups of websites and pages over NATS request-reply, for
+/// internal services such as the edge renderers.
+///
+/// - `websites-lookup.by_domain`: the payload is the domain, the reply a
+///   `GetWebsiteResponse`, like `GetWebsite` with `domain`. Served from the
+///   `WebsiteCache`.
+/// - `websites-lookup.page`: the payload is a `GetPageRequest`, the reply a
+///   `GetPageResponse`, like `GetPage`.
+///
+/// Replies are protobuf-encoded. On error the reply is empty and carries the
+/// gRPC status code and message in the `Nats-Service-Error-Code` and
+/// `Nats-Service-Error` headers. Replicas share the requests in one queue
+/// group. The subjects are outside of `websites.`, whose events may be
+/// captured by a JetStream stream, see `StreamSettings`.
+pub struct LookupResponder {
+    store: DynStore,
+    nats_client: async_nats::Client,
+    image_service: ImageService,
+    website_cache: WebsiteCache,
+}
+
+impl LookupResponder {
+    pub const BY_DOMAIN_SUBJECT: &'static str = "websites-lookup.by_domain";
+    pub const PAGE_SUBJECT: &'static str = "websites-lookup.page";
+    const QUEUE_GROUP: &'static str = "websites";
+    const ERROR_HEADER: &'static str = "Nats-Service-Error";
+    const ERROR_CODE_HEADER: &'static str = "Nats-Service-Error-Code";
+
+    pub fn new(
+        store: DynStore,
+        nats_client: async_nats::Client,
+        image_service: ImageService,
+        website_cache: WebsiteCache,
+    ) -> Self {
+        Self {
+            store,
+            nats_client,
+            image_service,
+            website_cache,
+        }
+    }
+
+    pub async fn spawn(
+        self,
+    ) -> Result<tokio::task::JoinHandle<()>, async_nats::SubscribeError> {
+        let mut by_domain = self
+            .nats_client
+            .queue_subscribe(Self::BY_DOMAIN_SUBJECT, Self::QUEUE_GROUP.into())
+            .await?;
+        let mut page = self
+            .nats_client
+            .queue_subscribe(Self::PAGE_SUBJECT, Self::QUEUE_GROUP.into())
+            .await?;
+
+        Ok(tokio::spawn(async move {
+            loop {
+                let message = tokio::select! {
+                    Some(message) = by_domain.next() => message,
+                    Some(message) = page.next() => message,
+                    else => break,
+                };
+
+                let Some(reply) = message.reply.clone() else {
+                    continue;
+                };
+
+                let store = self.store.clone();
+                let nats_client = self.nats_client.clone();
+                let image_service = self.image_service.clone();
+                let website_cache = self.website_cache.clone();
+
+                tokio::spawn(async move {
+                    let result = Self::handle(
+                        &store,
+                        &image_service,
+                        &website_cache,
+                        message.subject.as_str(),
+                        &message.payload,
+                    )
+                    .await;
+
+                    let sent = match result {
+                        Ok(payload) => {
+                            nats_client.publish(reply, payload.into()).await
+                        }
+                        Err(status) => {
+                            nats_client
+                                .publish_with_headers(
+                                    reply,
+                                    Self::error_headers(&status),
+                                    Default::default(),
+                                )
+                                .await
+                        }
+                    };
+
+                    if let Err(err) = sent {
+                        tracing::log::error!(
+                            "[LookupResponder] could not reply on '{}': {}",
+                            message.subject,
+                            err
+                        );
+                    }
+                });
+            }
+        }))
+    }
+
+    async fn handle(
+        store: &DynStore,
+        image_service: &ImageService,
+        website_cache: &WebsiteCache,
+        subject: &str,
+        payload: &[u8],
+    ) -> Result<Vec<u8>, Status> {
+        match subject {
+            Self::BY_DOMAIN_SUBJECT => {
+                let domain = std::str::from_utf8(payload)
+                    .map_err(|_| Status::invalid_argument("domain"))?
+                    .trim()
+                    .to_string();
+
+                let request = GetWebsiteRequest {
+                    domain: Some(domain),
+                    ..Default::default()
+                };
+                let response = website_cache
+                    .get_or_lookup(request, |request| {
+                        WebsiteService::lookup_website(
+                            store.as_ref(),
+                            image_service,
+                            request,
+                        )
+                    })
+                    .await?;
+
+                Ok(response.encode_to_vec())
+            }
+            Self::PAGE_SUBJECT => {
+                let request = GetPageRequest::decode(payload)
+                    .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+                let response =
+                    PageService::lookup_page(store.as_ref(), request).await?;
+
+                Ok(response.encode_to_vec())
+            }
+            _ => Err(Status::unimplemented(subject.to_string())),
+        }
+    }
+
+    fn error_headers(status: &Status) -> async_nats::HeaderMap {
+        let mut headers = async_nats::HeaderMap::new();
+        headers.insert(Self::ERROR_HEADER, status.message());
+        headers.insert(
+            Self::ERROR_CODE_HEADER,
+            (status.code() as i32).to_string().as_str(),
+        );
+        headers
+    }
+}
@@ -1,3 +1,6 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
 use http::{HeaderName, Method};
 use tonic::transport::Server;
@@ -5,15 +8,32 @@ use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::trace::TraceLayer;
 
 use websites::api::sited_io::websites::v1::website_service_server::WebsiteServiceServer;
+use websites::branding::BrandingSyncJob;
+use websites::broker::NatsBroker;
+use websites::cache_purge::CachePurgeJob;
 use websites::cloudflare::CloudflareService;
 use websites::db::{init_db_pool, migrate};
+use websites::dns::init_dns_resolver;
+use websites::domain_check::DomainCheckJob;
+use websites::edge::{
+    serve_on_demand_tls_ask, DynEdgeProvider, OnDemandTlsProvider,
+};
+use websites::identity::{DynIdentityProvider, InMemoryIdentityProvider};
 use websites::images::ImageService;
+use websites::jetstream::{ensure_stream, StreamSettings};
+use websites::lifecycle::LifecycleConsumer;
 use websites::logging::{LogOnFailure, LogOnRequest, LogOnResponse};
+use websites::lookup::LookupResponder;
+use websites::outbox::OutboxRelay;
 use websites::publisher::Publisher;
+use websites::reconcile::Reconciler;
+use websites::repository::{DynStore, PgStore};
+use websites::website_cache::WebsiteCache;
 use websites::zitadel::ZitadelService;
 use websites::{
-    get_env_var, init_jwks_verifier, CustomizationService, DomainService,
-    PageService, StaticPageService, WebsiteService,
+    get_env_var, init_jwks_verifier, parse_env_var, parse_env_var_secs,
+    CustomizationService, DomainService, PageService, StaticPageService,
+    WebsiteService, READ_PRIMARY_HEADER,
 };
 
 #[tokio::main]
@@ -25,21 +45,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let jwks_url = get_env_var("JWKS_URL");
     let jwks_host = get_env_var("JWKS_HOST");
 
-    let db_pool = init_db_pool(
+    // public reads are served from the replica if there is one
+    let db_pools = init_db_pool(
         get_env_var("DB_HOST"),
         get_env_var("DB_PORT").parse().unwrap(),
         get_env_var("DB_USER"),
         get_env_var("DB_PASSWORD"),
         get_env_var("DB_DBNAME"),
         std::env::var("DB_ROOT_CERT").ok(),
+        std::env::var("DB_REPLICA_HOST").ok(),
     )?;
+    let db_pool = db_pools.primary.clone();
     migrate(&db_pool).await?;
 
-    let cloudflare_service = CloudflareService::init(
-        get_env_var("CLOUDFLARE_API_URL"),
-        get_env_var("CLOUDFLARE_ZONE_ID"),
-        get_env_var("CLOUDFLARE_API_TOKEN"),
-    );
+    let mut store = PgStore::new(db_pool.clone());
+    let mut replica_lag = Duration::ZERO;
+    if let Some(replica) = db_pools.replica {
+        store = store.with_replica(replica);
+        replica_lag = parse_env_var_secs("DB_REPLICA_MAX_LAG_SECS")
+            .unwrap_or(WebsiteCache::DEFAULT_REPLICA_LAG);
+    }
+    let store: DynStore = Arc::new(store);
+
+    let edge_provider: DynEdgeProvider =
+        match std::env::var("EDGE_PROVIDER").ok().as_deref() {
+            None | Some("cloudflare") => Arc::new(CloudflareService::init(
+                get_env_var("CLOUDFLARE_API_URL"),
+                get_env_var("CLOUDFLARE_ZONE_ID"),
+                get_env_var("CLOUDFLARE_API_TOKEN"),
+                get_env_var("FALLBACK_DOMAIN"),
+            )),
+            Some("on_demand_tls") => {
+                serve_on_demand_tls_ask(
+                    store.clone(),
+                    get_env_var("EDGE_ASK_HOST").parse()?,
+                )?;
+                Arc::new(OnDemandTlsProvider::new(store.clone()))
+            }
+            Some(other) => {
+                return Err(format!("Unknown EDGE_PROVIDER '{other}'").into())
+            }
+        };
+
+    let dns_resolver = init_dns_resolver(
+        std::env::var("DNS_RESOLVER").ok(),
+        std::env::var("DNS_DOH_URL").ok(),
+        std::env::var("DNS_NAMESERVER").ok(),
+    )?;
 
     // initialize s3 bucket
     let image_service = ImageService::new(
@@ -52,16 +104,106 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     )
     .await;
 
-    // initialize publisher
-    let publisher = Publisher::new(
-        async_nats::ConnectOptions::new()
-            .user_and_password(
-                get_env_var("NATS_USER"),
-                get_env_var("NATS_PASSWORD"),
-            )
-            .connect(get_env_var("NATS_HOST"))
-            .await?,
-    );
+    // initialize publisher, messages are written to the outbox and relayed
+    // to NATS
+    let (publisher, nats_client) =
+        match std::env::var("PUBLISHER").ok().as_deref() {
+            None | Some("nats") => (
+                Publisher::new(),
+                Some(
+                    async_nats::ConnectOptions::new()
+                        .user_and_password(
+                            get_env_var("NATS_USER"),
+                            get_env_var("NATS_PASSWORD"),
+                        )
+                        .connect(get_env_var("NATS_HOST"))
+                        .await?,
+                ),
+            ),
+            Some("disabled") => {
+                tracing::log::warn!(
+                    "Publishing is disabled, NATS lookups and lifecycle \
+                     events are not served"
+                );
+                (Publisher::disabled(), None)
+            }
+            Some(other) => {
+                return Err(format!("Unknown PUBLISHER '{other}'").into())
+            }
+        };
+
+    // cache the websites renderers resolve by domain, a capacity of 0
+    // disables the cache
+    let website_cache = match parse_env_var("WEBSITE_CACHE_CAPACITY")
+        .unwrap_or(WebsiteCache::DEFAULT_CAPACITY)
+    {
+        0 => WebsiteCache::disabled(),
+        capacity => WebsiteCache::new(
+            parse_env_var_secs("WEBSITE_CACHE_TTL_SECS")
+                .unwrap_or(WebsiteCache::DEFAULT_TTL),
+            capacity,
+        )
+        .with_replica_lag(replica_lag),
+    };
+    website_cache.spawn_stats_logging();
+
+    let jetstream = if let Some(nats_client) = &nats_client {
+        // drop websites changed by other replicas from the cache
+        website_cache
+            .spawn_invalidation(nats_client.clone())
+            .await?;
+
+        // optionally keep the events in a JetStream stream for replay
+        let jetstream = match std::env::var("NATS_JETSTREAM") {
+            Ok(s) if s == "true" => {
+                let context = async_nats::jetstream::new(nats_client.clone());
+                ensure_stream(
+                    &context,
+                    &StreamSettings {
+                        name: std::env::var("NATS_STREAM_NAME").unwrap_or(
+                            StreamSettings::DEFAULT_NAME.to_string(),
+                        ),
+                        max_age: parse_env_var_secs("NATS_STREAM_MAX_AGE_SECS")
+                            .unwrap_or(StreamSettings::DEFAULT_MAX_AGE),
+                        max_bytes: parse_env_var("NATS_STREAM_MAX_BYTES"),
+                        duplicate_window: parse_env_var_secs(
+                            "NATS_STREAM_DUPLICATE_WINDOW_SECS",
+                        )
+                        .unwrap_or(StreamSettings::DEFAULT_DUPLICATE_WINDOW),
+                        num_replicas: parse_env_var("NATS_STREAM_REPLICAS")
+                            .unwrap_or(1),
+                    },
+                )
+                .await?;
+                Some(context)
+            }
+            _ => None,
+        };
+
+        // answer website and page lookups of internal services
+        LookupResponder::new(
+            store.clone(),
+            nats_client.clone(),
+            image_service.clone(),
+            website_cache.clone(),
+        )
+        .spawn()
+        .await?;
+
+        OutboxRelay::new(
+            store.clone(),
+            Arc::new(NatsBroker::new(nats_client.clone(), jetstream.clone())),
+            parse_env_var_secs("OUTBOX_RELAY_INTERVAL_SECS")
+                .unwrap_or(OutboxRelay::DEFAULT_INTERVAL),
+            parse_env_var("OUTBOX_MAX_ATTEMPTS")
+                .unwrap_or(OutboxRelay::DEFAULT_MAX_ATTEMPTS),
+        )
+        .spawn();
+
+        jetstream
+    } else {
+        None
+    };
 
     let (mut health_reporter, health_service) =
         tonic_health::server::health_reporter();
@@ -79,43 +221,151 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build()
         .unwrap();
 
-    let website_service = WebsiteService::build(
-        db_pool.clone(),
+    let identity_provider: DynIdentityProvider =
+        match std::env::var("IDENTITY_PROVIDER").ok().as_deref() {
+            None | Some("zitadel") => Arc::new(
+                ZitadelService::init(
+                    get_env_var("ZITADEL_API_URL"),
+                    get_env_var("ZITADEL_API_TOKEN"),
+                    get_env_var("ZITADEL_PROJECT_ID"),
+                )
+                .await?,
+            ),
+            Some("in_memory") => Arc::new(InMemoryIdentityProvider::new()),
+            Some(other) => {
+                return Err(
+                    format!("Unknown IDENTITY_PROVIDER '{other}'").into()
+                )
+            }
+        };
+
+    // purge the CDN cache of websites after their content changed
+    let cache_purge_job = CachePurgeJob::new(
+        store.clone(),
+        edge_provider.clone(),
+        parse_env_var_secs("CACHE_PURGE_DEBOUNCE_SECS")
+            .unwrap_or(CachePurgeJob::DEFAULT_DEBOUNCE),
+    );
+    let cache_purger = cache_purge_job.purger();
+    cache_purge_job.spawn();
+
+    // shared with `LifecycleConsumer`
+    let website_service = Arc::new(WebsiteService::new(
+        store.clone(),
         init_jwks_verifier(&jwks_host, &jwks_url)?,
         get_env_var("MAIN_DOMAIN"),
         get_env_var("FALLBACK_DOMAIN"),
-        ZitadelService::init(
-            get_env_var("ZITADEL_API_URL"),
-            get_env_var("ZITADEL_API_TOKEN"),
-            get_env_var("ZITADEL_PROJECT_ID"),
+        identity_provider.clone(),
+        edge_provider.clone(),
+        image_service.clone(),
+        publisher.clone(),
+        cache_purger.clone(),
+        website_cache.clone(),
+    ));
+
+    // clean up after users and shops deleted in other services
+    if let Some(nats_client) = nats_client {
+        LifecycleConsumer::new(
+            store.clone(),
+            website_service.clone(),
+            publisher.clone(),
+            cache_purger.clone(),
+            website_cache.clone(),
+            std::env::var("USER_DELETED_SUBJECT").unwrap_or(
+                LifecycleConsumer::DEFAULT_USER_DELETED_SUBJECT.to_string(),
+            ),
+            std::env::var("SHOP_DELETED_SUBJECT").unwrap_or(
+                LifecycleConsumer::DEFAULT_SHOP_DELETED_SUBJECT.to_string(),
+            ),
         )
-        .await?,
-        cloudflare_service.clone(),
+        .spawn(nats_client, jetstream)
+        .await?;
+    }
+
+    // push login branding of websites to the identity provider
+    let branding_sync_job = BrandingSyncJob::new(
+        store.clone(),
+        identity_provider.clone(),
         image_service.clone(),
-        publisher,
+        publisher.clone(),
+        website_cache.clone(),
+        parse_env_var_secs("BRANDING_SYNC_INTERVAL_SECS")
+            .unwrap_or(BrandingSyncJob::DEFAULT_INTERVAL),
     );
 
     let customization_service = CustomizationService::build(
-        db_pool.clone(),
+        store.clone(),
         init_jwks_verifier(&jwks_host, &jwks_url)?,
-        image_service,
+        image_service.clone(),
+        branding_sync_job.notifier(),
+        publisher.clone(),
+        cache_purger.clone(),
+        website_cache.clone(),
     );
 
+    branding_sync_job.spawn();
+
     let domain_service = DomainService::build(
-        db_pool.clone(),
+        store.clone(),
         init_jwks_verifier(&jwks_host, &jwks_url)?,
+        get_env_var("MAIN_DOMAIN"),
         get_env_var("FALLBACK_DOMAIN"),
-        cloudflare_service,
+        identity_provider.clone(),
+        edge_provider.clone(),
+        dns_resolver.clone(),
+        publisher.clone(),
+        website_cache.clone(),
     );
 
+    // find and fix drift between database, ZITADEL and the edge
+    Reconciler::new(
+        store.clone(),
+        identity_provider.clone(),
+        edge_provider.clone(),
+        publisher.clone(),
+        image_service,
+        website_cache.clone(),
+        get_env_var("MAIN_DOMAIN"),
+        get_env_var("FALLBACK_DOMAIN"),
+        parse_env_var_secs("RECONCILE_INTERVAL_SECS")
+            .unwrap_or(Reconciler::DEFAULT_INTERVAL),
+        parse_env_var_secs("RECONCILE_MIN_AGE_SECS")
+            .unwrap_or(Reconciler::DEFAULT_MIN_AGE),
+        std::env::var("RECONCILE_DRY_RUN")
+            .map(|s| s != "false")
+            .unwrap_or(true),
+    )
+    .spawn();
+
+    // periodically re-check active custom domains
+    DomainCheckJob::new(
+        store.clone(),
+        identity_provider,
+        edge_provider,
+        dns_resolver,
+        publisher.clone(),
+        website_cache.clone(),
+        get_env_var("FALLBACK_DOMAIN"),
+        parse_env_var_secs("DOMAIN_CHECK_INTERVAL_SECS")
+            .unwrap_or(DomainCheckJob::DEFAULT_INTERVAL),
+        parse_env_var_secs("DOMAIN_CHECK_GRACE_PERIOD_SECS")
+            .unwrap_or(DomainCheckJob::DEFAULT_GRACE_PERIOD),
+    )
+    .spawn();
+
     let page_service = PageService::build(
-        db_pool.clone(),
+        store.clone(),
         init_jwks_verifier(&jwks_host, &jwks_url)?,
+        publisher.clone(),
+        cache_purger.clone(),
+        website_cache,
     );
 
     let static_page_service = StaticPageService::build(
-        db_pool,
+        store,
         init_jwks_verifier(&jwks_host, &jwks_url)?,
+        publisher,
+        cache_purger,
     );
 
     tracing::log::info!("gRPC+web server listening on {}", host);
@@ -138,6 +388,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     HeaderName::from_static("grpc-message"),
                     HeaderName::from_static("x-grpc-web"),
                     HeaderName::from_static("x-user-agent"),
+                    HeaderName::from_static(READ_PRIMARY_HEADER),
                 ])
                 .allow_methods([Method::POST])
                 .allow_origin(AllowOrigin::any())
@@ -145,7 +396,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
         .add_service(tonic_web::enable(reflection_service))
         .add_service(tonic_web::enable(health_service))
-        .add_service(tonic_web::enable(website_service))
+        .add_service(tonic_web::enable(WebsiteServiceServer::from_arc(
+            website_service,
+        )))
         .add_service(tonic_web::enable(customization_service))
         .add_service(tonic_web::enable(domain_service))
         .add_service(tonic_web::enable(page_service))
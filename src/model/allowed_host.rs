@@ -0,0 +1,67 @@
+use deadpool_postgres::GenericClient;
+use sea_query::{Expr, Iden, OnConflict, PostgresQueryBuilder, Query};
+use sea_query_postgres::PostgresBinder;
+
+use crate::db::DbError;
+
+#[derive(Debug, Clone, Copy, Iden)]
+#[iden(rename = "allowed_hosts")]
+pub enum AllowedHostIden {
+    Table,
+    Hostname,
+}
+
+/// Hostnames an on-demand TLS edge (e.g. Caddy) may request certificates for.
+#[derive(Debug, Clone)]
+pub struct AllowedHost;
+
+impl AllowedHost {
+    pub async fn create(
+        client: &impl GenericClient,
+        hostname: &str,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(AllowedHostIden::Table)
+            .columns([AllowedHostIden::Hostname])
+            .values([hostname.into()])?
+            .on_conflict(
+                OnConflict::column(AllowedHostIden::Hostname)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    pub async fn exists(
+        client: &impl GenericClient,
+        hostname: &str,
+    ) -> Result<bool, DbError> {
+        let (sql, values) = Query::select()
+            .column(AllowedHostIden::Hostname)
+            .from(AllowedHostIden::Table)
+            .cond_where(Expr::col(AllowedHostIden::Hostname).eq(hostname))
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.is_some())
+    }
+
+    pub async fn delete(
+        client: &impl GenericClient,
+        hostname: &str,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(AllowedHostIden::Table)
+            .cond_where(Expr::col(AllowedHostIden::Hostname).eq(hostname))
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+}
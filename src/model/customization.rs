@@ -1,5 +1,6 @@
+use chrono::{DateTime, Utc};
 use deadpool_postgres::tokio_postgres::{Error, Row};
-use deadpool_postgres::Pool;
+use deadpool_postgres::GenericClient;
 use sea_query::{
     all, Asterisk, Expr, Iden, PostgresQueryBuilder, Query, SelectStatement,
 };
@@ -18,6 +19,8 @@ pub enum CustomizationIden {
     PrimaryColor,
     SecondaryColor,
     LogoImageUrl,
+    BrandingSyncAt,
+    BrandingSyncAttempts,
 }
 
 #[derive(Debug, Clone)]
@@ -27,16 +30,16 @@ pub struct Customization {
     pub primary_color: Option<String>,
     pub secondary_color: Option<String>,
     pub logo_image_url: Option<String>,
+    pub branding_sync_at: Option<DateTime<Utc>>,
+    pub branding_sync_attempts: i64,
 }
 
 impl Customization {
     pub async fn create(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::insert()
             .into_table(CustomizationIden::Table)
             .columns([CustomizationIden::WebsiteId, CustomizationIden::UserId])
@@ -44,35 +47,31 @@ impl Customization {
             .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
     pub async fn get(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(CustomizationIden::Table)
             .cond_where(Expr::col(CustomizationIden::WebsiteId).eq(website_id))
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn get_for_user(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(CustomizationIden::Table)
@@ -82,25 +81,28 @@ impl Customization {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn update(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
         primary_color: Option<String>,
         secondary_color: Option<String>,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::update()
             .table(CustomizationIden::Table)
             .values([
                 (CustomizationIden::PrimaryColor, primary_color.into()),
                 (CustomizationIden::SecondaryColor, secondary_color.into()),
+                (
+                    CustomizationIden::BrandingSyncAt,
+                    Expr::current_timestamp().into(),
+                ),
+                (CustomizationIden::BrandingSyncAttempts, 0i64.into()),
             ])
             .cond_where(all![
                 Expr::col(CustomizationIden::WebsiteId).eq(website_id),
@@ -109,22 +111,22 @@ impl Customization {
             .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
     pub async fn update_logo_image(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
         logo_image_url: Option<String>,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::update()
             .table(CustomizationIden::Table)
             .value(CustomizationIden::LogoImageUrl, logo_image_url)
+            .value(CustomizationIden::BrandingSyncAt, Expr::current_timestamp())
+            .value(CustomizationIden::BrandingSyncAttempts, 0i64)
             .cond_where(all![
                 Expr::col(CustomizationIden::WebsiteId).eq(website_id),
                 Expr::col(CustomizationIden::UserId).eq(user_id)
@@ -132,18 +134,104 @@ impl Customization {
             .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
+    /// Lists customizations whose branding is due to be pushed to the identity
+    /// provider at `now`.
+    pub async fn list_branding_due(
+        client: &impl GenericClient,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Self>, DbError> {
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(CustomizationIden::Table)
+            .cond_where(Expr::col(CustomizationIden::BrandingSyncAt).lte(now))
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = client.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.iter().map(Self::from).collect())
+    }
+
+    /// Postpones the branding sync from `sync_at` to `lease_until` and counts
+    /// the attempt. Returns `None` if the sync was already claimed by another
+    /// replica or the customization changed in the meantime.
+    pub async fn claim_branding_sync(
+        client: &impl GenericClient,
+        website_id: &String,
+        sync_at: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Option<Self>, DbError> {
+        let (sql, values) = Query::update()
+            .table(CustomizationIden::Table)
+            .value(CustomizationIden::BrandingSyncAt, lease_until)
+            .value(
+                CustomizationIden::BrandingSyncAttempts,
+                Expr::col(CustomizationIden::BrandingSyncAttempts).add(1i64),
+            )
+            .cond_where(all![
+                Expr::col(CustomizationIden::WebsiteId).eq(website_id),
+                Expr::col(CustomizationIden::BrandingSyncAt).eq(sync_at),
+            ])
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Marks the branding as synced, unless the customization changed while
+    /// syncing.
+    pub async fn finish_branding_sync(
+        client: &impl GenericClient,
+        website_id: &String,
+        lease_until: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::update()
+            .table(CustomizationIden::Table)
+            .value(CustomizationIden::BrandingSyncAt, None::<DateTime<Utc>>)
+            .value(CustomizationIden::BrandingSyncAttempts, 0i64)
+            .cond_where(all![
+                Expr::col(CustomizationIden::WebsiteId).eq(website_id),
+                Expr::col(CustomizationIden::BrandingSyncAt).eq(lease_until),
+            ])
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    /// Moves a failed branding sync from `lease_until` to `retry_at`.
+    pub async fn retry_branding_sync(
+        client: &impl GenericClient,
+        website_id: &String,
+        lease_until: DateTime<Utc>,
+        retry_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::update()
+            .table(CustomizationIden::Table)
+            .value(CustomizationIden::BrandingSyncAt, retry_at)
+            .cond_where(all![
+                Expr::col(CustomizationIden::WebsiteId).eq(website_id),
+                Expr::col(CustomizationIden::BrandingSyncAt).eq(lease_until),
+            ])
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
     pub async fn delete(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
     ) -> Result<(), DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::delete()
             .from_table(CustomizationIden::Table)
             .cond_where(all![
@@ -152,7 +240,7 @@ impl Customization {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        conn.query(sql.as_str(), &values.as_params()).await?;
+        client.query(sql.as_str(), &values.as_params()).await?;
 
         Ok(())
     }
@@ -170,6 +258,11 @@ impl From<&Row> for Customization {
                 .get(CustomizationIden::SecondaryColor.to_string().as_str()),
             logo_image_url: row
                 .get(CustomizationIden::LogoImageUrl.to_string().as_str()),
+            branding_sync_at: row
+                .get(CustomizationIden::BrandingSyncAt.to_string().as_str()),
+            branding_sync_attempts: row.get(
+                CustomizationIden::BrandingSyncAttempts.to_string().as_str(),
+            ),
         }
     }
 }
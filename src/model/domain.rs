@@ -1,16 +1,19 @@
 use chrono::{DateTime, Utc};
-use deadpool_postgres::tokio_postgres::types::{private, FromSql, Type};
+use deadpool_postgres::tokio_postgres::types::private::BytesMut;
+use deadpool_postgres::tokio_postgres::types::{
+    to_sql_checked, FromSql, IsNull, ToSql, Type,
+};
 use deadpool_postgres::tokio_postgres::Row;
-use deadpool_postgres::Pool;
-use fallible_iterator::FallibleIterator;
-use postgres_protocol::types;
+use deadpool_postgres::GenericClient;
 use sea_query::{
-    all, Alias, Asterisk, Expr, Func, Iden, JoinType, PostgresQueryBuilder,
-    Query, SelectStatement,
+    all, Alias, Asterisk, Expr, Func, Iden, Order, PostgresQueryBuilder, Query,
+    SelectStatement, SimpleExpr,
 };
 use sea_query_postgres::PostgresBinder;
+use serde::{de, Deserialize, Deserializer};
 
-use crate::db::{get_type_from_oid, ArrayAgg, DbError};
+use crate::api::sited_io::websites::v1::DomainStatus;
+use crate::db::{get_count_from_rows, json_object, DbError, JsonbAgg};
 
 use super::webiste::WebsiteIden;
 
@@ -25,6 +28,60 @@ pub enum DomainIden {
     UpdatedAt,
     Domain,
     Status,
+    LastCheckedAt,
+    LastCheckSucceeded,
+    LastCheckMessage,
+    DegradedAt,
+    DomainUnicode,
+}
+
+/// Postgres enum type of `domains.status`, labeled with the proto names.
+const DOMAIN_STATUS_TYPE: &str = "domain_status";
+
+impl ToSql for DomainStatus {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        if *self == Self::Unspecified {
+            return Err("[DomainStatus::ToSql]: unspecified status".into());
+        }
+        self.as_str_name().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == DOMAIN_STATUS_TYPE
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for DomainStatus {
+    fn from_sql(
+        _: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let name = std::str::from_utf8(raw)?;
+        Self::from_str_name(name)
+            .filter(|status| *status != Self::Unspecified)
+            .ok_or_else(|| {
+                format!("[DomainStatus::FromSql]: unknown status '{}'", name)
+                    .into()
+            })
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == DOMAIN_STATUS_TYPE
+    }
+}
+
+impl From<DomainStatus> for SimpleExpr {
+    fn from(status: DomainStatus) -> Self {
+        // inlined, since a bound parameter would be inferred as the enum type
+        Expr::expr(SimpleExpr::Constant(status.as_str_name().into()))
+            .as_enum(Alias::new(DOMAIN_STATUS_TYPE))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,48 +92,52 @@ pub struct Domain {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub domain: String,
-    pub status: String,
+    pub status: DomainStatus,
+    pub last_checked_at: Option<DateTime<Utc>>,
+    pub last_check_succeeded: Option<bool>,
+    pub last_check_message: Option<String>,
+    pub degraded_at: Option<DateTime<Utc>>,
+    pub domain_unicode: Option<String>,
 }
 
 impl Domain {
     pub async fn create(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
         domain: &String,
-        status: &'static str,
+        domain_unicode: &String,
+        status: DomainStatus,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::insert()
             .into_table(DomainIden::Table)
             .columns([
                 DomainIden::WebsiteId,
                 DomainIden::UserId,
                 DomainIden::Domain,
+                DomainIden::DomainUnicode,
                 DomainIden::Status,
             ])
             .values([
                 website_id.into(),
                 user_id.into(),
                 domain.into(),
+                domain_unicode.into(),
                 status.into(),
             ])?
             .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
     pub async fn get_for_user(
-        pool: &Pool,
+        client: &impl GenericClient,
         domain_id: i64,
         user_id: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(DomainIden::Table)
@@ -86,18 +147,16 @@ impl Domain {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn get_for_website(
-        pool: &Pool,
+        client: &impl GenericClient,
         domain: &String,
         website_id: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(DomainIden::Table)
@@ -107,35 +166,31 @@ impl Domain {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn get_by_domain(
-        pool: &Pool,
+        client: &impl GenericClient,
         domain: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(DomainIden::Table)
             .cond_where(Expr::col(DomainIden::Domain).eq(domain))
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn get_by_domain_and_status(
-        pool: &Pool,
+        client: &impl GenericClient,
         domain: &String,
-        status: &'static str,
+        status: DomainStatus,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(DomainIden::Table)
@@ -145,37 +200,77 @@ impl Domain {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn list_by_status(
-        pool: &Pool,
-        status: &'static str,
+        client: &impl GenericClient,
+        status: DomainStatus,
     ) -> Result<Vec<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(DomainIden::Table)
             .cond_where(Expr::col(DomainIden::Status).eq(status))
             .build_postgres(PostgresQueryBuilder);
 
-        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+        let rows = client.query(sql.as_str(), &values.as_params()).await?;
 
         Ok(rows.iter().map(Self::from).collect())
     }
 
+    pub async fn list(
+        client: &impl GenericClient,
+        website_id: &String,
+        user_id: &String,
+        status: Option<DomainStatus>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Self>, i64), DbError> {
+        let ((sql, values), (count_sql, count_values)) = {
+            let mut query = Query::select();
+
+            query.from(DomainIden::Table).cond_where(all![
+                Expr::col(DomainIden::WebsiteId).eq(website_id),
+                Expr::col(DomainIden::UserId).eq(user_id)
+            ]);
+
+            if let Some(status) = status {
+                query.and_where(Expr::col(DomainIden::Status).eq(status));
+            }
+
+            (
+                query
+                    .clone()
+                    .column(Asterisk)
+                    .order_by(DomainIden::DomainId, Order::Asc)
+                    .limit(limit)
+                    .offset(offset)
+                    .build_postgres(PostgresQueryBuilder),
+                query
+                    .expr(Expr::col(Asterisk).count())
+                    .build_postgres(PostgresQueryBuilder),
+            )
+        };
+
+        let rows = client.query(sql.as_str(), &values.as_params()).await?;
+        let count_rows = client
+            .query(count_sql.as_str(), &count_values.as_params())
+            .await?;
+
+        let count = get_count_from_rows(&count_rows);
+
+        Ok((rows.iter().map(Self::from).collect(), count))
+    }
+
     pub async fn update(
-        pool: &Pool,
+        client: &impl GenericClient,
         domain_id: i64,
         website_id: &String,
         user_id: &String,
-        status: &'static str,
+        status: DomainStatus,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::update()
             .table(DomainIden::Table)
             .value(DomainIden::Status, status)
@@ -187,18 +282,62 @@ impl Domain {
             .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
+
+        Ok(Self::from(row))
+    }
+
+    pub async fn update_last_check(
+        client: &impl GenericClient,
+        domain_id: i64,
+        succeeded: bool,
+        message: &String,
+    ) -> Result<Self, DbError> {
+        let (sql, values) = Query::update()
+            .table(DomainIden::Table)
+            .value(DomainIden::LastCheckedAt, Expr::current_timestamp())
+            .value(DomainIden::LastCheckSucceeded, succeeded)
+            .value(DomainIden::LastCheckMessage, message)
+            .cond_where(Expr::col(DomainIden::DomainId).eq(domain_id))
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
+    /// Moves domain from one status to another. Returns `None` if the domain
+    /// was not in `from_status` anymore, e.g. because another replica already
+    /// moved it.
+    pub async fn update_check_status(
+        client: &impl GenericClient,
+        domain_id: i64,
+        from_status: DomainStatus,
+        to_status: DomainStatus,
+        degraded_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Self>, DbError> {
+        let (sql, values) = Query::update()
+            .table(DomainIden::Table)
+            .value(DomainIden::Status, to_status)
+            .value(DomainIden::DegradedAt, degraded_at)
+            .cond_where(all![
+                Expr::col(DomainIden::DomainId).eq(domain_id),
+                Expr::col(DomainIden::Status).eq(from_status),
+            ])
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
     pub async fn delete_for_website(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
     ) -> Result<(), DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::delete()
             .from_table(DomainIden::Table)
             .cond_where(all![
@@ -207,19 +346,17 @@ impl Domain {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        conn.query(sql.as_str(), &values.as_params()).await?;
+        client.query(sql.as_str(), &values.as_params()).await?;
 
         Ok(())
     }
 
     pub async fn delete(
-        pool: &Pool,
+        client: &impl GenericClient,
         domain_id: i64,
         website_id: &String,
         user_id: &String,
     ) -> Result<(), DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::delete()
             .from_table(DomainIden::Table)
             .cond_where(all![
@@ -229,7 +366,7 @@ impl Domain {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        conn.query(sql.as_str(), &values.as_params()).await?;
+        client.query(sql.as_str(), &values.as_params()).await?;
 
         Ok(())
     }
@@ -245,6 +382,15 @@ impl From<&Row> for Domain {
             updated_at: row.get(DomainIden::UpdatedAt.to_string().as_str()),
             domain: row.get(DomainIden::Domain.to_string().as_str()),
             status: row.get(DomainIden::Status.to_string().as_str()),
+            last_checked_at: row
+                .get(DomainIden::LastCheckedAt.to_string().as_str()),
+            last_check_succeeded: row
+                .get(DomainIden::LastCheckSucceeded.to_string().as_str()),
+            last_check_message: row
+                .get(DomainIden::LastCheckMessage.to_string().as_str()),
+            degraded_at: row.get(DomainIden::DegradedAt.to_string().as_str()),
+            domain_unicode: row
+                .get(DomainIden::DomainUnicode.to_string().as_str()),
         }
     }
 }
@@ -255,76 +401,65 @@ impl From<Row> for Domain {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct DomainAsRel {
     pub domain_id: i64,
     pub domain: String,
-    pub status: String,
+    #[serde(deserialize_with = "deserialize_status")]
+    pub status: DomainStatus,
+    pub domain_unicode: Option<String>,
 }
 
 impl DomainAsRel {
-    pub fn add_join(query: &mut SelectStatement, alias: Alias) {
-        query
-            .column((DomainIden::Table, alias.clone()))
-            .join_subquery(
-                JoinType::LeftJoin,
-                Query::select()
-                    .column(DomainIden::WebsiteId)
-                    .expr_as(
-                        Func::cust(ArrayAgg).args([Expr::tuple([
+    /// Selects the domains of each website as a JSON array, aggregated in a
+    /// correlated subquery so only the domains of the selected websites are
+    /// built.
+    pub fn add_subquery(query: &mut SelectStatement, alias: Alias) {
+        query.expr_as(
+            SimpleExpr::SubQuery(
+                None,
+                Box::new(
+                    Query::select()
+                        .expr(Func::cust(JsonbAgg).arg(json_object(
+                            DomainIden::Table,
+                            [
+                                DomainIden::DomainId,
+                                DomainIden::Domain,
+                                DomainIden::Status,
+                                DomainIden::DomainUnicode,
+                            ],
+                        )))
+                        .from(DomainIden::Table)
+                        .and_where(
                             Expr::col((
                                 DomainIden::Table,
-                                DomainIden::DomainId,
+                                DomainIden::WebsiteId,
                             ))
-                            .into(),
-                            Expr::col((DomainIden::Table, DomainIden::Domain))
-                                .into(),
-                            Expr::col((DomainIden::Table, DomainIden::Status))
-                                .into(),
-                        ])
-                        .into()]),
-                        alias.clone(),
-                    )
-                    .from(DomainIden::Table)
-                    .group_by_col(DomainIden::WebsiteId)
-                    .take(),
-                alias.clone(),
-                Expr::col((WebsiteIden::Table, WebsiteIden::WebsiteId))
-                    .equals((DomainIden::Table, DomainIden::WebsiteId)),
-            )
-            .group_by_col((DomainIden::Table, alias));
+                            .equals((
+                                WebsiteIden::Table,
+                                WebsiteIden::WebsiteId,
+                            )),
+                        )
+                        .take()
+                        .into_sub_query_statement(),
+                ),
+            ),
+            alias,
+        );
     }
 }
 
-impl<'a> FromSql<'a> for DomainAsRel {
-    fn accepts(ty: &Type) -> bool {
-        matches!(*ty, Type::RECORD)
-    }
-
-    fn from_sql(
-        _: &Type,
-        mut raw: &'a [u8],
-    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
-        private::read_be_i32(&mut raw)?;
-
-        let oid = private::read_be_i32(&mut raw)?;
-        let ty = get_type_from_oid::<i64>(oid)?;
-        let domain_id: i64 = private::read_value(&ty, &mut raw)?;
-
-        let oid = private::read_be_i32(&mut raw)?;
-        let ty = get_type_from_oid::<String>(oid)?;
-        let domain: String = private::read_value(&ty, &mut raw)?;
-
-        let oid = private::read_be_i32(&mut raw)?;
-        let ty = get_type_from_oid::<String>(oid)?;
-        let status: String = private::read_value(&ty, &mut raw)?;
-
-        Ok(Self {
-            domain_id,
-            domain,
-            status,
+/// Enum values are aggregated with their labels, the proto names.
+fn deserialize_status<'de, D>(deserializer: D) -> Result<DomainStatus, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    DomainStatus::from_str_name(&name)
+        .filter(|status| *status != DomainStatus::Unspecified)
+        .ok_or_else(|| {
+            de::Error::custom(format!("unknown domain status '{}'", name))
         })
-    }
 }
 
 impl From<Domain> for DomainAsRel {
@@ -333,40 +468,7 @@ impl From<Domain> for DomainAsRel {
             domain_id: domain.domain_id,
             domain: domain.domain,
             status: domain.status,
+            domain_unicode: domain.domain_unicode,
         }
     }
 }
-
-pub struct DomainAsRelVec(pub Vec<DomainAsRel>);
-
-impl<'a> FromSql<'a> for DomainAsRelVec {
-    fn accepts(ty: &Type) -> bool {
-        match *ty {
-            Type::RECORD_ARRAY => true,
-            _ => {
-                tracing::log::error!("[DomainAsRelVec::FromSql::accepts]: postgres type {:?} not implemented", ty);
-                false
-            }
-        }
-    }
-
-    fn from_sql(
-        _: &Type,
-        raw: &'a [u8],
-    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
-        let array = types::array_from_sql(raw)?;
-
-        if array.dimensions().count()? > 1 {
-            return Err("[DomainAsRelVec::FromSql::from_sql]: array contains too many dimensions".into());
-        }
-
-        Ok(Self(
-            array
-                .values()
-                .filter_map(|v| {
-                    Ok(DomainAsRel::from_sql_nullable(&Type::RECORD, v).ok())
-                })
-                .collect()?,
-        ))
-    }
-}
@@ -1,11 +1,17 @@
+mod allowed_host;
 mod customization;
 mod domain;
+mod outbox;
 mod page;
 mod static_page;
+mod user_deletion;
 mod webiste;
 
+pub use allowed_host::AllowedHost;
 pub use customization::{Customization, CustomizationAsRel};
 pub use domain::{Domain, DomainAsRel};
+pub use outbox::OutboxMessage;
 pub use page::{Page, PageAsRel};
 pub use static_page::StaticPage;
+pub use user_deletion::UserDeletion;
 pub use webiste::Website;
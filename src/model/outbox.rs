@@ -0,0 +1,271 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::GenericClient;
+use sea_query::{
+    all, any, Alias, Asterisk, Expr, Iden, Order, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+
+use crate::db::DbError;
+
+#[derive(Debug, Clone, Copy, Iden)]
+#[iden(rename = "outbox")]
+pub enum OutboxIden {
+    Table,
+    OutboxId,
+    WebsiteId,
+    Subject,
+    MessageId,
+    Payload,
+    Status,
+    Attempts,
+    NextAttemptAt,
+    LockedUntil,
+    LastError,
+    DeliveredAt,
+}
+
+/// A message waiting to be published to NATS, written in the same
+/// transaction as the change it describes.
+#[derive(Debug, Clone)]
+pub struct OutboxMessage {
+    pub outbox_id: i64,
+    pub website_id: String,
+    pub subject: String,
+    pub message_id: String,
+    pub payload: Vec<u8>,
+    pub attempts: i64,
+    pub next_attempt_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl OutboxMessage {
+    pub const STATUS_PENDING: &'static str = "PENDING";
+    pub const STATUS_DELIVERED: &'static str = "DELIVERED";
+    /// Given up after too many failed attempts, kept for inspection until
+    /// `OutboxRelay` deletes it.
+    pub const STATUS_DEAD: &'static str = "DEAD";
+
+    pub async fn create(
+        client: &impl GenericClient,
+        website_id: &str,
+        subject: &str,
+        message_id: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(OutboxIden::Table)
+            .columns([
+                OutboxIden::WebsiteId,
+                OutboxIden::Subject,
+                OutboxIden::MessageId,
+                OutboxIden::Payload,
+            ])
+            .values([
+                website_id.into(),
+                subject.into(),
+                message_id.into(),
+                payload.into(),
+            ])?
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    /// Lists the oldest pending messages that can be delivered at `now`.
+    /// Websites whose oldest pending message waits for a retry or is claimed
+    /// by another relay are skipped with all their messages, so they do not
+    /// fill the batch.
+    pub async fn list_pending(
+        client: &impl GenericClient,
+        now: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<Self>, DbError> {
+        let earlier = Alias::new("earlier");
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(OutboxIden::Table)
+            .cond_where(all![
+                Expr::col(OutboxIden::Status).eq(Self::STATUS_PENDING),
+                Expr::exists(
+                    Query::select()
+                        .expr(Expr::val(1))
+                        .from_as(OutboxIden::Table, earlier.clone())
+                        .cond_where(all![
+                            Expr::col((earlier.clone(), OutboxIden::WebsiteId))
+                                .equals((
+                                    OutboxIden::Table,
+                                    OutboxIden::WebsiteId
+                                )),
+                            Expr::col((earlier.clone(), OutboxIden::OutboxId))
+                                .lte(Expr::col((
+                                    OutboxIden::Table,
+                                    OutboxIden::OutboxId
+                                ))),
+                            Expr::col((earlier.clone(), OutboxIden::Status))
+                                .eq(Self::STATUS_PENDING),
+                            any![
+                                Expr::col((
+                                    earlier.clone(),
+                                    OutboxIden::NextAttemptAt
+                                ))
+                                .gt(now),
+                                Expr::col((earlier, OutboxIden::LockedUntil))
+                                    .gt(now),
+                            ],
+                        ])
+                        .to_owned()
+                )
+                .not(),
+            ])
+            .order_by(OutboxIden::OutboxId, Order::Asc)
+            .limit(limit)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = client.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.iter().map(Self::from).collect())
+    }
+
+    /// Locks a pending message until `lease_until`. Returns false if it was
+    /// delivered or claimed by another relay since it was listed.
+    pub async fn claim(
+        client: &impl GenericClient,
+        outbox_id: i64,
+        locked_until: Option<DateTime<Utc>>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        let is_unchanged = match locked_until {
+            Some(locked_until) => {
+                Expr::col(OutboxIden::LockedUntil).eq(locked_until)
+            }
+            None => Expr::col(OutboxIden::LockedUntil).is_null(),
+        };
+
+        let (sql, values) = Query::update()
+            .table(OutboxIden::Table)
+            .value(OutboxIden::LockedUntil, lease_until)
+            .cond_where(all![
+                Expr::col(OutboxIden::OutboxId).eq(outbox_id),
+                Expr::col(OutboxIden::Status).eq(Self::STATUS_PENDING),
+                is_unchanged,
+            ])
+            .build_postgres(PostgresQueryBuilder);
+
+        let updated = client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(updated == 1)
+    }
+
+    pub async fn mark_delivered(
+        client: &impl GenericClient,
+        outbox_id: i64,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::update()
+            .table(OutboxIden::Table)
+            .value(OutboxIden::Status, Self::STATUS_DELIVERED)
+            .value(OutboxIden::DeliveredAt, Expr::current_timestamp())
+            .value(OutboxIden::LockedUntil, None::<DateTime<Utc>>)
+            .value(
+                OutboxIden::Attempts,
+                Expr::col(OutboxIden::Attempts).add(1i64),
+            )
+            .cond_where(Expr::col(OutboxIden::OutboxId).eq(outbox_id))
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    /// Records a failed attempt. The message is retried at `next_attempt_at`,
+    /// or moved to `STATUS_DEAD` if `next_attempt_at` is `None`.
+    pub async fn mark_failed(
+        client: &impl GenericClient,
+        outbox_id: i64,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DbError> {
+        let (sql, values) = {
+            let mut query = Query::update();
+            query
+                .table(OutboxIden::Table)
+                .value(OutboxIden::LastError, error)
+                .value(OutboxIden::LockedUntil, None::<DateTime<Utc>>)
+                .value(
+                    OutboxIden::Attempts,
+                    Expr::col(OutboxIden::Attempts).add(1i64),
+                )
+                .cond_where(Expr::col(OutboxIden::OutboxId).eq(outbox_id));
+
+            match next_attempt_at {
+                Some(next_attempt_at) => {
+                    query.value(OutboxIden::NextAttemptAt, next_attempt_at)
+                }
+                None => query.value(OutboxIden::Status, Self::STATUS_DEAD),
+            };
+
+            query.build_postgres(PostgresQueryBuilder)
+        };
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_delivered_before(
+        client: &impl GenericClient,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(OutboxIden::Table)
+            .cond_where(all![
+                Expr::col(OutboxIden::Status).eq(Self::STATUS_DELIVERED),
+                Expr::col(OutboxIden::DeliveredAt).lt(before),
+            ])
+            .build_postgres(PostgresQueryBuilder);
+
+        Ok(client.execute(sql.as_str(), &values.as_params()).await?)
+    }
+
+    /// Deletes the dead messages whose last attempt was before `before`.
+    pub async fn delete_dead_before(
+        client: &impl GenericClient,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(OutboxIden::Table)
+            .cond_where(all![
+                Expr::col(OutboxIden::Status).eq(Self::STATUS_DEAD),
+                Expr::col(OutboxIden::NextAttemptAt).lt(before),
+            ])
+            .build_postgres(PostgresQueryBuilder);
+
+        Ok(client.execute(sql.as_str(), &values.as_params()).await?)
+    }
+}
+
+impl From<&Row> for OutboxMessage {
+    fn from(row: &Row) -> Self {
+        Self {
+            outbox_id: row.get(OutboxIden::OutboxId.to_string().as_str()),
+            website_id: row.get(OutboxIden::WebsiteId.to_string().as_str()),
+            subject: row.get(OutboxIden::Subject.to_string().as_str()),
+            message_id: row.get(OutboxIden::MessageId.to_string().as_str()),
+            payload: row.get(OutboxIden::Payload.to_string().as_str()),
+            attempts: row.get(OutboxIden::Attempts.to_string().as_str()),
+            next_attempt_at: row
+                .get(OutboxIden::NextAttemptAt.to_string().as_str()),
+            locked_until: row.get(OutboxIden::LockedUntil.to_string().as_str()),
+        }
+    }
+}
+
+impl From<Row> for OutboxMessage {
+    fn from(row: Row) -> Self {
+        Self::from(&row)
+    }
+}
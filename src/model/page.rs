@@ -1,18 +1,22 @@
 use chrono::{DateTime, Utc};
-use deadpool_postgres::tokio_postgres::types::{private, FromSql, Type};
+use deadpool_postgres::tokio_postgres::types::private::BytesMut;
+use deadpool_postgres::tokio_postgres::types::{
+    to_sql_checked, FromSql, IsNull, ToSql, Type,
+};
 use deadpool_postgres::tokio_postgres::Row;
-use deadpool_postgres::Pool;
-use fallible_iterator::FallibleIterator;
-use postgres_protocol::types;
+use deadpool_postgres::GenericClient;
 use sea_query::{
-    all, Alias, Asterisk, Expr, Func, Iden, JoinType, PostgresQueryBuilder,
-    Query, SelectStatement,
+    all, Alias, Asterisk, Expr, Func, Iden, PostgresQueryBuilder, Query,
+    SelectStatement, SimpleExpr,
 };
 use sea_query_postgres::PostgresBinder;
+use serde::{de, Deserialize, Deserializer};
 
-use crate::db::{get_count_from_rows, get_type_from_oid, ArrayAgg, DbError};
+use crate::api::sited_io::websites::v1::PageType;
+use crate::db::{get_count_from_rows, json_object, DbError, JsonbAgg};
 
 use super::webiste::WebsiteIden;
+use super::StaticPage;
 
 #[derive(Debug, Clone, Copy, Iden)]
 #[iden(rename = "pages")]
@@ -30,6 +34,55 @@ pub enum PageIden {
     Path,
 }
 
+/// Postgres enum type of `pages.page_type`, labeled with the proto names.
+const PAGE_TYPE_TYPE: &str = "page_type";
+
+impl ToSql for PageType {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        if *self == Self::Unspecified {
+            return Err("[PageType::ToSql]: unspecified page type".into());
+        }
+        self.as_str_name().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == PAGE_TYPE_TYPE
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for PageType {
+    fn from_sql(
+        _: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let name = std::str::from_utf8(raw)?;
+        Self::from_str_name(name)
+            .filter(|page_type| *page_type != Self::Unspecified)
+            .ok_or_else(|| {
+                format!("[PageType::FromSql]: unknown page type '{}'", name)
+                    .into()
+            })
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == PAGE_TYPE_TYPE
+    }
+}
+
+impl From<PageType> for SimpleExpr {
+    fn from(page_type: PageType) -> Self {
+        // inlined, since a bound parameter would be inferred as the enum type
+        Expr::expr(SimpleExpr::Constant(page_type.as_str_name().into()))
+            .as_enum(Alias::new(PAGE_TYPE_TYPE))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Page {
     pub page_id: i64,
@@ -37,7 +90,7 @@ pub struct Page {
     pub user_id: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-    pub page_type: String,
+    pub page_type: PageType,
     pub content_id: String,
     pub title: String,
     pub is_home_page: bool,
@@ -47,17 +100,15 @@ pub struct Page {
 impl Page {
     #[allow(clippy::too_many_arguments)]
     pub async fn create(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
-        page_type: &str,
+        page_type: PageType,
         content_id: &String,
         title: &String,
         is_home_page: bool,
         path: &String,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::insert()
             .into_table(PageIden::Table)
             .columns([
@@ -81,35 +132,31 @@ impl Page {
             .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
     pub async fn get(
-        pool: &Pool,
+        client: &impl GenericClient,
         page_id: i64,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(PageIden::Table)
             .cond_where(Expr::col(PageIden::PageId).eq(page_id))
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn get_by_path(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         path: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(PageIden::Table)
@@ -119,17 +166,15 @@ impl Page {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn get_home_page(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(PageIden::Table)
@@ -139,19 +184,37 @@ impl Page {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
+    /// Lists pages of all websites showing the given content, e.g. a shop.
+    pub async fn list_by_content(
+        client: &impl GenericClient,
+        page_type: PageType,
+        content_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(PageIden::Table)
+            .cond_where(all![
+                Expr::col(PageIden::PageType).eq(page_type),
+                Expr::col(PageIden::ContentId).eq(content_id)
+            ])
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = client.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.iter().map(Self::from).collect())
+    }
+
     pub async fn list(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: Option<String>,
         limit: u64,
         offset: u64,
     ) -> Result<(Vec<Self>, i64), DbError> {
-        let conn = pool.get().await?;
-
         let ((sql, values), (count_sql, count_values)) = {
             let mut query = Query::select();
 
@@ -174,8 +237,8 @@ impl Page {
             )
         };
 
-        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
-        let count_rows = conn
+        let rows = client.query(sql.as_str(), &values.as_params()).await?;
+        let count_rows = client
             .query(count_sql.as_str(), &count_values.as_params())
             .await?;
 
@@ -186,17 +249,15 @@ impl Page {
 
     #[allow(clippy::too_many_arguments)]
     pub async fn update(
-        pool: &Pool,
+        client: &impl GenericClient,
         page_id: i64,
         user_id: &String,
-        page_type: Option<&str>,
+        page_type: Option<PageType>,
         content_id: Option<String>,
         title: Option<String>,
         is_home_page: Option<bool>,
         path: Option<String>,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = {
             let mut query = Query::update();
             query.table(PageIden::Table);
@@ -230,18 +291,16 @@ impl Page {
                 .build_postgres(PostgresQueryBuilder)
         };
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
     pub async fn delete(
-        pool: &Pool,
+        client: &impl GenericClient,
         page_id: i64,
         user_id: &String,
     ) -> Result<(), DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::delete()
             .from_table(PageIden::Table)
             .cond_where(all![
@@ -250,27 +309,36 @@ impl Page {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        conn.query(sql.as_str(), &values.as_params()).await?;
+        client.query(sql.as_str(), &values.as_params()).await?;
 
         Ok(())
     }
 
+    /// Deletes the page together with its static page, if any. Must be run
+    /// within a transaction.
+    pub async fn delete_with_static_page(
+        client: &impl GenericClient,
+        page_id: i64,
+        user_id: &String,
+    ) -> Result<(), DbError> {
+        StaticPage::delete(client, page_id, user_id).await?;
+        Self::delete(client, page_id, user_id).await
+    }
+
     pub async fn delete_for_website(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
     ) -> Result<(), DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::delete()
             .from_table(PageIden::Table)
             .cond_where(all![
-                Expr::col(WebsiteIden::WebsiteId).eq(website_id),
-                Expr::col(WebsiteIden::UserId).eq(user_id)
+                Expr::col(PageIden::WebsiteId).eq(website_id),
+                Expr::col(PageIden::UserId).eq(user_id)
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        conn.query(sql.as_str(), &values.as_params()).await?;
+        client.query(sql.as_str(), &values.as_params()).await?;
 
         Ok(())
     }
@@ -299,10 +367,11 @@ impl From<Row> for Page {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct PageAsRel {
     pub page_id: i64,
-    pub page_type: String,
+    #[serde(deserialize_with = "deserialize_page_type")]
+    pub page_type: PageType,
     pub content_id: String,
     pub title: String,
     pub is_home_page: bool,
@@ -310,85 +379,54 @@ pub struct PageAsRel {
 }
 
 impl PageAsRel {
-    pub fn add_join(query: &mut SelectStatement, alias: Alias) {
-        query
-            .column((PageIden::Table, alias.clone()))
-            .join_subquery(
-                JoinType::LeftJoin,
-                Query::select()
-                    .column(PageIden::WebsiteId)
-                    .expr_as(
-                        Func::cust(ArrayAgg).args([Expr::tuple([
-                            Expr::col((PageIden::Table, PageIden::PageId))
-                                .into(),
-                            Expr::col((PageIden::Table, PageIden::PageType))
-                                .into(),
-                            Expr::col((PageIden::Table, PageIden::ContentId))
-                                .into(),
-                            Expr::col((PageIden::Table, PageIden::Title))
-                                .into(),
-                            Expr::col((PageIden::Table, PageIden::IsHomePage))
-                                .into(),
-                            Expr::col((PageIden::Table, PageIden::Path)).into(),
-                        ])
-                        .into()]),
-                        alias.clone(),
-                    )
-                    .from(PageIden::Table)
-                    .group_by_col(PageIden::WebsiteId)
-                    .take(),
-                alias.clone(),
-                Expr::col((WebsiteIden::Table, WebsiteIden::WebsiteId))
-                    .equals((PageIden::Table, PageIden::WebsiteId)),
-            )
-            .group_by_col((PageIden::Table, alias));
+    /// Selects the pages of each website as a JSON array, aggregated in a
+    /// correlated subquery so only the pages of the selected websites are
+    /// built.
+    pub fn add_subquery(query: &mut SelectStatement, alias: Alias) {
+        query.expr_as(
+            SimpleExpr::SubQuery(
+                None,
+                Box::new(
+                    Query::select()
+                        .expr(Func::cust(JsonbAgg).arg(json_object(
+                            PageIden::Table,
+                            [
+                                PageIden::PageId,
+                                PageIden::PageType,
+                                PageIden::ContentId,
+                                PageIden::Title,
+                                PageIden::IsHomePage,
+                                PageIden::Path,
+                            ],
+                        )))
+                        .from(PageIden::Table)
+                        .and_where(
+                            Expr::col((PageIden::Table, PageIden::WebsiteId))
+                                .equals((
+                                    WebsiteIden::Table,
+                                    WebsiteIden::WebsiteId,
+                                )),
+                        )
+                        .take()
+                        .into_sub_query_statement(),
+                ),
+            ),
+            alias,
+        );
     }
 }
 
-impl<'a> FromSql<'a> for PageAsRel {
-    fn accepts(ty: &Type) -> bool {
-        matches!(*ty, Type::RECORD)
-    }
-
-    fn from_sql(
-        _: &Type,
-        mut raw: &'a [u8],
-    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
-        private::read_be_i32(&mut raw)?;
-
-        let oid = private::read_be_i32(&mut raw)?;
-        let ty = get_type_from_oid::<i64>(oid)?;
-        let page_id: i64 = private::read_value(&ty, &mut raw)?;
-
-        let oid = private::read_be_i32(&mut raw)?;
-        let ty = get_type_from_oid::<String>(oid)?;
-        let page_type: String = private::read_value(&ty, &mut raw)?;
-
-        let oid = private::read_be_i32(&mut raw)?;
-        let ty = get_type_from_oid::<String>(oid)?;
-        let content_id: String = private::read_value(&ty, &mut raw)?;
-
-        let oid = private::read_be_i32(&mut raw)?;
-        let ty = get_type_from_oid::<String>(oid)?;
-        let title: String = private::read_value(&ty, &mut raw)?;
-
-        let oid = private::read_be_i32(&mut raw)?;
-        let ty = get_type_from_oid::<bool>(oid)?;
-        let is_home_page: bool = private::read_value(&ty, &mut raw)?;
-
-        let oid = private::read_be_i32(&mut raw)?;
-        let ty = get_type_from_oid::<String>(oid)?;
-        let path: String = private::read_value(&ty, &mut raw)?;
-
-        Ok(Self {
-            page_id,
-            page_type,
-            content_id,
-            title,
-            is_home_page,
-            path,
+/// Enum values are aggregated with their labels, the proto names.
+fn deserialize_page_type<'de, D>(deserializer: D) -> Result<PageType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    PageType::from_str_name(&name)
+        .filter(|page_type| *page_type != PageType::Unspecified)
+        .ok_or_else(|| {
+            de::Error::custom(format!("unknown page type '{}'", name))
         })
-    }
 }
 
 impl From<Page> for PageAsRel {
@@ -403,31 +441,3 @@ impl From<Page> for PageAsRel {
         }
     }
 }
-
-pub struct PageAsRelVec(pub Vec<PageAsRel>);
-
-impl<'a> FromSql<'a> for PageAsRelVec {
-    fn accepts(ty: &Type) -> bool {
-        matches!(*ty, Type::RECORD_ARRAY)
-    }
-
-    fn from_sql(
-        _: &Type,
-        raw: &'a [u8],
-    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
-        let array = types::array_from_sql(raw)?;
-
-        if array.dimensions().count()? > 1 {
-            return Err("[PageAsRelVec::FromSql::from_sql]: array contains too many dimensions".into());
-        }
-
-        Ok(Self(
-            array
-                .values()
-                .filter_map(|v| {
-                    Ok(PageAsRel::from_sql_nullable(&Type::RECORD, v).ok())
-                })
-                .collect()?,
-        ))
-    }
-}
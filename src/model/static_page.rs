@@ -1,6 +1,6 @@
 use chrono::{DateTime, Utc};
 use deadpool_postgres::tokio_postgres::Row;
-use deadpool_postgres::Pool;
+use deadpool_postgres::GenericClient;
 use sea_query::{all, Asterisk, Expr, Iden, PostgresQueryBuilder, Query};
 use sea_query_postgres::PostgresBinder;
 use serde_json::Value;
@@ -31,14 +31,12 @@ pub struct StaticPage {
 
 impl StaticPage {
     pub async fn create(
-        pool: &Pool,
+        client: &impl GenericClient,
         page_id: i64,
         website_id: &String,
         user_id: &String,
         components: Value,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::insert()
             .into_table(StaticPageIden::Table)
             .columns([
@@ -56,36 +54,32 @@ impl StaticPage {
             .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
     pub async fn get(
-        pool: &Pool,
+        client: &impl GenericClient,
         page_id: i64,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(StaticPageIden::Table)
             .cond_where(Expr::col(StaticPageIden::PageId).eq(page_id))
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn update(
-        pool: &Pool,
+        client: &impl GenericClient,
         page_id: i64,
         user_id: &String,
         components: Value,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = {
             let mut query = Query::update();
             query.table(StaticPageIden::Table);
@@ -101,18 +95,16 @@ impl StaticPage {
                 .build_postgres(PostgresQueryBuilder)
         };
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
     pub async fn delete(
-        pool: &Pool,
+        client: &impl GenericClient,
         page_id: i64,
         user_id: &String,
     ) -> Result<(), DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::delete()
             .from_table(StaticPageIden::Table)
             .cond_where(all![
@@ -121,7 +113,25 @@ impl StaticPage {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        conn.query(sql.as_str(), &values.as_params()).await?;
+        client.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_for_website(
+        client: &impl GenericClient,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(StaticPageIden::Table)
+            .cond_where(all![
+                Expr::col(StaticPageIden::WebsiteId).eq(website_id),
+                Expr::col(StaticPageIden::UserId).eq(user_id)
+            ])
+            .build_postgres(PostgresQueryBuilder);
+
+        client.query(sql.as_str(), &values.as_params()).await?;
 
         Ok(())
     }
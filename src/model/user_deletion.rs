@@ -0,0 +1,148 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::GenericClient;
+use sea_query::{
+    all, Asterisk, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+
+use crate::db::DbError;
+
+#[derive(Debug, Clone, Copy, Iden)]
+#[iden(rename = "user_deletions")]
+pub enum UserDeletionIden {
+    Table,
+    UserId,
+    Attempts,
+    NextAttemptAt,
+    LastError,
+}
+
+/// A deleted user whose websites are still to be deleted, see
+/// `LifecycleConsumer`.
+#[derive(Debug, Clone)]
+pub struct UserDeletion {
+    pub user_id: String,
+    pub attempts: i64,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+impl UserDeletion {
+    /// Records the user, if not recorded yet. The first retry is made at
+    /// `next_attempt_at`.
+    pub async fn create(
+        client: &impl GenericClient,
+        user_id: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(UserDeletionIden::Table)
+            .columns([
+                UserDeletionIden::UserId,
+                UserDeletionIden::NextAttemptAt,
+            ])
+            .values([user_id.into(), next_attempt_at.into()])?
+            .on_conflict(
+                OnConflict::column(UserDeletionIden::UserId)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    /// Lists the users due for another attempt at `now`, oldest first.
+    pub async fn list_due(
+        client: &impl GenericClient,
+        now: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<Self>, DbError> {
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(UserDeletionIden::Table)
+            .cond_where(Expr::col(UserDeletionIden::NextAttemptAt).lte(now))
+            .order_by(UserDeletionIden::NextAttemptAt, Order::Asc)
+            .limit(limit)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = client.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.iter().map(Self::from).collect())
+    }
+
+    /// Postpones the next attempt to `lease_until`. Returns false if another
+    /// replica claimed the user since it was listed, or it was deleted.
+    pub async fn claim(
+        client: &impl GenericClient,
+        user_id: &str,
+        next_attempt_at: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        let (sql, values) = Query::update()
+            .table(UserDeletionIden::Table)
+            .value(UserDeletionIden::NextAttemptAt, lease_until)
+            .cond_where(all![
+                Expr::col(UserDeletionIden::UserId).eq(user_id),
+                Expr::col(UserDeletionIden::NextAttemptAt).eq(next_attempt_at),
+            ])
+            .build_postgres(PostgresQueryBuilder);
+
+        let updated = client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(updated == 1)
+    }
+
+    /// Records a failed attempt, the next one is made at `next_attempt_at`.
+    pub async fn mark_failed(
+        client: &impl GenericClient,
+        user_id: &str,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::update()
+            .table(UserDeletionIden::Table)
+            .value(UserDeletionIden::LastError, error)
+            .value(UserDeletionIden::NextAttemptAt, next_attempt_at)
+            .value(
+                UserDeletionIden::Attempts,
+                Expr::col(UserDeletionIden::Attempts).add(1i64),
+            )
+            .cond_where(Expr::col(UserDeletionIden::UserId).eq(user_id))
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(
+        client: &impl GenericClient,
+        user_id: &str,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(UserDeletionIden::Table)
+            .cond_where(Expr::col(UserDeletionIden::UserId).eq(user_id))
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+}
+
+impl From<&Row> for UserDeletion {
+    fn from(row: &Row) -> Self {
+        Self {
+            user_id: row.get(UserDeletionIden::UserId.to_string().as_str()),
+            attempts: row.get(UserDeletionIden::Attempts.to_string().as_str()),
+            next_attempt_at: row
+                .get(UserDeletionIden::NextAttemptAt.to_string().as_str()),
+            last_error: row
+                .get(UserDeletionIden::LastError.to_string().as_str()),
+        }
+    }
+}
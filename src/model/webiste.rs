@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::types::Json;
 use deadpool_postgres::tokio_postgres::Row;
-use deadpool_postgres::Pool;
+use deadpool_postgres::GenericClient;
 use sea_query::{
     all, Alias, Asterisk, Expr, Iden, PostgresQueryBuilder, Query,
     SelectStatement,
@@ -8,10 +9,12 @@ use sea_query::{
 use sea_query_postgres::PostgresBinder;
 
 use crate::db::{get_count_from_rows, DbError};
+use crate::identity::AppProject;
 
-use super::domain::{DomainAsRel, DomainAsRelVec};
-use super::page::PageAsRelVec;
-use super::{CustomizationAsRel, PageAsRel};
+use super::{
+    Customization, CustomizationAsRel, Domain, DomainAsRel, Page, PageAsRel,
+    StaticPage,
+};
 
 #[derive(Debug, Clone, Copy, Iden)]
 #[iden(rename = "websites")]
@@ -24,6 +27,8 @@ pub enum WebsiteIden {
     Name,
     ClientId,
     ZitadelAppId,
+    ZitadelOrgId,
+    ZitadelProjectId,
 }
 
 #[derive(Debug, Clone)]
@@ -35,6 +40,8 @@ pub struct Website {
     pub name: String,
     pub client_id: String,
     pub zitadel_app_id: String,
+    pub zitadel_org_id: Option<String>,
+    pub zitadel_project_id: Option<String>,
     pub customization: Option<CustomizationAsRel>,
     pub domains: Vec<DomainAsRel>,
     pub pages: Vec<PageAsRel>,
@@ -52,6 +59,14 @@ impl Website {
         Alias::new(Self::PAGES_ALIAS)
     }
 
+    /// Project of the ZITADEL app, `None` for the shared project.
+    pub fn zitadel_project(&self) -> Option<AppProject> {
+        Some(AppProject {
+            org_id: self.zitadel_org_id.clone()?,
+            project_id: self.zitadel_project_id.clone()?,
+        })
+    }
+
     fn select_with_relations() -> SelectStatement {
         let mut query = Query::select();
 
@@ -60,8 +75,8 @@ impl Website {
             .from(WebsiteIden::Table);
 
         CustomizationAsRel::add_join(&mut query);
-        DomainAsRel::add_join(&mut query, Self::get_domains_alias());
-        PageAsRel::add_join(&mut query, Self::get_pages_alias());
+        DomainAsRel::add_subquery(&mut query, Self::get_domains_alias());
+        PageAsRel::add_subquery(&mut query, Self::get_pages_alias());
 
         query.group_by_col((WebsiteIden::Table, WebsiteIden::WebsiteId));
 
@@ -79,15 +94,14 @@ impl Website {
     }
 
     pub async fn create(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
         name: &String,
         client_id: &String,
         zitadel_app_id: &String,
+        zitadel_project: &AppProject,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::insert()
             .into_table(WebsiteIden::Table)
             .columns([
@@ -96,6 +110,8 @@ impl Website {
                 WebsiteIden::Name,
                 WebsiteIden::ClientId,
                 WebsiteIden::ZitadelAppId,
+                WebsiteIden::ZitadelOrgId,
+                WebsiteIden::ZitadelProjectId,
             ])
             .values([
                 website_id.into(),
@@ -103,21 +119,21 @@ impl Website {
                 name.into(),
                 client_id.into(),
                 zitadel_app_id.into(),
+                zitadel_project.org_id.clone().into(),
+                zitadel_project.project_id.clone().into(),
             ])?
             .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
     pub async fn get(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Self::select_with_relations()
             .cond_where(
                 Expr::col((WebsiteIden::Table, WebsiteIden::WebsiteId))
@@ -125,19 +141,17 @@ impl Website {
             )
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     /// internal for check
     pub async fn get_for_user(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(WebsiteIden::Table)
@@ -147,19 +161,17 @@ impl Website {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     /// internal for check
     pub async fn get_by_name(
-        pool: &Pool,
+        client: &impl GenericClient,
         name: &String,
         user_id: &String,
     ) -> Result<Option<Self>, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::select()
             .column(Asterisk)
             .from(WebsiteIden::Table)
@@ -169,20 +181,17 @@ impl Website {
             ])
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_opt(sql.as_str(), &values.as_params()).await?;
 
         Ok(row.map(Self::from))
     }
 
     pub async fn list(
-        pool: &Pool,
+        client: &impl GenericClient,
         user_id: &Option<String>,
         limit: u64,
         offset: u64,
     ) -> Result<(Vec<Self>, i64), DbError> {
-        let mut conn = pool.get().await?;
-        let transaction = conn.transaction().await?;
-
         let ((sql, values), (count_sql, count_values)) = {
             let mut query = Self::select_with_relations();
             let mut count_query = Self::select_count();
@@ -204,25 +213,74 @@ impl Website {
             )
         };
 
-        let rows = transaction.query(sql.as_str(), &values.as_params()).await?;
-        let count_rows = transaction
+        let rows = client.query(sql.as_str(), &values.as_params()).await?;
+        let count_rows = client
             .query(count_sql.as_str(), &count_values.as_params())
             .await?;
-        transaction.commit().await?;
 
         let count = get_count_from_rows(&count_rows);
 
         Ok((rows.iter().map(Self::from).collect(), count))
     }
 
+    /// Lists all websites with their domains, used for reconciliation.
+    pub async fn list_all(
+        client: &impl GenericClient,
+    ) -> Result<Vec<Self>, DbError> {
+        let (sql, values) =
+            Self::select_with_relations().build_postgres(PostgresQueryBuilder);
+
+        let rows = client.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.iter().map(Self::from).collect())
+    }
+
+    pub async fn update_zitadel_app(
+        client: &impl GenericClient,
+        website_id: &String,
+        client_id: &String,
+        zitadel_app_id: &String,
+        project: Option<&AppProject>,
+    ) -> Result<Self, DbError> {
+        let (sql, values) = Query::update()
+            .table(WebsiteIden::Table)
+            .value(WebsiteIden::ClientId, client_id)
+            .value(WebsiteIden::ZitadelAppId, zitadel_app_id)
+            .value(WebsiteIden::ZitadelOrgId, project.map(|p| p.org_id.clone()))
+            .value(
+                WebsiteIden::ZitadelProjectId,
+                project.map(|p| p.project_id.clone()),
+            )
+            .cond_where(Expr::col(WebsiteIden::WebsiteId).eq(website_id))
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
+
+        Ok(Self::from(row))
+    }
+
+    pub async fn touch(
+        client: &impl GenericClient,
+        website_id: &String,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::update()
+            .table(WebsiteIden::Table)
+            .value(WebsiteIden::UpdatedAt, Expr::current_timestamp())
+            .cond_where(Expr::col(WebsiteIden::WebsiteId).eq(website_id))
+            .build_postgres(PostgresQueryBuilder);
+
+        client.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
     pub async fn update(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
         name: &Option<String>,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = {
             let mut query = Query::update();
             query.table(WebsiteIden::Table);
@@ -240,18 +298,16 @@ impl Website {
                 .build_postgres(PostgresQueryBuilder)
         };
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
 
     pub async fn delete(
-        pool: &Pool,
+        client: &impl GenericClient,
         website_id: &String,
         user_id: &String,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::delete()
             .from_table(WebsiteIden::Table)
             .and_where(Expr::col(WebsiteIden::WebsiteId).eq(website_id))
@@ -259,22 +315,39 @@ impl Website {
             .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+        let row = client.query_one(sql.as_str(), &values.as_params()).await?;
 
         Ok(Self::from(row))
     }
+
+    /// Deletes the website with its customization, domains, pages and
+    /// static pages. The foreign keys cascade as well, deleting the rows
+    /// explicitly keeps them scoped to the user. Must be run within a
+    /// transaction.
+    pub async fn delete_with_relations(
+        client: &impl GenericClient,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Self, DbError> {
+        StaticPage::delete_for_website(client, website_id, user_id).await?;
+        Page::delete_for_website(client, website_id, user_id).await?;
+        Domain::delete_for_website(client, website_id, user_id).await?;
+        Customization::delete(client, website_id, user_id).await?;
+
+        Self::delete(client, website_id, user_id).await
+    }
 }
 
 impl From<&Row> for Website {
     fn from(row: &Row) -> Self {
         let customization = CustomizationAsRel::try_from(row).ok();
         let domains = row
-            .try_get::<&str, DomainAsRelVec>(Self::DOMAINS_ALIAS)
+            .try_get::<&str, Json<Vec<DomainAsRel>>>(Self::DOMAINS_ALIAS)
             .ok()
             .map(|d| d.0)
             .unwrap_or_default();
         let pages = row
-            .try_get::<&str, PageAsRelVec>(Self::PAGES_ALIAS)
+            .try_get::<&str, Json<Vec<PageAsRel>>>(Self::PAGES_ALIAS)
             .ok()
             .map(|p| p.0)
             .unwrap_or_default();
@@ -288,6 +361,10 @@ impl From<&Row> for Website {
             client_id: row.get(WebsiteIden::ClientId.to_string().as_str()),
             zitadel_app_id: row
                 .get(WebsiteIden::ZitadelAppId.to_string().as_str()),
+            zitadel_org_id: row
+                .get(WebsiteIden::ZitadelOrgId.to_string().as_str()),
+            zitadel_project_id: row
+                .get(WebsiteIden::ZitadelProjectId.to_string().as_str()),
             customization,
             domains,
             pages,
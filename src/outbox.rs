@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::broker::{BrokerMessage, DynMessageBroker};
+use crate::db::DbError;
+use crate::model::OutboxMessage;
+use crate::repository::DynStore;
+
+/// Publishes the messages written to the outbox to the message broker.
+///
+/// Messages of one website are delivered in the order they were written: a
+/// message that fails, waits for a retry or is being delivered by another
+/// replica holds back all later messages of its website. Failed messages are
+/// retried with exponential backoff and moved to the dead-letter state after
+/// `max_attempts`, which releases the messages behind them. Delivered
+/// messages are deleted after `RETENTION`, dead messages `DEAD_RETENTION`
+/// after their last attempt.
+pub struct OutboxRelay {
+    store: DynStore,
+    broker: DynMessageBroker,
+    interval: Duration,
+    max_attempts: i64,
+}
+
+impl OutboxRelay {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+    pub const DEFAULT_MAX_ATTEMPTS: i64 = 10;
+    const BATCH_SIZE: u64 = 100;
+    const LEASE: Duration = Duration::from_secs(60);
+    const BASE_DELAY: Duration = Duration::from_secs(1);
+    const MAX_DELAY: Duration = Duration::from_secs(10 * 60);
+    const RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+    const DEAD_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+    const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    pub fn new(
+        store: DynStore,
+        broker: DynMessageBroker,
+        interval: Duration,
+        max_attempts: i64,
+    ) -> Self {
+        Self {
+            store,
+            broker,
+            interval,
+            max_attempts,
+        }
+    }
+
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            let mut cleanup = tokio::time::interval(Self::CLEANUP_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if let Err(err) = self.run_once().await {
+                            tracing::log::error!(
+                                "[OutboxRelay.run_once]: {}",
+                                err
+                            );
+                        }
+                    }
+                    _ = cleanup.tick() => {
+                        if let Err(err) = self.cleanup().await {
+                            tracing::log::error!(
+                                "[OutboxRelay.cleanup]: {}",
+                                err
+                            );
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Delivers due messages until none are left. Returns how many were
+    /// delivered.
+    pub async fn run_once(&self) -> Result<usize, DbError> {
+        let mut delivered = 0;
+
+        loop {
+            let messages = self
+                .store
+                .outbox()
+                .list_pending(Utc::now(), Self::BATCH_SIZE)
+                .await?;
+            let is_last_batch = messages.len() < Self::BATCH_SIZE as usize;
+
+            let delivered_in_batch = self.deliver_batch(messages).await?;
+            delivered += delivered_in_batch;
+
+            if is_last_batch || delivered_in_batch == 0 {
+                return Ok(delivered);
+            }
+        }
+    }
+
+    async fn deliver_batch(
+        &self,
+        messages: Vec<OutboxMessage>,
+    ) -> Result<usize, DbError> {
+        let mut held_back = HashSet::new();
+        let mut delivered = 0;
+
+        for message in messages {
+            if held_back.contains(&message.website_id) {
+                continue;
+            }
+
+            let now = Utc::now();
+            let is_due = message.next_attempt_at <= now
+                && message.locked_until.is_none_or(|l| l <= now);
+
+            if !is_due
+                || !self
+                    .store
+                    .outbox()
+                    .claim(
+                        message.outbox_id,
+                        message.locked_until,
+                        now + Self::LEASE,
+                    )
+                    .await?
+            {
+                held_back.insert(message.website_id);
+                continue;
+            }
+
+            let published = self
+                .broker
+                .publish(&BrokerMessage {
+                    subject: message.subject.clone(),
+                    message_id: message.message_id.clone(),
+                    payload: message.payload.clone(),
+                })
+                .await;
+
+            match published {
+                Ok(()) => {
+                    self.store
+                        .outbox()
+                        .mark_delivered(message.outbox_id)
+                        .await?;
+                    delivered += 1;
+                }
+                Err(err) => {
+                    let attempts = message.attempts + 1;
+                    let next_attempt_at = (attempts < self.max_attempts)
+                        .then(|| Utc::now() + Self::retry_delay(attempts));
+
+                    if next_attempt_at.is_some() {
+                        held_back.insert(message.website_id.clone());
+                    } else {
+                        tracing::log::error!(
+                            "[OutboxRelay] gave up on message {} on '{}' \
+                             after {} attempts, it is kept for {} days",
+                            message.message_id,
+                            message.subject,
+                            attempts,
+                            Self::DEAD_RETENTION.as_secs() / (24 * 60 * 60)
+                        );
+                    }
+
+                    tracing::log::error!(
+                        "[OutboxRelay] could not publish message {} on '{}' \
+                         (attempt {}): {}",
+                        message.message_id,
+                        message.subject,
+                        attempts,
+                        err.message()
+                    );
+
+                    self.store
+                        .outbox()
+                        .mark_failed(
+                            message.outbox_id,
+                            err.message(),
+                            next_attempt_at,
+                        )
+                        .await?;
+                }
+            }
+        }
+
+        Ok(delivered)
+    }
+
+    fn retry_delay(attempts: i64) -> Duration {
+        let exponent = u32::try_from(attempts.saturating_sub(1))
+            .unwrap_or(u32::MAX)
+            .min(16);
+        Self::BASE_DELAY
+            .saturating_mul(2u32.pow(exponent))
+            .min(Self::MAX_DELAY)
+    }
+
+    async fn cleanup(&self) -> Result<(), DbError> {
+        let deleted = self
+            .store
+            .outbox()
+            .delete_delivered_before(Utc::now() - Self::RETENTION)
+            .await?;
+
+        if deleted > 0 {
+            tracing::log::info!(
+                "[OutboxRelay] deleted {} delivered message(s)",
+                deleted
+            );
+        }
+
+        let deleted = self
+            .store
+            .outbox()
+            .delete_dead_before(Utc::now() - Self::DEAD_RETENTION)
+            .await?;
+
+        if deleted > 0 {
+            tracing::log::warn!(
+                "[OutboxRelay] deleted {} dead message(s) that were never \
+                 delivered",
+                deleted
+            );
+        }
+
+        Ok(())
+    }
+}
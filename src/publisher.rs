@@ -1,36 +1,224 @@
+use chrono::Utc;
 use prost::Message;
+use uuid::Uuid;
 
-use crate::api::sited_io::websites::v1::WebsiteResponse;
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
+use crate::api::sited_io::websites::v1::{
+    DomainStatus, DomainStatusEvent, EventEnvelope, WebsiteResponse,
+};
+use crate::datetime_to_timestamp;
+use crate::db::DbError;
+use crate::repository::OutboxRepository;
 
+/// Actor of the changes made by the service itself, e.g. by its background
+/// jobs, see `EventEnvelope.actor`.
+pub const SYSTEM_ACTOR: &str = "";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventAction {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl EventAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+/// Writes messages to the outbox, in the transaction of the change they
+/// describe. `OutboxRelay` publishes them to the message broker after the
+/// commit. A disabled publisher drops all messages.
 #[derive(Debug, Clone)]
 pub struct Publisher {
-    nats_client: async_nats::Client,
+    enabled: bool,
 }
 
 impl Publisher {
     const WEBSITE_UPSERT_SUBJECT: &'static str = "websites.website.upsert";
     const WEBSITE_DELETE_SUBJECT: &'static str = "websites.website.delete";
+    const DOMAIN_DEGRADED_SUBJECT: &'static str = "websites.domain.degraded";
+    const DOMAIN_RESTORED_SUBJECT: &'static str = "websites.domain.restored";
+    const DOMAIN_DEACTIVATED_SUBJECT: &'static str =
+        "websites.domain.deactivated";
+    pub(crate) const EVENT_SUBJECT_PREFIX: &'static str = "websites.v1";
+    pub const EVENT_VERSION: u32 = 1;
+
+    pub fn new() -> Self {
+        Self { enabled: true }
+    }
 
-    pub fn new(nats_client: async_nats::Client) -> Self {
-        Self { nats_client }
+    /// For running the service without a message broker.
+    pub fn disabled() -> Self {
+        Self { enabled: false }
     }
 
     pub async fn publish_website(
         &self,
+        outbox: &dyn OutboxRepository,
         website: &WebsiteResponse,
         is_delete: bool,
-    ) {
+    ) -> Result<(), DbError> {
+        if !self.enabled {
+            return Ok(());
+        }
+
         let subject = if is_delete {
             Self::WEBSITE_DELETE_SUBJECT
         } else {
             Self::WEBSITE_UPSERT_SUBJECT
         };
-        if let Err(err) = self
-            .nats_client
-            .publish(subject, website.encode_to_vec().into())
+
+        outbox
+            .create(
+                &website.website_id,
+                subject,
+                &Uuid::new_v4().to_string(),
+                website.encode_to_vec(),
+            )
+            .await
+    }
+
+    fn entity_name(payload: &Payload) -> &'static str {
+        match payload {
+            Payload::Website(_) => "website",
+            Payload::Page(_) => "page",
+            Payload::StaticPage(_) => "static_page",
+            Payload::Domain(_) => "domain",
+            Payload::Customization(_) => "customization",
+        }
+    }
+
+    pub fn build_event(
+        action: EventAction,
+        actor: &str,
+        website_id: &str,
+        payload: Payload,
+    ) -> EventEnvelope {
+        EventEnvelope {
+            version: Self::EVENT_VERSION,
+            event_id: Uuid::new_v4().to_string(),
+            event_type: format!(
+                "{}.{}",
+                Self::entity_name(&payload),
+                action.as_str()
+            ),
+            timestamp: datetime_to_timestamp(Utc::now()),
+            actor: actor.to_string(),
+            website_id: website_id.to_string(),
+            payload: Some(payload),
+        }
+    }
+
+    /// Publishes a change of a website or one of its entities on
+    /// `websites.v1.<entity>.<action>`, see `EventEnvelope`. `actor` is the
+    /// user who made the change, or `SYSTEM_ACTOR`.
+    pub async fn publish_event(
+        &self,
+        outbox: &dyn OutboxRepository,
+        action: EventAction,
+        actor: &str,
+        website_id: &str,
+        payload: Payload,
+    ) -> Result<(), DbError> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let event = Self::build_event(action, actor, website_id, payload);
+        let subject =
+            format!("{}.{}", Self::EVENT_SUBJECT_PREFIX, event.event_type);
+
+        outbox
+            .create(
+                website_id,
+                &subject,
+                &event.event_id,
+                event.encode_to_vec(),
+            )
             .await
-        {
-            tracing::log::error!("[WebsiteService.publish_website]: {}", err);
+    }
+
+    /// Publishes status changes made by the domain check job, so the owner
+    /// can be notified. The subject is chosen by the new status of the domain.
+    pub async fn publish_domain_status(
+        &self,
+        outbox: &dyn OutboxRepository,
+        event: &DomainStatusEvent,
+    ) -> Result<(), DbError> {
+        if !self.enabled {
+            return Ok(());
         }
+
+        let status = event
+            .domain
+            .as_ref()
+            .and_then(|d| DomainStatus::try_from(d.status).ok());
+
+        let subject = match status {
+            Some(DomainStatus::Degraded) => Self::DOMAIN_DEGRADED_SUBJECT,
+            Some(DomainStatus::Active) => Self::DOMAIN_RESTORED_SUBJECT,
+            Some(DomainStatus::Pending) => Self::DOMAIN_DEACTIVATED_SUBJECT,
+            _ => return Ok(()),
+        };
+
+        outbox
+            .create(
+                &event.website_id,
+                subject,
+                &Uuid::new_v4().to_string(),
+                event.encode_to_vec(),
+            )
+            .await
+    }
+}
+
+impl Default for Publisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::sited_io::websites::v1::event_envelope::Payload;
+    use crate::api::sited_io::websites::v1::{
+        PageResponse, StaticPageResponse,
+    };
+
+    use super::{EventAction, Publisher};
+
+    #[test]
+    fn builds_event_type_from_payload_and_action() {
+        let event = Publisher::build_event(
+            EventAction::Deleted,
+            "user-1",
+            "website-1",
+            Payload::StaticPage(StaticPageResponse::default()),
+        );
+
+        assert_eq!(event.version, Publisher::EVENT_VERSION);
+        assert_eq!(event.event_type, "static_page.deleted");
+        assert_eq!(event.actor, "user-1");
+        assert_eq!(event.website_id, "website-1");
+    }
+
+    #[test]
+    fn generates_unique_event_ids() {
+        let build = || {
+            Publisher::build_event(
+                EventAction::Created,
+                "",
+                "website-1",
+                Payload::Page(PageResponse::default()),
+            )
+        };
+
+        assert_ne!(build().event_id, build().event_id);
     }
 }
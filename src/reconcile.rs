@@ -0,0 +1,1092 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tonic::Status;
+
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
+use crate::api::sited_io::websites::v1::DomainStatus;
+use crate::edge::{DynEdgeProvider, EdgeHostname};
+use crate::identity::{AppProject, DynIdentityProvider, OidcApp, ProjectOrg};
+use crate::images::ImageService;
+use crate::model::Website;
+use crate::publisher::{EventAction, Publisher, SYSTEM_ACTOR};
+use crate::repository::DynStore;
+use crate::website_cache::WebsiteCache;
+use crate::WebsiteService;
+
+/// A difference between the `websites` and `domains` tables and the state in
+/// ZITADEL or on the edge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReconcileAction {
+    RemoveApp {
+        project: Option<AppProject>,
+        app_id: String,
+        name: String,
+    },
+    RecreateApp {
+        website_id: String,
+        project: Option<AppProject>,
+        domain: String,
+    },
+    /// Removes an organization made for a website, with its project and app.
+    RemoveProject {
+        org_id: String,
+        name: String,
+    },
+    CreateDnsRecord {
+        website_id: String,
+        name: String,
+    },
+    DeleteDnsRecord {
+        name: String,
+    },
+    AddCustomHostname {
+        website_id: String,
+        hostname: String,
+    },
+    RemoveCustomHostname {
+        hostname: String,
+    },
+}
+
+impl fmt::Display for ReconcileAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RemoveApp { app_id, name, .. } => {
+                write!(
+                    f,
+                    "remove ZITADEL app '{}' ({}) without website",
+                    name, app_id
+                )
+            }
+            Self::RecreateApp {
+                website_id, domain, ..
+            } => write!(
+                f,
+                "recreate missing ZITADEL app '{}' of website '{}'",
+                domain, website_id
+            ),
+            Self::RemoveProject { org_id, name } => write!(
+                f,
+                "remove ZITADEL organization '{}' ({}) without website",
+                name, org_id
+            ),
+            Self::CreateDnsRecord { name, .. } => {
+                write!(f, "create missing DNS record '{}'", name)
+            }
+            Self::DeleteDnsRecord { name } => {
+                write!(f, "delete DNS record '{}' without domain", name)
+            }
+            Self::AddCustomHostname { hostname, .. } => {
+                write!(f, "add missing custom hostname '{}'", hostname)
+            }
+            Self::RemoveCustomHostname { hostname } => write!(
+                f,
+                "remove custom hostname '{}' without active domain",
+                hostname
+            ),
+        }
+    }
+}
+
+/// Finds and fixes ZITADEL apps, DNS records and custom hostnames that got
+/// out of sync with the database, e.g. after a partial failure while creating
+/// or deleting a website.
+///
+/// Apps are reconciled in the shared ZITADEL project and in the organization
+/// of each website. An organization no website refers to is removed with its
+/// project and apps. A website whose organization is gone is left alone, as
+/// recreating it would lose the branding.
+///
+/// ZITADEL and the edge are reconciled independently, so one being
+/// unavailable does not hold back the other. DNS records and custom hostnames
+/// are only reconciled if the edge provider can list them.
+///
+/// Only apps and organizations named after a subdomain of the main domain,
+/// DNS records of such subdomains pointing to the fallback domain and the
+/// custom hostnames listed by the edge provider are considered to be managed
+/// by this service. Orphans younger than `min_age` are left alone, as they
+/// may belong to a website that is still being created. Likewise, nothing is
+/// recreated for a website updated within `min_age`, as it may be in the
+/// middle of being changed or deleted, see `WebsiteRepository::touch`. In
+/// dry-run mode the actions are only logged.
+pub struct Reconciler {
+    store: DynStore,
+    identity_provider: DynIdentityProvider,
+    edge_provider: DynEdgeProvider,
+    publisher: Publisher,
+    image_service: ImageService,
+    website_cache: WebsiteCache,
+    main_domain: String,
+    fallback_domain: String,
+    interval: Duration,
+    min_age: Duration,
+    dry_run: bool,
+}
+
+impl Reconciler {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+    pub const DEFAULT_MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        store: DynStore,
+        identity_provider: DynIdentityProvider,
+        edge_provider: DynEdgeProvider,
+        publisher: Publisher,
+        image_service: ImageService,
+        website_cache: WebsiteCache,
+        main_domain: String,
+        fallback_domain: String,
+        interval: Duration,
+        min_age: Duration,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            store,
+            identity_provider,
+            edge_provider,
+            publisher,
+            image_service,
+            website_cache,
+            main_domain,
+            fallback_domain,
+            interval,
+            min_age,
+            dry_run,
+        }
+    }
+
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            loop {
+                interval.tick().await;
+                if let Err(err) = self.run_once().await {
+                    tracing::log::error!("[Reconciler.run_once]: {}", err);
+                }
+            }
+        })
+    }
+
+    /// Applies all actions found, unless in dry-run mode. Returns the actions.
+    pub async fn run_once(&self) -> Result<Vec<ReconcileAction>, Status> {
+        let actions = self.plan().await?;
+
+        for action in &actions {
+            if self.dry_run {
+                tracing::log::info!("[Reconciler] would {}", action);
+            } else if let Err(err) = self.apply(action).await {
+                tracing::log::error!(
+                    "[Reconciler] could not {}: {}",
+                    action,
+                    err
+                );
+            } else {
+                tracing::log::info!("[Reconciler] did {}", action);
+            }
+        }
+
+        tracing::log::info!(
+            "[Reconciler] found {} difference(s){}",
+            actions.len(),
+            if self.dry_run { " (dry run)" } else { "" }
+        );
+
+        Ok(actions)
+    }
+
+    pub async fn plan(&self) -> Result<Vec<ReconcileAction>, Status> {
+        let websites = self.store.websites().list_all().await?;
+        let now = Utc::now();
+        let mut actions = Vec::new();
+
+        match self.plan_identity(&websites, now).await {
+            Ok(identity_actions) => actions.extend(identity_actions),
+            Err(err) => {
+                tracing::log::error!("[Reconciler.plan] ZITADEL: {}", err)
+            }
+        }
+        match self.plan_edge(&websites, now).await {
+            Ok(edge_actions) => actions.extend(edge_actions),
+            Err(err) => tracing::log::error!("[Reconciler.plan] edge: {}", err),
+        }
+
+        Ok(actions)
+    }
+
+    async fn plan_identity(
+        &self,
+        websites: &[Website],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<ReconcileAction>, Status> {
+        let apps = self.identity_provider.list_apps().await?;
+        let projects = self.identity_provider.list_projects().await?;
+
+        Ok(self.diff_identity(websites, &apps, &projects, now))
+    }
+
+    async fn plan_edge(
+        &self,
+        websites: &[Website],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<ReconcileAction>, Status> {
+        let mut actions = Vec::new();
+
+        if let Some(dns_records) = self
+            .edge_provider
+            .list_dns_records(&self.fallback_domain)
+            .await?
+        {
+            actions.extend(self.diff_dns_records(websites, &dns_records, now));
+        }
+        if let Some(custom_hostnames) =
+            self.edge_provider.list_custom_hostnames().await?
+        {
+            actions.extend(self.diff_custom_hostnames(
+                websites,
+                &custom_hostnames,
+                now,
+            ));
+        }
+
+        Ok(actions)
+    }
+
+    fn is_managed_name(&self, name: &str) -> bool {
+        name.ends_with(&format!(".{}", self.main_domain))
+    }
+
+    fn is_old_enough(
+        &self,
+        created_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        created_at.is_some_and(|created_at| {
+            now.signed_duration_since(created_at)
+                .to_std()
+                .is_ok_and(|age| age >= self.min_age)
+        })
+    }
+
+    fn is_settled(&self, website: &Website, now: DateTime<Utc>) -> bool {
+        self.is_old_enough(Some(website.updated_at), now)
+    }
+
+    /// Re-reads the website before anything is recreated for it, as it may
+    /// have been changed or deleted since it was listed.
+    async fn check_settled(
+        &self,
+        website_id: &String,
+        domain: &str,
+    ) -> Result<(), Status> {
+        let website = self.store.websites().get(website_id).await?;
+
+        if website.is_some_and(|w| {
+            self.is_settled(&w, Utc::now())
+                && w.domains.iter().any(|d| d.domain == domain)
+        }) {
+            Ok(())
+        } else {
+            Err(Status::failed_precondition(format!(
+                "website '{}' changed since it was listed",
+                website_id
+            )))
+        }
+    }
+
+    /// Stores the recreated app and publishes the new client id.
+    async fn update_zitadel_app(
+        &self,
+        website_id: &String,
+        client_id: &String,
+        zitadel_app_id: &String,
+        project: Option<&AppProject>,
+    ) -> Result<(), Status> {
+        let transaction = self.store.begin().await?;
+
+        let updated_website = transaction
+            .websites()
+            .update_zitadel_app(website_id, client_id, zitadel_app_id, project)
+            .await?;
+
+        let website_response =
+            WebsiteService::to_response(&self.image_service, updated_website);
+
+        self.publisher
+            .publish_website(transaction.outbox(), &website_response, false)
+            .await?;
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Updated,
+                SYSTEM_ACTOR,
+                website_id,
+                Payload::Website(website_response),
+            )
+            .await?;
+
+        transaction.commit().await?;
+        self.website_cache.invalidate(website_id);
+
+        Ok(())
+    }
+
+    /// Maps the domains with one of the statuses to their websites.
+    fn domains_with_status<'a>(
+        websites: &'a [Website],
+        statuses: &[DomainStatus],
+    ) -> HashMap<&'a str, &'a Website> {
+        websites
+            .iter()
+            .flat_map(|w| w.domains.iter().map(move |d| (d, w)))
+            .filter(|(d, _)| statuses.contains(&d.status))
+            .map(|(d, w)| (d.domain.as_str(), w))
+            .collect()
+    }
+
+    fn diff_identity(
+        &self,
+        websites: &[Website],
+        apps: &[OidcApp],
+        projects: &[ProjectOrg],
+        now: DateTime<Utc>,
+    ) -> Vec<ReconcileAction> {
+        let mut actions = Vec::new();
+
+        // ZITADEL apps
+        let app_ids: HashSet<&str> =
+            apps.iter().map(|a| a.app_id.as_str()).collect();
+        let website_app_ids: HashSet<&str> =
+            websites.iter().map(|w| w.zitadel_app_id.as_str()).collect();
+
+        for app in apps {
+            if self.is_managed_name(&app.name)
+                && !website_app_ids.contains(app.app_id.as_str())
+                && self.is_old_enough(app.created_at, now)
+            {
+                actions.push(ReconcileAction::RemoveApp {
+                    project: None,
+                    app_id: app.app_id.clone(),
+                    name: app.name.clone(),
+                });
+            }
+        }
+
+        // an empty project more likely means a wrong project than that every
+        // app was removed
+        let shared_project_websites = websites
+            .iter()
+            .filter(|w| w.zitadel_project().is_none())
+            .filter(|_| !apps.is_empty());
+
+        for website in shared_project_websites {
+            if app_ids.contains(website.zitadel_app_id.as_str())
+                || !self.is_settled(website, now)
+            {
+                continue;
+            }
+            if let Some(domain) = website
+                .domains
+                .iter()
+                .find(|d| d.status == DomainStatus::Internal)
+            {
+                actions.push(ReconcileAction::RecreateApp {
+                    website_id: website.website_id.clone(),
+                    project: None,
+                    domain: domain.domain.clone(),
+                });
+            }
+        }
+
+        // ZITADEL organizations of websites
+        let websites_by_org: HashMap<&str, &Website> = websites
+            .iter()
+            .filter_map(|w| Some((w.zitadel_org_id.as_deref()?, w)))
+            .collect();
+
+        for org in projects {
+            let Some(website) = websites_by_org.get(org.org_id.as_str()) else {
+                if self.is_managed_name(&org.name)
+                    && self.is_old_enough(org.created_at, now)
+                {
+                    actions.push(ReconcileAction::RemoveProject {
+                        org_id: org.org_id.clone(),
+                        name: org.name.clone(),
+                    });
+                }
+                continue;
+            };
+
+            // apps are only reconciled in the project the website refers to
+            let Some(project) = website
+                .zitadel_project()
+                .filter(|p| org.project_id.as_ref() == Some(&p.project_id))
+            else {
+                continue;
+            };
+
+            for app in &org.apps {
+                if app.app_id != website.zitadel_app_id
+                    && self.is_managed_name(&app.name)
+                    && self.is_old_enough(app.created_at, now)
+                {
+                    actions.push(ReconcileAction::RemoveApp {
+                        project: Some(project.clone()),
+                        app_id: app.app_id.clone(),
+                        name: app.name.clone(),
+                    });
+                }
+            }
+
+            if org.apps.iter().any(|a| a.app_id == website.zitadel_app_id)
+                || !self.is_settled(website, now)
+            {
+                continue;
+            }
+            if let Some(domain) = website
+                .domains
+                .iter()
+                .find(|d| d.status == DomainStatus::Internal)
+            {
+                actions.push(ReconcileAction::RecreateApp {
+                    website_id: website.website_id.clone(),
+                    project: Some(project),
+                    domain: domain.domain.clone(),
+                });
+            }
+        }
+
+        actions
+    }
+
+    /// Diffs the DNS records of internal domains.
+    fn diff_dns_records(
+        &self,
+        websites: &[Website],
+        dns_records: &[EdgeHostname],
+        now: DateTime<Utc>,
+    ) -> Vec<ReconcileAction> {
+        let mut actions = Vec::new();
+
+        let internal_domains = Self::domains_with_status(
+            websites,
+            &[DomainStatus::Internal, DomainStatus::Redirect],
+        );
+        let managed_records: Vec<_> = dns_records
+            .iter()
+            .filter(|r| self.is_managed_name(&r.name))
+            .collect();
+        let record_names: HashSet<&str> =
+            managed_records.iter().map(|r| r.name.as_str()).collect();
+
+        for (domain, website) in &internal_domains {
+            if !record_names.contains(domain) && self.is_settled(website, now) {
+                actions.push(ReconcileAction::CreateDnsRecord {
+                    website_id: website.website_id.clone(),
+                    name: domain.to_string(),
+                });
+            }
+        }
+
+        for record in managed_records {
+            if !internal_domains.contains_key(record.name.as_str())
+                && self.is_old_enough(record.created_at, now)
+            {
+                actions.push(ReconcileAction::DeleteDnsRecord {
+                    name: record.name.clone(),
+                });
+            }
+        }
+
+        actions
+    }
+
+    /// Diffs the custom hostnames of verified custom domains.
+    fn diff_custom_hostnames(
+        &self,
+        websites: &[Website],
+        custom_hostnames: &[EdgeHostname],
+        now: DateTime<Utc>,
+    ) -> Vec<ReconcileAction> {
+        let mut actions = Vec::new();
+
+        let active_domains = Self::domains_with_status(
+            websites,
+            &[DomainStatus::Active, DomainStatus::Degraded],
+        );
+        let hostnames: HashSet<&str> =
+            custom_hostnames.iter().map(|h| h.name.as_str()).collect();
+
+        for (domain, website) in &active_domains {
+            if !hostnames.contains(domain) && self.is_settled(website, now) {
+                actions.push(ReconcileAction::AddCustomHostname {
+                    website_id: website.website_id.clone(),
+                    hostname: domain.to_string(),
+                });
+            }
+        }
+
+        for custom_hostname in custom_hostnames {
+            if !active_domains.contains_key(custom_hostname.name.as_str())
+                && self.is_old_enough(custom_hostname.created_at, now)
+            {
+                actions.push(ReconcileAction::RemoveCustomHostname {
+                    hostname: custom_hostname.name.clone(),
+                });
+            }
+        }
+
+        actions
+    }
+
+    async fn apply(&self, action: &ReconcileAction) -> Result<(), Status> {
+        match action {
+            ReconcileAction::RemoveApp {
+                project, app_id, ..
+            } => {
+                self.identity_provider
+                    .remove_app(project.as_ref(), app_id)
+                    .await?;
+            }
+            ReconcileAction::RecreateApp {
+                website_id,
+                project,
+                domain,
+            } => {
+                self.check_settled(website_id, domain).await?;
+                let (redirect_uris, post_logout_redirect_uris) =
+                    WebsiteService::build_redirect_uris(&[domain]);
+                let app = self
+                    .identity_provider
+                    .add_app(
+                        project.as_ref(),
+                        domain,
+                        redirect_uris,
+                        post_logout_redirect_uris,
+                    )
+                    .await?;
+                self.update_zitadel_app(
+                    website_id,
+                    &app.client_id,
+                    &app.app_id,
+                    project.as_ref(),
+                )
+                .await?;
+                WebsiteService::sync_redirect_uris(
+                    self.store.as_ref(),
+                    self.identity_provider.as_ref(),
+                    website_id,
+                )
+                .await?;
+            }
+            ReconcileAction::RemoveProject { org_id, .. } => {
+                self.identity_provider.remove_org(org_id).await?;
+            }
+            ReconcileAction::CreateDnsRecord { website_id, name } => {
+                self.check_settled(website_id, name).await?;
+                self.edge_provider
+                    .create_dns_record(name, &self.fallback_domain)
+                    .await?;
+            }
+            ReconcileAction::DeleteDnsRecord { name } => {
+                self.edge_provider.delete_dns_records(name).await?;
+            }
+            ReconcileAction::AddCustomHostname {
+                website_id,
+                hostname,
+            } => {
+                self.check_settled(website_id, hostname).await?;
+                self.edge_provider.add_custom_hostname(hostname).await?;
+            }
+            ReconcileAction::RemoveCustomHostname { hostname } => {
+                self.edge_provider.remove_custom_hostname(hostname).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+
+    use crate::api::sited_io::websites::v1::DomainStatus;
+    use crate::edge::{
+        EdgeHostname, InMemoryEdgeProvider, OnDemandTlsProvider,
+    };
+    use crate::identity::{
+        AppProject, IdentityProvider, InMemoryIdentityProvider, OidcApp,
+        ProjectOrg,
+    };
+    use crate::images::ImageService;
+    use crate::model::{DomainAsRel, Website};
+    use crate::publisher::Publisher;
+    use crate::repository::{InMemoryStore, Repositories};
+    use crate::website_cache::WebsiteCache;
+
+    use super::{ReconcileAction, Reconciler};
+
+    const MAIN_DOMAIN: &str = "sited.io";
+    const FALLBACK_DOMAIN: &str = "fallback.sited.io";
+    const MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+    async fn image_service() -> ImageService {
+        ImageService::new(
+            "bucket".to_string(),
+            "http://localhost".to_string(),
+            "access-key-id".to_string(),
+            "secret-access-key".to_string(),
+            "https://images.sited.io".to_string(),
+            1024 * 1024,
+        )
+        .await
+    }
+
+    async fn reconciler() -> Reconciler {
+        Reconciler::new(
+            Arc::new(InMemoryStore::new()),
+            Arc::new(InMemoryIdentityProvider::new()),
+            Arc::new(InMemoryEdgeProvider::new()),
+            Publisher::new(),
+            image_service().await,
+            WebsiteCache::disabled(),
+            MAIN_DOMAIN.to_string(),
+            FALLBACK_DOMAIN.to_string(),
+            Duration::from_secs(60),
+            MIN_AGE,
+            false,
+        )
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    /// Created long enough ago to be removed if orphaned.
+    fn old() -> DateTime<Utc> {
+        now() - MIN_AGE
+    }
+
+    /// Possibly still being created.
+    fn young() -> DateTime<Utc> {
+        now() - MIN_AGE / 2
+    }
+
+    fn website(
+        website_id: &str,
+        app_id: &str,
+        domains: &[(&str, DomainStatus)],
+    ) -> Website {
+        Website {
+            website_id: website_id.to_string(),
+            user_id: "user-1".to_string(),
+            created_at: old(),
+            updated_at: old(),
+            name: website_id.to_string(),
+            client_id: format!("client-{website_id}"),
+            zitadel_app_id: app_id.to_string(),
+            zitadel_org_id: None,
+            zitadel_project_id: None,
+            customization: None,
+            domains: domains
+                .iter()
+                .enumerate()
+                .map(|(i, (domain, status))| DomainAsRel {
+                    domain_id: i as i64,
+                    domain: domain.to_string(),
+                    status: *status,
+                    domain_unicode: None,
+                })
+                .collect(),
+            pages: Vec::new(),
+        }
+    }
+
+    fn app(app_id: &str, name: &str, created_at: DateTime<Utc>) -> OidcApp {
+        OidcApp {
+            app_id: app_id.to_string(),
+            client_id: format!("client-{app_id}"),
+            name: name.to_string(),
+            redirect_uris: Vec::new(),
+            post_logout_redirect_uris: Vec::new(),
+            created_at: Some(created_at),
+        }
+    }
+
+    fn org(
+        org_id: &str,
+        project_id: Option<&str>,
+        name: &str,
+        created_at: DateTime<Utc>,
+        apps: Vec<OidcApp>,
+    ) -> ProjectOrg {
+        ProjectOrg {
+            org_id: org_id.to_string(),
+            project_id: project_id.map(str::to_string),
+            name: name.to_string(),
+            created_at: Some(created_at),
+            apps,
+        }
+    }
+
+    fn project() -> AppProject {
+        AppProject {
+            org_id: "o1".to_string(),
+            project_id: "p1".to_string(),
+        }
+    }
+
+    /// Websites, apps and organizations in ZITADEL, DNS records and custom
+    /// hostnames as they are passed to the diffs.
+    type State = (
+        Vec<Website>,
+        Vec<OidcApp>,
+        Vec<ProjectOrg>,
+        Vec<EdgeHostname>,
+        Vec<EdgeHostname>,
+    );
+
+    /// The website of `in_sync`, with its app in an organization of its own.
+    fn in_sync_with_project() -> State {
+        let mut state = in_sync();
+        state.0[0].zitadel_org_id = Some("o1".to_string());
+        state.0[0].zitadel_project_id = Some("p1".to_string());
+        state.1 = vec![app("a4", "other.example.com", old())];
+        state.2 = vec![org(
+            "o1",
+            Some("p1"),
+            "w1.sited.io",
+            old(),
+            vec![app("a1", "w1.sited.io", old())],
+        )];
+        state
+    }
+
+    fn hostname(name: &str, created_at: DateTime<Utc>) -> EdgeHostname {
+        EdgeHostname {
+            name: name.to_string(),
+            created_at: Some(created_at),
+        }
+    }
+
+    /// A website with an internal and an active custom domain, and
+    /// everything it needs in ZITADEL and Cloudflare.
+    fn in_sync() -> State {
+        (
+            vec![website(
+                "w1",
+                "a1",
+                &[
+                    ("w1.sited.io", DomainStatus::Internal),
+                    ("shop.example.com", DomainStatus::Active),
+                ],
+            )],
+            vec![app("a1", "w1.sited.io", old())],
+            vec![],
+            vec![hostname("w1.sited.io", old())],
+            vec![hostname("shop.example.com", old())],
+        )
+    }
+
+    #[tokio::test]
+    async fn diff() {
+        let cases: Vec<(&str, _, Vec<ReconcileAction>)> = vec![
+            ("in sync", in_sync(), vec![]),
+            (
+                "orphaned managed app",
+                {
+                    let mut state = in_sync();
+                    state.1.push(app("a2", "w2.sited.io", old()));
+                    state.1.push(app("a3", "w3.sited.io", young()));
+                    state.1.push(app("a4", "other.example.com", old()));
+                    state
+                },
+                vec![ReconcileAction::RemoveApp {
+                    project: None,
+                    app_id: "a2".to_string(),
+                    name: "w2.sited.io".to_string(),
+                }],
+            ),
+            (
+                "missing app",
+                {
+                    let mut state = in_sync();
+                    state.1 = vec![app("a4", "other.example.com", old())];
+                    state
+                },
+                vec![ReconcileAction::RecreateApp {
+                    website_id: "w1".to_string(),
+                    project: None,
+                    domain: "w1.sited.io".to_string(),
+                }],
+            ),
+            ("in sync with project", in_sync_with_project(), vec![]),
+            (
+                "missing organization of website",
+                {
+                    let mut state = in_sync_with_project();
+                    state.2 = vec![];
+                    state
+                },
+                vec![],
+            ),
+            (
+                "missing app in project of website",
+                {
+                    let mut state = in_sync_with_project();
+                    state.2[0].apps = vec![];
+                    state
+                },
+                vec![ReconcileAction::RecreateApp {
+                    website_id: "w1".to_string(),
+                    project: Some(project()),
+                    domain: "w1.sited.io".to_string(),
+                }],
+            ),
+            (
+                "orphaned app in project of website",
+                {
+                    let mut state = in_sync_with_project();
+                    state.2[0].apps.extend([
+                        app("a2", "w2.sited.io", old()),
+                        app("a3", "w3.sited.io", young()),
+                    ]);
+                    state
+                },
+                vec![ReconcileAction::RemoveApp {
+                    project: Some(project()),
+                    app_id: "a2".to_string(),
+                    name: "w2.sited.io".to_string(),
+                }],
+            ),
+            (
+                "orphaned managed organization",
+                {
+                    let mut state = in_sync_with_project();
+                    state.2.extend([
+                        org("o2", Some("p2"), "w2.sited.io", old(), vec![]),
+                        org("o3", Some("p3"), "w3.sited.io", young(), vec![]),
+                        org(
+                            "o4",
+                            Some("p4"),
+                            "other.example.com",
+                            old(),
+                            vec![],
+                        ),
+                        org("o5", None, "w5.sited.io", old(), vec![]),
+                    ]);
+                    state
+                },
+                vec![
+                    ReconcileAction::RemoveProject {
+                        org_id: "o2".to_string(),
+                        name: "w2.sited.io".to_string(),
+                    },
+                    ReconcileAction::RemoveProject {
+                        org_id: "o5".to_string(),
+                        name: "w5.sited.io".to_string(),
+                    },
+                ],
+            ),
+            (
+                "empty project",
+                {
+                    let mut state = in_sync();
+                    state.1 = vec![];
+                    state
+                },
+                vec![],
+            ),
+            (
+                "missing DNS record",
+                {
+                    let mut state = in_sync();
+                    state.3 = vec![];
+                    state
+                },
+                vec![ReconcileAction::CreateDnsRecord {
+                    website_id: "w1".to_string(),
+                    name: "w1.sited.io".to_string(),
+                }],
+            ),
+            (
+                "orphaned managed DNS record",
+                {
+                    let mut state = in_sync();
+                    state.3.extend([
+                        hostname("w2.sited.io", old()),
+                        hostname("w3.sited.io", young()),
+                        hostname("www.example.com", old()),
+                        EdgeHostname {
+                            name: "w4.sited.io".to_string(),
+                            created_at: None,
+                        },
+                    ]);
+                    state
+                },
+                vec![ReconcileAction::DeleteDnsRecord {
+                    name: "w2.sited.io".to_string(),
+                }],
+            ),
+            (
+                "missing custom hostname",
+                {
+                    let mut state = in_sync();
+                    state.4 = vec![];
+                    state
+                },
+                vec![ReconcileAction::AddCustomHostname {
+                    website_id: "w1".to_string(),
+                    hostname: "shop.example.com".to_string(),
+                }],
+            ),
+            (
+                "orphaned managed custom hostname",
+                {
+                    let mut state = in_sync();
+                    state.4.extend([
+                        hostname("old.example.com", old()),
+                        hostname("new.example.com", young()),
+                    ]);
+                    state
+                },
+                vec![ReconcileAction::RemoveCustomHostname {
+                    hostname: "old.example.com".to_string(),
+                }],
+            ),
+            (
+                "website being changed or deleted",
+                {
+                    let mut state = in_sync();
+                    state.0[0].updated_at = young();
+                    state.1 = vec![app("a4", "other.example.com", old())];
+                    state.3 = vec![];
+                    state.4 = vec![];
+                    state
+                },
+                vec![],
+            ),
+            (
+                "custom domain not active anymore",
+                {
+                    let mut state = in_sync();
+                    state.0[0].domains[1].status = DomainStatus::Pending;
+                    state
+                },
+                vec![ReconcileAction::RemoveCustomHostname {
+                    hostname: "shop.example.com".to_string(),
+                }],
+            ),
+        ];
+
+        let reconciler = reconciler().await;
+
+        for (name, (websites, apps, projects, records, hostnames), expected) in
+            cases
+        {
+            let mut actions =
+                reconciler.diff_identity(&websites, &apps, &projects, now());
+            actions.extend(reconciler.diff_dns_records(
+                &websites,
+                &records,
+                now(),
+            ));
+            actions.extend(reconciler.diff_custom_hostnames(
+                &websites,
+                &hostnames,
+                now(),
+            ));
+            assert_eq!(actions, expected, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn reconciles_zitadel_if_the_edge_cannot_be_listed() {
+        let store = Arc::new(InMemoryStore::new());
+        let identity_provider = Arc::new(InMemoryIdentityProvider::new());
+        identity_provider.add_project("w2.sited.io").await.unwrap();
+
+        let reconciler = Reconciler::new(
+            store.clone(),
+            identity_provider.clone(),
+            Arc::new(OnDemandTlsProvider::new(store)),
+            Publisher::new(),
+            image_service().await,
+            WebsiteCache::disabled(),
+            MAIN_DOMAIN.to_string(),
+            FALLBACK_DOMAIN.to_string(),
+            Duration::from_secs(60),
+            Duration::ZERO,
+            false,
+        );
+        let actions = reconciler.run_once().await.unwrap();
+
+        assert!(matches!(
+            actions[..],
+            [ReconcileAction::RemoveProject { .. }]
+        ));
+        assert!(identity_provider.list_projects().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn recreates_nothing_for_a_changed_website() {
+        let store = Arc::new(InMemoryStore::new());
+        let edge_provider = Arc::new(InMemoryEdgeProvider::new());
+        let user_id = "user-1".to_string();
+        store
+            .websites()
+            .create(
+                &"w1".to_string(),
+                &user_id,
+                &"w1".to_string(),
+                &"client-w1".to_string(),
+                &"a1".to_string(),
+                &project(),
+            )
+            .await
+            .unwrap();
+        store
+            .domains()
+            .create(
+                &"w1".to_string(),
+                &user_id,
+                &"w1.sited.io".to_string(),
+                &"w1.sited.io".to_string(),
+                DomainStatus::Internal,
+            )
+            .await
+            .unwrap();
+
+        let reconciler = Reconciler::new(
+            store,
+            Arc::new(InMemoryIdentityProvider::new()),
+            edge_provider.clone(),
+            Publisher::new(),
+            image_service().await,
+            WebsiteCache::disabled(),
+            MAIN_DOMAIN.to_string(),
+            FALLBACK_DOMAIN.to_string(),
+            Duration::from_secs(60),
+            MIN_AGE,
+            false,
+        );
+        let result = reconciler
+            .apply(&ReconcileAction::CreateDnsRecord {
+                website_id: "w1".to_string(),
+                name: "w1.sited.io".to_string(),
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(edge_provider.get_dns_record("w1.sited.io"), None);
+    }
+}
@@ -0,0 +1,1665 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tokio::sync::OwnedMutexGuard;
+use tonic::async_trait;
+
+use crate::api::sited_io::websites::v1::{DomainStatus, PageType};
+use crate::db::DbError;
+use crate::identity::AppProject;
+use crate::model::{
+    Customization, CustomizationAsRel, Domain, DomainAsRel, OutboxMessage,
+    Page, PageAsRel, StaticPage, UserDeletion, Website,
+};
+
+use super::{
+    AllowedHostRepository, CustomizationRepository, DomainRepository,
+    OutboxRepository, PageRepository, Repositories, StaticPageRepository,
+    Store, StoreTransaction, UserDeletionRepository, WebsiteRepository,
+};
+
+/// Store keeping the tables in memory, used by tests. Enforces the unique
+/// and foreign key constraints of the migrations. Transactions work on a
+/// copy of the tables and run one at a time.
+#[derive(Clone)]
+pub struct InMemoryStore {
+    repositories: InMemoryRepositories,
+    replica: Option<InMemoryRepositories>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self {
+            repositories: InMemoryRepositories {
+                tables: Arc::new(Mutex::new(Tables::default())),
+                lock: Arc::new(tokio::sync::Mutex::new(())),
+                in_transaction: false,
+            },
+            replica: None,
+        }
+    }
+
+    /// Serves the reads of `Store::replica` from the tables of `replica`,
+    /// which are not updated by writes to this store, like a lagging replica.
+    pub fn with_replica(mut self, replica: InMemoryStore) -> Self {
+        self.replica = Some(replica.repositories);
+        self
+    }
+
+    /// Messages committed to the outbox, oldest first.
+    pub fn messages(&self) -> Vec<OutboxMessage> {
+        self.repositories
+            .tables()
+            .outbox
+            .iter()
+            .map(|row| row.message.clone())
+            .collect()
+    }
+}
+
+impl Default for InMemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repositories for InMemoryStore {
+    fn websites(&self) -> &dyn WebsiteRepository {
+        &self.repositories
+    }
+
+    fn domains(&self) -> &dyn DomainRepository {
+        &self.repositories
+    }
+
+    fn pages(&self) -> &dyn PageRepository {
+        &self.repositories
+    }
+
+    fn static_pages(&self) -> &dyn StaticPageRepository {
+        &self.repositories
+    }
+
+    fn customizations(&self) -> &dyn CustomizationRepository {
+        &self.repositories
+    }
+
+    fn outbox(&self) -> &dyn OutboxRepository {
+        &self.repositories
+    }
+
+    fn user_deletions(&self) -> &dyn UserDeletionRepository {
+        &self.repositories
+    }
+
+    fn allowed_hosts(&self) -> &dyn AllowedHostRepository {
+        &self.repositories
+    }
+}
+
+#[async_trait]
+impl Store for InMemoryStore {
+    async fn begin(&self) -> Result<Box<dyn StoreTransaction>, DbError> {
+        let guard = self.repositories.lock.clone().lock_owned().await;
+        let tables = self.repositories.tables().clone();
+
+        Ok(Box::new(InMemoryTransaction {
+            committed: self.repositories.tables.clone(),
+            repositories: InMemoryRepositories {
+                tables: Arc::new(Mutex::new(tables)),
+                lock: self.repositories.lock.clone(),
+                in_transaction: true,
+            },
+            _guard: guard,
+        }))
+    }
+
+    fn replica(&self) -> &dyn Repositories {
+        match &self.replica {
+            Some(replica) => replica,
+            None => self,
+        }
+    }
+}
+
+/// Dropping the transaction discards the copy, which rolls it back.
+struct InMemoryTransaction {
+    committed: Arc<Mutex<Tables>>,
+    repositories: InMemoryRepositories,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl Repositories for InMemoryTransaction {
+    fn websites(&self) -> &dyn WebsiteRepository {
+        &self.repositories
+    }
+
+    fn domains(&self) -> &dyn DomainRepository {
+        &self.repositories
+    }
+
+    fn pages(&self) -> &dyn PageRepository {
+        &self.repositories
+    }
+
+    fn static_pages(&self) -> &dyn StaticPageRepository {
+        &self.repositories
+    }
+
+    fn customizations(&self) -> &dyn CustomizationRepository {
+        &self.repositories
+    }
+
+    fn outbox(&self) -> &dyn OutboxRepository {
+        &self.repositories
+    }
+
+    fn user_deletions(&self) -> &dyn UserDeletionRepository {
+        &self.repositories
+    }
+
+    fn allowed_hosts(&self) -> &dyn AllowedHostRepository {
+        &self.repositories
+    }
+}
+
+#[async_trait]
+impl StoreTransaction for InMemoryTransaction {
+    async fn commit(self: Box<Self>) -> Result<(), DbError> {
+        let tables = self.repositories.tables().clone();
+        *self.committed.lock().unwrap() = tables;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Tables {
+    /// Stored without relations, see `with_relations`.
+    websites: BTreeMap<String, Website>,
+    customizations: BTreeMap<String, Customization>,
+    domains: BTreeMap<i64, Domain>,
+    pages: BTreeMap<i64, Page>,
+    static_pages: BTreeMap<i64, StaticPage>,
+    outbox: Vec<OutboxRow>,
+    user_deletions: BTreeMap<String, UserDeletion>,
+    allowed_hosts: BTreeSet<String>,
+    /// Shared by all tables, like a sequence.
+    last_id: i64,
+}
+
+/// The columns of the outbox that `OutboxMessage` does not carry.
+#[derive(Debug, Clone)]
+struct OutboxRow {
+    message: OutboxMessage,
+    status: &'static str,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+impl Tables {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn check_website_exists(
+        &self,
+        website_id: &String,
+        constraint: &'static str,
+    ) -> Result<(), DbError> {
+        if self.websites.contains_key(website_id) {
+            Ok(())
+        } else {
+            Err(DbError::ForeignKeyViolation(constraint))
+        }
+    }
+
+    fn check_website_name(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        name: &String,
+    ) -> Result<(), DbError> {
+        if self.websites.values().any(|w| {
+            w.website_id != *website_id
+                && w.user_id == *user_id
+                && w.name == *name
+        }) {
+            return Err(DbError::UniqueViolation("uq_user_id_website_name"));
+        }
+
+        Ok(())
+    }
+
+    fn check_internal_domain(
+        &self,
+        domain_id: i64,
+        domain: &String,
+        status: DomainStatus,
+    ) -> Result<(), DbError> {
+        let is_internal = |status| {
+            status == DomainStatus::Internal || status == DomainStatus::Redirect
+        };
+
+        if is_internal(status)
+            && self.domains.values().any(|d| {
+                d.domain_id != domain_id
+                    && d.domain == *domain
+                    && is_internal(d.status)
+            })
+        {
+            return Err(DbError::UniqueViolation("uq_domains_internal_domain"));
+        }
+
+        Ok(())
+    }
+
+    fn check_active_domain(
+        &self,
+        domain_id: i64,
+        domain: &String,
+        status: DomainStatus,
+    ) -> Result<(), DbError> {
+        let is_active = |status| {
+            status == DomainStatus::Active || status == DomainStatus::Degraded
+        };
+
+        if is_active(status)
+            && self.domains.values().any(|d| {
+                d.domain_id != domain_id
+                    && d.domain == *domain
+                    && is_active(d.status)
+            })
+        {
+            return Err(DbError::UniqueViolation("uq_domains_active_domain"));
+        }
+
+        Ok(())
+    }
+
+    fn check_page(
+        &self,
+        page_id: i64,
+        website_id: &String,
+        title: &String,
+        path: &String,
+    ) -> Result<(), DbError> {
+        let others = self
+            .pages
+            .values()
+            .filter(|p| p.page_id != page_id && p.website_id == *website_id);
+
+        for page in others {
+            if page.title == *title {
+                return Err(DbError::UniqueViolation(
+                    "uq_pages_website_id_title",
+                ));
+            }
+            if page.path == *path {
+                return Err(DbError::UniqueViolation(
+                    "uq_pages_website_id_path",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn with_relations(&self, website: &Website) -> Website {
+        let mut website = website.clone();
+
+        website.customization = self
+            .customizations
+            .get(&website.website_id)
+            .cloned()
+            .map(CustomizationAsRel::from);
+        website.domains = self
+            .domains
+            .values()
+            .filter(|d| d.website_id == website.website_id)
+            .cloned()
+            .map(DomainAsRel::from)
+            .collect();
+        website.pages = self
+            .pages
+            .values()
+            .filter(|p| p.website_id == website.website_id)
+            .cloned()
+            .map(PageAsRel::from)
+            .collect();
+
+        website
+    }
+}
+
+fn paginate<T>(rows: Vec<T>, limit: u64, offset: u64) -> (Vec<T>, i64) {
+    let count = rows.len() as i64;
+    let rows = rows
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect();
+
+    (rows, count)
+}
+
+/// Works on the committed tables, or on the copy of a transaction. Outside
+/// of a transaction, writes wait for running transactions to finish, as
+/// they would be lost on commit otherwise.
+#[derive(Clone)]
+struct InMemoryRepositories {
+    tables: Arc<Mutex<Tables>>,
+    lock: Arc<tokio::sync::Mutex<()>>,
+    in_transaction: bool,
+}
+
+impl InMemoryRepositories {
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+
+    async fn lock_for_write(&self) -> Option<OwnedMutexGuard<()>> {
+        if self.in_transaction {
+            None
+        } else {
+            Some(self.lock.clone().lock_owned().await)
+        }
+    }
+}
+
+impl Repositories for InMemoryRepositories {
+    fn websites(&self) -> &dyn WebsiteRepository {
+        self
+    }
+
+    fn domains(&self) -> &dyn DomainRepository {
+        self
+    }
+
+    fn pages(&self) -> &dyn PageRepository {
+        self
+    }
+
+    fn static_pages(&self) -> &dyn StaticPageRepository {
+        self
+    }
+
+    fn customizations(&self) -> &dyn CustomizationRepository {
+        self
+    }
+
+    fn outbox(&self) -> &dyn OutboxRepository {
+        self
+    }
+
+    fn user_deletions(&self) -> &dyn UserDeletionRepository {
+        self
+    }
+
+    fn allowed_hosts(&self) -> &dyn AllowedHostRepository {
+        self
+    }
+}
+
+#[async_trait]
+impl WebsiteRepository for InMemoryRepositories {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        name: &String,
+        client_id: &String,
+        zitadel_app_id: &String,
+        zitadel_project: &AppProject,
+    ) -> Result<Website, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if tables.websites.contains_key(website_id) {
+            return Err(DbError::UniqueViolation("websites_pkey"));
+        }
+        tables.check_website_name(website_id, user_id, name)?;
+        for website in tables.websites.values() {
+            if website.client_id == *client_id {
+                return Err(DbError::UniqueViolation("websites_client_id_key"));
+            }
+            if website.zitadel_app_id == *zitadel_app_id {
+                return Err(DbError::UniqueViolation(
+                    "websites_zitadel_app_id_key",
+                ));
+            }
+        }
+
+        let now = Utc::now();
+        let website = Website {
+            website_id: website_id.clone(),
+            user_id: user_id.clone(),
+            created_at: now,
+            updated_at: now,
+            name: name.clone(),
+            client_id: client_id.clone(),
+            zitadel_app_id: zitadel_app_id.clone(),
+            zitadel_org_id: Some(zitadel_project.org_id.clone()),
+            zitadel_project_id: Some(zitadel_project.project_id.clone()),
+            customization: None,
+            domains: Vec::new(),
+            pages: Vec::new(),
+        };
+        tables.websites.insert(website_id.clone(), website.clone());
+
+        Ok(website)
+    }
+
+    async fn get(
+        &self,
+        website_id: &String,
+    ) -> Result<Option<Website>, DbError> {
+        let tables = self.tables();
+
+        Ok(tables
+            .websites
+            .get(website_id)
+            .map(|website| tables.with_relations(website)))
+    }
+
+    async fn get_for_user(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Option<Website>, DbError> {
+        Ok(self
+            .tables()
+            .websites
+            .get(website_id)
+            .filter(|website| website.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn get_by_name(
+        &self,
+        name: &String,
+        user_id: &String,
+    ) -> Result<Option<Website>, DbError> {
+        Ok(self
+            .tables()
+            .websites
+            .values()
+            .find(|website| {
+                website.name == *name && website.user_id == *user_id
+            })
+            .cloned())
+    }
+
+    async fn list(
+        &self,
+        user_id: &Option<String>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Website>, i64), DbError> {
+        let tables = self.tables();
+        let websites = tables
+            .websites
+            .values()
+            .filter(|website| {
+                user_id
+                    .as_ref()
+                    .is_none_or(|user_id| website.user_id == *user_id)
+            })
+            .map(|website| tables.with_relations(website))
+            .collect();
+
+        Ok(paginate(websites, limit, offset))
+    }
+
+    async fn list_all(&self) -> Result<Vec<Website>, DbError> {
+        let tables = self.tables();
+
+        Ok(tables
+            .websites
+            .values()
+            .map(|website| tables.with_relations(website))
+            .collect())
+    }
+
+    async fn update(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        name: &Option<String>,
+    ) -> Result<Website, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if let Some(name) = name {
+            tables.check_website_name(website_id, user_id, name)?;
+        }
+
+        let website = tables
+            .websites
+            .get_mut(website_id)
+            .filter(|website| website.user_id == *user_id)
+            .ok_or(DbError::RowCount)?;
+
+        if let Some(name) = name {
+            website.name = name.clone();
+        }
+        website.updated_at = Utc::now();
+
+        Ok(website.clone())
+    }
+
+    async fn update_zitadel_app(
+        &self,
+        website_id: &String,
+        client_id: &String,
+        zitadel_app_id: &String,
+        project: Option<&AppProject>,
+    ) -> Result<Website, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let website = tables
+            .websites
+            .get_mut(website_id)
+            .ok_or(DbError::RowCount)?;
+
+        website.client_id = client_id.clone();
+        website.zitadel_app_id = zitadel_app_id.clone();
+        website.zitadel_org_id = project.map(|p| p.org_id.clone());
+        website.zitadel_project_id = project.map(|p| p.project_id.clone());
+        website.updated_at = Utc::now();
+
+        Ok(website.clone())
+    }
+
+    async fn touch(&self, website_id: &String) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+
+        if let Some(website) = self.tables().websites.get_mut(website_id) {
+            website.updated_at = Utc::now();
+        }
+
+        Ok(())
+    }
+
+    async fn delete_with_relations(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Website, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if tables
+            .websites
+            .get(website_id)
+            .is_none_or(|website| website.user_id != *user_id)
+        {
+            return Err(DbError::RowCount);
+        }
+
+        // the foreign keys cascade
+        tables.customizations.remove(website_id);
+        tables.domains.retain(|_, d| d.website_id != *website_id);
+        tables.pages.retain(|_, p| p.website_id != *website_id);
+        tables
+            .static_pages
+            .retain(|_, s| s.website_id != *website_id);
+
+        tables.websites.remove(website_id).ok_or(DbError::RowCount)
+    }
+}
+
+#[async_trait]
+impl DomainRepository for InMemoryRepositories {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        domain: &String,
+        domain_unicode: &String,
+        status: DomainStatus,
+    ) -> Result<Domain, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        tables.check_website_exists(website_id, "fk_domains_website_id")?;
+        if tables
+            .domains
+            .values()
+            .any(|d| d.website_id == *website_id && d.domain == *domain)
+        {
+            return Err(DbError::UniqueViolation(
+                "uq_domains_website_id_domain",
+            ));
+        }
+        tables.check_internal_domain(0, domain, status)?;
+        tables.check_active_domain(0, domain, status)?;
+
+        let now = Utc::now();
+        let domain = Domain {
+            domain_id: tables.next_id(),
+            website_id: website_id.clone(),
+            user_id: user_id.clone(),
+            created_at: now,
+            updated_at: now,
+            domain: domain.clone(),
+            status,
+            last_checked_at: None,
+            last_check_succeeded: None,
+            last_check_message: None,
+            degraded_at: None,
+            domain_unicode: Some(domain_unicode.clone()),
+        };
+        tables.domains.insert(domain.domain_id, domain.clone());
+
+        Ok(domain)
+    }
+
+    async fn get_for_user(
+        &self,
+        domain_id: i64,
+        user_id: &String,
+    ) -> Result<Option<Domain>, DbError> {
+        Ok(self
+            .tables()
+            .domains
+            .get(&domain_id)
+            .filter(|domain| domain.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn get_by_domain(
+        &self,
+        domain: &String,
+    ) -> Result<Option<Domain>, DbError> {
+        Ok(self
+            .tables()
+            .domains
+            .values()
+            .find(|d| d.domain == *domain)
+            .cloned())
+    }
+
+    async fn get_by_domain_and_status(
+        &self,
+        domain: &String,
+        status: DomainStatus,
+    ) -> Result<Option<Domain>, DbError> {
+        Ok(self
+            .tables()
+            .domains
+            .values()
+            .find(|d| d.domain == *domain && d.status == status)
+            .cloned())
+    }
+
+    async fn list(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        status: Option<DomainStatus>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Domain>, i64), DbError> {
+        let domains = self
+            .tables()
+            .domains
+            .values()
+            .filter(|d| {
+                d.website_id == *website_id
+                    && d.user_id == *user_id
+                    && status.is_none_or(|status| d.status == status)
+            })
+            .cloned()
+            .collect();
+
+        Ok(paginate(domains, limit, offset))
+    }
+
+    async fn update(
+        &self,
+        domain_id: i64,
+        website_id: &String,
+        user_id: &String,
+        status: DomainStatus,
+    ) -> Result<Domain, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let found_domain = tables
+            .domains
+            .get(&domain_id)
+            .filter(|d| d.website_id == *website_id && d.user_id == *user_id)
+            .map(|d| d.domain.clone())
+            .ok_or(DbError::RowCount)?;
+        tables.check_internal_domain(domain_id, &found_domain, status)?;
+        tables.check_active_domain(domain_id, &found_domain, status)?;
+
+        let domain = tables
+            .domains
+            .get_mut(&domain_id)
+            .ok_or(DbError::RowCount)?;
+
+        domain.status = status;
+        domain.updated_at = Utc::now();
+
+        Ok(domain.clone())
+    }
+
+    async fn list_by_status(
+        &self,
+        status: DomainStatus,
+    ) -> Result<Vec<Domain>, DbError> {
+        Ok(self
+            .tables()
+            .domains
+            .values()
+            .filter(|d| d.status == status)
+            .cloned()
+            .collect())
+    }
+
+    async fn update_last_check(
+        &self,
+        domain_id: i64,
+        succeeded: bool,
+        message: &String,
+    ) -> Result<Domain, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let domain = tables
+            .domains
+            .get_mut(&domain_id)
+            .ok_or(DbError::RowCount)?;
+
+        let now = Utc::now();
+        domain.last_checked_at = Some(now);
+        domain.last_check_succeeded = Some(succeeded);
+        domain.last_check_message = Some(message.clone());
+        domain.updated_at = now;
+
+        Ok(domain.clone())
+    }
+
+    async fn update_check_status(
+        &self,
+        domain_id: i64,
+        from_status: DomainStatus,
+        to_status: DomainStatus,
+        degraded_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Domain>, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        Ok(tables
+            .domains
+            .get_mut(&domain_id)
+            .filter(|domain| domain.status == from_status)
+            .map(|domain| {
+                domain.status = to_status;
+                domain.degraded_at = degraded_at;
+                domain.clone()
+            }))
+    }
+
+    async fn delete(
+        &self,
+        domain_id: i64,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if tables.domains.get(&domain_id).is_some_and(|d| {
+            d.website_id == *website_id && d.user_id == *user_id
+        }) {
+            tables.domains.remove(&domain_id);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PageRepository for InMemoryRepositories {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        page_type: PageType,
+        content_id: &String,
+        title: &String,
+        is_home_page: bool,
+        path: &String,
+    ) -> Result<Page, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        tables.check_website_exists(website_id, "fk_pages_website_id")?;
+        tables.check_page(0, website_id, title, path)?;
+
+        let now = Utc::now();
+        let page = Page {
+            page_id: tables.next_id(),
+            website_id: website_id.clone(),
+            user_id: user_id.clone(),
+            created_at: now,
+            updated_at: now,
+            page_type,
+            content_id: content_id.clone(),
+            title: title.clone(),
+            is_home_page,
+            path: path.clone(),
+        };
+        tables.pages.insert(page.page_id, page.clone());
+
+        Ok(page)
+    }
+
+    async fn get(&self, page_id: i64) -> Result<Option<Page>, DbError> {
+        Ok(self.tables().pages.get(&page_id).cloned())
+    }
+
+    async fn get_by_path(
+        &self,
+        website_id: &String,
+        path: &String,
+    ) -> Result<Option<Page>, DbError> {
+        Ok(self
+            .tables()
+            .pages
+            .values()
+            .find(|p| p.website_id == *website_id && p.path == *path)
+            .cloned())
+    }
+
+    async fn get_home_page(
+        &self,
+        website_id: &String,
+    ) -> Result<Option<Page>, DbError> {
+        Ok(self
+            .tables()
+            .pages
+            .values()
+            .find(|p| p.website_id == *website_id && p.is_home_page)
+            .cloned())
+    }
+
+    async fn list_by_content(
+        &self,
+        page_type: PageType,
+        content_id: &String,
+    ) -> Result<Vec<Page>, DbError> {
+        Ok(self
+            .tables()
+            .pages
+            .values()
+            .filter(|p| p.page_type == page_type && p.content_id == *content_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn list(
+        &self,
+        website_id: Option<String>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Page>, i64), DbError> {
+        let pages = self
+            .tables()
+            .pages
+            .values()
+            .filter(|p| {
+                website_id
+                    .as_ref()
+                    .is_none_or(|website_id| p.website_id == *website_id)
+            })
+            .cloned()
+            .collect();
+
+        Ok(paginate(pages, limit, offset))
+    }
+
+    async fn update(
+        &self,
+        page_id: i64,
+        user_id: &String,
+        page_type: Option<PageType>,
+        content_id: Option<String>,
+        title: Option<String>,
+        is_home_page: Option<bool>,
+        path: Option<String>,
+    ) -> Result<Page, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let mut page = tables
+            .pages
+            .get(&page_id)
+            .filter(|p| p.user_id == *user_id)
+            .cloned()
+            .ok_or(DbError::RowCount)?;
+
+        if let Some(page_type) = page_type {
+            page.page_type = page_type;
+        }
+        if let Some(content_id) = content_id {
+            page.content_id = content_id;
+        }
+        if let Some(title) = title {
+            page.title = title;
+        }
+        if let Some(is_home_page) = is_home_page {
+            page.is_home_page = is_home_page;
+        }
+        if let Some(path) = path {
+            page.path = path;
+        }
+        page.updated_at = Utc::now();
+
+        tables.check_page(
+            page_id,
+            &page.website_id,
+            &page.title,
+            &page.path,
+        )?;
+        tables.pages.insert(page_id, page.clone());
+
+        Ok(page)
+    }
+
+    async fn delete_with_static_page(
+        &self,
+        page_id: i64,
+        user_id: &String,
+    ) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if tables
+            .static_pages
+            .get(&page_id)
+            .is_some_and(|s| s.user_id == *user_id)
+        {
+            tables.static_pages.remove(&page_id);
+        }
+        if tables
+            .pages
+            .get(&page_id)
+            .is_some_and(|p| p.user_id == *user_id)
+        {
+            tables.pages.remove(&page_id);
+            // the foreign key cascades
+            tables.static_pages.remove(&page_id);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StaticPageRepository for InMemoryRepositories {
+    async fn create(
+        &self,
+        page_id: i64,
+        website_id: &String,
+        user_id: &String,
+        components: Value,
+    ) -> Result<StaticPage, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        tables
+            .check_website_exists(website_id, "fk_static_pages_website_id")?;
+        if !tables.pages.contains_key(&page_id) {
+            return Err(DbError::ForeignKeyViolation(
+                "fk_static_pages_page_id",
+            ));
+        }
+        if tables.static_pages.contains_key(&page_id) {
+            return Err(DbError::UniqueViolation("static_pages_pkey"));
+        }
+
+        let now = Utc::now();
+        let static_page = StaticPage {
+            page_id,
+            website_id: website_id.clone(),
+            user_id: user_id.clone(),
+            created_at: now,
+            updated_at: now,
+            components,
+        };
+        tables.static_pages.insert(page_id, static_page.clone());
+
+        Ok(static_page)
+    }
+
+    async fn get(&self, page_id: i64) -> Result<Option<StaticPage>, DbError> {
+        Ok(self.tables().static_pages.get(&page_id).cloned())
+    }
+
+    async fn update(
+        &self,
+        page_id: i64,
+        user_id: &String,
+        components: Value,
+    ) -> Result<StaticPage, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let static_page = tables
+            .static_pages
+            .get_mut(&page_id)
+            .filter(|s| s.user_id == *user_id)
+            .ok_or(DbError::RowCount)?;
+
+        static_page.components = components;
+        static_page.updated_at = Utc::now();
+
+        Ok(static_page.clone())
+    }
+}
+
+#[async_trait]
+impl CustomizationRepository for InMemoryRepositories {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Customization, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        tables
+            .check_website_exists(website_id, "fk_customizations_website_id")?;
+        if tables.customizations.contains_key(website_id) {
+            return Err(DbError::UniqueViolation("customizations_pkey"));
+        }
+
+        let customization = Customization {
+            website_id: website_id.clone(),
+            user_id: user_id.clone(),
+            primary_color: None,
+            secondary_color: None,
+            logo_image_url: None,
+            branding_sync_at: None,
+            branding_sync_attempts: 0,
+        };
+        tables
+            .customizations
+            .insert(website_id.clone(), customization.clone());
+
+        Ok(customization)
+    }
+
+    async fn get(
+        &self,
+        website_id: &String,
+    ) -> Result<Option<Customization>, DbError> {
+        Ok(self.tables().customizations.get(website_id).cloned())
+    }
+
+    async fn get_for_user(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Option<Customization>, DbError> {
+        Ok(self
+            .tables()
+            .customizations
+            .get(website_id)
+            .filter(|c| c.user_id == *user_id)
+            .cloned())
+    }
+
+    async fn update(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        primary_color: Option<String>,
+        secondary_color: Option<String>,
+    ) -> Result<Customization, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let customization = tables
+            .customizations
+            .get_mut(website_id)
+            .filter(|c| c.user_id == *user_id)
+            .ok_or(DbError::RowCount)?;
+
+        customization.primary_color = primary_color;
+        customization.secondary_color = secondary_color;
+        customization.branding_sync_at = Some(Utc::now());
+        customization.branding_sync_attempts = 0;
+
+        Ok(customization.clone())
+    }
+
+    async fn update_logo_image(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        logo_image_url: Option<String>,
+    ) -> Result<Customization, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let customization = tables
+            .customizations
+            .get_mut(website_id)
+            .filter(|c| c.user_id == *user_id)
+            .ok_or(DbError::RowCount)?;
+
+        customization.logo_image_url = logo_image_url;
+        customization.branding_sync_at = Some(Utc::now());
+        customization.branding_sync_attempts = 0;
+
+        Ok(customization.clone())
+    }
+
+    async fn list_branding_due(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Customization>, DbError> {
+        Ok(self
+            .tables()
+            .customizations
+            .values()
+            .filter(|c| c.branding_sync_at.is_some_and(|at| at <= now))
+            .cloned()
+            .collect())
+    }
+
+    async fn claim_branding_sync(
+        &self,
+        website_id: &String,
+        sync_at: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Option<Customization>, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        match tables.customizations.get_mut(website_id) {
+            Some(customization)
+                if customization.branding_sync_at == Some(sync_at) =>
+            {
+                customization.branding_sync_at = Some(lease_until);
+                customization.branding_sync_attempts += 1;
+                Ok(Some(customization.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn finish_branding_sync(
+        &self,
+        website_id: &String,
+        lease_until: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if let Some(customization) = tables
+            .customizations
+            .get_mut(website_id)
+            .filter(|c| c.branding_sync_at == Some(lease_until))
+        {
+            customization.branding_sync_at = None;
+            customization.branding_sync_attempts = 0;
+        }
+
+        Ok(())
+    }
+
+    async fn retry_branding_sync(
+        &self,
+        website_id: &String,
+        lease_until: DateTime<Utc>,
+        retry_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if let Some(customization) = tables
+            .customizations
+            .get_mut(website_id)
+            .filter(|c| c.branding_sync_at == Some(lease_until))
+        {
+            customization.branding_sync_at = Some(retry_at);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for InMemoryRepositories {
+    async fn create(
+        &self,
+        website_id: &str,
+        subject: &str,
+        message_id: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let message = OutboxMessage {
+            outbox_id: tables.next_id(),
+            website_id: website_id.to_string(),
+            subject: subject.to_string(),
+            message_id: message_id.to_string(),
+            payload,
+            attempts: 0,
+            next_attempt_at: Utc::now(),
+            locked_until: None,
+        };
+        tables.outbox.push(OutboxRow {
+            message,
+            status: OutboxMessage::STATUS_PENDING,
+            delivered_at: None,
+        });
+
+        Ok(())
+    }
+
+    async fn list_pending(
+        &self,
+        now: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<OutboxMessage>, DbError> {
+        let tables = self.tables();
+        let mut blocked = BTreeSet::new();
+        let mut messages = Vec::new();
+
+        for row in tables
+            .outbox
+            .iter()
+            .filter(|row| row.status == OutboxMessage::STATUS_PENDING)
+        {
+            let message = &row.message;
+            if message.next_attempt_at > now
+                || message.locked_until.is_some_and(|l| l > now)
+            {
+                blocked.insert(message.website_id.clone());
+            }
+            if !blocked.contains(&message.website_id) {
+                messages.push(message.clone());
+            }
+        }
+        messages.truncate(limit as usize);
+
+        Ok(messages)
+    }
+
+    async fn claim(
+        &self,
+        outbox_id: i64,
+        locked_until: Option<DateTime<Utc>>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        match tables.outbox.iter_mut().find(|row| {
+            row.message.outbox_id == outbox_id
+                && row.status == OutboxMessage::STATUS_PENDING
+        }) {
+            Some(row) if row.message.locked_until == locked_until => {
+                row.message.locked_until = Some(lease_until);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn mark_delivered(&self, outbox_id: i64) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if let Some(row) = tables
+            .outbox
+            .iter_mut()
+            .find(|row| row.message.outbox_id == outbox_id)
+        {
+            row.status = OutboxMessage::STATUS_DELIVERED;
+            row.delivered_at = Some(Utc::now());
+            row.message.locked_until = None;
+            row.message.attempts += 1;
+        }
+
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        outbox_id: i64,
+        _error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if let Some(row) = tables
+            .outbox
+            .iter_mut()
+            .find(|row| row.message.outbox_id == outbox_id)
+        {
+            row.message.locked_until = None;
+            row.message.attempts += 1;
+            match next_attempt_at {
+                Some(next_attempt_at) => {
+                    row.message.next_attempt_at = next_attempt_at
+                }
+                None => row.status = OutboxMessage::STATUS_DEAD,
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn delete_delivered_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let count = tables.outbox.len();
+        tables.outbox.retain(|row| {
+            row.status != OutboxMessage::STATUS_DELIVERED
+                || row.delivered_at.is_none_or(|d| d >= before)
+        });
+
+        Ok((count - tables.outbox.len()) as u64)
+    }
+
+    async fn delete_dead_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        let count = tables.outbox.len();
+        tables.outbox.retain(|row| {
+            row.status != OutboxMessage::STATUS_DEAD
+                || row.message.next_attempt_at >= before
+        });
+
+        Ok((count - tables.outbox.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl AllowedHostRepository for InMemoryRepositories {
+    async fn create(&self, hostname: &str) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        self.tables().allowed_hosts.insert(hostname.to_string());
+
+        Ok(())
+    }
+
+    async fn exists(&self, hostname: &str) -> Result<bool, DbError> {
+        Ok(self.tables().allowed_hosts.contains(hostname))
+    }
+
+    async fn delete(&self, hostname: &str) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        self.tables().allowed_hosts.remove(hostname);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserDeletionRepository for InMemoryRepositories {
+    async fn create(
+        &self,
+        user_id: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        tables
+            .user_deletions
+            .entry(user_id.to_string())
+            .or_insert_with(|| UserDeletion {
+                user_id: user_id.to_string(),
+                attempts: 0,
+                next_attempt_at,
+                last_error: None,
+            });
+
+        Ok(())
+    }
+
+    async fn list_due(
+        &self,
+        now: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<UserDeletion>, DbError> {
+        let mut user_deletions: Vec<UserDeletion> = self
+            .tables()
+            .user_deletions
+            .values()
+            .filter(|u| u.next_attempt_at <= now)
+            .cloned()
+            .collect();
+        user_deletions.sort_by_key(|u| u.next_attempt_at);
+        user_deletions.truncate(limit as usize);
+
+        Ok(user_deletions)
+    }
+
+    async fn claim(
+        &self,
+        user_id: &str,
+        next_attempt_at: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        match tables.user_deletions.get_mut(user_id) {
+            Some(user_deletion)
+                if user_deletion.next_attempt_at == next_attempt_at =>
+            {
+                user_deletion.next_attempt_at = lease_until;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn mark_failed(
+        &self,
+        user_id: &str,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        let mut tables = self.tables();
+
+        if let Some(user_deletion) = tables.user_deletions.get_mut(user_id) {
+            user_deletion.attempts += 1;
+            user_deletion.next_attempt_at = next_attempt_at;
+            user_deletion.last_error = Some(error.to_string());
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, user_id: &str) -> Result<(), DbError> {
+        let _lock = self.lock_for_write().await;
+        self.tables().user_deletions.remove(user_id);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(id: &str) -> AppProject {
+        AppProject {
+            org_id: id.to_string(),
+            project_id: id.to_string(),
+        }
+    }
+
+    async fn create_website(repositories: &dyn Repositories, name: &str) {
+        let id = name.to_string();
+        repositories
+            .websites()
+            .create(&id, &"user".to_string(), &id, &id, &id, &project(name))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rolls_back_dropped_transactions() {
+        let store = InMemoryStore::new();
+
+        let transaction = store.begin().await.unwrap();
+        create_website(&*transaction, "dropped").await;
+        drop(transaction);
+
+        let transaction = store.begin().await.unwrap();
+        create_website(&*transaction, "committed").await;
+        transaction.commit().await.unwrap();
+
+        let (websites, count) =
+            store.websites().list(&None, 10, 0).await.unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(websites[0].name, "committed");
+    }
+
+    #[tokio::test]
+    async fn enforces_constraints() {
+        let store = InMemoryStore::new();
+        create_website(&store, "website").await;
+
+        let err = store
+            .websites()
+            .create(
+                &"other".to_string(),
+                &"user".to_string(),
+                &"website".to_string(),
+                &"other".to_string(),
+                &"other".to_string(),
+                &project("other"),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DbError::UniqueViolation("uq_user_id_website_name")
+        ));
+
+        let err = store
+            .customizations()
+            .create(&"missing".to_string(), &"user".to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DbError::ForeignKeyViolation("fk_customizations_website_id")
+        ));
+
+        create_website(&store, "other").await;
+        let create_domain = |website_id: &str, status| {
+            let store = store.clone();
+            let website_id = website_id.to_string();
+            async move {
+                let domain = "shop.sited.io".to_string();
+                store
+                    .domains()
+                    .create(
+                        &website_id,
+                        &"user".to_string(),
+                        &domain,
+                        &domain,
+                        status,
+                    )
+                    .await
+            }
+        };
+        create_domain("website", DomainStatus::Internal)
+            .await
+            .unwrap();
+        create_domain("other", DomainStatus::Pending).await.unwrap();
+        create_website(&store, "third").await;
+        let err = create_domain("third", DomainStatus::Redirect)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DbError::UniqueViolation("uq_domains_internal_domain")
+        ));
+    }
+
+    async fn pending_ids(
+        store: &InMemoryStore,
+        now: DateTime<Utc>,
+    ) -> Vec<String> {
+        let messages = store.outbox().list_pending(now, 10).await.unwrap();
+        messages.into_iter().map(|m| m.message_id).collect()
+    }
+
+    #[tokio::test]
+    async fn holds_back_outbox_messages_behind_a_retry() {
+        let store = InMemoryStore::new();
+        let outbox = store.outbox();
+        for (website_id, message_id) in [("a", "1"), ("a", "2"), ("b", "3")] {
+            outbox
+                .create(website_id, "subject", message_id, Vec::new())
+                .await
+                .unwrap();
+        }
+
+        let now = Utc::now();
+        let first = &store.messages()[0];
+        outbox
+            .mark_failed(
+                first.outbox_id,
+                "error",
+                Some(now + chrono::Duration::hours(1)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(pending_ids(&store, now).await, ["3"]);
+
+        outbox
+            .mark_failed(first.outbox_id, "error", None)
+            .await
+            .unwrap();
+        assert_eq!(pending_ids(&store, now).await, ["2", "3"]);
+
+        // dead messages are kept until their retention passed
+        let last_attempt_at = now + chrono::Duration::hours(1);
+        assert_eq!(
+            outbox.delete_dead_before(last_attempt_at).await.unwrap(),
+            0
+        );
+        assert_eq!(store.messages().len(), 3);
+        assert_eq!(
+            outbox
+                .delete_dead_before(
+                    last_attempt_at + chrono::Duration::seconds(1)
+                )
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(store.messages().len(), 2);
+    }
+}
@@ -0,0 +1,447 @@
+// the signatures follow the functions of the models
+#![allow(clippy::ptr_arg)]
+
+mod memory;
+mod postgres;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tonic::async_trait;
+
+use crate::api::sited_io::websites::v1::{DomainStatus, PageType};
+use crate::db::DbError;
+use crate::identity::AppProject;
+use crate::model::{
+    Customization, Domain, OutboxMessage, Page, StaticPage, UserDeletion,
+    Website,
+};
+
+pub use memory::InMemoryStore;
+pub use postgres::PgStore;
+
+#[async_trait]
+pub trait WebsiteRepository: Send + Sync {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        name: &String,
+        client_id: &String,
+        zitadel_app_id: &String,
+        zitadel_project: &AppProject,
+    ) -> Result<Website, DbError>;
+
+    /// Returns the website with its customization, domains and pages.
+    async fn get(
+        &self,
+        website_id: &String,
+    ) -> Result<Option<Website>, DbError>;
+
+    /// Returns the website without relations.
+    async fn get_for_user(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Option<Website>, DbError>;
+
+    /// Returns the website without relations.
+    async fn get_by_name(
+        &self,
+        name: &String,
+        user_id: &String,
+    ) -> Result<Option<Website>, DbError>;
+
+    /// Lists websites with their customization, domains and pages, returns
+    /// them with the total count.
+    async fn list(
+        &self,
+        user_id: &Option<String>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Website>, i64), DbError>;
+
+    /// Lists all websites with their customization, domains and pages.
+    async fn list_all(&self) -> Result<Vec<Website>, DbError>;
+
+    async fn update(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        name: &Option<String>,
+    ) -> Result<Website, DbError>;
+
+    /// Stores the ZITADEL app of the website with the project it is in,
+    /// `None` for the shared project.
+    async fn update_zitadel_app(
+        &self,
+        website_id: &String,
+        client_id: &String,
+        zitadel_app_id: &String,
+        project: Option<&AppProject>,
+    ) -> Result<Website, DbError>;
+
+    /// Sets `updated_at` to now, which keeps `Reconciler` from recreating
+    /// anything of the website for a while.
+    async fn touch(&self, website_id: &String) -> Result<(), DbError>;
+
+    /// Deletes the website with its customization, domains, pages and static
+    /// pages.
+    async fn delete_with_relations(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Website, DbError>;
+}
+
+#[async_trait]
+pub trait DomainRepository: Send + Sync {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        domain: &String,
+        domain_unicode: &String,
+        status: DomainStatus,
+    ) -> Result<Domain, DbError>;
+
+    async fn get_for_user(
+        &self,
+        domain_id: i64,
+        user_id: &String,
+    ) -> Result<Option<Domain>, DbError>;
+
+    async fn get_by_domain(
+        &self,
+        domain: &String,
+    ) -> Result<Option<Domain>, DbError>;
+
+    async fn get_by_domain_and_status(
+        &self,
+        domain: &String,
+        status: DomainStatus,
+    ) -> Result<Option<Domain>, DbError>;
+
+    /// Lists domains ordered by id, returns them with the total count.
+    async fn list(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        status: Option<DomainStatus>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Domain>, i64), DbError>;
+
+    async fn update(
+        &self,
+        domain_id: i64,
+        website_id: &String,
+        user_id: &String,
+        status: DomainStatus,
+    ) -> Result<Domain, DbError>;
+
+    /// Lists the domains of all websites with the status.
+    async fn list_by_status(
+        &self,
+        status: DomainStatus,
+    ) -> Result<Vec<Domain>, DbError>;
+
+    async fn update_last_check(
+        &self,
+        domain_id: i64,
+        succeeded: bool,
+        message: &String,
+    ) -> Result<Domain, DbError>;
+
+    /// Moves the domain from `from_status` to `to_status`. Returns `None` if
+    /// its status changed since it was read.
+    async fn update_check_status(
+        &self,
+        domain_id: i64,
+        from_status: DomainStatus,
+        to_status: DomainStatus,
+        degraded_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Domain>, DbError>;
+
+    async fn delete(
+        &self,
+        domain_id: i64,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<(), DbError>;
+}
+
+#[async_trait]
+pub trait PageRepository: Send + Sync {
+    #[allow(clippy::too_many_arguments)]
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        page_type: PageType,
+        content_id: &String,
+        title: &String,
+        is_home_page: bool,
+        path: &String,
+    ) -> Result<Page, DbError>;
+
+    async fn get(&self, page_id: i64) -> Result<Option<Page>, DbError>;
+
+    async fn get_by_path(
+        &self,
+        website_id: &String,
+        path: &String,
+    ) -> Result<Option<Page>, DbError>;
+
+    async fn get_home_page(
+        &self,
+        website_id: &String,
+    ) -> Result<Option<Page>, DbError>;
+
+    /// Lists pages of all websites showing the given content, e.g. a shop.
+    async fn list_by_content(
+        &self,
+        page_type: PageType,
+        content_id: &String,
+    ) -> Result<Vec<Page>, DbError>;
+
+    /// Lists pages, returns them with the total count.
+    async fn list(
+        &self,
+        website_id: Option<String>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Page>, i64), DbError>;
+
+    /// Updates the given fields, `None` keeps the current value.
+    #[allow(clippy::too_many_arguments)]
+    async fn update(
+        &self,
+        page_id: i64,
+        user_id: &String,
+        page_type: Option<PageType>,
+        content_id: Option<String>,
+        title: Option<String>,
+        is_home_page: Option<bool>,
+        path: Option<String>,
+    ) -> Result<Page, DbError>;
+
+    /// Deletes the page together with its static page, if any.
+    async fn delete_with_static_page(
+        &self,
+        page_id: i64,
+        user_id: &String,
+    ) -> Result<(), DbError>;
+}
+
+#[async_trait]
+pub trait StaticPageRepository: Send + Sync {
+    async fn create(
+        &self,
+        page_id: i64,
+        website_id: &String,
+        user_id: &String,
+        components: Value,
+    ) -> Result<StaticPage, DbError>;
+
+    async fn get(&self, page_id: i64) -> Result<Option<StaticPage>, DbError>;
+
+    async fn update(
+        &self,
+        page_id: i64,
+        user_id: &String,
+        components: Value,
+    ) -> Result<StaticPage, DbError>;
+}
+
+#[async_trait]
+pub trait CustomizationRepository: Send + Sync {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Customization, DbError>;
+
+    async fn get(
+        &self,
+        website_id: &String,
+    ) -> Result<Option<Customization>, DbError>;
+
+    async fn get_for_user(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Option<Customization>, DbError>;
+
+    /// Updates the colours and schedules the branding sync.
+    async fn update(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        primary_color: Option<String>,
+        secondary_color: Option<String>,
+    ) -> Result<Customization, DbError>;
+
+    /// Updates the logo and schedules the branding sync.
+    async fn update_logo_image(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        logo_image_url: Option<String>,
+    ) -> Result<Customization, DbError>;
+
+    /// The branding syncs of `BrandingSyncJob`.
+    async fn list_branding_due(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Customization>, DbError>;
+
+    async fn claim_branding_sync(
+        &self,
+        website_id: &String,
+        sync_at: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Option<Customization>, DbError>;
+
+    async fn finish_branding_sync(
+        &self,
+        website_id: &String,
+        lease_until: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+
+    async fn retry_branding_sync(
+        &self,
+        website_id: &String,
+        lease_until: DateTime<Utc>,
+        retry_at: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+}
+
+/// Messages waiting to be relayed to the message broker, see `Publisher`.
+#[async_trait]
+pub trait OutboxRepository: Send + Sync {
+    async fn create(
+        &self,
+        website_id: &str,
+        subject: &str,
+        message_id: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), DbError>;
+
+    /// The messages of `OutboxRelay`, see `OutboxMessage::list_pending`.
+    async fn list_pending(
+        &self,
+        now: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<OutboxMessage>, DbError>;
+
+    async fn claim(
+        &self,
+        outbox_id: i64,
+        locked_until: Option<DateTime<Utc>>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool, DbError>;
+
+    async fn mark_delivered(&self, outbox_id: i64) -> Result<(), DbError>;
+
+    async fn mark_failed(
+        &self,
+        outbox_id: i64,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DbError>;
+
+    /// Returns how many messages were deleted.
+    async fn delete_delivered_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError>;
+
+    /// Deletes the dead messages whose last attempt was before `before`,
+    /// returns how many were deleted.
+    async fn delete_dead_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError>;
+}
+
+/// Hostnames an on-demand TLS edge may request certificates for, see
+/// `OnDemandTlsProvider`.
+#[async_trait]
+pub trait AllowedHostRepository: Send + Sync {
+    /// Creating an allowed hostname again is not an error.
+    async fn create(&self, hostname: &str) -> Result<(), DbError>;
+
+    async fn exists(&self, hostname: &str) -> Result<bool, DbError>;
+
+    async fn delete(&self, hostname: &str) -> Result<(), DbError>;
+}
+
+/// Deleted users whose websites are still to be deleted, see
+/// `LifecycleConsumer`.
+#[async_trait]
+pub trait UserDeletionRepository: Send + Sync {
+    async fn create(
+        &self,
+        user_id: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+
+    async fn list_due(
+        &self,
+        now: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<UserDeletion>, DbError>;
+
+    async fn claim(
+        &self,
+        user_id: &str,
+        next_attempt_at: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool, DbError>;
+
+    async fn mark_failed(
+        &self,
+        user_id: &str,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), DbError>;
+
+    async fn delete(&self, user_id: &str) -> Result<(), DbError>;
+}
+
+/// The repositories of all aggregates, either on their own or within one
+/// transaction.
+pub trait Repositories: Send + Sync {
+    fn websites(&self) -> &dyn WebsiteRepository;
+    fn domains(&self) -> &dyn DomainRepository;
+    fn pages(&self) -> &dyn PageRepository;
+    fn static_pages(&self) -> &dyn StaticPageRepository;
+    fn customizations(&self) -> &dyn CustomizationRepository;
+    fn outbox(&self) -> &dyn OutboxRepository;
+    fn user_deletions(&self) -> &dyn UserDeletionRepository;
+    fn allowed_hosts(&self) -> &dyn AllowedHostRepository;
+}
+
+/// Storage of websites used by the services. Changes spanning several rows,
+/// including the messages written to the outbox, are made within one
+/// transaction started by `begin`.
+#[async_trait]
+pub trait Store: Repositories {
+    async fn begin(&self) -> Result<Box<dyn StoreTransaction>, DbError>;
+
+    /// The repositories on the read replica, which may lag behind. Only for
+    /// public reads, editors must read their own writes from the store
+    /// itself. Stores without replica return themselves.
+    fn replica(&self) -> &dyn Repositories;
+}
+
+/// Changes become visible to others on `commit`. Dropping the transaction
+/// before rolls them back.
+#[async_trait]
+pub trait StoreTransaction: Repositories {
+    async fn commit(self: Box<Self>) -> Result<(), DbError>;
+}
+
+pub type DynStore = Arc<dyn Store>;
@@ -0,0 +1,875 @@
+use std::ops::Deref;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{GenericClient, Object, Pool};
+use serde_json::Value;
+use tonic::async_trait;
+
+use crate::api::sited_io::websites::v1::{DomainStatus, PageType};
+use crate::db::DbError;
+use crate::identity::AppProject;
+use crate::model::{
+    AllowedHost, Customization, Domain, OutboxMessage, Page, StaticPage,
+    UserDeletion, Website,
+};
+
+use super::{
+    AllowedHostRepository, CustomizationRepository, DomainRepository,
+    OutboxRepository, PageRepository, Repositories, StaticPageRepository,
+    Store, StoreTransaction, UserDeletionRepository, WebsiteRepository,
+};
+
+/// Store on Postgres, running the sea-query functions of the models.
+pub struct PgStore {
+    repositories: PgRepositories,
+    replica: Option<PgRepositories>,
+}
+
+impl PgStore {
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            repositories: PgRepositories {
+                pool,
+                transaction: None,
+            },
+            replica: None,
+        }
+    }
+
+    /// Serves the reads of `Store::replica` from the pool of the replica.
+    pub fn with_replica(mut self, pool: Pool) -> Self {
+        self.replica = Some(PgRepositories {
+            pool,
+            transaction: None,
+        });
+        self
+    }
+}
+
+impl Repositories for PgStore {
+    fn websites(&self) -> &dyn WebsiteRepository {
+        &self.repositories
+    }
+
+    fn domains(&self) -> &dyn DomainRepository {
+        &self.repositories
+    }
+
+    fn pages(&self) -> &dyn PageRepository {
+        &self.repositories
+    }
+
+    fn static_pages(&self) -> &dyn StaticPageRepository {
+        &self.repositories
+    }
+
+    fn customizations(&self) -> &dyn CustomizationRepository {
+        &self.repositories
+    }
+
+    fn outbox(&self) -> &dyn OutboxRepository {
+        &self.repositories
+    }
+
+    fn user_deletions(&self) -> &dyn UserDeletionRepository {
+        &self.repositories
+    }
+
+    fn allowed_hosts(&self) -> &dyn AllowedHostRepository {
+        &self.repositories
+    }
+}
+
+#[async_trait]
+impl Store for PgStore {
+    async fn begin(&self) -> Result<Box<dyn StoreTransaction>, DbError> {
+        let client = self.repositories.pool.get().await?;
+        client.batch_execute("BEGIN").await?;
+
+        Ok(Box::new(PgTransaction {
+            repositories: PgRepositories {
+                pool: self.repositories.pool.clone(),
+                transaction: Some(client),
+            },
+        }))
+    }
+
+    fn replica(&self) -> &dyn Repositories {
+        match &self.replica {
+            Some(replica) => replica,
+            None => self,
+        }
+    }
+}
+
+/// Owns the connection of the transaction, unlike
+/// `deadpool_postgres::Transaction`, so it can be boxed.
+pub struct PgTransaction {
+    repositories: PgRepositories,
+}
+
+impl Repositories for PgTransaction {
+    fn websites(&self) -> &dyn WebsiteRepository {
+        &self.repositories
+    }
+
+    fn domains(&self) -> &dyn DomainRepository {
+        &self.repositories
+    }
+
+    fn pages(&self) -> &dyn PageRepository {
+        &self.repositories
+    }
+
+    fn static_pages(&self) -> &dyn StaticPageRepository {
+        &self.repositories
+    }
+
+    fn customizations(&self) -> &dyn CustomizationRepository {
+        &self.repositories
+    }
+
+    fn outbox(&self) -> &dyn OutboxRepository {
+        &self.repositories
+    }
+
+    fn user_deletions(&self) -> &dyn UserDeletionRepository {
+        &self.repositories
+    }
+
+    fn allowed_hosts(&self) -> &dyn AllowedHostRepository {
+        &self.repositories
+    }
+}
+
+#[async_trait]
+impl StoreTransaction for PgTransaction {
+    async fn commit(mut self: Box<Self>) -> Result<(), DbError> {
+        if let Some(client) = self.repositories.transaction.take() {
+            if let Err(err) = client.batch_execute("COMMIT").await {
+                discard(client);
+                return Err(err.into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for PgTransaction {
+    fn drop(&mut self) {
+        let Some(client) = self.repositories.transaction.take() else {
+            return;
+        };
+
+        // the connection goes back to the pool once rolled back
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    if let Err(err) = client.batch_execute("ROLLBACK").await {
+                        tracing::log::error!("[PgTransaction.drop]: {}", err);
+                        discard(client);
+                    }
+                });
+            }
+            Err(_) => discard(client),
+        }
+    }
+}
+
+/// Removes a connection that may still be in a transaction from the pool.
+/// Closing it makes the server roll the transaction back.
+fn discard(client: Object) {
+    drop(Object::take(client));
+}
+
+/// Runs every query on a connection of the pool, or on the connection of
+/// the transaction if there is one.
+struct PgRepositories {
+    pool: Pool,
+    transaction: Option<Object>,
+}
+
+enum PgClient<'a> {
+    Pooled(Box<Object>),
+    Transaction(&'a Object),
+}
+
+impl Deref for PgClient<'_> {
+    type Target = Object;
+
+    fn deref(&self) -> &Object {
+        match self {
+            Self::Pooled(client) => client,
+            Self::Transaction(client) => client,
+        }
+    }
+}
+
+impl PgRepositories {
+    async fn client(&self) -> Result<PgClient<'_>, DbError> {
+        match &self.transaction {
+            Some(client) => Ok(PgClient::Transaction(client)),
+            None => Ok(PgClient::Pooled(Box::new(self.pool.get().await?))),
+        }
+    }
+}
+
+impl Repositories for PgRepositories {
+    fn websites(&self) -> &dyn WebsiteRepository {
+        self
+    }
+
+    fn domains(&self) -> &dyn DomainRepository {
+        self
+    }
+
+    fn pages(&self) -> &dyn PageRepository {
+        self
+    }
+
+    fn static_pages(&self) -> &dyn StaticPageRepository {
+        self
+    }
+
+    fn customizations(&self) -> &dyn CustomizationRepository {
+        self
+    }
+
+    fn outbox(&self) -> &dyn OutboxRepository {
+        self
+    }
+
+    fn user_deletions(&self) -> &dyn UserDeletionRepository {
+        self
+    }
+
+    fn allowed_hosts(&self) -> &dyn AllowedHostRepository {
+        self
+    }
+}
+
+#[async_trait]
+impl WebsiteRepository for PgRepositories {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        name: &String,
+        client_id: &String,
+        zitadel_app_id: &String,
+        zitadel_project: &AppProject,
+    ) -> Result<Website, DbError> {
+        let client = self.client().await?;
+        Website::create(
+            &*client,
+            website_id,
+            user_id,
+            name,
+            client_id,
+            zitadel_app_id,
+            zitadel_project,
+        )
+        .await
+    }
+
+    async fn get(
+        &self,
+        website_id: &String,
+    ) -> Result<Option<Website>, DbError> {
+        let client = self.client().await?;
+        Website::get(&*client, website_id).await
+    }
+
+    async fn get_for_user(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Option<Website>, DbError> {
+        let client = self.client().await?;
+        Website::get_for_user(&*client, website_id, user_id).await
+    }
+
+    async fn get_by_name(
+        &self,
+        name: &String,
+        user_id: &String,
+    ) -> Result<Option<Website>, DbError> {
+        let client = self.client().await?;
+        Website::get_by_name(&*client, name, user_id).await
+    }
+
+    async fn list(
+        &self,
+        user_id: &Option<String>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Website>, i64), DbError> {
+        let client = self.client().await?;
+        Website::list(&*client, user_id, limit, offset).await
+    }
+
+    async fn list_all(&self) -> Result<Vec<Website>, DbError> {
+        let client = self.client().await?;
+        Website::list_all(&*client).await
+    }
+
+    async fn update(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        name: &Option<String>,
+    ) -> Result<Website, DbError> {
+        let client = self.client().await?;
+        Website::update(&*client, website_id, user_id, name).await
+    }
+
+    async fn update_zitadel_app(
+        &self,
+        website_id: &String,
+        client_id: &String,
+        zitadel_app_id: &String,
+        project: Option<&AppProject>,
+    ) -> Result<Website, DbError> {
+        let client = self.client().await?;
+        Website::update_zitadel_app(
+            &*client,
+            website_id,
+            client_id,
+            zitadel_app_id,
+            project,
+        )
+        .await
+    }
+
+    async fn touch(&self, website_id: &String) -> Result<(), DbError> {
+        let client = self.client().await?;
+        Website::touch(&*client, website_id).await
+    }
+
+    async fn delete_with_relations(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Website, DbError> {
+        if let Some(client) = &self.transaction {
+            return Website::delete_with_relations(client, website_id, user_id)
+                .await;
+        }
+
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        let website =
+            Website::delete_with_relations(&transaction, website_id, user_id)
+                .await?;
+        transaction.commit().await?;
+
+        Ok(website)
+    }
+}
+
+#[async_trait]
+impl DomainRepository for PgRepositories {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        domain: &String,
+        domain_unicode: &String,
+        status: DomainStatus,
+    ) -> Result<Domain, DbError> {
+        let client = self.client().await?;
+        Domain::create(
+            &*client,
+            website_id,
+            user_id,
+            domain,
+            domain_unicode,
+            status,
+        )
+        .await
+    }
+
+    async fn get_for_user(
+        &self,
+        domain_id: i64,
+        user_id: &String,
+    ) -> Result<Option<Domain>, DbError> {
+        let client = self.client().await?;
+        Domain::get_for_user(&*client, domain_id, user_id).await
+    }
+
+    async fn get_by_domain(
+        &self,
+        domain: &String,
+    ) -> Result<Option<Domain>, DbError> {
+        let client = self.client().await?;
+        Domain::get_by_domain(&*client, domain).await
+    }
+
+    async fn get_by_domain_and_status(
+        &self,
+        domain: &String,
+        status: DomainStatus,
+    ) -> Result<Option<Domain>, DbError> {
+        let client = self.client().await?;
+        Domain::get_by_domain_and_status(&*client, domain, status).await
+    }
+
+    async fn list(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        status: Option<DomainStatus>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Domain>, i64), DbError> {
+        let client = self.client().await?;
+        Domain::list(&*client, website_id, user_id, status, limit, offset).await
+    }
+
+    async fn update(
+        &self,
+        domain_id: i64,
+        website_id: &String,
+        user_id: &String,
+        status: DomainStatus,
+    ) -> Result<Domain, DbError> {
+        let client = self.client().await?;
+        Domain::update(&*client, domain_id, website_id, user_id, status).await
+    }
+
+    async fn list_by_status(
+        &self,
+        status: DomainStatus,
+    ) -> Result<Vec<Domain>, DbError> {
+        let client = self.client().await?;
+        Domain::list_by_status(&*client, status).await
+    }
+
+    async fn update_last_check(
+        &self,
+        domain_id: i64,
+        succeeded: bool,
+        message: &String,
+    ) -> Result<Domain, DbError> {
+        let client = self.client().await?;
+        Domain::update_last_check(&*client, domain_id, succeeded, message).await
+    }
+
+    async fn update_check_status(
+        &self,
+        domain_id: i64,
+        from_status: DomainStatus,
+        to_status: DomainStatus,
+        degraded_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Domain>, DbError> {
+        let client = self.client().await?;
+        Domain::update_check_status(
+            &*client,
+            domain_id,
+            from_status,
+            to_status,
+            degraded_at,
+        )
+        .await
+    }
+
+    async fn delete(
+        &self,
+        domain_id: i64,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<(), DbError> {
+        let client = self.client().await?;
+        Domain::delete(&*client, domain_id, website_id, user_id).await
+    }
+}
+
+#[async_trait]
+impl PageRepository for PgRepositories {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        page_type: PageType,
+        content_id: &String,
+        title: &String,
+        is_home_page: bool,
+        path: &String,
+    ) -> Result<Page, DbError> {
+        let client = self.client().await?;
+        Page::create(
+            &*client,
+            website_id,
+            user_id,
+            page_type,
+            content_id,
+            title,
+            is_home_page,
+            path,
+        )
+        .await
+    }
+
+    async fn get(&self, page_id: i64) -> Result<Option<Page>, DbError> {
+        let client = self.client().await?;
+        Page::get(&*client, page_id).await
+    }
+
+    async fn get_by_path(
+        &self,
+        website_id: &String,
+        path: &String,
+    ) -> Result<Option<Page>, DbError> {
+        let client = self.client().await?;
+        Page::get_by_path(&*client, website_id, path).await
+    }
+
+    async fn get_home_page(
+        &self,
+        website_id: &String,
+    ) -> Result<Option<Page>, DbError> {
+        let client = self.client().await?;
+        Page::get_home_page(&*client, website_id).await
+    }
+
+    async fn list_by_content(
+        &self,
+        page_type: PageType,
+        content_id: &String,
+    ) -> Result<Vec<Page>, DbError> {
+        let client = self.client().await?;
+        Page::list_by_content(&*client, page_type, content_id).await
+    }
+
+    async fn list(
+        &self,
+        website_id: Option<String>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(Vec<Page>, i64), DbError> {
+        let client = self.client().await?;
+        Page::list(&*client, website_id, limit, offset).await
+    }
+
+    async fn update(
+        &self,
+        page_id: i64,
+        user_id: &String,
+        page_type: Option<PageType>,
+        content_id: Option<String>,
+        title: Option<String>,
+        is_home_page: Option<bool>,
+        path: Option<String>,
+    ) -> Result<Page, DbError> {
+        let client = self.client().await?;
+        Page::update(
+            &*client,
+            page_id,
+            user_id,
+            page_type,
+            content_id,
+            title,
+            is_home_page,
+            path,
+        )
+        .await
+    }
+
+    async fn delete_with_static_page(
+        &self,
+        page_id: i64,
+        user_id: &String,
+    ) -> Result<(), DbError> {
+        if let Some(client) = &self.transaction {
+            return Page::delete_with_static_page(client, page_id, user_id)
+                .await;
+        }
+
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        Page::delete_with_static_page(&transaction, page_id, user_id).await?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StaticPageRepository for PgRepositories {
+    async fn create(
+        &self,
+        page_id: i64,
+        website_id: &String,
+        user_id: &String,
+        components: Value,
+    ) -> Result<StaticPage, DbError> {
+        let client = self.client().await?;
+        StaticPage::create(&*client, page_id, website_id, user_id, components)
+            .await
+    }
+
+    async fn get(&self, page_id: i64) -> Result<Option<StaticPage>, DbError> {
+        let client = self.client().await?;
+        StaticPage::get(&*client, page_id).await
+    }
+
+    async fn update(
+        &self,
+        page_id: i64,
+        user_id: &String,
+        components: Value,
+    ) -> Result<StaticPage, DbError> {
+        let client = self.client().await?;
+        StaticPage::update(&*client, page_id, user_id, components).await
+    }
+}
+
+#[async_trait]
+impl CustomizationRepository for PgRepositories {
+    async fn create(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Customization, DbError> {
+        let client = self.client().await?;
+        Customization::create(&*client, website_id, user_id).await
+    }
+
+    async fn get(
+        &self,
+        website_id: &String,
+    ) -> Result<Option<Customization>, DbError> {
+        let client = self.client().await?;
+        Customization::get(&*client, website_id).await
+    }
+
+    async fn get_for_user(
+        &self,
+        website_id: &String,
+        user_id: &String,
+    ) -> Result<Option<Customization>, DbError> {
+        let client = self.client().await?;
+        Customization::get_for_user(&*client, website_id, user_id).await
+    }
+
+    async fn update(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        primary_color: Option<String>,
+        secondary_color: Option<String>,
+    ) -> Result<Customization, DbError> {
+        let client = self.client().await?;
+        Customization::update(
+            &*client,
+            website_id,
+            user_id,
+            primary_color,
+            secondary_color,
+        )
+        .await
+    }
+
+    async fn update_logo_image(
+        &self,
+        website_id: &String,
+        user_id: &String,
+        logo_image_url: Option<String>,
+    ) -> Result<Customization, DbError> {
+        let client = self.client().await?;
+        Customization::update_logo_image(
+            &*client,
+            website_id,
+            user_id,
+            logo_image_url,
+        )
+        .await
+    }
+
+    async fn list_branding_due(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Customization>, DbError> {
+        let client = self.client().await?;
+        Customization::list_branding_due(&*client, now).await
+    }
+
+    async fn claim_branding_sync(
+        &self,
+        website_id: &String,
+        sync_at: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Option<Customization>, DbError> {
+        let client = self.client().await?;
+        Customization::claim_branding_sync(
+            &*client,
+            website_id,
+            sync_at,
+            lease_until,
+        )
+        .await
+    }
+
+    async fn finish_branding_sync(
+        &self,
+        website_id: &String,
+        lease_until: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let client = self.client().await?;
+        Customization::finish_branding_sync(&*client, website_id, lease_until)
+            .await
+    }
+
+    async fn retry_branding_sync(
+        &self,
+        website_id: &String,
+        lease_until: DateTime<Utc>,
+        retry_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let client = self.client().await?;
+        Customization::retry_branding_sync(
+            &*client,
+            website_id,
+            lease_until,
+            retry_at,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for PgRepositories {
+    async fn create(
+        &self,
+        website_id: &str,
+        subject: &str,
+        message_id: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), DbError> {
+        let client = self.client().await?;
+        OutboxMessage::create(
+            &*client, website_id, subject, message_id, payload,
+        )
+        .await
+    }
+
+    async fn list_pending(
+        &self,
+        now: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<OutboxMessage>, DbError> {
+        let client = self.client().await?;
+        OutboxMessage::list_pending(&*client, now, limit).await
+    }
+
+    async fn claim(
+        &self,
+        outbox_id: i64,
+        locked_until: Option<DateTime<Utc>>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        let client = self.client().await?;
+        OutboxMessage::claim(&*client, outbox_id, locked_until, lease_until)
+            .await
+    }
+
+    async fn mark_delivered(&self, outbox_id: i64) -> Result<(), DbError> {
+        let client = self.client().await?;
+        OutboxMessage::mark_delivered(&*client, outbox_id).await
+    }
+
+    async fn mark_failed(
+        &self,
+        outbox_id: i64,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DbError> {
+        let client = self.client().await?;
+        OutboxMessage::mark_failed(&*client, outbox_id, error, next_attempt_at)
+            .await
+    }
+
+    async fn delete_delivered_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        let client = self.client().await?;
+        OutboxMessage::delete_delivered_before(&*client, before).await
+    }
+
+    async fn delete_dead_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<u64, DbError> {
+        let client = self.client().await?;
+        OutboxMessage::delete_dead_before(&*client, before).await
+    }
+}
+
+#[async_trait]
+impl UserDeletionRepository for PgRepositories {
+    async fn create(
+        &self,
+        user_id: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let client = self.client().await?;
+        UserDeletion::create(&*client, user_id, next_attempt_at).await
+    }
+
+    async fn list_due(
+        &self,
+        now: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<UserDeletion>, DbError> {
+        let client = self.client().await?;
+        UserDeletion::list_due(&*client, now, limit).await
+    }
+
+    async fn claim(
+        &self,
+        user_id: &str,
+        next_attempt_at: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> Result<bool, DbError> {
+        let client = self.client().await?;
+        UserDeletion::claim(&*client, user_id, next_attempt_at, lease_until)
+            .await
+    }
+
+    async fn mark_failed(
+        &self,
+        user_id: &str,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), DbError> {
+        let client = self.client().await?;
+        UserDeletion::mark_failed(&*client, user_id, error, next_attempt_at)
+            .await
+    }
+
+    async fn delete(&self, user_id: &str) -> Result<(), DbError> {
+        let client = self.client().await?;
+        UserDeletion::delete(&*client, user_id).await
+    }
+}
+
+#[async_trait]
+impl AllowedHostRepository for PgRepositories {
+    async fn create(&self, hostname: &str) -> Result<(), DbError> {
+        let client = self.client().await?;
+        AllowedHost::create(&*client, hostname).await
+    }
+
+    async fn exists(&self, hostname: &str) -> Result<bool, DbError> {
+        let client = self.client().await?;
+        AllowedHost::exists(&*client, hostname).await
+    }
+
+    async fn delete(&self, hostname: &str) -> Result<(), DbError> {
+        let client = self.client().await?;
+        AllowedHost::delete(&*client, hostname).await
+    }
+}
@@ -1,37 +1,77 @@
-use deadpool_postgres::Pool;
+use std::sync::Arc;
+
 use jwtk::jwk::RemoteJwksVerifier;
+use tokio::sync::Notify;
 use tonic::{async_trait, Request, Response, Status};
 use uuid::Uuid;
 
 use crate::api::sited_io::websites::v1::customization_service_server::{
     self, CustomizationServiceServer,
 };
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
 use crate::api::sited_io::websites::v1::{
     CustomizationResponse, PutLogoImageRequest, PutLogoImageResponse,
     RemoveLogoImageRequest, RemoveLogoImageResponse,
     UpdateCustomizationRequest, UpdateCustomizationResponse,
 };
 use crate::auth::get_user_id;
+use crate::cache_purge::CachePurger;
 use crate::images::ImageService;
 use crate::model::{Customization, CustomizationAsRel};
+use crate::publisher::{EventAction, Publisher};
+use crate::repository::{DynStore, StoreTransaction};
+use crate::website_cache::WebsiteCache;
 
 pub struct CustomizationService {
-    pool: Pool,
+    store: DynStore,
     verifier: RemoteJwksVerifier,
     image_service: ImageService,
+    branding_sync: Arc<Notify>,
+    publisher: Publisher,
+    cache_purger: CachePurger,
+    website_cache: WebsiteCache,
 }
 
 impl CustomizationService {
     pub fn build(
-        pool: Pool,
+        store: DynStore,
         verifier: RemoteJwksVerifier,
         image_service: ImageService,
+        branding_sync: Arc<Notify>,
+        publisher: Publisher,
+        cache_purger: CachePurger,
+        website_cache: WebsiteCache,
     ) -> CustomizationServiceServer<Self> {
-        CustomizationServiceServer::new(Self {
-            pool,
+        CustomizationServiceServer::new(Self::new(
+            store,
             verifier,
             image_service,
-        })
+            branding_sync,
+            publisher,
+            cache_purger,
+            website_cache,
+        ))
+    }
+
+    /// Builds the service without the gRPC server, e.g. for tests.
+    pub fn new(
+        store: DynStore,
+        verifier: RemoteJwksVerifier,
+        image_service: ImageService,
+        branding_sync: Arc<Notify>,
+        publisher: Publisher,
+        cache_purger: CachePurger,
+        website_cache: WebsiteCache,
+    ) -> Self {
+        Self {
+            store,
+            verifier,
+            image_service,
+            branding_sync,
+            publisher,
+            cache_purger,
+            website_cache,
+        }
     }
 
     pub fn to_response(
@@ -47,6 +87,38 @@ impl CustomizationService {
         }
     }
 
+    /// Publishes the changed customization in the transaction of the change,
+    /// commits, wakes up the branding sync and purges the cached website.
+    async fn commit_update(
+        &self,
+        transaction: Box<dyn StoreTransaction>,
+        user_id: &str,
+        customization: Customization,
+    ) -> Result<(), Status> {
+        let website_id = customization.website_id.clone();
+
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Updated,
+                user_id,
+                &website_id,
+                Payload::Customization(Self::to_response(
+                    &self.image_service,
+                    customization,
+                )),
+            )
+            .await?;
+
+        transaction.commit().await?;
+        self.website_cache.invalidate(&website_id);
+
+        self.branding_sync.notify_one();
+        self.cache_purger.purge_website(&website_id);
+
+        Ok(())
+    }
+
     fn gen_image_path(user_id: &String, website_id: &String) -> String {
         format!("{}/{}/{}", user_id, website_id, Uuid::new_v4())
     }
@@ -68,12 +140,17 @@ impl customization_service_server::CustomizationService
             secondary_color,
         } = request.into_inner();
 
-        let updated_customization = Customization::update(
-            &self.pool,
-            &website_id,
+        let transaction = self.store.begin().await?;
+
+        let updated_customization = transaction
+            .customizations()
+            .update(&website_id, &user_id, primary_color, secondary_color)
+            .await?;
+
+        self.commit_update(
+            transaction,
             &user_id,
-            primary_color,
-            secondary_color,
+            updated_customization.clone(),
         )
         .await?;
 
@@ -100,7 +177,7 @@ impl customization_service_server::CustomizationService
         self.image_service.validate_image(&image.data)?;
 
         let existing_customization =
-            Customization::get(&self.pool, &website_id).await?;
+            self.store.customizations().get(&website_id).await?;
 
         if let Some(existing) = existing_customization
             .as_ref()
@@ -115,13 +192,15 @@ impl customization_service_server::CustomizationService
             .put_image(&image_path, &image.data)
             .await?;
 
-        Customization::update_logo_image(
-            &self.pool,
-            &website_id,
-            &user_id,
-            Some(image_path),
-        )
-        .await?;
+        let transaction = self.store.begin().await?;
+
+        let updated_customization = transaction
+            .customizations()
+            .update_logo_image(&website_id, &user_id, Some(image_path))
+            .await?;
+
+        self.commit_update(transaction, &user_id, updated_customization)
+            .await?;
 
         Ok(Response::new(PutLogoImageResponse {}))
     }
@@ -135,7 +214,7 @@ impl customization_service_server::CustomizationService
         let RemoveLogoImageRequest { website_id } = request.into_inner();
 
         let existing_customization =
-            Customization::get(&self.pool, &website_id).await?;
+            self.store.customizations().get(&website_id).await?;
 
         if let Some(existing) = existing_customization
             .as_ref()
@@ -144,13 +223,15 @@ impl customization_service_server::CustomizationService
             self.image_service.remove_image(existing).await?;
         }
 
-        Customization::update_logo_image(
-            &self.pool,
-            &website_id,
-            &user_id,
-            None,
-        )
-        .await?;
+        let transaction = self.store.begin().await?;
+
+        let updated_customization = transaction
+            .customizations()
+            .update_logo_image(&website_id, &user_id, None)
+            .await?;
+
+        self.commit_update(transaction, &user_id, updated_customization)
+            .await?;
 
         Ok(Response::new(RemoveLogoImageResponse {}))
     }
@@ -1,109 +1,319 @@
 use std::collections::HashSet;
 
-use deadpool_postgres::Pool;
-use http::Uri;
 use jwtk::jwk::RemoteJwksVerifier;
 use tonic::{async_trait, Request, Response, Status};
 
 use crate::api::sited_io::websites::v1::domain_service_server::{
     self, DomainServiceServer,
 };
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
 use crate::api::sited_io::websites::v1::{
     CheckDomainStatusRequest, CheckDomainStatusResponse, CreateDomainRequest,
     CreateDomainResponse, DeleteDomainRequest, DeleteDomainResponse,
-    DomainResponse, DomainStatus,
+    DomainCheckResult, DomainResponse, DomainStatus, DomainVerification,
+    GetDomainRequest, GetDomainResponse, ListDomainsRequest,
+    ListDomainsResponse,
 };
 use crate::auth::get_user_id;
-use crate::cloudflare::{CloudflareService, DnsLookupResponse};
-use crate::model::{Domain, DomainAsRel, Website};
+use crate::dns::{
+    DnsAnswer, DnsResolver, DynDnsResolver, RECORD_TYPE_A, RECORD_TYPE_AAAA,
+    RECORD_TYPE_CNAME,
+};
+use crate::domain_check::DomainCheckJob;
+use crate::domain_name::{DomainName, DomainNameError};
+use crate::edge::DynEdgeProvider;
+use crate::identity::DynIdentityProvider;
+use crate::model::{Domain, DomainAsRel};
+use crate::publisher::{EventAction, Publisher};
+use crate::repository::DynStore;
+use crate::website_cache::WebsiteCache;
+use crate::{datetime_to_timestamp, i64_to_u32, WebsiteService};
+
+use super::get_limit_offset_from_pagination;
 
 pub struct DomainService {
-    pool: Pool,
+    store: DynStore,
     verifier: RemoteJwksVerifier,
+    main_domain: String,
     fallback_domain: String,
-    cloudflare_service: CloudflareService,
+    identity_provider: DynIdentityProvider,
+    edge_provider: DynEdgeProvider,
+    dns_resolver: DynDnsResolver,
+    publisher: Publisher,
+    website_cache: WebsiteCache,
 }
 
 impl DomainService {
+    const VERIFICATION_RECORD_TYPE: &'static str = "CNAME";
+
+    #[allow(clippy::too_many_arguments)]
     pub fn build(
-        pool: Pool,
+        store: DynStore,
         verifier: RemoteJwksVerifier,
+        main_domain: String,
         fallback_domain: String,
-        cloudflare_service: CloudflareService,
+        identity_provider: DynIdentityProvider,
+        edge_provider: DynEdgeProvider,
+        dns_resolver: DynDnsResolver,
+        publisher: Publisher,
+        website_cache: WebsiteCache,
     ) -> DomainServiceServer<Self> {
-        DomainServiceServer::new(Self {
-            pool,
+        DomainServiceServer::new(Self::new(
+            store,
             verifier,
+            main_domain,
             fallback_domain,
-            cloudflare_service,
-        })
+            identity_provider,
+            edge_provider,
+            dns_resolver,
+            publisher,
+            website_cache,
+        ))
+    }
+
+    /// Builds the service without the gRPC server, e.g. for tests.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        store: DynStore,
+        verifier: RemoteJwksVerifier,
+        main_domain: String,
+        fallback_domain: String,
+        identity_provider: DynIdentityProvider,
+        edge_provider: DynEdgeProvider,
+        dns_resolver: DynDnsResolver,
+        publisher: Publisher,
+        website_cache: WebsiteCache,
+    ) -> Self {
+        Self {
+            store,
+            verifier,
+            main_domain,
+            fallback_domain,
+            identity_provider,
+            edge_provider,
+            dns_resolver,
+            publisher,
+            website_cache,
+        }
     }
 
     pub fn to_response(domain: impl Into<DomainAsRel>) -> DomainResponse {
         let domain: DomainAsRel = domain.into();
         DomainResponse {
             domain_id: domain.domain_id,
+            domain_unicode: domain
+                .domain_unicode
+                .unwrap_or_else(|| domain.domain.clone()),
             domain: domain.domain,
-            status: DomainStatus::from_str_name(&domain.status).unwrap().into(),
+            status: domain.status.into(),
+            ..Default::default()
         }
     }
 
-    pub fn validate_domain(input: &String) -> Result<(), Status> {
-        if !input.contains('.') || input.ends_with('.') {
-            return Err(Status::invalid_argument(
-                "Domain must contain a dot ('.') and must not end on a dot",
-            ));
-        }
+    fn to_full_response(&self, domain: Domain) -> DomainResponse {
+        let is_internal = domain.status == DomainStatus::Internal
+            || domain.status == DomainStatus::Redirect;
+
+        let verification = if is_internal {
+            None
+        } else {
+            Some(DomainVerification {
+                record_type: Self::VERIFICATION_RECORD_TYPE.to_string(),
+                record_name: domain.domain.clone(),
+                record_value: self.fallback_domain.clone(),
+            })
+        };
 
-        let parsed = input
-            .parse::<Uri>()
-            .map_err(|_| Status::invalid_argument("Domain is not valid"))?;
+        let last_check =
+            domain.last_checked_at.map(|checked_at| DomainCheckResult {
+                checked_at: datetime_to_timestamp(checked_at),
+                succeeded: domain.last_check_succeeded.unwrap_or_default(),
+                message: domain.last_check_message.clone().unwrap_or_default(),
+            });
 
-        let host = parsed
-            .host()
-            .ok_or_else(|| Status::invalid_argument("Domain is not valid"))?;
+        DomainResponse {
+            created_at: datetime_to_timestamp(domain.created_at),
+            updated_at: datetime_to_timestamp(domain.updated_at),
+            verification,
+            last_check,
+            ..Self::to_response(domain)
+        }
+    }
 
-        if host != input {
-            Err(Status::invalid_argument("Domain is not valid"))
+    fn domain_status_from_request(status: i32) -> Result<DomainStatus, String> {
+        let status = DomainStatus::try_from(status)
+            .map_err(|_| format!("Unknown domain status {}", status))?;
+        if status == DomainStatus::Unspecified {
+            Err("Please provide known domain status".to_string())
         } else {
-            Ok(())
+            Ok(status)
         }
     }
 
-    fn has_same_destination_ips(
+    /// Domains of the platform itself must not be claimed as custom domains.
+    pub fn parse_domain(
         &self,
-        a: &DnsLookupResponse,
-        b: &DnsLookupResponse,
+        input: &str,
+    ) -> Result<DomainName, DomainNameError> {
+        DomainName::parse(input, &[&self.main_domain, &self.fallback_domain])
+    }
+
+    fn get_ips(answers: &[DnsAnswer]) -> HashSet<&String> {
+        answers
+            .iter()
+            .filter(|a| {
+                a.record_type == RECORD_TYPE_A
+                    || a.record_type == RECORD_TYPE_AAAA
+            })
+            .map(|a| &a.data)
+            .collect()
+    }
+
+    /// Returns true if all IPs of `fallback` are also IPs of `domain`.
+    fn has_same_destination_ips(
+        fallback: &[DnsAnswer],
+        domain: &[DnsAnswer],
     ) -> bool {
-        let a_ips: HashSet<&String> = a
-            .answer
-            .as_ref()
-            .map(|a| a.iter().map(|a| &a.data).collect())
-            .unwrap_or_default();
-        let b_ips: HashSet<&String> = b
-            .answer
-            .as_ref()
-            .map(|b| b.iter().map(|b| &b.data).collect())
-            .unwrap_or_default();
-        a_ips.intersection(&b_ips).eq(a_ips.iter())
+        let fallback_ips = Self::get_ips(fallback);
+        let domain_ips = Self::get_ips(domain);
+        !fallback_ips.is_empty() && fallback_ips.is_subset(&domain_ips)
     }
 
     fn has_cname_to_fallback(
-        &self,
         domain: &String,
-        dns_lookup_response: &DnsLookupResponse,
+        fallback_domain: &String,
+        answers: &[DnsAnswer],
     ) -> bool {
-        dns_lookup_response
-            .answer
-            .as_ref()
-            .map(|answers| {
-                answers.iter().find(|a| {
-                    a.name == *domain
-                        && a._type == 5
-                        && a.data == self.fallback_domain
-                })
-            })
-            .is_some()
+        answers.iter().any(|a| {
+            a.name.trim_end_matches('.') == domain
+                && a.record_type == RECORD_TYPE_CNAME
+                && a.data.trim_end_matches('.') == fallback_domain
+        })
+    }
+
+    /// Checks DNS records of domain, whether it points to the fallback domain
+    /// either by CNAME or by resolving to the same IPs.
+    pub(crate) async fn points_to_fallback(
+        dns_resolver: &dyn DnsResolver,
+        fallback_domain: &String,
+        domain: &String,
+    ) -> Result<bool, Status> {
+        let domain_answers = dns_resolver.lookup(domain).await?;
+
+        if Self::has_cname_to_fallback(domain, fallback_domain, &domain_answers)
+        {
+            return Ok(true);
+        }
+
+        let fallback_answers = dns_resolver.lookup(fallback_domain).await?;
+
+        Ok(Self::has_same_destination_ips(
+            &fallback_answers,
+            &domain_answers,
+        ))
+    }
+
+    /// Restores a degraded domain that points to the fallback domain again,
+    /// like `DomainCheckJob` does. Its custom hostname is kept while degraded.
+    async fn check_degraded_domain(
+        &self,
+        domain: Domain,
+        user_id: &str,
+    ) -> Result<Domain, Status> {
+        let points_to_fallback = Self::points_to_fallback(
+            self.dns_resolver.as_ref(),
+            &self.fallback_domain,
+            &domain.domain,
+        )
+        .await?;
+
+        let domain = self
+            .store
+            .domains()
+            .update_last_check(
+                domain.domain_id,
+                points_to_fallback,
+                &Self::build_check_message(
+                    &domain.domain,
+                    &self.fallback_domain,
+                    points_to_fallback,
+                ),
+            )
+            .await?;
+
+        if !points_to_fallback {
+            return Ok(domain);
+        }
+
+        let restored = DomainCheckJob::update_status(
+            self.store.as_ref(),
+            &self.publisher,
+            &self.website_cache,
+            &domain,
+            DomainStatus::Degraded,
+            DomainStatus::Active,
+            None,
+            user_id,
+        )
+        .await?;
+
+        Ok(restored.unwrap_or(domain))
+    }
+
+    async fn try_sync_redirect_uris(&self, website_id: &String) {
+        if let Err(err) = WebsiteService::sync_redirect_uris(
+            self.store.as_ref(),
+            self.identity_provider.as_ref(),
+            website_id,
+        )
+        .await
+        {
+            tracing::log::error!(
+                "[DomainService.sync_redirect_uris] website_id {}: {}",
+                website_id,
+                err
+            );
+        }
+    }
+
+    pub(crate) fn build_check_message(
+        domain: &String,
+        fallback_domain: &String,
+        points_to_fallback: bool,
+    ) -> String {
+        if points_to_fallback {
+            format!("Domain '{}' points to '{}'", domain, fallback_domain)
+        } else {
+            format!(
+                "Domain '{}' does not point to '{}' yet",
+                domain, fallback_domain
+            )
+        }
+    }
+
+    /// An active or degraded domain belongs to its website, pending copies
+    /// of it on other websites can not be activated.
+    async fn check_domain_not_in_use(
+        &self,
+        domain: &String,
+    ) -> Result<(), Status> {
+        for status in [DomainStatus::Active, DomainStatus::Degraded] {
+            if self
+                .store
+                .domains()
+                .get_by_domain_and_status(domain, status)
+                .await?
+                .is_some()
+            {
+                return Err(Self::domain_in_use());
+            };
+        }
+
+        Ok(())
+    }
+
+    fn domain_in_use() -> Status {
+        Status::invalid_argument("Domain is already in use")
     }
 }
 
@@ -117,36 +327,50 @@ impl domain_service_server::DomainService for DomainService {
 
         let CreateDomainRequest { website_id, domain } = request.into_inner();
 
-        Self::validate_domain(&domain)?;
+        let DomainName {
+            ascii: domain,
+            unicode: domain_unicode,
+        } = self.parse_domain(&domain)?;
 
-        if Website::get(&self.pool, &website_id)
+        if self
+            .store
+            .websites()
+            .get(&website_id)
             .await?
             .is_some_and(|w| w.user_id == user_id)
         {
-            if Domain::get_by_domain_and_status(
-                &self.pool,
-                &domain,
-                DomainStatus::Active.as_str_name(),
-            )
-            .await?
-            .is_some()
-            {
-                return Err(Status::invalid_argument(
-                    "Domain is already in use",
-                ));
-            };
+            self.check_domain_not_in_use(&domain).await?;
 
-            let created_domain = Domain::create(
-                &self.pool,
-                &website_id,
-                &user_id,
-                &domain,
-                DomainStatus::Pending.as_str_name(),
-            )
-            .await?;
+            let transaction = self.store.begin().await?;
+
+            let created_domain = transaction
+                .domains()
+                .create(
+                    &website_id,
+                    &user_id,
+                    &domain,
+                    &domain_unicode,
+                    DomainStatus::Pending,
+                )
+                .await?;
+
+            let domain_response = self.to_full_response(created_domain);
+
+            self.publisher
+                .publish_event(
+                    transaction.outbox(),
+                    EventAction::Created,
+                    &user_id,
+                    &website_id,
+                    Payload::Domain(domain_response.clone()),
+                )
+                .await?;
+
+            transaction.commit().await?;
+            self.website_cache.invalidate(&website_id);
 
             Ok(Response::new(CreateDomainResponse {
-                domain: Some(Self::to_response(created_domain)),
+                domain: Some(domain_response),
             }))
         } else {
             Err(Status::invalid_argument(format!(
@@ -156,6 +380,72 @@ impl domain_service_server::DomainService for DomainService {
         }
     }
 
+    async fn get_domain(
+        &self,
+        request: Request<GetDomainRequest>,
+    ) -> Result<Response<GetDomainResponse>, Status> {
+        let user_id = get_user_id(request.metadata(), &self.verifier).await?;
+
+        let GetDomainRequest { domain_id } = request.into_inner();
+
+        let found_domain = self
+            .store
+            .domains()
+            .get_for_user(domain_id, &user_id)
+            .await?
+            .ok_or_else(|| {
+                Status::not_found(format!(
+                    "Could not find domain '{}'",
+                    domain_id
+                ))
+            })?;
+
+        Ok(Response::new(GetDomainResponse {
+            domain: Some(self.to_full_response(found_domain)),
+        }))
+    }
+
+    async fn list_domains(
+        &self,
+        request: Request<ListDomainsRequest>,
+    ) -> Result<Response<ListDomainsResponse>, Status> {
+        let user_id = get_user_id(request.metadata(), &self.verifier).await?;
+
+        let ListDomainsRequest {
+            website_id,
+            status,
+            pagination,
+        } = request.into_inner();
+
+        let status = match status {
+            Some(s) => Some(
+                Self::domain_status_from_request(s)
+                    .map_err(Status::invalid_argument)?,
+            ),
+            None => None,
+        };
+
+        let (limit, offset, mut pagination) =
+            get_limit_offset_from_pagination(pagination)
+                .map_err(Status::invalid_argument)?;
+
+        let (found_domains, count) = self
+            .store
+            .domains()
+            .list(&website_id, &user_id, status, limit, offset)
+            .await?;
+
+        pagination.total_elements = i64_to_u32(count);
+
+        Ok(Response::new(ListDomainsResponse {
+            domains: found_domains
+                .into_iter()
+                .map(|d| self.to_full_response(d))
+                .collect(),
+            pagination: Some(pagination),
+        }))
+    }
+
     async fn check_domain_status(
         &self,
         request: Request<CheckDomainStatusRequest>,
@@ -164,45 +454,88 @@ impl domain_service_server::DomainService for DomainService {
 
         let CheckDomainStatusRequest { domain_id } = request.into_inner();
 
-        if let Some(mut domain) =
-            Domain::get_for_user(&self.pool, domain_id, &user_id).await?
+        if let Some(mut domain) = self
+            .store
+            .domains()
+            .get_for_user(domain_id, &user_id)
+            .await?
         {
-            if domain.status == DomainStatus::Pending.as_str_name() {
-                let domain_lookup =
-                    self.cloudflare_service.dns_lookup(&domain.domain).await?;
-
-                let mut points_to_fallback =
-                    self.has_cname_to_fallback(&domain.domain, &domain_lookup);
+            if domain.status == DomainStatus::Pending {
+                self.check_domain_not_in_use(&domain.domain).await?;
 
-                if !points_to_fallback {
-                    let fallback_lookup = self
-                        .cloudflare_service
-                        .dns_lookup(&self.fallback_domain)
-                        .await?;
+                let points_to_fallback = Self::points_to_fallback(
+                    self.dns_resolver.as_ref(),
+                    &self.fallback_domain,
+                    &domain.domain,
+                )
+                .await?;
 
-                    points_to_fallback = self.has_same_destination_ips(
-                        &fallback_lookup,
-                        &domain_lookup,
-                    );
-                }
+                let check_message = Self::build_check_message(
+                    &domain.domain,
+                    &self.fallback_domain,
+                    points_to_fallback,
+                );
 
                 if points_to_fallback {
-                    self.cloudflare_service
-                        .create_custom_hostname(domain.domain)
+                    self.edge_provider
+                        .add_custom_hostname(&domain.domain)
                         .await?;
-                    domain = Domain::update(
-                        &self.pool,
+                }
+
+                let transaction = self.store.begin().await?;
+
+                domain = transaction
+                    .domains()
+                    .update_last_check(
                         domain.domain_id,
+                        points_to_fallback,
+                        &check_message,
+                    )
+                    .await?;
+
+                // the unique index of active domains decides concurrent
+                // activations of pending copies
+                if points_to_fallback {
+                    domain = transaction
+                        .domains()
+                        .update(
+                            domain.domain_id,
+                            &domain.website_id,
+                            &domain.user_id,
+                            DomainStatus::Active,
+                        )
+                        .await
+                        .map_err(|err| {
+                            if err.is_unique_violation() {
+                                Self::domain_in_use()
+                            } else {
+                                err.into()
+                            }
+                        })?;
+                }
+
+                self.publisher
+                    .publish_event(
+                        transaction.outbox(),
+                        EventAction::Updated,
+                        &user_id,
                         &domain.website_id,
-                        &domain.user_id,
-                        DomainStatus::Active.as_str_name(),
+                        Payload::Domain(self.to_full_response(domain.clone())),
                     )
                     .await?;
+
+                transaction.commit().await?;
+                self.website_cache.invalidate(&domain.website_id);
+
+                if points_to_fallback {
+                    self.try_sync_redirect_uris(&domain.website_id).await;
                 }
+            } else if domain.status == DomainStatus::Degraded {
+                domain = self.check_degraded_domain(domain, &user_id).await?;
             }
 
             Ok(Response::new(CheckDomainStatusResponse {
-                domain: Some(Self::to_response(domain)),
+                domain: Some(self.to_full_response(domain)),
             }))
         } else {
             Err(Status::invalid_argument(format!(
@@ -220,29 +553,52 @@ impl domain_service_server::DomainService for DomainService {
 
         let DeleteDomainRequest { domain_id } = request.into_inner();
 
-        if let Some(found_domain) =
-            Domain::get_for_user(&self.pool, domain_id, &user_id).await?
+        if let Some(found_domain) = self
+            .store
+            .domains()
+            .get_for_user(domain_id, &user_id)
+            .await?
         {
-            if found_domain.status != DomainStatus::Internal.as_str_name() {
-                if let Ok(found_custom_hostnames) = self
-                    .cloudflare_service
-                    .list_custom_hostnames(&found_domain.domain)
-                    .await
+            if found_domain.status != DomainStatus::Internal
+                && found_domain.status != DomainStatus::Redirect
+            {
+                // pending copies of the domain may exist on other websites,
+                // only the one holding it owns the custom hostname
+                if found_domain.status == DomainStatus::Active
+                    || found_domain.status == DomainStatus::Degraded
                 {
-                    for custom_hostname in found_custom_hostnames.result {
-                        self.cloudflare_service
-                            .delete_custom_hostname(custom_hostname.id)
-                            .await?;
-                    }
+                    self.edge_provider
+                        .remove_custom_hostname(&found_domain.domain)
+                        .await?;
                 }
 
-                Domain::delete(
-                    &self.pool,
-                    found_domain.domain_id,
-                    &found_domain.website_id,
-                    &user_id,
-                )
-                .await?;
+                let transaction = self.store.begin().await?;
+
+                transaction
+                    .domains()
+                    .delete(
+                        found_domain.domain_id,
+                        &found_domain.website_id,
+                        &user_id,
+                    )
+                    .await?;
+
+                self.publisher
+                    .publish_event(
+                        transaction.outbox(),
+                        EventAction::Deleted,
+                        &user_id,
+                        &found_domain.website_id,
+                        Payload::Domain(
+                            self.to_full_response(found_domain.clone()),
+                        ),
+                    )
+                    .await?;
+
+                transaction.commit().await?;
+                self.website_cache.invalidate(&found_domain.website_id);
+
+                self.try_sync_redirect_uris(&found_domain.website_id).await;
 
                 return Ok(Response::new(DeleteDomainResponse {}));
             }
@@ -254,3 +610,114 @@ impl domain_service_server::DomainService for DomainService {
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::dns::{
+        DnsAnswer, InMemoryResolver, RECORD_TYPE_A, RECORD_TYPE_CNAME,
+    };
+
+    use super::DomainService;
+
+    const FALLBACK_DOMAIN: &str = "fallback.sited.io";
+
+    fn fallback_resolver() -> InMemoryResolver {
+        let resolver = InMemoryResolver::new();
+        resolver.set(
+            FALLBACK_DOMAIN,
+            vec![
+                DnsAnswer::new(FALLBACK_DOMAIN, RECORD_TYPE_A, "104.21.0.1"),
+                DnsAnswer::new(FALLBACK_DOMAIN, RECORD_TYPE_A, "172.67.0.1"),
+            ],
+        );
+        resolver
+    }
+
+    async fn points_to_fallback(
+        resolver: &InMemoryResolver,
+        domain: &str,
+    ) -> bool {
+        DomainService::points_to_fallback(
+            resolver,
+            &FALLBACK_DOMAIN.to_string(),
+            &domain.to_string(),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cname_to_fallback() {
+        let resolver = fallback_resolver();
+        resolver.set(
+            "shop.example.com",
+            vec![
+                DnsAnswer::new(
+                    "shop.example.com.",
+                    RECORD_TYPE_CNAME,
+                    "fallback.sited.io.",
+                ),
+                DnsAnswer::new(FALLBACK_DOMAIN, RECORD_TYPE_A, "104.21.0.1"),
+            ],
+        );
+
+        assert!(points_to_fallback(&resolver, "shop.example.com").await);
+    }
+
+    #[tokio::test]
+    async fn cname_to_other_domain() {
+        let resolver = fallback_resolver();
+        resolver.set(
+            "shop.example.com",
+            vec![
+                DnsAnswer::new(
+                    "shop.example.com",
+                    RECORD_TYPE_CNAME,
+                    "other.example.net",
+                ),
+                DnsAnswer::new("other.example.net", RECORD_TYPE_A, "10.0.0.1"),
+            ],
+        );
+
+        assert!(!points_to_fallback(&resolver, "shop.example.com").await);
+    }
+
+    #[tokio::test]
+    async fn same_destination_ips() {
+        let resolver = fallback_resolver();
+        resolver.set(
+            "example.com",
+            vec![
+                DnsAnswer::new("example.com", RECORD_TYPE_A, "172.67.0.1"),
+                DnsAnswer::new("example.com", RECORD_TYPE_A, "104.21.0.1"),
+            ],
+        );
+
+        assert!(points_to_fallback(&resolver, "example.com").await);
+    }
+
+    #[tokio::test]
+    async fn partially_same_destination_ips() {
+        let resolver = fallback_resolver();
+        resolver.set(
+            "example.com",
+            vec![DnsAnswer::new("example.com", RECORD_TYPE_A, "104.21.0.1")],
+        );
+
+        assert!(!points_to_fallback(&resolver, "example.com").await);
+    }
+
+    #[tokio::test]
+    async fn unresolvable_domain() {
+        let resolver = fallback_resolver();
+
+        assert!(!points_to_fallback(&resolver, "example.com").await);
+    }
+
+    #[tokio::test]
+    async fn unresolvable_fallback() {
+        let resolver = InMemoryResolver::new();
+
+        assert!(!points_to_fallback(&resolver, "example.com").await);
+    }
+}
@@ -4,9 +4,14 @@ mod page;
 mod static_page;
 mod website;
 
-use tonic::Status;
+#[cfg(test)]
+mod tests;
+
+use http::header::AUTHORIZATION;
+use tonic::metadata::MetadataMap;
 
 use crate::api::sited_io::types::v1::{PaginationRequest, PaginationResponse};
+use crate::repository::{Repositories, Store};
 
 pub use customization::CustomizationService;
 pub use domain::DomainService;
@@ -17,7 +22,7 @@ pub use website::WebsiteService;
 /// Returns limit and offset from PaginationRequest
 fn get_limit_offset_from_pagination(
     request: Option<PaginationRequest>,
-) -> Result<(u64, u64, PaginationResponse), Status> {
+) -> Result<(u64, u64, PaginationResponse), String> {
     let mut limit = 10;
     let mut offset = 0;
     let mut pagination = PaginationResponse {
@@ -28,9 +33,7 @@ fn get_limit_offset_from_pagination(
 
     if let Some(request) = request {
         if request.page < 1 {
-            return Err(Status::invalid_argument(
-                "pagination.page less than 1",
-            ));
+            return Err("pagination.page less than 1".to_string());
         }
         limit = request.size;
         offset = (request.page - 1) * request.size;
@@ -40,3 +43,28 @@ fn get_limit_offset_from_pagination(
 
     Ok((limit.into(), offset.into(), pagination))
 }
+
+/// Sent by editors on public reads to read their own writes, e.g. when
+/// previewing a website right after changing it.
+pub const READ_PRIMARY_HEADER: &str = "x-read-primary";
+
+/// Whether a read may be served from the replica and the website cache, i.e.
+/// it is neither authenticated nor carries `READ_PRIMARY_HEADER`, so that
+/// editors read their own writes.
+fn is_public_read(metadata: &MetadataMap) -> bool {
+    !metadata.contains_key(AUTHORIZATION.as_str())
+        && !metadata.contains_key(READ_PRIMARY_HEADER)
+}
+
+/// Returns the repositories to serve a read from, the replica for public
+/// reads, see `is_public_read`.
+fn read_repositories<'a>(
+    store: &'a dyn Store,
+    metadata: &MetadataMap,
+) -> &'a dyn Repositories {
+    if is_public_read(metadata) {
+        store.replica()
+    } else {
+        store
+    }
+}
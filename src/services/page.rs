@@ -1,9 +1,9 @@
-use deadpool_postgres::Pool;
 use jwtk::jwk::RemoteJwksVerifier;
 use serde_json::Value;
 use slug::slugify;
 use tonic::{async_trait, Request, Response, Status};
 
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
 use crate::api::sited_io::websites::v1::page_service_server::{
     self, PageServiceServer,
 };
@@ -14,14 +14,22 @@ use crate::api::sited_io::websites::v1::{
     UpdatePageResponse,
 };
 use crate::auth::get_user_id;
+use crate::cache_purge::CachePurger;
 use crate::i64_to_u32;
-use crate::model::{Page, PageAsRel, StaticPage, Website};
+use crate::model::PageAsRel;
+use crate::publisher::{EventAction, Publisher};
+use crate::repository::{DynStore, Repositories, StoreTransaction};
+use crate::website_cache::WebsiteCache;
+use crate::StaticPageService;
 
-use super::get_limit_offset_from_pagination;
+use super::{get_limit_offset_from_pagination, read_repositories};
 
 pub struct PageService {
-    pool: Pool,
+    store: DynStore,
     verifier: RemoteJwksVerifier,
+    publisher: Publisher,
+    cache_purger: CachePurger,
+    website_cache: WebsiteCache,
 }
 
 impl PageService {
@@ -29,17 +37,43 @@ impl PageService {
     pub const DEFAULT_HOME_PAGE_TITLE: &'static str = "Home";
 
     pub fn build(
-        pool: Pool,
+        store: DynStore,
         verifier: RemoteJwksVerifier,
+        publisher: Publisher,
+        cache_purger: CachePurger,
+        website_cache: WebsiteCache,
     ) -> PageServiceServer<Self> {
-        PageServiceServer::new(Self { pool, verifier })
+        PageServiceServer::new(Self::new(
+            store,
+            verifier,
+            publisher,
+            cache_purger,
+            website_cache,
+        ))
+    }
+
+    /// Builds the service without the gRPC server, e.g. for tests.
+    pub fn new(
+        store: DynStore,
+        verifier: RemoteJwksVerifier,
+        publisher: Publisher,
+        cache_purger: CachePurger,
+        website_cache: WebsiteCache,
+    ) -> Self {
+        Self {
+            store,
+            verifier,
+            publisher,
+            cache_purger,
+            website_cache,
+        }
     }
 
     pub fn to_response(page: impl Into<PageAsRel>) -> PageResponse {
         let page: PageAsRel = page.into();
         PageResponse {
             page_id: page.page_id,
-            page_type: PageType::from_str_name(&page.page_type).unwrap().into(),
+            page_type: page.page_type.into(),
             content_id: page.content_id,
             title: page.title,
             is_home_page: page.is_home_page,
@@ -47,12 +81,38 @@ impl PageService {
         }
     }
 
-    fn page_type_from_request(page_type: i32) -> Result<PageType, Status> {
-        let page_type = PageType::try_from(page_type).map_err(|_| {
-            Status::invalid_argument(format!("Unknown page type {}", page_type))
-        })?;
+    /// Finds a page by id or by its path. Shared by `GetPage` and the NATS
+    /// lookup, see `LookupResponder`.
+    pub(crate) async fn lookup_page(
+        repositories: &dyn Repositories,
+        request: GetPageRequest,
+    ) -> Result<GetPageResponse, Status> {
+        let GetPageRequest {
+            page_id,
+            website_id,
+            path,
+        } = request;
+
+        let found_page = match (page_id, website_id, path) {
+            (Some(page_id), _, _) => repositories.pages().get(page_id).await?,
+            (_, Some(website_id), Some(path)) => {
+                repositories.pages().get_by_path(&website_id, &path).await?
+            }
+            _ => return Err(Status::invalid_argument(
+                "Please provide either page_id or both of website_id and path",
+            )),
+        };
+
+        Ok(GetPageResponse {
+            page: found_page.map(Self::to_response),
+        })
+    }
+
+    fn page_type_from_request(page_type: i32) -> Result<PageType, String> {
+        let page_type = PageType::try_from(page_type)
+            .map_err(|_| format!("Unknown page type {}", page_type))?;
         if page_type == PageType::Unspecified {
-            Err(Status::invalid_argument("Please provide known page type"))
+            Err("Please provide known page type".to_string())
         } else {
             Ok(page_type)
         }
@@ -64,43 +124,66 @@ impl PageService {
 
     async fn make_current_home_page_not_home_page(
         &self,
+        transaction: &dyn StoreTransaction,
         website_id: &String,
         user_id: &String,
     ) -> Result<(), Status> {
         if let Some(current_home_page) =
-            Page::get_home_page(&self.pool, website_id).await?
+            transaction.pages().get_home_page(website_id).await?
         {
-            Page::update(
-                &self.pool,
-                current_home_page.page_id,
-                user_id,
-                None,
-                None,
-                None,
-                Some(false),
-                Some(Self::get_slugified_path(&current_home_page.title)),
-            )
-            .await?;
+            let updated_page = transaction
+                .pages()
+                .update(
+                    current_home_page.page_id,
+                    user_id,
+                    None,
+                    None,
+                    None,
+                    Some(false),
+                    Some(Self::get_slugified_path(&current_home_page.title)),
+                )
+                .await?;
+
+            self.publisher
+                .publish_event(
+                    transaction.outbox(),
+                    EventAction::Updated,
+                    user_id,
+                    website_id,
+                    Payload::Page(Self::to_response(updated_page)),
+                )
+                .await?;
         }
 
         Ok(())
     }
 
-    async fn ensure_static_page(
-        &self,
+    /// Creates the empty static page of a page that became static. Shared
+    /// with `LifecycleConsumer`.
+    pub(crate) async fn ensure_static_page(
+        publisher: &Publisher,
+        transaction: &dyn StoreTransaction,
         page_id: i64,
         website_id: &String,
         user_id: &String,
     ) -> Result<(), Status> {
-        if StaticPage::get(&self.pool, page_id).await?.is_none() {
-            StaticPage::create(
-                &self.pool,
-                page_id,
-                website_id,
-                user_id,
-                Value::Array(Vec::new()),
-            )
-            .await?;
+        if transaction.static_pages().get(page_id).await?.is_none() {
+            let created_static_page = transaction
+                .static_pages()
+                .create(page_id, website_id, user_id, Value::Array(Vec::new()))
+                .await?;
+
+            publisher
+                .publish_event(
+                    transaction.outbox(),
+                    EventAction::Created,
+                    user_id,
+                    website_id,
+                    Payload::StaticPage(StaticPageService::to_response(
+                        created_static_page,
+                    )),
+                )
+                .await?;
         }
 
         Ok(())
@@ -124,11 +207,14 @@ impl page_service_server::PageService for PageService {
             path,
         } = request.into_inner();
 
-        let page_type = Self::page_type_from_request(page_type)?;
+        let page_type = Self::page_type_from_request(page_type)
+            .map_err(Status::invalid_argument)?;
 
         let mut path = path.unwrap_or_else(|| Self::get_slugified_path(&title));
 
-        Website::get_for_user(&self.pool, &website_id, &user_id)
+        self.store
+            .websites()
+            .get_for_user(&website_id, &user_id)
             .await?
             .ok_or_else(|| {
                 Status::not_found(format!(
@@ -137,27 +223,46 @@ impl page_service_server::PageService for PageService {
                 ))
             })?;
 
+        let transaction = self.store.begin().await?;
+
         if is_home_page {
-            self.make_current_home_page_not_home_page(&website_id, &user_id)
-                .await?;
+            self.make_current_home_page_not_home_page(
+                &*transaction,
+                &website_id,
+                &user_id,
+            )
+            .await?;
 
             path = Self::HOME_PAGE_PATH.to_string();
         }
 
-        let created_page = Page::create(
-            &self.pool,
-            &website_id,
-            &user_id,
-            page_type.as_str_name(),
-            &content_id,
-            &title,
-            is_home_page,
-            &path,
-        )
-        .await?;
+        let created_page = transaction
+            .pages()
+            .create(
+                &website_id,
+                &user_id,
+                page_type,
+                &content_id,
+                &title,
+                is_home_page,
+                &path,
+            )
+            .await?;
+
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Created,
+                &user_id,
+                &website_id,
+                Payload::Page(Self::to_response(created_page.clone())),
+            )
+            .await?;
 
         if page_type == PageType::Static {
-            self.ensure_static_page(
+            Self::ensure_static_page(
+                &self.publisher,
+                &*transaction,
                 created_page.page_id,
                 &website_id,
                 &user_id,
@@ -165,6 +270,12 @@ impl page_service_server::PageService for PageService {
             .await?;
         }
 
+        transaction.commit().await?;
+        self.website_cache.invalidate(&website_id);
+
+        // the navigation of all pages changed
+        self.cache_purger.purge_website(&website_id);
+
         Ok(Response::new(CreatePageResponse {
             page: Some(Self::to_response(created_page)),
         }))
@@ -174,43 +285,35 @@ impl page_service_server::PageService for PageService {
         &self,
         request: Request<GetPageRequest>,
     ) -> Result<Response<GetPageResponse>, Status> {
-        let GetPageRequest {
-            page_id,
-            website_id,
-            path,
-        } = request.into_inner();
-
-        let found_page = match (page_id, website_id, path) {
-            (Some(page_id), _, _) => Page::get(&self.pool, page_id).await?,
-            (_, Some(website_id), Some(path)) => {
-                Page::get_by_path(&self.pool, &website_id, &path).await?
-            }
-            _ => return Err(Status::invalid_argument(
-                "Please provide either page_id or both of website_id and path",
-            )),
-        };
-
-        Ok(Response::new(GetPageResponse {
-            page: found_page.map(Self::to_response),
-        }))
+        Ok(Response::new(
+            Self::lookup_page(
+                read_repositories(self.store.as_ref(), request.metadata()),
+                request.into_inner(),
+            )
+            .await?,
+        ))
     }
 
     async fn list_pages(
         &self,
         request: Request<ListPagesRequest>,
     ) -> Result<Response<ListPagesResponse>, Status> {
+        let repositories =
+            read_repositories(self.store.as_ref(), request.metadata());
+
         let ListPagesRequest {
             website_id,
             pagination,
         } = request.into_inner();
 
         let (limit, offset, mut pagination) =
-            get_limit_offset_from_pagination(pagination)?;
+            get_limit_offset_from_pagination(pagination)
+                .map_err(Status::invalid_argument)?;
 
         let (found_pages, count) =
-            Page::list(&self.pool, website_id, limit, offset).await?;
+            repositories.pages().list(website_id, limit, offset).await?;
 
-        pagination.total_elements = i64_to_u32(count)?;
+        pagination.total_elements = i64_to_u32(count);
 
         Ok(Response::new(ListPagesResponse {
             pages: found_pages.into_iter().map(Self::to_response).collect(),
@@ -233,13 +336,16 @@ impl page_service_server::PageService for PageService {
             mut path,
         } = request.into_inner();
 
+        let transaction = self.store.begin().await?;
+
         if matches!(is_home_page, Some(true)) {
             let found_page =
-                Page::get(&self.pool, page_id).await?.ok_or_else(|| {
+                transaction.pages().get(page_id).await?.ok_or_else(|| {
                     Status::not_found("Could not find page to update")
                 })?;
 
             self.make_current_home_page_not_home_page(
+                &*transaction,
                 &found_page.website_id,
                 &user_id,
             )
@@ -249,24 +355,40 @@ impl page_service_server::PageService for PageService {
         }
 
         let page_type = match page_type {
-            Some(p) => Some(Self::page_type_from_request(p)?.as_str_name()),
+            Some(p) => Some(
+                Self::page_type_from_request(p)
+                    .map_err(Status::invalid_argument)?,
+            ),
             None => None,
         };
 
-        let updated_page = Page::update(
-            &self.pool,
-            page_id,
-            &user_id,
-            page_type,
-            content_id,
-            title,
-            is_home_page,
-            path,
-        )
-        .await?;
+        let updated_page = transaction
+            .pages()
+            .update(
+                page_id,
+                &user_id,
+                page_type,
+                content_id,
+                title,
+                is_home_page,
+                path,
+            )
+            .await?;
 
-        if page_type.is_some_and(|p| p == PageType::Static.as_str_name()) {
-            self.ensure_static_page(
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Updated,
+                &user_id,
+                &updated_page.website_id,
+                Payload::Page(Self::to_response(updated_page.clone())),
+            )
+            .await?;
+
+        if page_type.is_some_and(|p| p == PageType::Static) {
+            Self::ensure_static_page(
+                &self.publisher,
+                &*transaction,
                 page_id,
                 &updated_page.website_id,
                 &user_id,
@@ -274,6 +396,11 @@ impl page_service_server::PageService for PageService {
             .await?;
         }
 
+        transaction.commit().await?;
+        self.website_cache.invalidate(&updated_page.website_id);
+
+        self.cache_purger.purge_website(&updated_page.website_id);
+
         Ok(Response::new(UpdatePageResponse {
             page: Some(Self::to_response(updated_page)),
         }))
@@ -287,17 +414,57 @@ impl page_service_server::PageService for PageService {
 
         let DeletePageRequest { page_id } = request.into_inner();
 
-        let found_page = Page::get(&self.pool, page_id)
+        let found_page = self
+            .store
+            .pages()
+            .get(page_id)
             .await?
+            .filter(|p| p.user_id == user_id)
             .ok_or_else(|| Status::not_found(""))?;
 
         if found_page.path == Self::HOME_PAGE_PATH {
             return Err(Status::invalid_argument("Cannot delete home page"));
         }
 
-        StaticPage::delete(&self.pool, page_id, &user_id).await?;
+        let found_static_page = self.store.static_pages().get(page_id).await?;
+
+        let website_id = found_page.website_id.clone();
+
+        let transaction = self.store.begin().await?;
+
+        transaction
+            .pages()
+            .delete_with_static_page(page_id, &user_id)
+            .await?;
+
+        if let Some(static_page) = found_static_page {
+            self.publisher
+                .publish_event(
+                    transaction.outbox(),
+                    EventAction::Deleted,
+                    &user_id,
+                    &website_id,
+                    Payload::StaticPage(StaticPageService::to_response(
+                        static_page,
+                    )),
+                )
+                .await?;
+        }
+
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Deleted,
+                &user_id,
+                &website_id,
+                Payload::Page(Self::to_response(found_page)),
+            )
+            .await?;
+
+        transaction.commit().await?;
+        self.website_cache.invalidate(&website_id);
 
-        Page::delete(&self.pool, page_id, &user_id).await?;
+        self.cache_purger.purge_website(&website_id);
 
         Ok(Response::new(DeletePageResponse {}))
     }
@@ -1,7 +1,7 @@
-use deadpool_postgres::Pool;
 use jwtk::jwk::RemoteJwksVerifier;
 use tonic::{async_trait, Request, Response, Status};
 
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
 use crate::api::sited_io::websites::v1::static_page_service_server::{
     self, StaticPageServiceServer,
 };
@@ -10,22 +10,51 @@ use crate::api::sited_io::websites::v1::{
     UpdateStaticPageRequest, UpdateStaticPageResponse,
 };
 use crate::auth::get_user_id;
+use crate::cache_purge::CachePurger;
 use crate::model::StaticPage;
+use crate::publisher::{EventAction, Publisher};
+use crate::repository::DynStore;
+
+use super::read_repositories;
 
 pub struct StaticPageService {
-    pool: Pool,
+    store: DynStore,
     verifier: RemoteJwksVerifier,
+    publisher: Publisher,
+    cache_purger: CachePurger,
 }
 
 impl StaticPageService {
     pub fn build(
-        pool: Pool,
+        store: DynStore,
         verifier: RemoteJwksVerifier,
+        publisher: Publisher,
+        cache_purger: CachePurger,
     ) -> StaticPageServiceServer<Self> {
-        StaticPageServiceServer::new(Self { pool, verifier })
+        StaticPageServiceServer::new(Self::new(
+            store,
+            verifier,
+            publisher,
+            cache_purger,
+        ))
+    }
+
+    /// Builds the service without the gRPC server, e.g. for tests.
+    pub fn new(
+        store: DynStore,
+        verifier: RemoteJwksVerifier,
+        publisher: Publisher,
+        cache_purger: CachePurger,
+    ) -> Self {
+        Self {
+            store,
+            verifier,
+            publisher,
+            cache_purger,
+        }
     }
 
-    fn to_response(static_page: StaticPage) -> StaticPageResponse {
+    pub fn to_response(static_page: StaticPage) -> StaticPageResponse {
         StaticPageResponse {
             page_id: static_page.page_id,
             website_id: static_page.website_id,
@@ -41,9 +70,13 @@ impl static_page_service_server::StaticPageService for StaticPageService {
         &self,
         request: Request<GetStaticPageRequest>,
     ) -> Result<Response<GetStaticPageResponse>, Status> {
+        let repositories =
+            read_repositories(self.store.as_ref(), request.metadata());
+
         let GetStaticPageRequest { page_id } = request.into_inner();
 
-        let found_static_page = StaticPage::get(&self.pool, page_id).await?;
+        let found_static_page =
+            repositories.static_pages().get(page_id).await?;
 
         Ok(Response::new(GetStaticPageResponse {
             static_page: found_static_page.map(Self::to_response),
@@ -61,16 +94,36 @@ impl static_page_service_server::StaticPageService for StaticPageService {
             components,
         } = request.into_inner();
 
-        let updated_static_page = StaticPage::update(
-            &self.pool,
-            page_id,
-            &user_id,
-            serde_json::to_value(components).unwrap(),
-        )
-        .await?;
+        let transaction = self.store.begin().await?;
+
+        let updated_static_page = transaction
+            .static_pages()
+            .update(
+                page_id,
+                &user_id,
+                serde_json::to_value(components).unwrap(),
+            )
+            .await?;
+
+        let static_page_response = Self::to_response(updated_static_page);
+
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Updated,
+                &user_id,
+                &static_page_response.website_id,
+                Payload::StaticPage(static_page_response.clone()),
+            )
+            .await?;
+
+        transaction.commit().await?;
+
+        self.cache_purger
+            .purge_page(&static_page_response.website_id, page_id);
 
         Ok(Response::new(UpdateStaticPageResponse {
-            static_page: Some(Self::to_response(updated_static_page)),
+            static_page: Some(static_page_response),
         }))
     }
 }
@@ -0,0 +1,1511 @@
+//! Runs the RPCs of all services and the handlers of `LifecycleConsumer`
+//! against the in-memory store. Tokens are
+//! signed with a generated key served by a mocked JWKS endpoint, images are
+//! stored in a mocked bucket.
+
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use jwtk::ecdsa::{EcdsaAlgorithm, EcdsaPrivateKey};
+use jwtk::jwk::{JwkSet, WithKid};
+use jwtk::{HeaderAndClaims, PublicKeyToJwk};
+use prost::Message;
+use tokio::sync::Notify;
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request, Status};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::api::sited_io::media::v1::MediaUpload;
+use crate::api::sited_io::types::v1::PaginationRequest;
+use crate::api::sited_io::websites::v1::customization_service_server::CustomizationService as _;
+use crate::api::sited_io::websites::v1::domain_service_server::DomainService as _;
+use crate::api::sited_io::websites::v1::page_service_server::PageService as _;
+use crate::api::sited_io::websites::v1::static_page_service_server::StaticPageService as _;
+use crate::api::sited_io::websites::v1::website_service_server::WebsiteService as _;
+use crate::api::sited_io::websites::v1::{
+    CheckDomainStatusRequest, CheckSubdomainAvailabilityRequest, Component,
+    CreateDomainRequest, CreatePageRequest, CreateWebsiteRequest,
+    DeleteDomainRequest, DeletePageRequest, DeleteWebsiteRequest, DomainStatus,
+    GetDomainRequest, GetPageRequest, GetStaticPageRequest, GetWebsiteRequest,
+    ListDomainsRequest, ListPagesRequest, ListWebsitesRequest, PageType,
+    PutLogoImageRequest, RemoveLogoImageRequest, ResyncWebsiteRequest,
+    SetSubdomainRequest, ShopDeletedEvent, UpdateCustomizationRequest,
+    UpdatePageRequest, UpdateStaticPageRequest, UpdateWebsiteRequest,
+    UserDeletedEvent, WebsiteResponse,
+};
+use crate::auth::init_jwks_verifier;
+use crate::cache_purge::CachePurger;
+use crate::dns::{
+    DnsAnswer, InMemoryResolver, RECORD_TYPE_A, RECORD_TYPE_CNAME,
+};
+use crate::edge::InMemoryEdgeProvider;
+use crate::identity::{AppProject, InMemoryIdentityProvider};
+use crate::images::ImageService;
+use crate::lifecycle::LifecycleConsumer;
+use crate::publisher::Publisher;
+use crate::repository::{InMemoryStore, Repositories as _};
+use crate::website_cache::{WebsiteCache, WebsiteCacheStats};
+
+use super::{
+    CustomizationService, DomainService, PageService, StaticPageService,
+    WebsiteService, READ_PRIMARY_HEADER,
+};
+
+const MAIN_DOMAIN: &str = "sited.io";
+const FALLBACK_DOMAIN: &str = "fallback.sited.io";
+const USER_ID: &str = "user-1";
+const OTHER_USER_ID: &str = "user-2";
+
+struct Harness {
+    key: WithKid<EcdsaPrivateKey>,
+    store: InMemoryStore,
+    identity_provider: InMemoryIdentityProvider,
+    edge_provider: InMemoryEdgeProvider,
+    dns_resolver: InMemoryResolver,
+    website_cache: WebsiteCache,
+    website_service: Arc<WebsiteService>,
+    domain_service: DomainService,
+    page_service: PageService,
+    static_page_service: StaticPageService,
+    customization_service: CustomizationService,
+    lifecycle: LifecycleConsumer,
+    published: Cell<usize>,
+    _jwks_server: MockServer,
+    _bucket_server: MockServer,
+}
+
+impl Harness {
+    async fn new() -> Self {
+        let key = WithKid::new(
+            "test".to_string(),
+            EcdsaPrivateKey::generate(EcdsaAlgorithm::ES256).unwrap(),
+        );
+
+        let jwks_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/jwks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(JwkSet {
+                keys: vec![key.public_key_to_jwk().unwrap()],
+            }))
+            .mount(&jwks_server)
+            .await;
+
+        let bucket_server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&bucket_server)
+            .await;
+        Mock::given(method("DELETE"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&bucket_server)
+            .await;
+
+        let verifier = || {
+            init_jwks_verifier(
+                "localhost",
+                &format!("{}/jwks", jwks_server.uri()),
+            )
+            .unwrap()
+        };
+        let image_service = ImageService::new(
+            "bucket".to_string(),
+            bucket_server.uri(),
+            "access-key-id".to_string(),
+            "secret-access-key".to_string(),
+            "https://images.sited.io".to_string(),
+            1024 * 1024,
+        )
+        .await;
+
+        let store = InMemoryStore::new();
+        let identity_provider = InMemoryIdentityProvider::new();
+        let edge_provider = InMemoryEdgeProvider::new();
+        let dns_resolver = InMemoryResolver::new();
+        let website_cache = WebsiteCache::new(
+            WebsiteCache::DEFAULT_TTL,
+            WebsiteCache::DEFAULT_CAPACITY,
+        );
+
+        let website_service = Arc::new(WebsiteService::new(
+            Arc::new(store.clone()),
+            verifier(),
+            MAIN_DOMAIN.to_string(),
+            FALLBACK_DOMAIN.to_string(),
+            Arc::new(identity_provider.clone()),
+            Arc::new(edge_provider.clone()),
+            image_service.clone(),
+            Publisher::new(),
+            CachePurger::disabled(),
+            website_cache.clone(),
+        ));
+        let domain_service = DomainService::new(
+            Arc::new(store.clone()),
+            verifier(),
+            MAIN_DOMAIN.to_string(),
+            FALLBACK_DOMAIN.to_string(),
+            Arc::new(identity_provider.clone()),
+            Arc::new(edge_provider.clone()),
+            Arc::new(dns_resolver.clone()),
+            Publisher::new(),
+            website_cache.clone(),
+        );
+        let page_service = PageService::new(
+            Arc::new(store.clone()),
+            verifier(),
+            Publisher::new(),
+            CachePurger::disabled(),
+            website_cache.clone(),
+        );
+        let static_page_service = StaticPageService::new(
+            Arc::new(store.clone()),
+            verifier(),
+            Publisher::new(),
+            CachePurger::disabled(),
+        );
+        let customization_service = CustomizationService::new(
+            Arc::new(store.clone()),
+            verifier(),
+            image_service.clone(),
+            Arc::new(Notify::new()),
+            Publisher::new(),
+            CachePurger::disabled(),
+            website_cache.clone(),
+        );
+        let lifecycle = LifecycleConsumer::new(
+            Arc::new(store.clone()),
+            website_service.clone(),
+            Publisher::new(),
+            CachePurger::disabled(),
+            website_cache.clone(),
+            LifecycleConsumer::DEFAULT_USER_DELETED_SUBJECT.to_string(),
+            LifecycleConsumer::DEFAULT_SHOP_DELETED_SUBJECT.to_string(),
+        );
+
+        Self {
+            key,
+            store,
+            identity_provider,
+            edge_provider,
+            dns_resolver,
+            website_cache,
+            website_service,
+            domain_service,
+            page_service,
+            static_page_service,
+            customization_service,
+            lifecycle,
+            published: Cell::new(0),
+            _jwks_server: jwks_server,
+            _bucket_server: bucket_server,
+        }
+    }
+
+    /// Wraps the message in a request authenticated as the user.
+    fn request<T>(&self, user_id: &str, message: T) -> Request<T> {
+        let token = jwtk::sign(
+            HeaderAndClaims::new_dynamic()
+                .set_sub(user_id)
+                .set_exp_from_now(Duration::from_secs(300)),
+            &self.key,
+        )
+        .unwrap();
+
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        request
+    }
+
+    fn subjects(&self) -> Vec<String> {
+        self.store
+            .messages()
+            .into_iter()
+            .map(|m| m.subject)
+            .collect()
+    }
+
+    /// Subjects written to the outbox since the last call.
+    fn published(&self) -> Vec<String> {
+        let subjects = self.subjects();
+        let seen = self.published.replace(subjects.len());
+        subjects[seen..].to_vec()
+    }
+
+    /// Asserts that the RPC was rejected without publishing anything.
+    fn rejected<T: Debug>(&self, result: Result<T, Status>) -> Status {
+        let err = result.unwrap_err();
+        assert!(self.published().is_empty());
+        err
+    }
+
+    /// The ZITADEL project of the website.
+    async fn project(&self, website_id: &str) -> AppProject {
+        self.store
+            .websites()
+            .get(&website_id.to_string())
+            .await
+            .unwrap()
+            .unwrap()
+            .zitadel_project()
+            .unwrap()
+    }
+
+    /// Creates a website and reads it back with its domains and pages. Its
+    /// events are skipped by `published`.
+    async fn create_website(&self, name: &str) -> WebsiteResponse {
+        let created = self
+            .website_service
+            .create_website(self.request(
+                USER_ID,
+                CreateWebsiteRequest {
+                    name: name.to_string(),
+                },
+            ))
+            .await
+            .unwrap()
+            .into_inner()
+            .website
+            .unwrap();
+
+        self.published();
+
+        self.website_service
+            .get_website(Request::new(GetWebsiteRequest {
+                website_id: Some(created.website_id),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .website
+            .unwrap()
+    }
+}
+
+fn png() -> Vec<u8> {
+    let mut data = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(2, 2))
+        .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+        .unwrap();
+    data
+}
+
+#[tokio::test]
+async fn create_website() {
+    let harness = Harness::new().await;
+
+    let website = harness.create_website("My Website").await;
+
+    let domain = format!("{}.{}", website.website_id, MAIN_DOMAIN);
+    assert_eq!(website.user_id, USER_ID);
+    assert_eq!(website.name, "My Website");
+    assert_eq!(website.domains.len(), 1);
+    assert_eq!(website.domains[0].domain, domain);
+    assert_eq!(website.domains[0].status, DomainStatus::Internal as i32);
+    assert_eq!(website.pages.len(), 1);
+    assert!(website.pages[0].is_home_page);
+    assert!(website.customization.is_some());
+    assert_eq!(
+        harness.edge_provider.get_dns_record(&domain).as_deref(),
+        Some(FALLBACK_DOMAIN)
+    );
+    let project = harness.project(&website.website_id).await;
+    let app = harness
+        .identity_provider
+        .list_apps_of(&project)
+        .into_iter()
+        .next()
+        .unwrap();
+    assert_eq!(app.client_id, website.client_id);
+    assert_eq!(app.name, domain);
+    assert_eq!(
+        harness.subjects(),
+        vec!["websites.website.upsert", "websites.v1.website.created"]
+    );
+
+    let err = harness
+        .website_service
+        .create_website(harness.request(
+            USER_ID,
+            CreateWebsiteRequest {
+                name: "My Website".to_string(),
+            },
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn create_website_unauthenticated() {
+    let harness = Harness::new().await;
+
+    let err = harness
+        .website_service
+        .create_website(Request::new(CreateWebsiteRequest {
+            name: "My Website".to_string(),
+        }))
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), Code::Unauthenticated);
+    assert!(harness.store.messages().is_empty());
+}
+
+#[tokio::test]
+async fn get_and_list_websites() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+    harness.create_website("Other Website").await;
+
+    let found = harness
+        .website_service
+        .get_website(Request::new(GetWebsiteRequest {
+            domain: Some(website.domains[0].domain.clone()),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(found.website, Some(website.clone()));
+    assert_eq!(found.redirect_to, None);
+
+    let listed = harness
+        .website_service
+        .list_websites(Request::new(ListWebsitesRequest {
+            user_id: Some(USER_ID.to_string()),
+            pagination: Some(PaginationRequest { page: 1, size: 1 }),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(listed.websites.len(), 1);
+    assert_eq!(listed.pagination.unwrap().total_elements, 2);
+}
+
+#[tokio::test]
+async fn update_website() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+
+    let updated = harness
+        .website_service
+        .update_website(harness.request(
+            USER_ID,
+            UpdateWebsiteRequest {
+                website_id: website.website_id.clone(),
+                name: Some("Renamed".to_string()),
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .website
+        .unwrap();
+    assert_eq!(updated.name, "Renamed");
+    assert_eq!(
+        harness.published(),
+        vec!["websites.website.upsert", "websites.v1.website.updated"]
+    );
+
+    let result = harness
+        .website_service
+        .update_website(harness.request(
+            OTHER_USER_ID,
+            UpdateWebsiteRequest {
+                website_id: website.website_id,
+                name: Some("Stolen".to_string()),
+            },
+        ))
+        .await;
+    harness.rejected(result);
+}
+
+#[tokio::test]
+async fn set_subdomain() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+    let generated_domain = website.domains[0].domain.clone();
+
+    let available = harness
+        .website_service
+        .check_subdomain_availability(harness.request(
+            USER_ID,
+            CheckSubdomainAvailabilityRequest {
+                subdomain: "myshop".to_string(),
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(available.available);
+    assert!(harness.published().is_empty());
+
+    let updated = harness
+        .website_service
+        .set_subdomain(harness.request(
+            USER_ID,
+            SetSubdomainRequest {
+                website_id: website.website_id.clone(),
+                subdomain: "MyShop".to_string(),
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .website
+        .unwrap();
+
+    let status = |domain: &str| {
+        updated
+            .domains
+            .iter()
+            .find(|d| d.domain == domain)
+            .map(|d| d.status)
+    };
+    assert_eq!(
+        status("myshop.sited.io"),
+        Some(DomainStatus::Internal as i32)
+    );
+    assert_eq!(
+        status(&generated_domain),
+        Some(DomainStatus::Redirect as i32)
+    );
+    assert_eq!(
+        harness.published(),
+        vec!["websites.website.upsert", "websites.v1.website.updated"]
+    );
+
+    let result = harness
+        .website_service
+        .set_subdomain(harness.request(
+            OTHER_USER_ID,
+            SetSubdomainRequest {
+                website_id: website.website_id.clone(),
+                subdomain: "stolen".to_string(),
+            },
+        ))
+        .await;
+    assert_eq!(harness.rejected(result).code(), Code::NotFound);
+
+    let redirect = harness
+        .website_service
+        .get_website(Request::new(GetWebsiteRequest {
+            domain: Some(generated_domain.clone()),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(redirect.redirect_to.as_deref(), Some("myshop.sited.io"));
+
+    let taken = harness
+        .website_service
+        .check_subdomain_availability(harness.request(
+            OTHER_USER_ID,
+            CheckSubdomainAvailabilityRequest {
+                subdomain: "myshop".to_string(),
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!taken.available);
+
+    let set_subdomain = |subdomain: &str| {
+        harness.website_service.set_subdomain(harness.request(
+            USER_ID,
+            SetSubdomainRequest {
+                website_id: website.website_id.clone(),
+                subdomain: subdomain.to_string(),
+            },
+        ))
+    };
+
+    // a failed dns record keeps the claim, the reconciler creates it later
+    harness
+        .edge_provider
+        .set_unavailable("mystore.sited.io", true);
+    set_subdomain("mystore").await.unwrap();
+    assert!(harness
+        .store
+        .domains()
+        .get_by_domain(&"mystore.sited.io".to_string())
+        .await
+        .unwrap()
+        .is_some());
+    assert_eq!(
+        harness.edge_provider.get_dns_record("mystore.sited.io"),
+        None
+    );
+
+    // the released subdomain loses its dns record
+    assert_eq!(
+        harness.edge_provider.get_dns_record("myshop.sited.io"),
+        None
+    );
+    harness
+        .edge_provider
+        .set_unavailable("mystore.sited.io", false);
+    set_subdomain("myshop").await.unwrap();
+    assert_eq!(
+        harness.edge_provider.get_dns_record("myshop.sited.io"),
+        Some(FALLBACK_DOMAIN.to_string())
+    );
+
+    // the redirect of the generated domain turns back into its internal
+    // domain and the claimed subdomain is released
+    let reverted =
+        set_subdomain(generated_domain.strip_suffix(".sited.io").unwrap())
+            .await
+            .unwrap()
+            .into_inner()
+            .website
+            .unwrap();
+    assert_eq!(
+        reverted
+            .domains
+            .iter()
+            .map(|d| (d.domain.as_str(), d.status))
+            .collect::<Vec<_>>(),
+        vec![(generated_domain.as_str(), DomainStatus::Internal as i32)]
+    );
+    assert_eq!(
+        harness.edge_provider.get_dns_record("myshop.sited.io"),
+        None
+    );
+    assert_eq!(
+        harness.edge_provider.get_dns_record(&generated_domain),
+        Some(FALLBACK_DOMAIN.to_string())
+    );
+}
+
+#[tokio::test]
+async fn resync_website() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+
+    let resync = |user_id| {
+        harness.website_service.resync_website(harness.request(
+            user_id,
+            ResyncWebsiteRequest {
+                website_id: website.website_id.clone(),
+            },
+        ))
+    };
+
+    // the app was created with the redirect uris of the generated domain
+    assert!(!resync(USER_ID).await.unwrap().into_inner().changed);
+    assert!(harness.published().is_empty());
+    assert_eq!(
+        harness.rejected(resync(OTHER_USER_ID).await).code(),
+        Code::NotFound
+    );
+}
+
+#[tokio::test]
+async fn delete_website() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+    let domain = website.domains[0].domain.clone();
+    let project = harness.project(&website.website_id).await;
+
+    let result = harness
+        .website_service
+        .delete_website(harness.request(
+            OTHER_USER_ID,
+            DeleteWebsiteRequest {
+                website_id: website.website_id.clone(),
+            },
+        ))
+        .await;
+    assert_eq!(harness.rejected(result).code(), Code::NotFound);
+
+    harness
+        .website_service
+        .delete_website(harness.request(
+            USER_ID,
+            DeleteWebsiteRequest {
+                website_id: website.website_id.clone(),
+            },
+        ))
+        .await
+        .unwrap();
+
+    let found = harness
+        .website_service
+        .get_website(Request::new(GetWebsiteRequest {
+            website_id: Some(website.website_id),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(found.website, None);
+    assert_eq!(harness.edge_provider.get_dns_record(&domain), None);
+    assert_eq!(harness.identity_provider.get_branding(&project), None);
+    assert!(harness.identity_provider.list_apps_of(&project).is_empty());
+    assert_eq!(
+        harness.published(),
+        vec!["websites.website.delete", "websites.v1.website.deleted"]
+    );
+}
+
+#[tokio::test]
+async fn domains() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+
+    let created = harness
+        .domain_service
+        .create_domain(harness.request(
+            USER_ID,
+            CreateDomainRequest {
+                website_id: website.website_id.clone(),
+                domain: "Shop.Example.com".to_string(),
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .domain
+        .unwrap();
+    assert_eq!(created.domain, "shop.example.com");
+    assert_eq!(created.status, DomainStatus::Pending as i32);
+    assert_eq!(harness.published(), vec!["websites.v1.domain.created"]);
+
+    let result = harness
+        .domain_service
+        .create_domain(harness.request(
+            OTHER_USER_ID,
+            CreateDomainRequest {
+                website_id: website.website_id.clone(),
+                domain: "other.example.com".to_string(),
+            },
+        ))
+        .await;
+    assert_eq!(harness.rejected(result).code(), Code::InvalidArgument);
+
+    let found = harness
+        .domain_service
+        .get_domain(harness.request(
+            USER_ID,
+            GetDomainRequest {
+                domain_id: created.domain_id,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(found.domain, Some(created.clone()));
+
+    for (user_id, domain_id) in [
+        (OTHER_USER_ID, created.domain_id),
+        (USER_ID, created.domain_id + 1),
+    ] {
+        let result = harness
+            .domain_service
+            .get_domain(
+                harness.request(user_id, GetDomainRequest { domain_id }),
+            )
+            .await;
+        assert_eq!(harness.rejected(result).code(), Code::NotFound);
+    }
+
+    let listed = harness
+        .domain_service
+        .list_domains(harness.request(
+            USER_ID,
+            ListDomainsRequest {
+                website_id: website.website_id.clone(),
+                status: Some(DomainStatus::Pending as i32),
+                pagination: None,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(listed.domains, vec![created.clone()]);
+
+    let check_status = || {
+        harness.domain_service.check_domain_status(harness.request(
+            USER_ID,
+            CheckDomainStatusRequest {
+                domain_id: created.domain_id,
+            },
+        ))
+    };
+
+    let pending = check_status().await.unwrap().into_inner().domain.unwrap();
+    assert_eq!(pending.status, DomainStatus::Pending as i32);
+    assert!(!harness
+        .edge_provider
+        .has_custom_hostname("shop.example.com"));
+    // every check of a pending domain records its result
+    assert_eq!(harness.published(), vec!["websites.v1.domain.updated"]);
+
+    harness.dns_resolver.set(
+        FALLBACK_DOMAIN,
+        vec![DnsAnswer::new(FALLBACK_DOMAIN, RECORD_TYPE_A, "104.21.0.1")],
+    );
+    harness.dns_resolver.set(
+        "shop.example.com",
+        vec![
+            DnsAnswer::new(
+                "shop.example.com",
+                RECORD_TYPE_CNAME,
+                FALLBACK_DOMAIN,
+            ),
+            DnsAnswer::new(FALLBACK_DOMAIN, RECORD_TYPE_A, "104.21.0.1"),
+        ],
+    );
+
+    let active = check_status().await.unwrap().into_inner().domain.unwrap();
+    assert_eq!(active.status, DomainStatus::Active as i32);
+    assert!(harness
+        .edge_provider
+        .has_custom_hostname("shop.example.com"));
+    assert_eq!(harness.published(), vec!["websites.v1.domain.updated"]);
+
+    // active domains are not checked again
+    check_status().await.unwrap();
+    assert!(harness.published().is_empty());
+
+    // degraded domains are restored once they point to the fallback again
+    harness
+        .store
+        .domains()
+        .update_check_status(
+            created.domain_id,
+            DomainStatus::Active,
+            DomainStatus::Degraded,
+            Some(Utc::now()),
+        )
+        .await
+        .unwrap();
+    let restored = check_status().await.unwrap().into_inner().domain.unwrap();
+    assert_eq!(restored.status, DomainStatus::Active as i32);
+    assert_eq!(
+        harness.published(),
+        vec!["websites.domain.restored", "websites.v1.domain.updated"]
+    );
+
+    // the redirect uris of the activated domain are already registered
+    let resync = harness
+        .website_service
+        .resync_website(harness.request(
+            USER_ID,
+            ResyncWebsiteRequest {
+                website_id: website.website_id.clone(),
+            },
+        ))
+        .await
+        .unwrap();
+    assert!(!resync.into_inner().changed);
+
+    harness
+        .domain_service
+        .delete_domain(harness.request(
+            USER_ID,
+            DeleteDomainRequest {
+                domain_id: created.domain_id,
+            },
+        ))
+        .await
+        .unwrap();
+    assert!(!harness
+        .edge_provider
+        .has_custom_hostname("shop.example.com"));
+    assert_eq!(harness.published(), vec!["websites.v1.domain.deleted"]);
+
+    // internal domains can only be replaced with SetSubdomain
+    let result = harness
+        .domain_service
+        .delete_domain(harness.request(
+            USER_ID,
+            DeleteDomainRequest {
+                domain_id: website.domains[0].domain_id,
+            },
+        ))
+        .await;
+    assert_eq!(harness.rejected(result).code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn pending_copies_of_a_domain() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+    let other_website = harness
+        .website_service
+        .create_website(harness.request(
+            OTHER_USER_ID,
+            CreateWebsiteRequest {
+                name: "Other Website".to_string(),
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .website
+        .unwrap();
+
+    let create_domain = |user_id, website_id: &String| {
+        harness.domain_service.create_domain(harness.request(
+            user_id,
+            CreateDomainRequest {
+                website_id: website_id.clone(),
+                domain: "shop.example.com".to_string(),
+            },
+        ))
+    };
+    let created = create_domain(USER_ID, &website.website_id)
+        .await
+        .unwrap()
+        .into_inner()
+        .domain
+        .unwrap();
+    let other_created = create_domain(OTHER_USER_ID, &other_website.website_id)
+        .await
+        .unwrap()
+        .into_inner()
+        .domain
+        .unwrap();
+
+    harness.dns_resolver.set(
+        "shop.example.com",
+        vec![DnsAnswer::new(
+            "shop.example.com",
+            RECORD_TYPE_CNAME,
+            FALLBACK_DOMAIN,
+        )],
+    );
+    let active = harness
+        .domain_service
+        .check_domain_status(harness.request(
+            USER_ID,
+            CheckDomainStatusRequest {
+                domain_id: created.domain_id,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .domain
+        .unwrap();
+    assert_eq!(active.status, DomainStatus::Active as i32);
+    assert_eq!(
+        harness.published().last().map(String::as_str),
+        Some("websites.v1.domain.updated")
+    );
+
+    // pending copies of the active domain can not be activated
+    let result = harness
+        .domain_service
+        .check_domain_status(harness.request(
+            OTHER_USER_ID,
+            CheckDomainStatusRequest {
+                domain_id: other_created.domain_id,
+            },
+        ))
+        .await;
+    assert_eq!(harness.rejected(result).code(), Code::InvalidArgument);
+    let other_domain = harness
+        .store
+        .domains()
+        .get_by_domain_and_status(
+            &"shop.example.com".to_string(),
+            DomainStatus::Pending,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(other_domain.website_id, other_website.website_id);
+
+    // nor concurrently, which the unique index of active domains decides
+    let err = harness
+        .store
+        .domains()
+        .update(
+            other_created.domain_id,
+            &other_website.website_id,
+            &OTHER_USER_ID.to_string(),
+            DomainStatus::Active,
+        )
+        .await
+        .unwrap_err();
+    assert!(err.is_unique_violation());
+
+    // deleting a pending copy keeps the custom hostname of the active one
+    harness
+        .domain_service
+        .delete_domain(harness.request(
+            OTHER_USER_ID,
+            DeleteDomainRequest {
+                domain_id: other_created.domain_id,
+            },
+        ))
+        .await
+        .unwrap();
+    assert!(harness
+        .edge_provider
+        .has_custom_hostname("shop.example.com"));
+}
+
+#[tokio::test]
+async fn pages() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+    let home_page = website.pages[0].clone();
+
+    let created = harness
+        .page_service
+        .create_page(harness.request(
+            USER_ID,
+            CreatePageRequest {
+                website_id: website.website_id.clone(),
+                page_type: PageType::Static as i32,
+                content_id: String::new(),
+                title: "About Us".to_string(),
+                is_home_page: false,
+                path: None,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .page
+        .unwrap();
+    assert_eq!(created.path, "/about-us");
+    assert_eq!(
+        harness.published(),
+        vec![
+            "websites.v1.page.created",
+            "websites.v1.static_page.created"
+        ]
+    );
+
+    let found = harness
+        .page_service
+        .get_page(Request::new(GetPageRequest {
+            page_id: None,
+            website_id: Some(website.website_id.clone()),
+            path: Some("/about-us".to_string()),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(found.page, Some(created.clone()));
+
+    let updated = harness
+        .page_service
+        .update_page(harness.request(
+            USER_ID,
+            UpdatePageRequest {
+                page_id: created.page_id,
+                page_type: None,
+                content_id: None,
+                title: None,
+                is_home_page: Some(true),
+                path: None,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .page
+        .unwrap();
+    assert!(updated.is_home_page);
+    assert_eq!(updated.path, PageService::HOME_PAGE_PATH);
+    // the former home page is moved to its own path
+    assert_eq!(
+        harness.published(),
+        vec!["websites.v1.page.updated", "websites.v1.page.updated"]
+    );
+
+    let listed = harness
+        .page_service
+        .list_pages(Request::new(ListPagesRequest {
+            website_id: Some(website.website_id.clone()),
+            pagination: None,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(listed.pages.len(), 2);
+    let former_home_page = listed
+        .pages
+        .iter()
+        .find(|p| p.page_id == home_page.page_id)
+        .unwrap();
+    assert!(!former_home_page.is_home_page);
+
+    let result = harness
+        .page_service
+        .delete_page(harness.request(
+            USER_ID,
+            DeletePageRequest {
+                page_id: created.page_id,
+            },
+        ))
+        .await;
+    assert_eq!(harness.rejected(result).code(), Code::InvalidArgument);
+
+    harness
+        .page_service
+        .delete_page(harness.request(
+            USER_ID,
+            DeletePageRequest {
+                page_id: home_page.page_id,
+            },
+        ))
+        .await
+        .unwrap();
+    // the default home page has no static page yet
+    assert_eq!(harness.published(), vec!["websites.v1.page.deleted"]);
+}
+
+#[tokio::test]
+async fn static_pages() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+
+    let created = harness
+        .page_service
+        .create_page(harness.request(
+            USER_ID,
+            CreatePageRequest {
+                website_id: website.website_id.clone(),
+                page_type: PageType::Static as i32,
+                content_id: String::new(),
+                title: "About Us".to_string(),
+                is_home_page: false,
+                path: None,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .page
+        .unwrap();
+    harness.published();
+
+    let get_static_page = || {
+        harness.static_page_service.get_static_page(Request::new(
+            GetStaticPageRequest {
+                page_id: created.page_id,
+            },
+        ))
+    };
+
+    let found = get_static_page()
+        .await
+        .unwrap()
+        .into_inner()
+        .static_page
+        .unwrap();
+    assert!(found.components.is_empty());
+
+    let components = vec![Component {
+        component_id: "component-1".to_string(),
+        component_type: None,
+    }];
+
+    let result = harness
+        .static_page_service
+        .update_static_page(harness.request(
+            OTHER_USER_ID,
+            UpdateStaticPageRequest {
+                page_id: created.page_id,
+                components: components.clone(),
+            },
+        ))
+        .await;
+    harness.rejected(result);
+
+    harness
+        .static_page_service
+        .update_static_page(harness.request(
+            USER_ID,
+            UpdateStaticPageRequest {
+                page_id: created.page_id,
+                components: components.clone(),
+            },
+        ))
+        .await
+        .unwrap();
+    assert_eq!(harness.published(), vec!["websites.v1.static_page.updated"]);
+
+    let found = get_static_page()
+        .await
+        .unwrap()
+        .into_inner()
+        .static_page
+        .unwrap();
+    assert_eq!(found.components, components);
+}
+
+#[tokio::test]
+async fn customization() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+
+    let updated = harness
+        .customization_service
+        .update_customization(harness.request(
+            USER_ID,
+            UpdateCustomizationRequest {
+                website_id: website.website_id.clone(),
+                primary_color: Some("#ff0000".to_string()),
+                secondary_color: None,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .customization
+        .unwrap();
+    assert_eq!(updated.primary_color.as_deref(), Some("#ff0000"));
+    assert_eq!(
+        harness.published(),
+        vec!["websites.v1.customization.updated"]
+    );
+
+    let logo_image_url = || async {
+        harness
+            .website_service
+            .get_website(Request::new(GetWebsiteRequest {
+                website_id: Some(website.website_id.clone()),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .website
+            .unwrap()
+            .customization
+            .unwrap()
+            .logo_image_url
+    };
+
+    harness
+        .customization_service
+        .put_logo_image(harness.request(
+            USER_ID,
+            PutLogoImageRequest {
+                website_id: website.website_id.clone(),
+                image: Some(MediaUpload {
+                    content_type: "image/png".to_string(),
+                    data: png(),
+                }),
+            },
+        ))
+        .await
+        .unwrap();
+    assert!(logo_image_url()
+        .await
+        .is_some_and(|url| url
+            .starts_with(&format!("https://images.sited.io/{}/", USER_ID))));
+    assert_eq!(
+        harness.published(),
+        vec!["websites.v1.customization.updated"]
+    );
+
+    harness
+        .customization_service
+        .remove_logo_image(harness.request(
+            USER_ID,
+            RemoveLogoImageRequest {
+                website_id: website.website_id.clone(),
+            },
+        ))
+        .await
+        .unwrap();
+    assert_eq!(logo_image_url().await, None);
+    assert_eq!(
+        harness.published(),
+        vec!["websites.v1.customization.updated"]
+    );
+
+    let result = harness
+        .customization_service
+        .update_customization(harness.request(
+            OTHER_USER_ID,
+            UpdateCustomizationRequest {
+                website_id: website.website_id,
+                primary_color: Some("#00ff00".to_string()),
+                secondary_color: None,
+            },
+        ))
+        .await;
+    harness.rejected(result);
+}
+
+#[tokio::test]
+async fn user_deleted() {
+    let harness = Harness::new().await;
+    let failing = harness.create_website("Failing").await;
+    let deleted = harness.create_website("Deleted").await;
+    let other = harness
+        .website_service
+        .create_website(harness.request(
+            OTHER_USER_ID,
+            CreateWebsiteRequest {
+                name: "Other".to_string(),
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .website
+        .unwrap();
+    harness.published();
+
+    let store = &harness.store;
+    let exists = |website: &WebsiteResponse| {
+        let website_id = website.website_id.clone();
+        async move { store.websites().get(&website_id).await.unwrap().is_some() }
+    };
+    let user_deletions = || async {
+        harness
+            .store
+            .user_deletions()
+            .list_due(Utc::now() + Duration::from_secs(24 * 60 * 60), 10)
+            .await
+            .unwrap()
+    };
+
+    harness
+        .edge_provider
+        .set_unavailable(&failing.domains[0].domain, true);
+    let event = UserDeletedEvent {
+        user_id: USER_ID.to_string(),
+    };
+
+    // a failed website does not stop the others
+    let err = harness
+        .lifecycle
+        .on_user_deleted(&event.encode_to_vec())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::Unavailable);
+    assert!(exists(&failing).await);
+    assert!(!exists(&deleted).await);
+    assert!(exists(&other).await);
+    assert_eq!(
+        harness.published(),
+        vec!["websites.website.delete", "websites.v1.website.deleted"]
+    );
+
+    let pending = user_deletions().await;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].user_id, USER_ID);
+    assert_eq!(pending[0].attempts, 1);
+    assert!(pending[0].next_attempt_at > Utc::now());
+
+    // not retried before the backoff
+    harness
+        .edge_provider
+        .set_unavailable(&failing.domains[0].domain, false);
+    harness
+        .lifecycle
+        .retry_user_deletions(Utc::now())
+        .await
+        .unwrap();
+    assert!(exists(&failing).await);
+
+    harness
+        .lifecycle
+        .retry_user_deletions(pending[0].next_attempt_at)
+        .await
+        .unwrap();
+    assert!(!exists(&failing).await);
+    assert!(exists(&other).await);
+    assert!(user_deletions().await.is_empty());
+
+    // redelivered events find nothing left to delete
+    harness
+        .lifecycle
+        .on_user_deleted(&event.encode_to_vec())
+        .await
+        .unwrap();
+    assert!(user_deletions().await.is_empty());
+}
+
+#[tokio::test]
+async fn shop_deleted() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+    let home_page = website.pages[0].clone();
+
+    harness
+        .page_service
+        .update_page(harness.request(
+            USER_ID,
+            UpdatePageRequest {
+                page_id: home_page.page_id,
+                page_type: Some(PageType::Shop as i32),
+                content_id: Some("shop-1".to_string()),
+                title: None,
+                is_home_page: None,
+                path: None,
+            },
+        ))
+        .await
+        .unwrap();
+    let shop_page = harness
+        .page_service
+        .create_page(harness.request(
+            USER_ID,
+            CreatePageRequest {
+                website_id: website.website_id.clone(),
+                page_type: PageType::Shop as i32,
+                content_id: "shop-1".to_string(),
+                title: "Shop".to_string(),
+                is_home_page: false,
+                path: None,
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner()
+        .page
+        .unwrap();
+    harness.published();
+
+    let event = ShopDeletedEvent {
+        shop_id: "shop-1".to_string(),
+        user_id: USER_ID.to_string(),
+    };
+    harness
+        .lifecycle
+        .on_shop_deleted(&event.encode_to_vec())
+        .await
+        .unwrap();
+
+    let pages = harness.store.pages();
+    assert!(pages.get(shop_page.page_id).await.unwrap().is_none());
+    let home_page = pages.get(home_page.page_id).await.unwrap().unwrap();
+    assert_eq!(home_page.page_type, PageType::Static);
+    assert!(home_page.content_id.is_empty());
+    // the home page can be edited like any static page
+    let static_page = harness
+        .store
+        .static_pages()
+        .get(home_page.page_id)
+        .await
+        .unwrap();
+    assert!(static_page.is_some());
+    assert_eq!(
+        harness.published(),
+        vec![
+            "websites.v1.page.updated",
+            "websites.v1.static_page.created",
+            "websites.v1.page.deleted"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn read_repositories() {
+    let store = InMemoryStore::new().with_replica(InMemoryStore::new());
+    let website_id = "website-1".to_string();
+    store
+        .websites()
+        .create(
+            &website_id,
+            &USER_ID.to_string(),
+            &"My Website".to_string(),
+            &"client-1".to_string(),
+            &"app-1".to_string(),
+            &AppProject {
+                org_id: "org-1".to_string(),
+                project_id: "project-1".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+    let public = MetadataMap::new();
+    let mut authenticated = MetadataMap::new();
+    authenticated.insert("authorization", "Bearer token".parse().unwrap());
+    let mut read_primary = MetadataMap::new();
+    read_primary.insert(READ_PRIMARY_HEADER, "true".parse().unwrap());
+
+    // the replica has not caught up with the write
+    let repositories = super::read_repositories(&store, &public);
+    assert!(repositories
+        .websites()
+        .get(&website_id)
+        .await
+        .unwrap()
+        .is_none());
+
+    for metadata in [authenticated, read_primary] {
+        let repositories = super::read_repositories(&store, &metadata);
+        assert!(repositories
+            .websites()
+            .get(&website_id)
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
+
+#[tokio::test]
+async fn website_cache() {
+    let harness = Harness::new().await;
+    let website = harness.create_website("My Website").await;
+    let by_domain = || {
+        Request::new(GetWebsiteRequest {
+            domain: Some(website.domains[0].domain.clone()),
+            ..Default::default()
+        })
+    };
+    let get_name = |request| async {
+        harness
+            .website_service
+            .get_website(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .website
+            .unwrap()
+            .name
+    };
+    let stats = harness.website_cache.stats();
+
+    assert_eq!(get_name(by_domain()).await, "My Website");
+    assert_eq!(get_name(by_domain()).await, "My Website");
+    assert_eq!(
+        harness.website_cache.stats(),
+        WebsiteCacheStats {
+            hits: stats.hits + 1,
+            misses: stats.misses + 1,
+        }
+    );
+
+    // authenticated reads bypass the cache
+    get_name(harness.request(USER_ID, by_domain().into_inner())).await;
+    assert_eq!(harness.website_cache.stats().hits, stats.hits + 1);
+
+    harness
+        .website_service
+        .update_website(harness.request(
+            USER_ID,
+            UpdateWebsiteRequest {
+                website_id: website.website_id.clone(),
+                name: Some("Renamed".to_string()),
+            },
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(get_name(by_domain()).await, "Renamed");
+}
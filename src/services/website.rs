@@ -1,44 +1,93 @@
-use deadpool_postgres::Pool;
+use std::collections::HashSet;
+
 use jwtk::jwk::RemoteJwksVerifier;
 use tonic::{async_trait, Request, Response, Status};
-use zitadel::api::zitadel::management::v1::AddOidcAppResponse;
 
+use crate::api::sited_io::websites::v1::event_envelope::Payload;
 use crate::api::sited_io::websites::v1::website_service_server::WebsiteServiceServer;
 use crate::api::sited_io::websites::v1::{
-    website_service_server, CreateWebsiteRequest, CreateWebsiteResponse,
-    DeleteWebsiteRequest, DeleteWebsiteResponse, DomainStatus,
-    GetWebsiteRequest, GetWebsiteResponse, ListWebsitesRequest,
-    ListWebsitesResponse, PageType, UpdateWebsiteRequest,
-    UpdateWebsiteResponse, WebsiteResponse,
+    website_service_server, CheckSubdomainAvailabilityRequest,
+    CheckSubdomainAvailabilityResponse, CreateWebsiteRequest,
+    CreateWebsiteResponse, DeleteWebsiteRequest, DeleteWebsiteResponse,
+    DomainStatus, GetWebsiteRequest, GetWebsiteResponse, ListWebsitesRequest,
+    ListWebsitesResponse, PageType, ResyncWebsiteRequest,
+    ResyncWebsiteResponse, SetSubdomainRequest, SetSubdomainResponse,
+    UpdateWebsiteRequest, UpdateWebsiteResponse, WebsiteResponse,
 };
 use crate::auth::get_user_id;
-use crate::cloudflare::CloudflareService;
+use crate::cache_purge::CachePurger;
+use crate::edge::DynEdgeProvider;
+use crate::identity::{
+    AppProject, DynIdentityProvider, IdentityProvider, OidcApp,
+};
 use crate::images::ImageService;
-use crate::model::{Customization, Domain, Page, Website};
-use crate::publisher::Publisher;
-use crate::zitadel::ZitadelService;
+use crate::model::Website;
+use crate::publisher::{EventAction, Publisher};
+use crate::repository::{DynStore, Repositories};
+use crate::website_cache::WebsiteCache;
 use crate::{
     datetime_to_timestamp, i64_to_u32, CustomizationService, DomainService,
     PageService,
 };
 
-use super::get_limit_offset_from_pagination;
+use super::{get_limit_offset_from_pagination, is_public_read};
 
 pub struct WebsiteService {
-    pool: Pool,
+    store: DynStore,
     verifier: RemoteJwksVerifier,
     main_domain: String,
     fallback_domain: String,
-    zitadel_service: ZitadelService,
-    cloudflare_service: CloudflareService,
+    identity_provider: DynIdentityProvider,
+    edge_provider: DynEdgeProvider,
     image_service: ImageService,
     publisher: Publisher,
+    cache_purger: CachePurger,
+    website_cache: WebsiteCache,
 }
 
 const WEBSITE_ID_LENGTH: usize = 14;
 
 const MININUM_WEBSITE_NAME_LENGTH: usize = 4;
 
+/// Domains a website is served on and users sign in from.
+const SIGN_IN_DOMAIN_STATUSES: [DomainStatus; 4] = [
+    DomainStatus::Internal,
+    DomainStatus::Redirect,
+    DomainStatus::Active,
+    DomainStatus::Degraded,
+];
+
+const MINIMUM_SUBDOMAIN_LENGTH: usize = 3;
+
+const MAXIMUM_SUBDOMAIN_LENGTH: usize = 63;
+
+const RESERVED_SUBDOMAINS: [&str; 24] = [
+    "admin",
+    "api",
+    "app",
+    "assets",
+    "auth",
+    "blog",
+    "cdn",
+    "dashboard",
+    "dev",
+    "docs",
+    "fallback",
+    "ftp",
+    "help",
+    "login",
+    "mail",
+    "shop",
+    "sited",
+    "smtp",
+    "staging",
+    "static",
+    "status",
+    "support",
+    "test",
+    "www",
+];
+
 const DOMAIN_ALPHABET: [char; 36] = [
     '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e',
     'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't',
@@ -48,28 +97,64 @@ const DOMAIN_ALPHABET: [char; 36] = [
 impl WebsiteService {
     #[allow(clippy::too_many_arguments)]
     pub fn build(
-        pool: Pool,
+        store: DynStore,
         verifier: RemoteJwksVerifier,
         main_domain: String,
         fallback_domain: String,
-        zitadel_service: ZitadelService,
-        cloudflare_service: CloudflareService,
+        identity_provider: DynIdentityProvider,
+        edge_provider: DynEdgeProvider,
         image_service: ImageService,
         publisher: Publisher,
+        cache_purger: CachePurger,
+        website_cache: WebsiteCache,
     ) -> WebsiteServiceServer<Self> {
-        WebsiteServiceServer::new(Self {
-            pool,
+        WebsiteServiceServer::new(Self::new(
+            store,
             verifier,
             main_domain,
             fallback_domain,
-            zitadel_service,
-            cloudflare_service,
+            identity_provider,
+            edge_provider,
             image_service,
             publisher,
-        })
+            cache_purger,
+            website_cache,
+        ))
     }
 
-    fn to_response(&self, website: Website) -> WebsiteResponse {
+    /// Builds the service without the gRPC server, e.g. to share it with
+    /// `LifecycleConsumer`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        store: DynStore,
+        verifier: RemoteJwksVerifier,
+        main_domain: String,
+        fallback_domain: String,
+        identity_provider: DynIdentityProvider,
+        edge_provider: DynEdgeProvider,
+        image_service: ImageService,
+        publisher: Publisher,
+        cache_purger: CachePurger,
+        website_cache: WebsiteCache,
+    ) -> Self {
+        Self {
+            store,
+            verifier,
+            main_domain,
+            fallback_domain,
+            identity_provider,
+            edge_provider,
+            image_service,
+            publisher,
+            cache_purger,
+            website_cache,
+        }
+    }
+
+    pub fn to_response(
+        image_service: &ImageService,
+        website: Website,
+    ) -> WebsiteResponse {
         WebsiteResponse {
             website_id: website.website_id.to_string(),
             user_id: website.user_id,
@@ -77,9 +162,9 @@ impl WebsiteService {
             updated_at: datetime_to_timestamp(website.updated_at),
             name: website.name,
             client_id: website.client_id,
-            customization: website.customization.map(|c| {
-                CustomizationService::to_response(&self.image_service, c)
-            }),
+            customization: website
+                .customization
+                .map(|c| CustomizationService::to_response(image_service, c)),
             domains: website
                 .domains
                 .into_iter()
@@ -100,45 +185,182 @@ impl WebsiteService {
     fn build_main_domain(&self, website_id: &String) -> String {
         format!("{}.{}", website_id, self.main_domain)
     }
-}
 
-#[async_trait]
-impl website_service_server::WebsiteService for WebsiteService {
-    async fn create_website(
+    /// Finds a website by id or by one of its domains. Shared by
+    /// `GetWebsite` and the NATS lookup, see `LookupResponder`.
+    pub(crate) async fn lookup_website(
+        repositories: &dyn Repositories,
+        image_service: &ImageService,
+        request: GetWebsiteRequest,
+    ) -> Result<GetWebsiteResponse, Status> {
+        let GetWebsiteRequest {
+            website_id, domain, ..
+        } = request;
+
+        let mut is_redirect = false;
+
+        let found_website = match (website_id, domain) {
+            (Some(website_id), _) => {
+                repositories.websites().get(&website_id).await?
+            }
+            (_, Some(domain)) => {
+                // domains are stored in their ASCII form
+                let domain = idna::domain_to_ascii(&domain).unwrap_or(domain);
+                let domain = repositories
+                    .domains()
+                    .get_by_domain(&domain)
+                    .await?
+                    .ok_or_else(|| Status::not_found(""))?;
+                is_redirect = domain.status == DomainStatus::Redirect;
+                repositories.websites().get(&domain.website_id).await?
+            }
+            _ => {
+                return Err(Status::invalid_argument(
+                    "Please provide at least one of 'website_id' or 'domain'",
+                ))
+            }
+        };
+
+        let redirect_to = found_website
+            .as_ref()
+            .filter(|_| is_redirect)
+            .and_then(|w| {
+                w.domains
+                    .iter()
+                    .find(|d| d.status == DomainStatus::Internal)
+            })
+            .map(|d| d.domain.clone());
+
+        Ok(GetWebsiteResponse {
+            website: found_website.map(|w| Self::to_response(image_service, w)),
+            redirect_to,
+        })
+    }
+
+    /// Deletes a website with its ZITADEL project or app, DNS records, custom
+    /// hostnames and logo. External resources are removed first, so a failed
+    /// deletion can be repeated. `actor` is recorded in the deleted event.
+    pub(crate) async fn remove_website(
         &self,
-        request: Request<CreateWebsiteRequest>,
-    ) -> Result<Response<CreateWebsiteResponse>, Status> {
-        let user_id = get_user_id(request.metadata(), &self.verifier).await?;
+        website: Website,
+        actor: &str,
+    ) -> Result<(), Status> {
+        // keeps the reconciler from recreating what is removed below
+        self.store.websites().touch(&website.website_id).await?;
+
+        match website.zitadel_project() {
+            Some(project) => {
+                self.identity_provider.remove_project(&project).await?
+            }
+            None => {
+                if let Some(app) = self
+                    .identity_provider
+                    .get_app(None, &website.zitadel_app_id)
+                    .await?
+                {
+                    self.identity_provider
+                        .remove_app(None, &app.app_id)
+                        .await?;
+                }
+            }
+        }
 
-        let CreateWebsiteRequest { name } = request.into_inner();
+        let Website {
+            website_id,
+            user_id,
+            domains,
+            ..
+        } = website;
 
-        if name.len() < MININUM_WEBSITE_NAME_LENGTH {
-            return Err(Status::invalid_argument("name is too short"));
+        for domain in domains {
+            self.edge_provider
+                .delete_dns_records(&domain.domain)
+                .await?;
+
+            if domain.status == DomainStatus::Active
+                || domain.status == DomainStatus::Degraded
+            {
+                self.edge_provider
+                    .remove_custom_hostname(&domain.domain)
+                    .await?;
+            }
         }
 
-        if Website::get_by_name(&self.pool, &name, &user_id)
+        if let Some(customization) = self
+            .store
+            .customizations()
+            .get_for_user(&website_id, &user_id)
             .await?
-            .is_some()
         {
-            return Err(Status::invalid_argument("duplicate name"));
+            if let Some(logo) = customization.logo_image_url {
+                self.image_service.remove_image(&logo).await?;
+            }
         }
 
-        let website_id = self.generate_website_id();
-        let domain = self.build_main_domain(&website_id);
+        let transaction = self.store.begin().await?;
+
+        let deleted_website = transaction
+            .websites()
+            .delete_with_relations(&website_id, &user_id)
+            .await?;
+
+        let website_response =
+            Self::to_response(&self.image_service, deleted_website);
+
+        self.publisher
+            .publish_website(transaction.outbox(), &website_response, true)
+            .await?;
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Deleted,
+                actor,
+                &website_id,
+                Payload::Website(website_response.clone()),
+            )
+            .await?;
 
-        let mut zitadel_service = self.zitadel_service.clone();
-        let redirect_uri = format!("https://{}/user/sign-in-callback", domain);
-        let post_logout_redirect_uri = format!("https://{}", domain);
+        transaction.commit().await?;
+        self.website_cache.invalidate(&website_id);
 
-        let res = match zitadel_service
+        Ok(())
+    }
+
+    /// Left-over records are removed by `Reconciler`, so failures are only
+    /// logged.
+    async fn try_delete_dns_records(&self, domain: &str) {
+        if let Err(err) = self.edge_provider.delete_dns_records(domain).await {
+            tracing::log::error!(
+                "[WebsiteService.try_delete_dns_records] {}: {}",
+                domain,
+                err
+            );
+        }
+    }
+
+    /// Creates the website with its ZITADEL app in `project`.
+    async fn create_website_in_project(
+        &self,
+        project: &AppProject,
+        website_id: &String,
+        user_id: &String,
+        name: &String,
+        domain: &String,
+    ) -> Result<WebsiteResponse, Status> {
+        let (redirect_uris, post_logout_redirect_uris) =
+            Self::build_redirect_uris(&[domain]);
+
+        let app = match self
+            .identity_provider
             .add_app(
-                domain.clone(),
-                vec![redirect_uri],
-                vec![post_logout_redirect_uri],
+                Some(project),
+                domain,
+                redirect_uris,
+                post_logout_redirect_uris,
             )
             .await
         {
-            Ok(res) => res,
+            Ok(app) => app,
             Err(err) => {
                 tracing::log::error!(
                     "[WebsiteService.create_website] add_app: {}",
@@ -148,95 +370,320 @@ impl website_service_server::WebsiteService for WebsiteService {
             }
         };
 
-        let AddOidcAppResponse {
+        let OidcApp {
             client_id, app_id, ..
-        } = res.into_inner();
+        } = app;
 
         if let Err(err) = self
-            .cloudflare_service
-            .create_dns_record(domain.clone(), self.fallback_domain.clone())
+            .edge_provider
+            .create_dns_record(domain, &self.fallback_domain)
             .await
         {
             tracing::log::error!(
                 "[WebsiteService.create_website] create_dns_record: {}",
                 err
             );
-            return Err(Status::internal(
-                "Error while adding dns record to cloudflare",
-            ));
+            return Err(Status::internal("Error while adding dns record"));
         }
 
-        let created_website = Website::create(
-            &self.pool,
-            &website_id,
-            &user_id,
-            &name,
-            &client_id,
-            &app_id,
-        )
-        .await?;
+        let transaction = self.store.begin().await?;
+
+        let created_website = transaction
+            .websites()
+            .create(website_id, user_id, name, &client_id, &app_id, project)
+            .await?;
+
+        transaction
+            .customizations()
+            .create(website_id, user_id)
+            .await?;
+
+        transaction
+            .domains()
+            .create(website_id, user_id, domain, domain, DomainStatus::Internal)
+            .await?;
+
+        transaction
+            .pages()
+            .create(
+                website_id,
+                user_id,
+                PageType::Static,
+                &"".to_string(),
+                &PageService::DEFAULT_HOME_PAGE_TITLE.to_string(),
+                true,
+                &PageService::HOME_PAGE_PATH.to_string(),
+            )
+            .await?;
 
-        Customization::create(&self.pool, &website_id, &user_id).await?;
+        let website_response =
+            Self::to_response(&self.image_service, created_website);
 
-        Domain::create(
-            &self.pool,
-            &website_id,
-            &user_id,
-            &domain,
-            DomainStatus::Internal.as_str_name(),
-        )
-        .await?;
+        self.publisher
+            .publish_website(transaction.outbox(), &website_response, false)
+            .await?;
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Created,
+                user_id,
+                website_id,
+                Payload::Website(website_response.clone()),
+            )
+            .await?;
 
-        Page::create(
-            &self.pool,
-            &website_id,
-            &user_id,
-            PageType::Static.as_str_name(),
-            &"".to_string(),
-            &PageService::DEFAULT_HOME_PAGE_TITLE.to_string(),
-            true,
-            &PageService::HOME_PAGE_PATH.to_string(),
+        transaction.commit().await?;
+
+        Ok(website_response)
+    }
+
+    pub(crate) fn build_redirect_uris(
+        domains: &[&String],
+    ) -> (Vec<String>, Vec<String>) {
+        domains
+            .iter()
+            .map(|domain| {
+                (
+                    format!("https://{}/user/sign-in-callback", domain),
+                    format!("https://{}", domain),
+                )
+            })
+            .unzip()
+    }
+
+    /// Registers the sign-in callback and post logout URIs of all domains a
+    /// website is served on at its ZITADEL app. The app is only updated if
+    /// the URIs differ, returns whether it was updated.
+    pub(crate) async fn sync_redirect_uris(
+        repositories: &dyn Repositories,
+        identity_provider: &dyn IdentityProvider,
+        website_id: &String,
+    ) -> Result<bool, Status> {
+        let website =
+            repositories.websites().get(website_id).await?.ok_or_else(
+                || {
+                    Status::not_found(format!(
+                        "Could not find website by websiteId '{}'",
+                        website_id
+                    ))
+                },
+            )?;
+
+        let mut domains: Vec<_> = website
+            .domains
+            .iter()
+            .filter(|d| SIGN_IN_DOMAIN_STATUSES.contains(&d.status))
+            .collect();
+        domains
+            .sort_by_key(|d| (d.status != DomainStatus::Internal, d.domain_id));
+
+        let (redirect_uris, post_logout_redirect_uris) =
+            Self::build_redirect_uris(
+                &domains.iter().map(|d| &d.domain).collect::<Vec<_>>(),
+            );
+
+        let project = website.zitadel_project();
+
+        if let Some(app) = identity_provider
+            .get_app(project.as_ref(), &website.zitadel_app_id)
+            .await?
+        {
+            let same = |a: &[String], b: &[String]| {
+                a.iter().collect::<HashSet<_>>()
+                    == b.iter().collect::<HashSet<_>>()
+            };
+            if same(&app.redirect_uris, &redirect_uris)
+                && same(
+                    &app.post_logout_redirect_uris,
+                    &post_logout_redirect_uris,
+                )
+            {
+                return Ok(false);
+            }
+        }
+
+        identity_provider
+            .update_app(
+                project.as_ref(),
+                &website.zitadel_app_id,
+                redirect_uris,
+                post_logout_redirect_uris,
+            )
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Failures only leave sign-in broken on new domains, so they are logged
+    /// and can be repaired with `ResyncWebsite`.
+    async fn try_sync_redirect_uris(&self, website_id: &String) {
+        if let Err(err) = Self::sync_redirect_uris(
+            self.store.as_ref(),
+            self.identity_provider.as_ref(),
+            website_id,
         )
-        .await?;
+        .await
+        {
+            tracing::log::error!(
+                "[WebsiteService.sync_redirect_uris] website_id {}: {}",
+                website_id,
+                err
+            );
+        }
+    }
 
-        let website_response = self.to_response(created_website);
+    /// Returns the lowercase subdomain or a message why it can not be used.
+    fn validate_subdomain(&self, subdomain: &str) -> Result<String, String> {
+        let subdomain = subdomain.trim().to_lowercase();
 
-        self.publisher
-            .publish_website(&website_response, false)
-            .await;
+        if subdomain.len() < MINIMUM_SUBDOMAIN_LENGTH
+            || subdomain.len() > MAXIMUM_SUBDOMAIN_LENGTH
+        {
+            return Err(format!(
+                "Subdomain must have between {} and {} characters",
+                MINIMUM_SUBDOMAIN_LENGTH, MAXIMUM_SUBDOMAIN_LENGTH
+            ));
+        }
 
-        Ok(Response::new(CreateWebsiteResponse {
-            website: Some(website_response),
-        }))
+        if subdomain.starts_with('-')
+            || subdomain.ends_with('-')
+            || !subdomain
+                .chars()
+                .all(|c| DOMAIN_ALPHABET.contains(&c) || c == '-')
+        {
+            return Err("Subdomain may only contain letters, digits and \
+                 hyphens and must not start or end with a hyphen"
+                .to_string());
+        }
+
+        if RESERVED_SUBDOMAINS.contains(&subdomain.as_str())
+            || format!("{}.{}", subdomain, self.main_domain)
+                == self.fallback_domain
+        {
+            return Err("Subdomain is reserved".to_string());
+        }
+
+        Ok(subdomain)
+    }
+
+    /// Checks the subdomain is valid and not used by any website yet.
+    async fn check_subdomain(
+        &self,
+        subdomain: &str,
+    ) -> Result<Result<String, String>, Status> {
+        let subdomain = match self.validate_subdomain(subdomain) {
+            Ok(subdomain) => subdomain,
+            Err(message) => return Ok(Err(message)),
+        };
+
+        let domain = format!("{}.{}", subdomain, self.main_domain);
+
+        if self.store.domains().get_by_domain(&domain).await?.is_some() {
+            Ok(Err("Subdomain is already taken".to_string()))
+        } else {
+            Ok(Ok(domain))
+        }
+    }
+}
+
+#[async_trait]
+impl website_service_server::WebsiteService for WebsiteService {
+    async fn create_website(
+        &self,
+        request: Request<CreateWebsiteRequest>,
+    ) -> Result<Response<CreateWebsiteResponse>, Status> {
+        let user_id = get_user_id(request.metadata(), &self.verifier).await?;
+
+        let CreateWebsiteRequest { name } = request.into_inner();
+
+        if name.len() < MININUM_WEBSITE_NAME_LENGTH {
+            return Err(Status::invalid_argument("name is too short"));
+        }
+
+        if self
+            .store
+            .websites()
+            .get_by_name(&name, &user_id)
+            .await?
+            .is_some()
+        {
+            return Err(Status::invalid_argument("duplicate name"));
+        }
+
+        let website_id = self.generate_website_id();
+        let domain = self.build_main_domain(&website_id);
+
+        let project = match self.identity_provider.add_project(&domain).await {
+            Ok(project) => project,
+            Err(err) => {
+                tracing::log::error!(
+                    "[WebsiteService.create_website] add_project: {}",
+                    err
+                );
+                return Err(Status::internal(
+                    "Could not create ZITADEL project",
+                ));
+            }
+        };
+
+        match self
+            .create_website_in_project(
+                &project,
+                &website_id,
+                &user_id,
+                &name,
+                &domain,
+            )
+            .await
+        {
+            Ok(website_response) => Ok(Response::new(CreateWebsiteResponse {
+                website: Some(website_response),
+            })),
+            Err(err) => {
+                // the reconciler only sees the apps of the shared project
+                if let Err(err) =
+                    self.identity_provider.remove_project(&project).await
+                {
+                    tracing::log::error!(
+                        "[WebsiteService.create_website] remove_project: {}",
+                        err
+                    );
+                }
+                Err(err)
+            }
+        }
     }
 
     async fn get_website(
         &self,
         request: Request<GetWebsiteRequest>,
     ) -> Result<Response<GetWebsiteResponse>, Status> {
-        let GetWebsiteRequest {
-            website_id, domain, ..
-        } = request.into_inner();
-
-        let found_website =
-            match (website_id, domain) {
-                (Some(website_id), _) => {
-                    Website::get(&self.pool, &website_id).await?
-                }
-                (_, Some(domain)) => {
-                    let domain = Domain::get_by_domain(&self.pool, &domain)
-                        .await?
-                        .ok_or_else(|| Status::not_found(""))?;
-                    Website::get(&self.pool, &domain.website_id).await?
-                }
-                _ => return Err(Status::invalid_argument(
-                    "Please provide at least one of 'website_id' or 'domain'",
-                )),
-            };
+        if !is_public_read(request.metadata()) {
+            return Ok(Response::new(
+                Self::lookup_website(
+                    self.store.as_ref(),
+                    &self.image_service,
+                    request.into_inner(),
+                )
+                .await?,
+            ));
+        }
 
-        Ok(Response::new(GetWebsiteResponse {
-            website: found_website.map(|w| self.to_response(w)),
-        }))
+        // the cache does not take websites changed within the lag of the
+        // replica, see `WebsiteCache::with_replica_lag`
+        let repositories = self.store.replica();
+
+        Ok(Response::new(
+            self.website_cache
+                .get_or_lookup(request.into_inner(), |request| {
+                    Self::lookup_website(
+                        repositories,
+                        &self.image_service,
+                        request,
+                    )
+                })
+                .await?,
+        ))
     }
 
     async fn list_websites(
@@ -249,17 +696,18 @@ impl website_service_server::WebsiteService for WebsiteService {
         } = request.into_inner();
 
         let (limit, offset, mut pagination) =
-            get_limit_offset_from_pagination(pagination)?;
+            get_limit_offset_from_pagination(pagination)
+                .map_err(Status::invalid_argument)?;
 
         let (found_websites, count) =
-            Website::list(&self.pool, &user_id, limit, offset).await?;
+            self.store.websites().list(&user_id, limit, offset).await?;
 
-        pagination.total_elements = i64_to_u32(count)?;
+        pagination.total_elements = i64_to_u32(count);
 
         Ok(Response::new(ListWebsitesResponse {
             websites: found_websites
                 .into_iter()
-                .map(|w| self.to_response(w))
+                .map(|w| Self::to_response(&self.image_service, w))
                 .collect(),
             pagination: Some(pagination),
         }))
@@ -278,30 +726,56 @@ impl website_service_server::WebsiteService for WebsiteService {
             return Err(Status::invalid_argument("name is too short"));
         }
 
-        let updated_website =
-            Website::update(&self.pool, &website_id, &user_id, &name).await?;
+        let transaction = self.store.begin().await?;
 
-        let website_response = self.to_response(updated_website);
+        let updated_website = transaction
+            .websites()
+            .update(&website_id, &user_id, &name)
+            .await?;
 
+        let website_response =
+            Self::to_response(&self.image_service, updated_website);
+
+        self.publisher
+            .publish_website(transaction.outbox(), &website_response, false)
+            .await?;
         self.publisher
-            .publish_website(&website_response, false)
-            .await;
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Updated,
+                &user_id,
+                &website_id,
+                Payload::Website(website_response.clone()),
+            )
+            .await?;
+
+        transaction.commit().await?;
+        self.website_cache.invalidate(&website_id);
+
+        self.cache_purger.purge_website(&website_id);
 
         Ok(Response::new(UpdateWebsiteResponse {
             website: Some(website_response),
         }))
     }
 
-    async fn delete_website(
+    async fn set_subdomain(
         &self,
-        request: Request<DeleteWebsiteRequest>,
-    ) -> Result<Response<DeleteWebsiteResponse>, Status> {
+        request: Request<SetSubdomainRequest>,
+    ) -> Result<Response<SetSubdomainResponse>, Status> {
         let user_id = get_user_id(request.metadata(), &self.verifier).await?;
 
-        let DeleteWebsiteRequest { website_id } = request.into_inner();
+        let SetSubdomainRequest {
+            website_id,
+            subdomain,
+        } = request.into_inner();
 
-        let found_website = Website::get(&self.pool, &website_id)
+        let found_website = self
+            .store
+            .websites()
+            .get(&website_id)
             .await?
+            .filter(|w| w.user_id == user_id)
             .ok_or_else(|| {
                 Status::not_found(format!(
                     "Could not find website by websiteId '{}'",
@@ -309,59 +783,237 @@ impl website_service_server::WebsiteService for WebsiteService {
                 ))
             })?;
 
-        let mut zitadel_service = self.zitadel_service.clone();
+        let generated_domain = self.build_main_domain(&website_id);
 
-        if let Some(app) = zitadel_service
-            .get_app(found_website.zitadel_app_id)
-            .await
-            .ok()
-            .and_then(|f| f.into_inner().app)
+        let current_domain = found_website
+            .domains
+            .iter()
+            .find(|d| d.status == DomainStatus::Internal)
+            .cloned();
+
+        let requested_domain =
+            format!("{}.{}", subdomain.trim().to_lowercase(), self.main_domain);
+
+        if current_domain
+            .as_ref()
+            .is_some_and(|d| d.domain == requested_domain)
         {
-            zitadel_service.remove_app(app.id).await?;
+            self.try_sync_redirect_uris(&website_id).await;
+            return Ok(Response::new(SetSubdomainResponse {
+                website: Some(Self::to_response(
+                    &self.image_service,
+                    found_website,
+                )),
+            }));
         }
 
-        for domain in found_website.domains {
-            let found_records = self
-                .cloudflare_service
-                .list_dns_records(Some(domain.domain.clone()))
-                .await?;
-            for record in found_records.result {
-                self.cloudflare_service.delete_dns_record(record.id).await?;
-            }
+        // asking for the generated domain again turns its redirect back into
+        // the internal domain
+        let generated_redirect = found_website
+            .domains
+            .iter()
+            .find(|d| {
+                d.status == DomainStatus::Redirect
+                    && d.domain == generated_domain
+                    && d.domain == requested_domain
+            })
+            .cloned();
+
+        let domain = match &generated_redirect {
+            Some(generated_redirect) => generated_redirect.domain.clone(),
+            None => self
+                .check_subdomain(&subdomain)
+                .await?
+                .map_err(Status::invalid_argument)?,
+        };
 
-            if domain.status == DomainStatus::Active.as_str_name() {
-                let found_custom_hostnames = self
-                    .cloudflare_service
-                    .list_custom_hostnames(&domain.domain)
+        // released subdomains lose their dns record after the commit
+        let released_domain = current_domain
+            .as_ref()
+            .filter(|d| d.domain != generated_domain)
+            .map(|d| d.domain.clone());
+
+        // the unique index of internal domains decides concurrent claims of
+        // the subdomain, its dns record is only created after the commit
+        let transaction = self.store.begin().await?;
+
+        // the generated domain keeps working as a redirect to the subdomain,
+        // a previously claimed subdomain is released
+        if let Some(current_domain) = current_domain {
+            if current_domain.domain == generated_domain {
+                transaction
+                    .domains()
+                    .update(
+                        current_domain.domain_id,
+                        &website_id,
+                        &user_id,
+                        DomainStatus::Redirect,
+                    )
+                    .await?;
+            } else {
+                transaction
+                    .domains()
+                    .delete(current_domain.domain_id, &website_id, &user_id)
                     .await?;
-                for custom_hostname in found_custom_hostnames.result {
-                    self.cloudflare_service
-                        .delete_custom_hostname(custom_hostname.id)
-                        .await?;
-                }
             }
         }
 
-        if let Some(logo) =
-            Customization::get_for_user(&self.pool, &website_id, &user_id)
-                .await?
-                .and_then(|c| c.logo_image_url)
-        {
-            self.image_service.remove_image(&logo).await?;
+        if let Some(generated_redirect) = &generated_redirect {
+            transaction
+                .domains()
+                .update(
+                    generated_redirect.domain_id,
+                    &website_id,
+                    &user_id,
+                    DomainStatus::Internal,
+                )
+                .await?;
+        } else {
+            transaction
+                .domains()
+                .create(
+                    &website_id,
+                    &user_id,
+                    &domain,
+                    &domain,
+                    DomainStatus::Internal,
+                )
+                .await
+                .map_err(|err| {
+                    if err.is_unique_violation() {
+                        Status::already_exists("Subdomain is already taken")
+                    } else {
+                        err.into()
+                    }
+                })?;
         }
 
-        Customization::delete(&self.pool, &website_id, &user_id).await?;
+        let updated_website = transaction
+            .websites()
+            .get(&website_id)
+            .await?
+            .ok_or_else(|| Status::internal(""))?;
 
-        Domain::delete_for_website(&self.pool, &website_id, &user_id).await?;
+        let website_response =
+            Self::to_response(&self.image_service, updated_website);
 
-        Page::delete_for_website(&self.pool, &website_id, &user_id).await?;
+        self.publisher
+            .publish_website(transaction.outbox(), &website_response, false)
+            .await?;
+        self.publisher
+            .publish_event(
+                transaction.outbox(),
+                EventAction::Updated,
+                &user_id,
+                &website_id,
+                Payload::Website(website_response.clone()),
+            )
+            .await?;
+
+        transaction.commit().await?;
+        self.website_cache.invalidate(&website_id);
+
+        // the redirect kept its dns record, a missing one is created by the
+        // `Reconciler`
+        if generated_redirect.is_none() {
+            if let Err(err) = self
+                .edge_provider
+                .create_dns_record(&domain, &self.fallback_domain)
+                .await
+            {
+                tracing::log::error!(
+                    "[WebsiteService.set_subdomain] create_dns_record: {}",
+                    err
+                );
+            }
+        }
 
-        let deleted_website =
-            Website::delete(&self.pool, &website_id, &user_id).await?;
+        if let Some(released_domain) = released_domain {
+            self.try_delete_dns_records(&released_domain).await;
+        }
 
-        self.publisher
-            .publish_website(&self.to_response(deleted_website), true)
-            .await;
+        self.try_sync_redirect_uris(&website_id).await;
+
+        Ok(Response::new(SetSubdomainResponse {
+            website: Some(website_response),
+        }))
+    }
+
+    async fn resync_website(
+        &self,
+        request: Request<ResyncWebsiteRequest>,
+    ) -> Result<Response<ResyncWebsiteResponse>, Status> {
+        let user_id = get_user_id(request.metadata(), &self.verifier).await?;
+
+        let ResyncWebsiteRequest { website_id } = request.into_inner();
+
+        self.store
+            .websites()
+            .get(&website_id)
+            .await?
+            .filter(|w| w.user_id == user_id)
+            .ok_or_else(|| {
+                Status::not_found(format!(
+                    "Could not find website by websiteId '{}'",
+                    website_id
+                ))
+            })?;
+
+        let changed = Self::sync_redirect_uris(
+            self.store.as_ref(),
+            self.identity_provider.as_ref(),
+            &website_id,
+        )
+        .await?;
+
+        Ok(Response::new(ResyncWebsiteResponse { changed }))
+    }
+
+    async fn check_subdomain_availability(
+        &self,
+        request: Request<CheckSubdomainAvailabilityRequest>,
+    ) -> Result<Response<CheckSubdomainAvailabilityResponse>, Status> {
+        get_user_id(request.metadata(), &self.verifier).await?;
+
+        let CheckSubdomainAvailabilityRequest { subdomain } =
+            request.into_inner();
+
+        let response = match self.check_subdomain(&subdomain).await? {
+            Ok(domain) => CheckSubdomainAvailabilityResponse {
+                available: true,
+                message: format!("'{}' is available", domain),
+            },
+            Err(message) => CheckSubdomainAvailabilityResponse {
+                available: false,
+                message,
+            },
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn delete_website(
+        &self,
+        request: Request<DeleteWebsiteRequest>,
+    ) -> Result<Response<DeleteWebsiteResponse>, Status> {
+        let user_id = get_user_id(request.metadata(), &self.verifier).await?;
+
+        let DeleteWebsiteRequest { website_id } = request.into_inner();
+
+        let found_website = self
+            .store
+            .websites()
+            .get(&website_id)
+            .await?
+            .filter(|w| w.user_id == user_id)
+            .ok_or_else(|| {
+                Status::not_found(format!(
+                    "Could not find website by websiteId '{}'",
+                    website_id
+                ))
+            })?;
+
+        self.remove_website(found_website, &user_id).await?;
 
         Ok(Response::new(DeleteWebsiteResponse::default()))
     }
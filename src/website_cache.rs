@@ -0,0 +1,447 @@
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use lru::LruCache;
+use prost::Message;
+use tokio::time::Instant;
+
+use crate::api::sited_io::websites::v1::{
+    EventEnvelope, GetWebsiteRequest, GetWebsiteResponse,
+};
+use crate::publisher::Publisher;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    WebsiteId(String),
+    /// In its ASCII form, like domains are stored.
+    Domain(String),
+}
+
+impl CacheKey {
+    fn from_request(request: &GetWebsiteRequest) -> Option<Self> {
+        match (&request.website_id, &request.domain) {
+            (Some(website_id), _) => Some(Self::WebsiteId(website_id.clone())),
+            (_, Some(domain)) => Some(Self::Domain(
+                idna::domain_to_ascii(domain).unwrap_or(domain.clone()),
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    website_id: String,
+    response: GetWebsiteResponse,
+    expires_at: Instant,
+}
+
+/// Entries expire `ttl` after they were inserted. When full, the least
+/// recently used entry is dropped.
+#[derive(Debug)]
+struct Entries {
+    ttl: Duration,
+    /// How far the replica may lag behind the primary. Websites invalidated
+    /// within it are not cached, the replica may still return them as they
+    /// were.
+    replica_lag: Duration,
+    /// `None` with a capacity of zero.
+    entries: Option<LruCache<CacheKey, CacheEntry>>,
+    /// The keys of every cached website, so all of them are invalidated.
+    keys: HashMap<String, HashSet<CacheKey>>,
+    /// When websites were invalidated within `replica_lag`.
+    invalidated: HashMap<String, Instant>,
+    /// Counts invalidations, so lookups that started before one do not put
+    /// the website back into the cache as it was.
+    generation: u64,
+}
+
+impl Entries {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            replica_lag: Duration::ZERO,
+            entries: NonZeroUsize::new(capacity).map(LruCache::new),
+            keys: HashMap::new(),
+            invalidated: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    fn get(
+        &mut self,
+        key: &CacheKey,
+        now: Instant,
+    ) -> Option<GetWebsiteResponse> {
+        let entries = self.entries.as_mut()?;
+
+        match entries.get(key) {
+            Some(entry) if entry.expires_at > now => {
+                return Some(entry.response.clone())
+            }
+            Some(_) => {}
+            None => return None,
+        }
+
+        if let Some(expired) = entries.pop(key) {
+            self.unlink(key, &expired.website_id);
+        }
+        None
+    }
+
+    fn insert(
+        &mut self,
+        key: CacheKey,
+        response: &GetWebsiteResponse,
+        generation: u64,
+        now: Instant,
+    ) {
+        // not found websites are not cached, so they show up once created
+        let Some(website) = &response.website else {
+            return;
+        };
+
+        if generation != self.generation
+            || self
+                .invalidated
+                .get(&website.website_id)
+                .is_some_and(|at| now < *at + self.replica_lag)
+        {
+            return;
+        }
+
+        let Some(entries) = &mut self.entries else {
+            return;
+        };
+
+        // the replaced entry of the key, or the least recently used one
+        let removed = entries.push(
+            key.clone(),
+            CacheEntry {
+                website_id: website.website_id.clone(),
+                response: response.clone(),
+                expires_at: now + self.ttl,
+            },
+        );
+        if let Some((removed_key, removed)) = removed {
+            self.unlink(&removed_key, &removed.website_id);
+        }
+
+        self.keys
+            .entry(website.website_id.clone())
+            .or_default()
+            .insert(key);
+    }
+
+    fn invalidate(&mut self, website_id: &str, now: Instant) {
+        self.generation += 1;
+
+        if !self.replica_lag.is_zero() {
+            let replica_lag = self.replica_lag;
+            self.invalidated.retain(|_, at| now < *at + replica_lag);
+            self.invalidated.insert(website_id.to_string(), now);
+        }
+
+        if let (Some(entries), Some(keys)) =
+            (&mut self.entries, self.keys.remove(website_id))
+        {
+            for key in keys {
+                entries.pop(&key);
+            }
+        }
+    }
+
+    fn unlink(&mut self, key: &CacheKey, website_id: &str) {
+        if let Some(keys) = self.keys.get_mut(website_id) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.keys.remove(website_id);
+            }
+        }
+    }
+}
+
+/// Hits and misses of the cache since the start of the service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WebsiteCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Bounded cache of `GetWebsite` responses by website id and by domain, for
+/// the renderers resolving the website of every visited domain.
+///
+/// Websites are invalidated after the services changed them, and on the
+/// events of other replicas, see `spawn_invalidation`. Misses may be looked
+/// up on the database replica, see `with_replica_lag`. A disabled cache
+/// misses on every lookup.
+#[derive(Debug, Clone)]
+pub struct WebsiteCache {
+    inner: Option<Arc<Inner>>,
+}
+
+impl WebsiteCache {
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+    pub const DEFAULT_REPLICA_LAG: Duration = Duration::from_secs(10);
+    const STATS_INTERVAL: Duration = Duration::from_secs(300);
+
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            inner: Some(Arc::new(Inner {
+                entries: Mutex::new(Entries::new(ttl, capacity)),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            })),
+        }
+    }
+
+    /// Keeps websites invalidated within `replica_lag` out of the cache, so
+    /// misses can be looked up on a replica lagging behind by up to that.
+    pub fn with_replica_lag(self, replica_lag: Duration) -> Self {
+        if let Some(inner) = &self.inner {
+            Self::entries(inner).replica_lag = replica_lag;
+        }
+        self
+    }
+
+    pub fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    fn entries(inner: &Inner) -> std::sync::MutexGuard<'_, Entries> {
+        inner.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Returns the cached response, or runs `lookup` and caches its response
+    /// if it found a website.
+    pub async fn get_or_lookup<F>(
+        &self,
+        request: GetWebsiteRequest,
+        lookup: impl FnOnce(GetWebsiteRequest) -> F,
+    ) -> Result<GetWebsiteResponse, tonic::Status>
+    where
+        F: std::future::Future<
+            Output = Result<GetWebsiteResponse, tonic::Status>,
+        >,
+    {
+        let (Some(inner), Some(key)) =
+            (&self.inner, CacheKey::from_request(&request))
+        else {
+            return lookup(request).await;
+        };
+
+        let generation = {
+            let mut entries = Self::entries(inner);
+            if let Some(response) = entries.get(&key, Instant::now()) {
+                inner.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(response);
+            }
+            entries.generation
+        };
+        inner.misses.fetch_add(1, Ordering::Relaxed);
+
+        let response = lookup(request).await?;
+
+        Self::entries(inner).insert(key, &response, generation, Instant::now());
+
+        Ok(response)
+    }
+
+    /// Drops the website from the cache. Called after changes to the website
+    /// or its domains, pages or customization were committed.
+    pub fn invalidate(&self, website_id: &str) {
+        if let Some(inner) = &self.inner {
+            Self::entries(inner).invalidate(website_id, Instant::now());
+        }
+    }
+
+    pub fn stats(&self) -> WebsiteCacheStats {
+        match &self.inner {
+            Some(inner) => WebsiteCacheStats {
+                hits: inner.hits.load(Ordering::Relaxed),
+                misses: inner.misses.load(Ordering::Relaxed),
+            },
+            None => WebsiteCacheStats::default(),
+        }
+    }
+
+    /// Logs the hit and miss counters periodically.
+    pub fn spawn_stats_logging(&self) -> tokio::task::JoinHandle<()> {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Self::STATS_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let WebsiteCacheStats { hits, misses } = cache.stats();
+                tracing::log::info!(
+                    "[WebsiteCache] hits: {}, misses: {}",
+                    hits,
+                    misses
+                );
+            }
+        })
+    }
+
+    /// Invalidates websites changed by other replicas, on the events every
+    /// change publishes, see `Publisher::publish_event`. Every replica
+    /// receives every event, so this does not use a queue group.
+    pub async fn spawn_invalidation(
+        &self,
+        nats_client: async_nats::Client,
+    ) -> Result<tokio::task::JoinHandle<()>, async_nats::SubscribeError> {
+        let mut events = nats_client
+            .subscribe(format!("{}.>", Publisher::EVENT_SUBJECT_PREFIX))
+            .await?;
+        let cache = self.clone();
+
+        Ok(tokio::spawn(async move {
+            while let Some(message) = events.next().await {
+                match EventEnvelope::decode(message.payload) {
+                    Ok(event) => cache.invalidate(&event.website_id),
+                    Err(err) => tracing::log::error!(
+                        "[WebsiteCache] could not decode event on '{}': {}",
+                        message.subject,
+                        err
+                    ),
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::api::sited_io::websites::v1::{
+        GetWebsiteRequest, GetWebsiteResponse, WebsiteResponse,
+    };
+
+    use super::{CacheKey, Entries, WebsiteCache, WebsiteCacheStats};
+
+    fn response(website_id: &str) -> GetWebsiteResponse {
+        GetWebsiteResponse {
+            website: Some(WebsiteResponse {
+                website_id: website_id.to_string(),
+                ..Default::default()
+            }),
+            redirect_to: None,
+        }
+    }
+
+    fn key(website_id: &str) -> CacheKey {
+        CacheKey::WebsiteId(website_id.to_string())
+    }
+
+    #[test]
+    fn expires_after_ttl() {
+        let mut entries = Entries::new(Duration::from_secs(10), 10);
+        let now = Instant::now();
+
+        entries.insert(key("w1"), &response("w1"), 0, now);
+
+        assert!(entries.get(&key("w1"), now).is_some());
+        assert!(entries
+            .get(&key("w1"), now + Duration::from_secs(10))
+            .is_none());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_when_full() {
+        let mut entries = Entries::new(Duration::from_secs(10), 2);
+        let now = Instant::now();
+
+        entries.insert(key("w1"), &response("w1"), 0, now);
+        entries.insert(key("w2"), &response("w2"), 0, now);
+        assert!(entries.get(&key("w1"), now).is_some());
+        entries.insert(key("w3"), &response("w3"), 0, now);
+
+        assert!(entries.get(&key("w1"), now).is_some());
+        assert!(entries.get(&key("w2"), now).is_none());
+        assert!(entries.get(&key("w3"), now).is_some());
+        assert!(!entries.keys.contains_key("w2"));
+    }
+
+    #[test]
+    fn skips_websites_invalidated_within_replica_lag() {
+        let mut entries = Entries::new(Duration::from_secs(10), 10);
+        entries.replica_lag = Duration::from_secs(5);
+        let now = Instant::now();
+
+        entries.invalidate("w1", now);
+
+        // the replica may not have seen the change yet
+        let generation = entries.generation;
+        let soon = now + Duration::from_secs(4);
+        entries.insert(key("w1"), &response("w1"), generation, soon);
+        assert!(entries.get(&key("w1"), soon).is_none());
+
+        let later = now + Duration::from_secs(5);
+        entries.insert(key("w1"), &response("w1"), generation, later);
+        assert!(entries.get(&key("w1"), later).is_some());
+    }
+
+    #[test]
+    fn invalidates_all_keys_of_website() {
+        let mut entries = Entries::new(Duration::from_secs(10), 10);
+        let now = Instant::now();
+        let domain = CacheKey::Domain("example.com".to_string());
+
+        entries.insert(key("w1"), &response("w1"), 0, now);
+        entries.insert(domain.clone(), &response("w1"), 0, now);
+        entries.insert(key("w2"), &response("w2"), 0, now);
+        entries.invalidate("w1", now);
+
+        assert!(entries.get(&key("w1"), now).is_none());
+        assert!(entries.get(&domain, now).is_none());
+        assert!(entries.get(&key("w2"), now).is_some());
+
+        // a lookup started before the invalidation may have read the old
+        // website
+        entries.insert(key("w1"), &response("w1"), 0, now);
+        assert!(entries.get(&key("w1"), now).is_none());
+    }
+
+    #[tokio::test]
+    async fn counts_hits_and_misses() {
+        let cache = WebsiteCache::new(Duration::from_secs(10), 10);
+        let request = GetWebsiteRequest {
+            domain: Some("example.com".to_string()),
+            ..Default::default()
+        };
+
+        for _ in 0..3 {
+            let response = cache
+                .get_or_lookup(request.clone(), |_| async {
+                    Ok(response("w1"))
+                })
+                .await
+                .unwrap();
+            assert_eq!(response, self::response("w1"));
+        }
+
+        assert_eq!(cache.stats(), WebsiteCacheStats { hits: 2, misses: 1 });
+
+        cache.invalidate("w1");
+        cache
+            .get_or_lookup(request, |_| async { Ok(response("w1")) })
+            .await
+            .unwrap();
+        assert_eq!(cache.stats(), WebsiteCacheStats { hits: 2, misses: 2 });
+    }
+}
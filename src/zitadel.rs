@@ -1,87 +1,723 @@
+use chrono::{DateTime, Utc};
 use tonic::transport::Channel;
-use tonic::{Request, Response, Status};
+use tonic::{async_trait, Code, Request, Status};
+use zitadel::api::zitadel::app::v1::app::Config;
 use zitadel::api::zitadel::app::v1::{
-    OidcAppType, OidcAuthMethodType, OidcGrantType, OidcResponseType,
+    App, OidcAppType, OidcAuthMethodType, OidcGrantType, OidcResponseType,
 };
+use zitadel::api::zitadel::auth::v1::auth_service_client::AuthServiceClient;
+use zitadel::api::zitadel::auth::v1::ListMyMembershipsRequest;
 use zitadel::api::zitadel::management::v1::management_service_client::ManagementServiceClient;
 use zitadel::api::zitadel::management::v1::{
-    AddOidcAppRequest, AddOidcAppResponse, GetAppByIdRequest,
-    GetAppByIdResponse, RemoveAppRequest, RemoveAppResponse,
+    ActivateCustomLabelPolicyRequest, AddCustomLabelPolicyRequest,
+    AddOidcAppRequest, AddOrgRequest, AddProjectRequest, GetAppByIdRequest,
+    GetLabelPolicyRequest, GetMyOrgRequest, GetPreviewLabelPolicyRequest,
+    ListAppsRequest, ListProjectsRequest, RemoveAppRequest,
+    RemoveCustomLabelPolicyLogoRequest, RemoveOrgRequest,
+    UpdateCustomLabelPolicyRequest, UpdateOidcAppConfigRequest,
 };
+use zitadel::api::zitadel::policy::v1::LabelPolicy;
+use zitadel::api::zitadel::project::v1::PrivateLabelingSetting;
+use zitadel::api::zitadel::user::v1::membership::Type as MembershipType;
 use zitadel::api::zitadel::user::v1::AccessTokenType;
+use zitadel::api::zitadel::v1::{ListQuery, ObjectDetails};
+
+use crate::identity::{
+    AppProject, Branding, IdentityProvider, OidcApp, ProjectOrg,
+};
 
 #[derive(Debug, Clone)]
 pub struct ZitadelService {
     management_service_client: ManagementServiceClient<Channel>,
+    auth_service_client: AuthServiceClient<Channel>,
+    http_client: reqwest::Client,
+    url: String,
     service_user_token: String,
     project_id: String,
 }
 
 impl ZitadelService {
+    const LIST_LIMIT: u32 = 100;
+
     pub async fn init(
         url: String,
         service_user_token: String,
         project_id: String,
     ) -> Result<Self, tonic::transport::Error> {
         Ok(Self {
-            management_service_client: ManagementServiceClient::connect(url)
-                .await
-                .unwrap(),
+            management_service_client: ManagementServiceClient::connect(
+                url.clone(),
+            )
+            .await?,
+            auth_service_client: AuthServiceClient::connect(url.clone())
+                .await?,
+            http_client: reqwest::Client::new(),
+            url,
             service_user_token,
             project_id,
         })
     }
 
-    pub async fn add_app(
-        &mut self,
-        name: String,
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut req = Request::new(message);
+        req.metadata_mut()
+            .insert("authorization", self.service_user_token.parse().unwrap());
+        req
+    }
+
+    /// Like `request`, but acting on the organization `org_id` instead of the
+    /// organization of the service user.
+    fn org_request<T>(&self, org_id: &str, message: T) -> Request<T> {
+        let mut req = self.request(message);
+        req.metadata_mut()
+            .insert("x-zitadel-orgid", org_id.parse().unwrap());
+        req
+    }
+
+    /// Like `request`, but acting on the organization owning `project`. The
+    /// shared project is in the organization of the service user.
+    fn project_request<T>(
+        &self,
+        project: Option<&AppProject>,
+        message: T,
+    ) -> Request<T> {
+        match project {
+            Some(project) => self.org_request(&project.org_id, message),
+            None => self.request(message),
+        }
+    }
+
+    fn project_id(&self, project: Option<&AppProject>) -> String {
+        project
+            .map(|p| &p.project_id)
+            .unwrap_or(&self.project_id)
+            .clone()
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, Status> {
+        let to_status = |err: reqwest::Error| {
+            tracing::log::error!("[ZitadelService.download]: {}", err);
+            Status::unavailable("Could not download logo")
+        };
+
+        Ok(self
+            .http_client
+            .get(url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(to_status)?
+            .bytes()
+            .await
+            .map_err(to_status)?
+            .to_vec())
+    }
+
+    /// Downloads the logo of `policy`, if it has one.
+    async fn download_logo(
+        &self,
+        policy: &LabelPolicy,
+    ) -> Result<Option<Vec<u8>>, Status> {
+        if policy.is_default || policy.logo_url.is_empty() {
+            Ok(None)
+        } else {
+            self.download(&policy.logo_url).await.map(Some)
+        }
+    }
+
+    /// Uploads `logo` as logo of the label policy of `org_id`. Assets are not
+    /// part of the gRPC API.
+    async fn upload_logo(
+        &self,
+        org_id: &str,
+        logo: &[u8],
+    ) -> Result<(), Status> {
+        let to_status = |err: reqwest::Error| {
+            tracing::log::error!("[ZitadelService.upload_logo]: {}", err);
+            Status::unavailable("Could not upload logo")
+        };
+
+        let content_type = infer::get(logo)
+            .map(|t| t.mime_type())
+            .unwrap_or("application/octet-stream");
+        let boundary = uuid::Uuid::new_v4().simple().to_string();
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; \
+             filename=\"logo\"\r\nContent-Type: {content_type}\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(logo);
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        self.http_client
+            .post(format!("{}/assets/v1/org/policy/label/logo", self.url))
+            .header("authorization", &self.service_user_token)
+            .header("x-zitadel-orgid", org_id)
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(to_status)?;
+
+        Ok(())
+    }
+
+    async fn list_project_apps(
+        &self,
+        project: Option<&AppProject>,
+    ) -> Result<Vec<OidcApp>, Status> {
+        let mut apps = Vec::new();
+
+        loop {
+            let req = self.project_request(
+                project,
+                ListAppsRequest {
+                    project_id: self.project_id(project),
+                    query: Some(ListQuery {
+                        offset: apps.len() as u64,
+                        limit: Self::LIST_LIMIT,
+                        asc: true,
+                    }),
+                    queries: vec![],
+                },
+            );
+
+            let result = self
+                .management_service_client
+                .clone()
+                .list_apps(req)
+                .await?
+                .into_inner()
+                .result;
+            let is_last_page = result.len() < Self::LIST_LIMIT as usize;
+            apps.extend(result.into_iter().map(Self::to_oidc_app));
+
+            if is_last_page {
+                return Ok(apps);
+            }
+        }
+    }
+
+    fn created_at(details: &Option<ObjectDetails>) -> Option<DateTime<Utc>> {
+        details
+            .as_ref()
+            .and_then(|d| d.creation_date.as_ref())
+            .and_then(|d| DateTime::from_timestamp(d.seconds, 0))
+    }
+
+    fn to_oidc_app(app: App) -> OidcApp {
+        let created_at = Self::created_at(&app.details);
+
+        let (client_id, redirect_uris, post_logout_redirect_uris) =
+            match app.config {
+                Some(Config::OidcConfig(config)) => (
+                    config.client_id,
+                    config.redirect_uris,
+                    config.post_logout_redirect_uris,
+                ),
+                _ => Default::default(),
+            };
+
+        OidcApp {
+            app_id: app.id,
+            client_id,
+            name: app.name,
+            redirect_uris,
+            post_logout_redirect_uris,
+            created_at,
+        }
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for ZitadelService {
+    /// ZITADEL brands the login screen with the label policy of an
+    /// organization. Every website gets an organization with a project that
+    /// enforces the policy of its owner, so the login screen of the app shows
+    /// the branding without the organization being requested in the scopes.
+    async fn add_project(&self, name: &str) -> Result<AppProject, Status> {
+        let mut client = self.management_service_client.clone();
+
+        let org_id = client
+            .add_org(self.request(AddOrgRequest {
+                name: name.to_string(),
+            }))
+            .await?
+            .into_inner()
+            .id;
+
+        let req = self.org_request(
+            &org_id,
+            AddProjectRequest {
+                name: name.to_string(),
+                private_labeling_setting:
+                    PrivateLabelingSetting::EnforceProjectResourceOwnerPolicy
+                        .into(),
+                ..Default::default()
+            },
+        );
+
+        match client.add_project(req).await {
+            Ok(res) => Ok(AppProject {
+                org_id,
+                project_id: res.into_inner().id,
+            }),
+            Err(status) => {
+                if let Err(err) = self.remove_org(&org_id).await {
+                    tracing::log::error!(
+                        "[ZitadelService.add_project] remove_org: {}",
+                        err
+                    );
+                }
+                Err(status)
+            }
+        }
+    }
+
+    /// Removing the organization removes its project and apps.
+    async fn remove_project(&self, project: &AppProject) -> Result<(), Status> {
+        self.remove_org(&project.org_id).await
+    }
+
+    async fn remove_org(&self, org_id: &str) -> Result<(), Status> {
+        match self
+            .management_service_client
+            .clone()
+            .remove_org(self.org_request(org_id, RemoveOrgRequest {}))
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(status) if status.code() == Code::NotFound => Ok(()),
+            Err(status) => Err(status),
+        }
+    }
+
+    async fn add_app(
+        &self,
+        project: Option<&AppProject>,
+        name: &str,
         redirect_uris: Vec<String>,
         post_logout_redirect_uris: Vec<String>,
-    ) -> Result<Response<AddOidcAppResponse>, Status> {
-        let mut req = Request::new(AddOidcAppRequest {
-            project_id: self.project_id.clone(),
-            name,
+    ) -> Result<OidcApp, Status> {
+        let req = self.project_request(
+            project,
+            AddOidcAppRequest {
+                project_id: self.project_id(project),
+                name: name.to_string(),
+                redirect_uris: redirect_uris.clone(),
+                response_types: vec![OidcResponseType::Code.into()],
+                grant_types: vec![
+                    OidcGrantType::AuthorizationCode.into(),
+                    OidcGrantType::RefreshToken.into(),
+                ],
+                app_type: OidcAppType::Web.into(),
+                auth_method_type: OidcAuthMethodType::None.into(),
+                post_logout_redirect_uris: post_logout_redirect_uris.clone(),
+                access_token_type: AccessTokenType::Jwt.into(),
+                ..Default::default()
+            },
+        );
+
+        let res = self
+            .management_service_client
+            .clone()
+            .add_oidc_app(req)
+            .await?
+            .into_inner();
+
+        Ok(OidcApp {
+            app_id: res.app_id,
+            client_id: res.client_id,
+            name: name.to_string(),
             redirect_uris,
-            response_types: vec![OidcResponseType::Code.into()],
-            grant_types: vec![
-                OidcGrantType::AuthorizationCode.into(),
-                OidcGrantType::RefreshToken.into(),
-            ],
-            app_type: OidcAppType::Web.into(),
-            auth_method_type: OidcAuthMethodType::None.into(),
             post_logout_redirect_uris,
-            access_token_type: AccessTokenType::Jwt.into(),
-            ..Default::default()
-        });
-        req.metadata_mut()
-            .insert("authorization", self.service_user_token.parse().unwrap());
+            created_at: Self::created_at(&res.details),
+        })
+    }
+
+    async fn get_app(
+        &self,
+        project: Option<&AppProject>,
+        app_id: &str,
+    ) -> Result<Option<OidcApp>, Status> {
+        let req = self.project_request(
+            project,
+            GetAppByIdRequest {
+                project_id: self.project_id(project),
+                app_id: app_id.to_string(),
+            },
+        );
 
-        self.management_service_client.add_oidc_app(req).await
+        match self
+            .management_service_client
+            .clone()
+            .get_app_by_id(req)
+            .await
+        {
+            Ok(res) => Ok(res.into_inner().app.map(Self::to_oidc_app)),
+            Err(status) if status.code() == Code::NotFound => Ok(None),
+            Err(status) => Err(status),
+        }
     }
 
-    pub async fn get_app(
-        &mut self,
-        app_id: String,
-    ) -> Result<Response<GetAppByIdResponse>, Status> {
-        let mut req = Request::new(GetAppByIdRequest {
-            project_id: self.project_id.clone(),
-            app_id,
-        });
-        req.metadata_mut()
-            .insert("authorization", self.service_user_token.parse().unwrap());
-        self.management_service_client.get_app_by_id(req).await
+    async fn update_app(
+        &self,
+        project: Option<&AppProject>,
+        app_id: &str,
+        redirect_uris: Vec<String>,
+        post_logout_redirect_uris: Vec<String>,
+    ) -> Result<(), Status> {
+        let req = self.project_request(
+            project,
+            UpdateOidcAppConfigRequest {
+                project_id: self.project_id(project),
+                app_id: app_id.to_string(),
+                redirect_uris,
+                response_types: vec![OidcResponseType::Code.into()],
+                grant_types: vec![
+                    OidcGrantType::AuthorizationCode.into(),
+                    OidcGrantType::RefreshToken.into(),
+                ],
+                app_type: OidcAppType::Web.into(),
+                auth_method_type: OidcAuthMethodType::None.into(),
+                post_logout_redirect_uris,
+                access_token_type: AccessTokenType::Jwt.into(),
+                ..Default::default()
+            },
+        );
+
+        self.management_service_client
+            .clone()
+            .update_oidc_app_config(req)
+            .await?;
+
+        Ok(())
     }
 
-    pub async fn remove_app(
-        &mut self,
-        app_id: String,
-    ) -> Result<Response<RemoveAppResponse>, Status> {
-        let mut req = Request::new(RemoveAppRequest {
-            project_id: self.project_id.clone(),
-            app_id,
+    async fn remove_app(
+        &self,
+        project: Option<&AppProject>,
+        app_id: &str,
+    ) -> Result<(), Status> {
+        let req = self.project_request(
+            project,
+            RemoveAppRequest {
+                project_id: self.project_id(project),
+                app_id: app_id.to_string(),
+            },
+        );
+
+        self.management_service_client
+            .clone()
+            .remove_app(req)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_apps(&self) -> Result<Vec<OidcApp>, Status> {
+        self.list_project_apps(None).await
+    }
+
+    /// The service user owns the organizations it added, they are found
+    /// through its memberships. Every organization has one project. The
+    /// organization of the service user holds the shared project and is left
+    /// out.
+    async fn list_projects(&self) -> Result<Vec<ProjectOrg>, Status> {
+        let own_org_id = self
+            .management_service_client
+            .clone()
+            .get_my_org(self.request(GetMyOrgRequest {}))
+            .await?
+            .into_inner()
+            .org
+            .map(|org| org.id)
+            .ok_or_else(|| {
+                Status::internal("Could not get own organization")
+            })?;
+
+        let mut memberships = Vec::new();
+
+        loop {
+            let req = self.request(ListMyMembershipsRequest {
+                query: Some(ListQuery {
+                    offset: memberships.len() as u64,
+                    limit: Self::LIST_LIMIT,
+                    asc: true,
+                }),
+                queries: vec![],
+            });
+
+            let result = self
+                .auth_service_client
+                .clone()
+                .list_my_memberships(req)
+                .await?
+                .into_inner()
+                .result;
+            let is_last_page = result.len() < Self::LIST_LIMIT as usize;
+            memberships.extend(result);
+
+            if is_last_page {
+                break;
+            }
+        }
+
+        let mut orgs = Vec::new();
+
+        for membership in memberships {
+            let Some(MembershipType::OrgId(org_id)) = membership.r#type else {
+                continue;
+            };
+            if org_id == own_org_id {
+                continue;
+            }
+
+            let project_id = self
+                .management_service_client
+                .clone()
+                .list_projects(self.org_request(
+                    &org_id,
+                    ListProjectsRequest {
+                        query: Some(ListQuery {
+                            offset: 0,
+                            limit: 1,
+                            asc: true,
+                        }),
+                        queries: vec![],
+                    },
+                ))
+                .await?
+                .into_inner()
+                .result
+                .into_iter()
+                .next()
+                .map(|project| project.id);
+
+            let apps = match &project_id {
+                Some(project_id) => {
+                    self.list_project_apps(Some(&AppProject {
+                        org_id: org_id.clone(),
+                        project_id: project_id.clone(),
+                    }))
+                    .await?
+                }
+                None => Vec::new(),
+            };
+
+            orgs.push(ProjectOrg {
+                created_at: Self::created_at(&membership.details),
+                org_id,
+                project_id,
+                name: membership.display_name,
+                apps,
+            });
+        }
+
+        Ok(orgs)
+    }
+
+    /// The login screen shows the active label policy, the preview policy is
+    /// only changed and activated if the active one differs from `branding`.
+    /// Only the colours of the branding and the logo are changed, the other
+    /// fields are kept as they are in the preview policy, see
+    /// `LabelColors`. Logos are compared by their content, as ZITADEL serves
+    /// them from its own URLs.
+    async fn set_branding(
+        &self,
+        project: &AppProject,
+        branding: &Branding,
+    ) -> Result<(), Status> {
+        let mut client = self.management_service_client.clone();
+        let org_id = &project.org_id;
+
+        let colors = LabelColors::from(branding);
+        let logo = match &branding.logo_url {
+            Some(logo_url) => Some(self.download(logo_url).await?),
+            None => None,
+        };
+
+        let active = client
+            .get_label_policy(
+                self.org_request(org_id, GetLabelPolicyRequest {}),
+            )
+            .await?
+            .into_inner()
+            .policy
+            .unwrap_or_default();
+
+        if !active.is_default
+            && colors.is_applied(&active)
+            && self.download_logo(&active).await? == logo
+        {
+            return Ok(());
+        }
+
+        let preview = client
+            .get_preview_label_policy(
+                self.org_request(org_id, GetPreviewLabelPolicyRequest {}),
+            )
+            .await?
+            .into_inner()
+            .policy
+            .unwrap_or_default();
+
+        if preview.is_default {
+            client
+                .add_custom_label_policy(
+                    self.org_request(org_id, colors.add_request(&preview)),
+                )
+                .await?;
+        } else if !colors.is_applied(&preview) {
+            client
+                .update_custom_label_policy(
+                    self.org_request(org_id, colors.update_request(&preview)),
+                )
+                .await?;
+        }
+
+        if self.download_logo(&preview).await? != logo {
+            match &logo {
+                Some(logo) => self.upload_logo(org_id, logo).await?,
+                None => {
+                    client
+                        .remove_custom_label_policy_logo(self.org_request(
+                            org_id,
+                            RemoveCustomLabelPolicyLogoRequest {},
+                        ))
+                        .await?;
+                }
+            }
+        }
+
+        client
+            .activate_custom_label_policy(
+                self.org_request(org_id, ActivateCustomLabelPolicyRequest {}),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// The fields of a label policy set from a `Branding`. The secondary colour
+/// of a website is the background colour of its login screen. Colours the
+/// website does not set are left empty, so ZITADEL falls back to its default.
+#[derive(Debug, PartialEq, Eq)]
+struct LabelColors {
+    primary_color: String,
+    background_color: String,
+}
+
+impl LabelColors {
+    fn is_applied(&self, policy: &LabelPolicy) -> bool {
+        policy.primary_color == self.primary_color
+            && policy.background_color == self.background_color
+    }
+
+    /// Creates the custom policy of an organization from the inherited
+    /// `default` policy.
+    fn add_request(
+        &self,
+        default: &LabelPolicy,
+    ) -> AddCustomLabelPolicyRequest {
+        AddCustomLabelPolicyRequest {
+            primary_color: self.primary_color.clone(),
+            background_color: self.background_color.clone(),
+            hide_login_name_suffix: default.hide_login_name_suffix,
+            warn_color: default.warn_color.clone(),
+            font_color: default.font_color.clone(),
+            primary_color_dark: default.primary_color_dark.clone(),
+            background_color_dark: default.background_color_dark.clone(),
+            warn_color_dark: default.warn_color_dark.clone(),
+            font_color_dark: default.font_color_dark.clone(),
+            disable_watermark: default.disable_watermark,
+            theme_mode: default.theme_mode,
+        }
+    }
+
+    fn update_request(
+        &self,
+        preview: &LabelPolicy,
+    ) -> UpdateCustomLabelPolicyRequest {
+        UpdateCustomLabelPolicyRequest {
+            primary_color: self.primary_color.clone(),
+            background_color: self.background_color.clone(),
+            hide_login_name_suffix: preview.hide_login_name_suffix,
+            warn_color: preview.warn_color.clone(),
+            font_color: preview.font_color.clone(),
+            primary_color_dark: preview.primary_color_dark.clone(),
+            background_color_dark: preview.background_color_dark.clone(),
+            warn_color_dark: preview.warn_color_dark.clone(),
+            font_color_dark: preview.font_color_dark.clone(),
+            disable_watermark: preview.disable_watermark,
+            theme_mode: preview.theme_mode,
+        }
+    }
+}
+
+impl From<&Branding> for LabelColors {
+    fn from(branding: &Branding) -> Self {
+        Self {
+            primary_color: branding.primary_color.clone().unwrap_or_default(),
+            background_color: branding
+                .secondary_color
+                .clone()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zitadel::api::zitadel::policy::v1::LabelPolicy;
+
+    use crate::identity::Branding;
+
+    use super::LabelColors;
+
+    #[test]
+    fn label_colors_keep_other_fields_of_policy() {
+        let policy = LabelPolicy {
+            primary_color: "#000000".to_string(),
+            background_color: "#111111".to_string(),
+            warn_color: "#222222".to_string(),
+            font_color: "#333333".to_string(),
+            primary_color_dark: "#444444".to_string(),
+            hide_login_name_suffix: true,
+            theme_mode: 2,
+            ..Default::default()
+        };
+        let colors = LabelColors::from(&Branding {
+            primary_color: Some("#ff0000".to_string()),
+            secondary_color: Some("#00ff00".to_string()),
+            logo_url: None,
         });
-        req.metadata_mut()
-            .insert("authorization", self.service_user_token.parse().unwrap());
-        self.management_service_client.remove_app(req).await
+
+        assert!(!colors.is_applied(&policy));
+
+        let req = colors.update_request(&policy);
+        assert_eq!(req.primary_color, "#ff0000");
+        assert_eq!(req.background_color, "#00ff00");
+        assert_eq!(req.warn_color, "#222222");
+        assert_eq!(req.font_color, "#333333");
+        assert_eq!(req.primary_color_dark, "#444444");
+        assert!(req.hide_login_name_suffix);
+        assert_eq!(req.theme_mode, 2);
+
+        let req = colors.add_request(&policy);
+        assert_eq!(req.background_color, "#00ff00");
+        assert_eq!(req.warn_color, "#222222");
+
+        let applied = LabelPolicy {
+            primary_color: req.primary_color,
+            background_color: req.background_color,
+            ..policy
+        };
+        assert!(colors.is_applied(&applied));
     }
 }
@@ -0,0 +1,452 @@
+//! Harness for the integration tests. Runs all gRPC services in-process
+//! against a throwaway CockroachDB node, with stand-ins for JWKS, ZITADEL,
+//! Cloudflare, S3 and NATS.
+//!
+//! The tests run against CockroachDB instead of Postgres, as the migrations
+//! use CockroachDB-only syntax and plain Postgres cannot run them. The node
+//! is started with the `cockroach` binary found in `COCKROACH_BIN` or on the
+//! `PATH`. The tests needing it are ignored by default, run them with
+//! `cargo test -- --ignored`; they fail if there is no binary.
+
+// only used by the tests of the NATS integrations
+#[allow(dead_code)]
+pub mod nats;
+
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use deadpool_postgres::Pool;
+use http::uri::PathAndQuery;
+use jwtk::ecdsa::{EcdsaAlgorithm, EcdsaPrivateKey};
+use jwtk::jwk::{JwkSet, WithKid};
+use jwtk::{HeaderAndClaims, PublicKeyToJwk};
+use serde_json::json;
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use tonic::client::Grpc;
+use tonic::codec::ProstCodec;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Channel, Server};
+use tonic::{Request, Status};
+use websites::broker::InMemoryBroker;
+use websites::cache_purge::CachePurger;
+use websites::cloudflare::CloudflareService;
+use websites::db::{init_db_pool, migrate};
+use websites::dns::InMemoryResolver;
+use websites::identity::InMemoryIdentityProvider;
+use websites::images::ImageService;
+use websites::outbox::OutboxRelay;
+use websites::publisher::Publisher;
+use websites::repository::{DynStore, PgStore};
+use websites::website_cache::WebsiteCache;
+use websites::{
+    init_jwks_verifier, CustomizationService, DomainService, PageService,
+    StaticPageService, WebsiteService,
+};
+use wiremock::matchers::{method, path, path_regex};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+pub const MAIN_DOMAIN: &str = "sited.io";
+pub const FALLBACK_DOMAIN: &str = "fallback.sited.io";
+pub const IMAGE_BASE_URL: &str = "https://images.sited.io";
+const CLOUDFLARE_ZONE_ID: &str = "zone";
+
+/// Single CockroachDB node keeping its data in memory. The node is stopped
+/// when this is dropped.
+pub struct TestDatabase {
+    process: Child,
+    _dir: TempDir,
+    pub pool: Pool,
+}
+
+impl TestDatabase {
+    const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Starts the node and runs the migrations.
+    pub async fn start() -> Self {
+        let binary = std::env::var("COCKROACH_BIN")
+            .unwrap_or_else(|_| "cockroach".to_string());
+        let dir = tempfile::tempdir().unwrap();
+        let url_file = dir.path().join("url");
+
+        let mut process = Command::new(&binary)
+            .arg("start-single-node")
+            .arg("--insecure")
+            .arg("--store=type=mem,size=512MiB")
+            .arg("--listen-addr=127.0.0.1:0")
+            .arg("--http-addr=127.0.0.1:0")
+            .arg(format!("--listening-url-file={}", url_file.display()))
+            .current_dir(dir.path())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|err| {
+                panic!("could not start '{}': {}", binary, err)
+            });
+
+        let url = match Self::wait_for_url(&mut process, &url_file).await {
+            Ok(url) => url,
+            Err(err) => {
+                let _ = process.kill();
+                panic!("{}", err);
+            }
+        };
+
+        let database = Self {
+            process,
+            _dir: dir,
+            pool: init_db_pool(
+                url.host_str().unwrap().to_string(),
+                url.port().unwrap(),
+                url.username().to_string(),
+                String::new(),
+                url.path().trim_start_matches('/').to_string(),
+                None,
+                None,
+            )
+            .unwrap()
+            .primary,
+        };
+
+        migrate(&database.pool).await.unwrap();
+
+        database
+    }
+
+    /// The node writes its connection URL to the file once it accepts
+    /// connections.
+    async fn wait_for_url(
+        process: &mut Child,
+        url_file: &Path,
+    ) -> Result<reqwest::Url, String> {
+        let started = Instant::now();
+        loop {
+            if let Some(url) = std::fs::read_to_string(url_file)
+                .ok()
+                .filter(|url| url.ends_with('\n'))
+            {
+                return reqwest::Url::parse(url.trim())
+                    .map_err(|err| err.to_string());
+            }
+            if let Some(status) = process.try_wait().unwrap() {
+                return Err(format!("cockroach exited with {}", status));
+            }
+            if started.elapsed() > Self::STARTUP_TIMEOUT {
+                return Err(format!(
+                    "cockroach did not start within {:?}",
+                    Self::STARTUP_TIMEOUT
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// The services served on a local port, with the stand-ins they talk to.
+pub struct TestContext {
+    _database: TestDatabase,
+    pub dns_resolver: InMemoryResolver,
+    pub broker: InMemoryBroker,
+    pub relay: OutboxRelay,
+    pub cloudflare: MockServer,
+    pub bucket: MockServer,
+    _jwks: MockServer,
+    key: WithKid<EcdsaPrivateKey>,
+    channel: Channel,
+}
+
+impl TestContext {
+    /// Starts the database, see [`TestDatabase::start`], and the services.
+    pub async fn start() -> Self {
+        let database = TestDatabase::start().await;
+
+        let key = WithKid::new(
+            "test".to_string(),
+            EcdsaPrivateKey::generate(EcdsaAlgorithm::ES256).unwrap(),
+        );
+        let jwks = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/jwks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(JwkSet {
+                keys: vec![key.public_key_to_jwk().unwrap()],
+            }))
+            .mount(&jwks)
+            .await;
+
+        let cloudflare = start_cloudflare().await;
+
+        let bucket = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&bucket)
+            .await;
+        Mock::given(method("DELETE"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&bucket)
+            .await;
+
+        let store: DynStore = Arc::new(PgStore::new(database.pool.clone()));
+        let identity_provider = InMemoryIdentityProvider::new();
+        let dns_resolver = InMemoryResolver::new();
+        let broker = InMemoryBroker::new();
+        let edge_provider = Arc::new(CloudflareService::init(
+            cloudflare.uri(),
+            CLOUDFLARE_ZONE_ID.to_string(),
+            "Bearer token".to_string(),
+            FALLBACK_DOMAIN.to_string(),
+        ));
+        let image_service = ImageService::new(
+            "bucket".to_string(),
+            bucket.uri(),
+            "access-key-id".to_string(),
+            "secret-access-key".to_string(),
+            IMAGE_BASE_URL.to_string(),
+            1024 * 1024,
+        )
+        .await;
+        let verifier = || {
+            init_jwks_verifier("localhost", &format!("{}/jwks", jwks.uri()))
+                .unwrap()
+        };
+
+        let relay = OutboxRelay::new(
+            store.clone(),
+            Arc::new(broker.clone()),
+            OutboxRelay::DEFAULT_INTERVAL,
+            OutboxRelay::DEFAULT_MAX_ATTEMPTS,
+        );
+
+        let router = Server::builder()
+            .add_service(WebsiteService::build(
+                store.clone(),
+                verifier(),
+                MAIN_DOMAIN.to_string(),
+                FALLBACK_DOMAIN.to_string(),
+                Arc::new(identity_provider.clone()),
+                edge_provider.clone(),
+                image_service.clone(),
+                Publisher::new(),
+                CachePurger::disabled(),
+                WebsiteCache::disabled(),
+            ))
+            .add_service(DomainService::build(
+                store.clone(),
+                verifier(),
+                MAIN_DOMAIN.to_string(),
+                FALLBACK_DOMAIN.to_string(),
+                Arc::new(identity_provider.clone()),
+                edge_provider,
+                Arc::new(dns_resolver.clone()),
+                Publisher::new(),
+                WebsiteCache::disabled(),
+            ))
+            .add_service(PageService::build(
+                store.clone(),
+                verifier(),
+                Publisher::new(),
+                CachePurger::disabled(),
+                WebsiteCache::disabled(),
+            ))
+            .add_service(StaticPageService::build(
+                store.clone(),
+                verifier(),
+                Publisher::new(),
+                CachePurger::disabled(),
+            ))
+            .add_service(CustomizationService::build(
+                store,
+                verifier(),
+                image_service,
+                Arc::new(Notify::new()),
+                Publisher::new(),
+                CachePurger::disabled(),
+                WebsiteCache::disabled(),
+            ));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let incoming =
+            TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(router.serve_with_incoming(incoming));
+
+        let channel = Channel::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+
+        Self {
+            _database: database,
+            dns_resolver,
+            broker,
+            relay,
+            cloudflare,
+            bucket,
+            _jwks: jwks,
+            key,
+            channel,
+        }
+    }
+
+    /// Calls the unary RPC at `path`, authenticated as the user if given.
+    pub async fn call<Req, Res>(
+        &self,
+        path: &'static str,
+        user_id: Option<&str>,
+        message: Req,
+    ) -> Result<Res, Status>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Res: prost::Message + Default + Send + Sync + 'static,
+    {
+        let mut request = Request::new(message);
+        if let Some(user_id) = user_id {
+            request.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", self.token(user_id)).parse().unwrap(),
+            );
+        }
+
+        let mut grpc = Grpc::new(self.channel.clone());
+        grpc.ready()
+            .await
+            .map_err(|err| Status::unavailable(err.to_string()))?;
+        grpc.unary(
+            request,
+            PathAndQuery::from_static(path),
+            ProstCodec::<Req, Res>::default(),
+        )
+        .await
+        .map(|response| response.into_inner())
+    }
+
+    fn token(&self, user_id: &str) -> String {
+        jwtk::sign(
+            HeaderAndClaims::new_dynamic()
+                .set_sub(user_id)
+                .set_exp_from_now(Duration::from_secs(300)),
+            &self.key,
+        )
+        .unwrap()
+    }
+
+    /// Delivers the outbox to the broker stand-in and returns the types of
+    /// the delivered events, e.g. `website.created`.
+    pub async fn relay_events(&self) -> Vec<String> {
+        self.broker.clear();
+        self.relay.run_once().await.unwrap();
+        self.broker
+            .events()
+            .into_iter()
+            .map(|event| event.event_type)
+            .collect()
+    }
+
+    /// Number of requests the Cloudflare stand-in received for the method
+    /// and the path below the zone.
+    pub async fn cloudflare_requests(&self, method: &str, path: &str) -> usize {
+        let path = format!("/zones/{}/{}", CLOUDFLARE_ZONE_ID, path);
+        self.cloudflare
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.method.as_str() == method && r.url.path() == path)
+            .count()
+    }
+
+    /// Number of requests the S3 stand-in received for the method.
+    pub async fn bucket_requests(&self, method: &str) -> usize {
+        self.bucket
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.method.as_str() == method)
+            .count()
+    }
+}
+
+/// Answers the Cloudflare API calls of `CloudflareService`. Listings always
+/// contain one entry, so deletions are requested.
+async fn start_cloudflare() -> MockServer {
+    let server = MockServer::start().await;
+    let zone_path =
+        |path: &str| format!("/zones/{}/{}", CLOUDFLARE_ZONE_ID, path);
+    let success = |result: serde_json::Value| {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "success": true,
+            "errors": [],
+            "result": result,
+            "result_info": { "page": 1, "total_pages": 1 },
+        }))
+    };
+    let dns_record = json!({
+        "id": "record",
+        "name": "record.sited.io",
+        "content": FALLBACK_DOMAIN,
+        "proxied": true,
+        "type": "CNAME",
+    });
+    let custom_hostname = json!({
+        "id": "hostname",
+        "hostname": "hostname.example.com",
+    });
+
+    Mock::given(method("POST"))
+        .and(path(zone_path("dns_records")))
+        .respond_with(success(dns_record.clone()))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(zone_path("dns_records")))
+        .respond_with(success(json!([dns_record])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(zone_path("custom_hostnames")))
+        .respond_with(success(custom_hostname.clone()))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(zone_path("custom_hostnames")))
+        .respond_with(success(json!([custom_hostname])))
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path_regex(format!(
+            "^/zones/{}/(dns_records|custom_hostnames)/",
+            CLOUDFLARE_ZONE_ID
+        )))
+        .respond_with(success(json!({ "id": "deleted" })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(zone_path("purge_cache")))
+        .respond_with(success(json!({ "id": "purge" })))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+/// A PNG accepted as logo image.
+pub fn png() -> Vec<u8> {
+    let mut data = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(2, 2))
+        .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+        .unwrap();
+    data
+}
@@ -0,0 +1,66 @@
+//! `nats-server` for the tests of the NATS integrations, found in
+//! `NATS_SERVER_BIN` or on the `PATH`.
+
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+/// `nats-server` with JetStream, stopped when this is dropped.
+pub struct NatsServer {
+    process: Child,
+    _dir: TempDir,
+    url: String,
+}
+
+impl NatsServer {
+    const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub async fn start() -> (Self, async_nats::Client) {
+        let binary = std::env::var("NATS_SERVER_BIN")
+            .unwrap_or_else(|_| "nats-server".to_string());
+        let dir = tempfile::tempdir().unwrap();
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let process = Command::new(&binary)
+            .arg("--jetstream")
+            .arg(format!("--store_dir={}", dir.path().display()))
+            .arg("--addr=127.0.0.1")
+            .arg(format!("--port={}", port))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|err| panic!("could not start '{binary}': {err}"));
+
+        let server = Self {
+            process,
+            _dir: dir,
+            url: format!("nats://127.0.0.1:{}", port),
+        };
+
+        let started = Instant::now();
+        loop {
+            match async_nats::connect(&server.url).await {
+                Ok(client) => return (server, client),
+                Err(_) if started.elapsed() < Self::STARTUP_TIMEOUT => {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                Err(err) => {
+                    panic!("could not connect to {}: {}", server.url, err)
+                }
+            }
+        }
+    }
+}
+
+impl Drop for NatsServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
@@ -0,0 +1,536 @@
+//! Drives the gRPC services through a real server and database, see
+//! `common::TestContext`. Ignored by default, as they need the `cockroach`
+//! binary, run them with `cargo test --test grpc -- --ignored`.
+
+mod common;
+
+use tonic::Code;
+use websites::api::sited_io::media::v1::MediaUpload;
+use websites::api::sited_io::websites::v1::{
+    CheckDomainStatusRequest, CheckDomainStatusResponse, Component,
+    CreateDomainRequest, CreateDomainResponse, CreatePageRequest,
+    CreatePageResponse, CreateWebsiteRequest, CreateWebsiteResponse,
+    CustomizationResponse, DeleteDomainRequest, DeleteDomainResponse,
+    DeletePageRequest, DeletePageResponse, DeleteWebsiteRequest,
+    DeleteWebsiteResponse, DomainStatus, GetPageRequest, GetPageResponse,
+    GetStaticPageRequest, GetStaticPageResponse, GetWebsiteRequest,
+    GetWebsiteResponse, ListDomainsRequest, ListDomainsResponse, PageType,
+    PutLogoImageRequest, PutLogoImageResponse, RemoveLogoImageRequest,
+    RemoveLogoImageResponse, SetSubdomainRequest, SetSubdomainResponse,
+    UpdateCustomizationRequest, UpdateCustomizationResponse, UpdatePageRequest,
+    UpdatePageResponse, UpdateStaticPageRequest, UpdateStaticPageResponse,
+    UpdateWebsiteRequest, UpdateWebsiteResponse, WebsiteResponse,
+};
+use websites::dns::{DnsAnswer, RECORD_TYPE_A, RECORD_TYPE_CNAME};
+
+use common::{png, TestContext, FALLBACK_DOMAIN, IMAGE_BASE_URL, MAIN_DOMAIN};
+
+const USER_ID: &str = "user-1";
+const OTHER_USER_ID: &str = "user-2";
+
+const CREATE_WEBSITE: &str =
+    "/sited_io.websites.v1.WebsiteService/CreateWebsite";
+const GET_WEBSITE: &str = "/sited_io.websites.v1.WebsiteService/GetWebsite";
+const UPDATE_WEBSITE: &str =
+    "/sited_io.websites.v1.WebsiteService/UpdateWebsite";
+const SET_SUBDOMAIN: &str = "/sited_io.websites.v1.WebsiteService/SetSubdomain";
+const DELETE_WEBSITE: &str =
+    "/sited_io.websites.v1.WebsiteService/DeleteWebsite";
+const CREATE_DOMAIN: &str = "/sited_io.websites.v1.DomainService/CreateDomain";
+const LIST_DOMAINS: &str = "/sited_io.websites.v1.DomainService/ListDomains";
+const CHECK_DOMAIN_STATUS: &str =
+    "/sited_io.websites.v1.DomainService/CheckDomainStatus";
+const DELETE_DOMAIN: &str = "/sited_io.websites.v1.DomainService/DeleteDomain";
+const CREATE_PAGE: &str = "/sited_io.websites.v1.PageService/CreatePage";
+const GET_PAGE: &str = "/sited_io.websites.v1.PageService/GetPage";
+const UPDATE_PAGE: &str = "/sited_io.websites.v1.PageService/UpdatePage";
+const DELETE_PAGE: &str = "/sited_io.websites.v1.PageService/DeletePage";
+const GET_STATIC_PAGE: &str =
+    "/sited_io.websites.v1.StaticPageService/GetStaticPage";
+const UPDATE_STATIC_PAGE: &str =
+    "/sited_io.websites.v1.StaticPageService/UpdateStaticPage";
+const UPDATE_CUSTOMIZATION: &str =
+    "/sited_io.websites.v1.CustomizationService/UpdateCustomization";
+const PUT_LOGO_IMAGE: &str =
+    "/sited_io.websites.v1.CustomizationService/PutLogoImage";
+const REMOVE_LOGO_IMAGE: &str =
+    "/sited_io.websites.v1.CustomizationService/RemoveLogoImage";
+
+async fn get_website(
+    context: &TestContext,
+    website_id: &str,
+) -> Option<WebsiteResponse> {
+    context
+        .call::<_, GetWebsiteResponse>(
+            GET_WEBSITE,
+            None,
+            GetWebsiteRequest {
+                website_id: Some(website_id.to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .website
+}
+
+/// Creates a website and reads it back with its domains and pages.
+async fn create_website(context: &TestContext, name: &str) -> WebsiteResponse {
+    let created = context
+        .call::<_, CreateWebsiteResponse>(
+            CREATE_WEBSITE,
+            Some(USER_ID),
+            CreateWebsiteRequest {
+                name: name.to_string(),
+            },
+        )
+        .await
+        .unwrap()
+        .website
+        .unwrap();
+
+    get_website(context, &created.website_id).await.unwrap()
+}
+
+#[tokio::test]
+#[ignore]
+async fn websites() {
+    let context = TestContext::start().await;
+
+    let err = context
+        .call::<_, CreateWebsiteResponse>(
+            CREATE_WEBSITE,
+            None,
+            CreateWebsiteRequest {
+                name: "My Website".to_string(),
+            },
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::Unauthenticated);
+
+    let website = create_website(&context, "My Website").await;
+    let generated_domain = format!("{}.{}", website.website_id, MAIN_DOMAIN);
+    assert_eq!(website.user_id, USER_ID);
+    assert_eq!(website.domains.len(), 1);
+    assert_eq!(website.domains[0].domain, generated_domain);
+    assert_eq!(website.pages.len(), 1);
+    assert!(website.customization.is_some());
+    assert_eq!(context.cloudflare_requests("POST", "dns_records").await, 1);
+    assert_eq!(context.relay_events().await, vec!["website.created"]);
+
+    let err = context
+        .call::<_, CreateWebsiteResponse>(
+            CREATE_WEBSITE,
+            Some(USER_ID),
+            CreateWebsiteRequest {
+                name: "My Website".to_string(),
+            },
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+
+    let updated = context
+        .call::<_, UpdateWebsiteResponse>(
+            UPDATE_WEBSITE,
+            Some(USER_ID),
+            UpdateWebsiteRequest {
+                website_id: website.website_id.clone(),
+                name: Some("Renamed".to_string()),
+            },
+        )
+        .await
+        .unwrap()
+        .website
+        .unwrap();
+    assert_eq!(updated.name, "Renamed");
+    assert_eq!(context.relay_events().await, vec!["website.updated"]);
+
+    let updated = context
+        .call::<_, SetSubdomainResponse>(
+            SET_SUBDOMAIN,
+            Some(USER_ID),
+            SetSubdomainRequest {
+                website_id: website.website_id.clone(),
+                subdomain: "myshop".to_string(),
+            },
+        )
+        .await
+        .unwrap()
+        .website
+        .unwrap();
+    let status = |domain: &str| {
+        updated
+            .domains
+            .iter()
+            .find(|d| d.domain == domain)
+            .map(|d| d.status)
+    };
+    assert_eq!(
+        status("myshop.sited.io"),
+        Some(DomainStatus::Internal as i32)
+    );
+    assert_eq!(
+        status(&generated_domain),
+        Some(DomainStatus::Redirect as i32)
+    );
+    assert_eq!(context.cloudflare_requests("POST", "dns_records").await, 2);
+
+    let err = context
+        .call::<_, DeleteWebsiteResponse>(
+            DELETE_WEBSITE,
+            Some(OTHER_USER_ID),
+            DeleteWebsiteRequest {
+                website_id: website.website_id.clone(),
+            },
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::NotFound);
+
+    context.relay_events().await;
+    context
+        .call::<_, DeleteWebsiteResponse>(
+            DELETE_WEBSITE,
+            Some(USER_ID),
+            DeleteWebsiteRequest {
+                website_id: website.website_id.clone(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(get_website(&context, &website.website_id).await, None);
+    // the stand-in lists one record per domain
+    assert_eq!(
+        context
+            .cloudflare_requests("DELETE", "dns_records/record")
+            .await,
+        2
+    );
+    assert_eq!(context.relay_events().await, vec!["website.deleted"]);
+}
+
+#[tokio::test]
+#[ignore]
+async fn pages_and_static_pages() {
+    let context = TestContext::start().await;
+    let website = create_website(&context, "My Website").await;
+    context.relay_events().await;
+
+    let created = context
+        .call::<_, CreatePageResponse>(
+            CREATE_PAGE,
+            Some(USER_ID),
+            CreatePageRequest {
+                website_id: website.website_id.clone(),
+                page_type: PageType::Static as i32,
+                content_id: String::new(),
+                title: "About Us".to_string(),
+                is_home_page: false,
+                path: None,
+            },
+        )
+        .await
+        .unwrap()
+        .page
+        .unwrap();
+    assert_eq!(created.path, "/about-us");
+    assert_eq!(
+        context.relay_events().await,
+        vec!["page.created", "static_page.created"]
+    );
+
+    let updated = context
+        .call::<_, UpdatePageResponse>(
+            UPDATE_PAGE,
+            Some(USER_ID),
+            UpdatePageRequest {
+                page_id: created.page_id,
+                page_type: None,
+                content_id: None,
+                title: Some("Team".to_string()),
+                is_home_page: None,
+                path: Some("/team".to_string()),
+            },
+        )
+        .await
+        .unwrap()
+        .page
+        .unwrap();
+    assert_eq!(updated.title, "Team");
+
+    let found = context
+        .call::<_, GetPageResponse>(
+            GET_PAGE,
+            None,
+            GetPageRequest {
+                page_id: None,
+                website_id: Some(website.website_id.clone()),
+                path: Some("/team".to_string()),
+            },
+        )
+        .await
+        .unwrap()
+        .page;
+    assert_eq!(found, Some(updated));
+
+    let get_static_page = || {
+        context.call::<_, GetStaticPageResponse>(
+            GET_STATIC_PAGE,
+            None,
+            GetStaticPageRequest {
+                page_id: created.page_id,
+            },
+        )
+    };
+    let static_page = get_static_page().await.unwrap().static_page.unwrap();
+    assert!(static_page.components.is_empty());
+
+    let components = vec![Component {
+        component_id: "component-1".to_string(),
+        component_type: None,
+    }];
+    context
+        .call::<_, UpdateStaticPageResponse>(
+            UPDATE_STATIC_PAGE,
+            Some(USER_ID),
+            UpdateStaticPageRequest {
+                page_id: created.page_id,
+                components: components.clone(),
+            },
+        )
+        .await
+        .unwrap();
+    let static_page = get_static_page().await.unwrap().static_page.unwrap();
+    assert_eq!(static_page.components, components);
+    assert_eq!(
+        context.relay_events().await,
+        vec!["page.updated", "static_page.updated"]
+    );
+
+    // the home page can not be deleted
+    let err = context
+        .call::<_, DeletePageResponse>(
+            DELETE_PAGE,
+            Some(USER_ID),
+            DeletePageRequest {
+                page_id: website.pages[0].page_id,
+            },
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+
+    context
+        .call::<_, DeletePageResponse>(
+            DELETE_PAGE,
+            Some(USER_ID),
+            DeletePageRequest {
+                page_id: created.page_id,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(get_static_page().await.unwrap().static_page, None);
+    let mut events = context.relay_events().await;
+    events.sort();
+    assert_eq!(events, vec!["page.deleted", "static_page.deleted"]);
+}
+
+#[tokio::test]
+#[ignore]
+async fn domains() {
+    let context = TestContext::start().await;
+    let website = create_website(&context, "My Website").await;
+    context.relay_events().await;
+
+    let created = context
+        .call::<_, CreateDomainResponse>(
+            CREATE_DOMAIN,
+            Some(USER_ID),
+            CreateDomainRequest {
+                website_id: website.website_id.clone(),
+                domain: "Shop.Example.com".to_string(),
+            },
+        )
+        .await
+        .unwrap()
+        .domain
+        .unwrap();
+    assert_eq!(created.domain, "shop.example.com");
+    assert_eq!(created.status, DomainStatus::Pending as i32);
+
+    let listed = context
+        .call::<_, ListDomainsResponse>(
+            LIST_DOMAINS,
+            Some(USER_ID),
+            ListDomainsRequest {
+                website_id: website.website_id.clone(),
+                status: Some(DomainStatus::Pending as i32),
+                pagination: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(listed.domains, vec![created.clone()]);
+
+    let check_status = || {
+        context.call::<_, CheckDomainStatusResponse>(
+            CHECK_DOMAIN_STATUS,
+            Some(USER_ID),
+            CheckDomainStatusRequest {
+                domain_id: created.domain_id,
+            },
+        )
+    };
+
+    let pending = check_status().await.unwrap().domain.unwrap();
+    assert_eq!(pending.status, DomainStatus::Pending as i32);
+    assert_eq!(
+        context
+            .cloudflare_requests("POST", "custom_hostnames")
+            .await,
+        0
+    );
+
+    context.dns_resolver.set(
+        FALLBACK_DOMAIN,
+        vec![DnsAnswer::new(FALLBACK_DOMAIN, RECORD_TYPE_A, "104.21.0.1")],
+    );
+    context.dns_resolver.set(
+        "shop.example.com",
+        vec![
+            DnsAnswer::new(
+                "shop.example.com",
+                RECORD_TYPE_CNAME,
+                FALLBACK_DOMAIN,
+            ),
+            DnsAnswer::new(FALLBACK_DOMAIN, RECORD_TYPE_A, "104.21.0.1"),
+        ],
+    );
+
+    let active = check_status().await.unwrap().domain.unwrap();
+    assert_eq!(active.status, DomainStatus::Active as i32);
+    assert_eq!(
+        context
+            .cloudflare_requests("POST", "custom_hostnames")
+            .await,
+        1
+    );
+    assert_eq!(
+        context.relay_events().await,
+        vec!["domain.created", "domain.updated", "domain.updated"]
+    );
+
+    context
+        .call::<_, DeleteDomainResponse>(
+            DELETE_DOMAIN,
+            Some(USER_ID),
+            DeleteDomainRequest {
+                domain_id: created.domain_id,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        context
+            .cloudflare_requests("DELETE", "custom_hostnames/hostname")
+            .await,
+        1
+    );
+    assert_eq!(context.relay_events().await, vec!["domain.deleted"]);
+
+    let website = get_website(&context, &website.website_id).await.unwrap();
+    assert_eq!(website.domains.len(), 1);
+}
+
+#[tokio::test]
+#[ignore]
+async fn customizations() {
+    let context = TestContext::start().await;
+    let website = create_website(&context, "My Website").await;
+    context.relay_events().await;
+
+    let updated = context
+        .call::<_, UpdateCustomizationResponse>(
+            UPDATE_CUSTOMIZATION,
+            Some(USER_ID),
+            UpdateCustomizationRequest {
+                website_id: website.website_id.clone(),
+                primary_color: Some("#ff0000".to_string()),
+                secondary_color: Some("#00ff00".to_string()),
+            },
+        )
+        .await
+        .unwrap()
+        .customization
+        .unwrap();
+    assert_eq!(updated.primary_color.as_deref(), Some("#ff0000"));
+    assert_eq!(updated.secondary_color.as_deref(), Some("#00ff00"));
+
+    let customization = || async {
+        get_website(&context, &website.website_id)
+            .await
+            .unwrap()
+            .customization
+            .unwrap()
+    };
+    let put_logo_image = || {
+        context.call::<_, PutLogoImageResponse>(
+            PUT_LOGO_IMAGE,
+            Some(USER_ID),
+            PutLogoImageRequest {
+                website_id: website.website_id.clone(),
+                image: Some(MediaUpload {
+                    content_type: "image/png".to_string(),
+                    data: png(),
+                }),
+            },
+        )
+    };
+
+    put_logo_image().await.unwrap();
+    let CustomizationResponse { logo_image_url, .. } = customization().await;
+    let first_logo_image_url = logo_image_url.unwrap();
+    assert!(first_logo_image_url
+        .starts_with(&format!("{}/{}/", IMAGE_BASE_URL, USER_ID)));
+    assert_eq!(context.bucket_requests("PUT").await, 1);
+
+    // the previous logo is removed from the bucket
+    put_logo_image().await.unwrap();
+    let CustomizationResponse { logo_image_url, .. } = customization().await;
+    assert_ne!(logo_image_url.unwrap(), first_logo_image_url);
+    assert_eq!(context.bucket_requests("PUT").await, 2);
+    assert_eq!(context.bucket_requests("DELETE").await, 1);
+
+    context
+        .call::<_, RemoveLogoImageResponse>(
+            REMOVE_LOGO_IMAGE,
+            Some(USER_ID),
+            RemoveLogoImageRequest {
+                website_id: website.website_id.clone(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(customization().await.logo_image_url, None);
+    assert_eq!(context.bucket_requests("DELETE").await, 2);
+    assert_eq!(
+        context.relay_events().await,
+        vec!["customization.updated"; 4]
+    );
+
+    let result = context
+        .call::<_, UpdateCustomizationResponse>(
+            UPDATE_CUSTOMIZATION,
+            Some(OTHER_USER_ID),
+            UpdateCustomizationRequest {
+                website_id: website.website_id,
+                primary_color: Some("#0000ff".to_string()),
+                secondary_color: None,
+            },
+        )
+        .await;
+    assert!(result.is_err());
+}
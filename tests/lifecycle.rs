@@ -0,0 +1,162 @@
+//! Runs `LifecycleConsumer` against a `nats-server` with JetStream enabled,
+//! like with `NATS_JETSTREAM=true`.
+//!
+//! Ignored by default, as it needs the `nats-server` binary found in
+//! `NATS_SERVER_BIN` or on the `PATH`. Run it with
+//! `cargo test --test lifecycle -- --ignored`.
+
+#[allow(dead_code)]
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use prost::Message;
+use websites::api::sited_io::websites::v1::{PageType, ShopDeletedEvent};
+use websites::cache_purge::CachePurger;
+use websites::edge::InMemoryEdgeProvider;
+use websites::identity::{AppProject, InMemoryIdentityProvider};
+use websites::images::ImageService;
+use websites::init_jwks_verifier;
+use websites::lifecycle::LifecycleConsumer;
+use websites::publisher::Publisher;
+use websites::repository::{InMemoryStore, Repositories};
+use websites::website_cache::WebsiteCache;
+use websites::WebsiteService;
+
+use common::nats::NatsServer;
+
+const WEBSITE_ID: &str = "website-1";
+const USER_ID: &str = "user-1";
+const SHOP_ID: &str = "shop-1";
+
+async fn store() -> InMemoryStore {
+    let store = InMemoryStore::new();
+    store
+        .websites()
+        .create(
+            &WEBSITE_ID.to_string(),
+            &USER_ID.to_string(),
+            &"My Website".to_string(),
+            &"client-1".to_string(),
+            &"app-1".to_string(),
+            &AppProject {
+                org_id: "org-1".to_string(),
+                project_id: "project-1".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    store
+        .pages()
+        .create(
+            &WEBSITE_ID.to_string(),
+            &USER_ID.to_string(),
+            PageType::Shop,
+            &SHOP_ID.to_string(),
+            &"Shop".to_string(),
+            false,
+            &"/shop".to_string(),
+        )
+        .await
+        .unwrap();
+    store
+}
+
+async fn consumer(store: &InMemoryStore) -> LifecycleConsumer {
+    let image_service = ImageService::new(
+        "bucket".to_string(),
+        "http://127.0.0.1:1".to_string(),
+        "access-key-id".to_string(),
+        "secret-access-key".to_string(),
+        "https://images.sited.io".to_string(),
+        1024 * 1024,
+    )
+    .await;
+    let website_service = WebsiteService::new(
+        Arc::new(store.clone()),
+        init_jwks_verifier("localhost", &"http://127.0.0.1:1/jwks".to_string())
+            .unwrap(),
+        "sited.io".to_string(),
+        "fallback.sited.io".to_string(),
+        Arc::new(InMemoryIdentityProvider::new()),
+        Arc::new(InMemoryEdgeProvider::new()),
+        image_service,
+        Publisher::new(),
+        CachePurger::disabled(),
+        WebsiteCache::disabled(),
+    );
+
+    LifecycleConsumer::new(
+        Arc::new(store.clone()),
+        Arc::new(website_service),
+        Publisher::new(),
+        CachePurger::disabled(),
+        WebsiteCache::disabled(),
+        LifecycleConsumer::DEFAULT_USER_DELETED_SUBJECT.to_string(),
+        LifecycleConsumer::DEFAULT_SHOP_DELETED_SUBJECT.to_string(),
+    )
+}
+
+#[tokio::test]
+#[ignore]
+async fn events_published_while_down_are_processed() {
+    let (_server, client) = NatsServer::start().await;
+    let context = async_nats::jetstream::new(client.clone());
+    let store = store().await;
+
+    // the first start creates the stream and the durable consumer
+    let handle = consumer(&store)
+        .await
+        .spawn(client.clone(), Some(context.clone()))
+        .await
+        .unwrap();
+    handle.abort();
+    // drops the pull requests, so they no longer take messages
+    assert!(handle.await.unwrap_err().is_cancelled());
+    client.flush().await.unwrap();
+
+    context
+        .publish(
+            LifecycleConsumer::DEFAULT_SHOP_DELETED_SUBJECT,
+            ShopDeletedEvent {
+                shop_id: SHOP_ID.to_string(),
+                user_id: USER_ID.to_string(),
+            }
+            .encode_to_vec()
+            .into(),
+        )
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    consumer(&store)
+        .await
+        .spawn(client.clone(), Some(context.clone()))
+        .await
+        .unwrap();
+
+    let started = Instant::now();
+    while !store
+        .pages()
+        .list_by_content(PageType::Shop, &SHOP_ID.to_string())
+        .await
+        .unwrap()
+        .is_empty()
+    {
+        assert!(started.elapsed() < Duration::from_secs(10));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // acked events are removed from the work queue
+    let started = Instant::now();
+    let mut stream = context
+        .get_stream(LifecycleConsumer::STREAM_NAME)
+        .await
+        .unwrap();
+    while stream.info().await.unwrap().state.messages > 0 {
+        assert!(started.elapsed() < Duration::from_secs(10));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
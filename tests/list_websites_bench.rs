@@ -0,0 +1,124 @@
+//! Times `ListWebsites` on a seeded CockroachDB, see `common::TestContext`.
+//! Ignored by default, run it with
+//! `cargo test --release --test list_websites_bench -- --ignored --nocapture`.
+//! No reference timings are kept, compare runs on the commits in question on
+//! the same machine.
+
+#[allow(dead_code)]
+mod common;
+
+use std::time::{Duration, Instant};
+
+use websites::api::sited_io::types::v1::PaginationRequest;
+use websites::api::sited_io::websites::v1::{
+    CreatePageRequest, CreatePageResponse, CreateWebsiteRequest,
+    CreateWebsiteResponse, ListWebsitesRequest, ListWebsitesResponse, PageType,
+};
+
+use common::TestContext;
+
+const USER_ID: &str = "user-1";
+
+const CREATE_WEBSITE: &str =
+    "/sited_io.websites.v1.WebsiteService/CreateWebsite";
+const LIST_WEBSITES: &str = "/sited_io.websites.v1.WebsiteService/ListWebsites";
+const CREATE_PAGE: &str = "/sited_io.websites.v1.PageService/CreatePage";
+
+const WEBSITES: u32 = 100;
+/// Pages besides the home page every website is created with.
+const PAGES_PER_WEBSITE: u32 = 9;
+const WARMUP_ITERATIONS: u32 = 5;
+const ITERATIONS: u32 = 50;
+
+async fn seed(context: &TestContext) {
+    for website in 0..WEBSITES {
+        let website_id = context
+            .call::<_, CreateWebsiteResponse>(
+                CREATE_WEBSITE,
+                Some(USER_ID),
+                CreateWebsiteRequest {
+                    name: format!("Website {}", website),
+                },
+            )
+            .await
+            .unwrap()
+            .website
+            .unwrap()
+            .website_id;
+
+        for page in 0..PAGES_PER_WEBSITE {
+            context
+                .call::<_, CreatePageResponse>(
+                    CREATE_PAGE,
+                    Some(USER_ID),
+                    CreatePageRequest {
+                        website_id: website_id.clone(),
+                        page_type: PageType::Static as i32,
+                        content_id: String::new(),
+                        title: format!("Page {}", page),
+                        is_home_page: false,
+                        path: None,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+    }
+}
+
+async fn list_websites(context: &TestContext) -> Duration {
+    let started = Instant::now();
+
+    let response = context
+        .call::<_, ListWebsitesResponse>(
+            LIST_WEBSITES,
+            None,
+            ListWebsitesRequest {
+                user_id: Some(USER_ID.to_string()),
+                pagination: Some(PaginationRequest {
+                    page: 1,
+                    size: WEBSITES,
+                }),
+            },
+        )
+        .await
+        .unwrap();
+
+    let elapsed = started.elapsed();
+
+    assert_eq!(response.websites.len(), WEBSITES as usize);
+    assert!(response
+        .websites
+        .iter()
+        .all(|w| w.pages.len() == PAGES_PER_WEBSITE as usize + 1
+            && w.domains.len() == 1));
+
+    elapsed
+}
+
+#[tokio::test]
+#[ignore]
+async fn list_websites_with_relations() {
+    let context = TestContext::start().await;
+    seed(&context).await;
+
+    for _ in 0..WARMUP_ITERATIONS {
+        list_websites(&context).await;
+    }
+
+    let mut durations = Vec::new();
+    for _ in 0..ITERATIONS {
+        durations.push(list_websites(&context).await);
+    }
+    durations.sort();
+
+    println!(
+        "ListWebsites of {} websites with {} pages each: \
+        median {:?}, min {:?}, max {:?}",
+        WEBSITES,
+        PAGES_PER_WEBSITE + 1,
+        durations[durations.len() / 2],
+        durations[0],
+        durations[durations.len() - 1],
+    );
+}
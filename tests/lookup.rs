@@ -0,0 +1,100 @@
+//! Runs NATS lookups against a `nats-server` with JetStream enabled and the
+//! event stream in place, like with `NATS_JETSTREAM=true`.
+//!
+//! Ignored by default, as it needs the `nats-server` binary found in
+//! `NATS_SERVER_BIN` or on the `PATH`. Run it with
+//! `cargo test --test lookup -- --ignored`.
+
+#[allow(dead_code)]
+mod common;
+
+use std::sync::Arc;
+
+use prost::Message;
+use websites::api::sited_io::websites::v1::{DomainStatus, GetWebsiteResponse};
+use websites::identity::AppProject;
+use websites::images::ImageService;
+use websites::jetstream::{ensure_stream, StreamSettings};
+use websites::lookup::LookupResponder;
+use websites::repository::{InMemoryStore, Repositories};
+use websites::website_cache::WebsiteCache;
+
+use common::nats::NatsServer;
+
+const WEBSITE_ID: &str = "website-1";
+const DOMAIN: &str = "website-1.sited.io";
+
+async fn store() -> InMemoryStore {
+    let store = InMemoryStore::new();
+    let user_id = "user-1".to_string();
+    store
+        .websites()
+        .create(
+            &WEBSITE_ID.to_string(),
+            &user_id,
+            &"My Website".to_string(),
+            &"client-1".to_string(),
+            &"app-1".to_string(),
+            &AppProject {
+                org_id: "org-1".to_string(),
+                project_id: "project-1".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    store
+        .domains()
+        .create(
+            &WEBSITE_ID.to_string(),
+            &user_id,
+            &DOMAIN.to_string(),
+            &DOMAIN.to_string(),
+            DomainStatus::Internal,
+        )
+        .await
+        .unwrap();
+    store
+}
+
+#[tokio::test]
+#[ignore]
+async fn lookup_with_jetstream() {
+    let (_server, client) = NatsServer::start().await;
+    let context = async_nats::jetstream::new(client.clone());
+    let settings = StreamSettings::default();
+    ensure_stream(&context, &settings).await.unwrap();
+
+    let image_service = ImageService::new(
+        "bucket".to_string(),
+        "http://127.0.0.1:1".to_string(),
+        "access-key-id".to_string(),
+        "secret-access-key".to_string(),
+        "https://images.sited.io".to_string(),
+        1024 * 1024,
+    )
+    .await;
+    LookupResponder::new(
+        Arc::new(store().await),
+        client.clone(),
+        image_service,
+        WebsiteCache::disabled(),
+    )
+    .spawn()
+    .await
+    .unwrap();
+    client.flush().await.unwrap();
+
+    for _ in 0..3 {
+        let reply = client
+            .request(LookupResponder::BY_DOMAIN_SUBJECT, DOMAIN.into())
+            .await
+            .unwrap();
+
+        // a JetStream ack would be answered first and fail to decode
+        let response = GetWebsiteResponse::decode(reply.payload).unwrap();
+        assert_eq!(response.website.unwrap().website_id, WEBSITE_ID);
+    }
+
+    let mut stream = context.get_stream(&settings.name).await.unwrap();
+    assert_eq!(stream.info().await.unwrap().state.messages, 0);
+}